@@ -0,0 +1,74 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use hickory_proto::rr::Name;
+use loadants::config::{MatchType, RouteAction, RouteRuleConfig};
+use loadants::router::{RegexPrefilter, Router};
+
+// 规则数量
+const RULES: usize = 5000;
+
+// 生成模拟拦截列表的正则表达式模式，每条规则包含多个必需的字面片段
+fn regex_patterns() -> Vec<String> {
+    (0..RULES)
+        .map(|i| {
+            format!(
+                r"^(ads|track)\d*\.vendor{}\d*\.cdn-{}\.(com|net)$",
+                i % 100,
+                i
+            )
+        })
+        .collect()
+}
+
+fn bench_prefilter_build(c: &mut Criterion) {
+    let patterns = regex_patterns();
+    c.bench_function("regex_prefilter_build", |b| {
+        b.iter(|| RegexPrefilter::build(patterns.iter().map(String::as_str)))
+    });
+}
+
+fn bench_prefilter_candidates(c: &mut Criterion) {
+    let patterns = regex_patterns();
+    let prefilter = RegexPrefilter::build(patterns.iter().map(String::as_str));
+    c.bench_function("regex_prefilter_candidates", |b| {
+        b.iter(|| prefilter.candidates(black_box("ads1.vendor42.cdn-42.com")))
+    });
+    // 只包含部分必需片段的域名：单关键词预筛选需要逐条执行同一 vendor 下的所有正则
+    c.bench_function("regex_prefilter_candidates_partial", |b| {
+        b.iter(|| prefilter.candidates(black_box("ads1.vendor42.cdn-9999.org")))
+    });
+}
+
+fn bench_regex_find_match(c: &mut Criterion) {
+    let rules = regex_patterns()
+        .into_iter()
+        .map(|pattern| RouteRuleConfig {
+            match_type: MatchType::Regex,
+            patterns: vec![pattern],
+            action: RouteAction::Block,
+            target: None,
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        })
+        .collect();
+    let router = Router::new(rules).expect("Failed to create Router");
+    let hit = Name::from_ascii("ads1.vendor42.cdn-42.com.").unwrap();
+    let miss = Name::from_ascii("www.vendor42.cdn-7.org.").unwrap();
+
+    c.bench_function("regex_find_match_hit", |b| {
+        b.iter(|| router.find_match(black_box(&hit)))
+    });
+    c.bench_function("regex_find_match_miss", |b| {
+        b.iter(|| router.find_match(black_box(&miss)))
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_prefilter_build,
+    bench_prefilter_candidates,
+    bench_regex_find_match
+);
+criterion_main!(benches);
@@ -2,12 +2,19 @@
 
 use crate::cache::DnsCache;
 use crate::error::AppError;
+use crate::handler::RequestHandler;
 use crate::metrics;
+use crate::r#const::{client_stats_limits, rule_listing_limits};
+use crate::remote_rule::RuleReloader;
+use crate::stats::{ClientStats, RuleSourceStats, UpstreamStats};
+use crate::warmup::Readiness;
 use axum::{
+    extract::{Query, State},
     http::StatusCode,
     routing::{get, post},
     Json, Router,
 };
+use serde::Deserialize;
 use serde_json::json;
 use std::net::SocketAddr;
 use std::sync::Arc;
@@ -24,6 +31,18 @@ pub struct AdminServer {
     shutdown_requested: watch::Sender<bool>,
     // DNS缓存引用
     cache: Option<Arc<DnsCache>>,
+    // 客户端查询统计引用
+    client_stats: Option<Arc<ClientStats>>,
+    // 上游统计引用
+    upstream_stats: Option<Arc<UpstreamStats>>,
+    // 远程规则源新鲜度统计引用
+    rule_source_stats: Option<Arc<RuleSourceStats>>,
+    // 请求处理器引用（用于读取当前生效的路由规则）
+    handler: Option<Arc<RequestHandler>>,
+    // 就绪状态（默认就绪，启用预热时在预热完成后就绪）
+    readiness: Arc<Readiness>,
+    // 规则重载器（用于按需重载路由规则）
+    rule_reloader: Option<Arc<RuleReloader>>,
 }
 
 impl AdminServer {
@@ -33,6 +52,12 @@ impl AdminServer {
             listen_addr,
             shutdown_requested: watch::channel(false).0,
             cache: None,
+            client_stats: None,
+            upstream_stats: None,
+            rule_source_stats: None,
+            handler: None,
+            readiness: Arc::new(Readiness::ready()),
+            rule_reloader: None,
         }
     }
 
@@ -42,6 +67,42 @@ impl AdminServer {
         self
     }
 
+    // 设置客户端查询统计引用
+    pub fn with_client_stats(mut self, client_stats: Arc<ClientStats>) -> Self {
+        self.client_stats = Some(client_stats);
+        self
+    }
+
+    // 设置上游统计引用
+    pub fn with_upstream_stats(mut self, upstream_stats: Arc<UpstreamStats>) -> Self {
+        self.upstream_stats = Some(upstream_stats);
+        self
+    }
+
+    // 设置远程规则源新鲜度统计引用
+    pub fn with_rule_source_stats(mut self, rule_source_stats: Arc<RuleSourceStats>) -> Self {
+        self.rule_source_stats = Some(rule_source_stats);
+        self
+    }
+
+    // 设置请求处理器引用
+    pub fn with_handler(mut self, handler: Arc<RequestHandler>) -> Self {
+        self.handler = Some(handler);
+        self
+    }
+
+    // 设置就绪状态
+    pub fn with_readiness(mut self, readiness: Arc<Readiness>) -> Self {
+        self.readiness = readiness;
+        self
+    }
+
+    // 设置规则重载器
+    pub fn with_rule_reloader(mut self, rule_reloader: Arc<RuleReloader>) -> Self {
+        self.rule_reloader = Some(rule_reloader);
+        self
+    }
+
     // 停止管理服务器
     pub fn shutdown(&self) {
         self.shutdown_requested.send_replace(true);
@@ -51,11 +112,61 @@ impl AdminServer {
     // 启动管理服务器
     pub async fn start(&self) -> Result<(), AppError> {
         // 组合健康检查和指标路由
-        let app = Router::new()
+        let mut app = Router::new()
             .route("/health", get(health_handler))
             .route("/api/cache/refresh", post(refresh_cache_handler))
             .with_state(self.cache.clone())
-            .merge(metrics::metrics_routes());
+            .merge(metrics::metrics_routes())
+            .merge(
+                Router::new()
+                    .route("/readyz", get(readiness_handler))
+                    .with_state(self.readiness.clone()),
+            );
+
+        // 客户端查询统计路由
+        if let Some(client_stats) = &self.client_stats {
+            app = app.merge(
+                Router::new()
+                    .route("/stats/top-clients", get(top_clients_handler))
+                    .with_state(client_stats.clone()),
+            );
+        }
+
+        // 上游统计路由
+        if let Some(upstream_stats) = &self.upstream_stats {
+            app = app.merge(
+                Router::new()
+                    .route("/stats/upstreams", get(upstream_stats_handler))
+                    .with_state(upstream_stats.clone()),
+            );
+        }
+
+        // 远程规则新鲜度健康检查路由
+        if let Some(rule_source_stats) = &self.rule_source_stats {
+            app = app.merge(
+                Router::new()
+                    .route("/health/rules", get(rule_health_handler))
+                    .with_state(rule_source_stats.clone()),
+            );
+        }
+
+        // 生效规则导出路由
+        if let Some(handler) = &self.handler {
+            app = app.merge(
+                Router::new()
+                    .route("/rules", get(rules_handler))
+                    .with_state(handler.clone()),
+            );
+
+            // 规则重载路由
+            if let Some(rule_reloader) = &self.rule_reloader {
+                app = app.merge(
+                    Router::new()
+                        .route("/rules/reload", post(rules_reload_handler))
+                        .with_state((handler.clone(), rule_reloader.clone())),
+                );
+            }
+        }
 
         let listener = TcpListener::bind(self.listen_addr).await?;
         info!("Admin server listening on {}", self.listen_addr);
@@ -151,3 +262,146 @@ async fn refresh_cache_handler(
         }
     }
 }
+
+// Top Clients 查询参数
+#[derive(Debug, Deserialize)]
+pub struct TopClientsParams {
+    // 返回的客户端数量
+    pub limit: Option<usize>,
+}
+
+// Top Clients 处理程序
+pub async fn top_clients_handler(
+    State(client_stats): State<Arc<ClientStats>>,
+    Query(params): Query<TopClientsParams>,
+) -> Json<serde_json::Value> {
+    let limit = params
+        .limit
+        .unwrap_or(client_stats_limits::DEFAULT_TOP_N)
+        .clamp(1, client_stats_limits::MAX_TOP_N);
+
+    Json(json!({
+        "tracked_clients": client_stats.len(),
+        "clients": client_stats.top(limit),
+    }))
+}
+
+// 上游统计处理程序
+pub async fn upstream_stats_handler(
+    State(upstream_stats): State<Arc<UpstreamStats>>,
+) -> Json<serde_json::Value> {
+    Json(json!({
+        "groups": upstream_stats.snapshot(),
+    }))
+}
+
+// 远程规则新鲜度健康检查处理程序：任一规则源超过最大陈旧时长时返回 503
+pub async fn rule_health_handler(
+    State(rule_source_stats): State<Arc<RuleSourceStats>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let sources = rule_source_stats.snapshot();
+    let healthy = !sources.iter().any(|source| source.stale);
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(json!({
+            "status": if healthy { "healthy" } else { "unhealthy" },
+            "max_staleness": rule_source_stats.max_staleness().map(|max| max.as_secs()),
+            "sources": sources,
+        })),
+    )
+}
+
+// 就绪检查处理程序：启动预热完成前返回 503
+pub async fn readiness_handler(
+    State(readiness): State<Arc<Readiness>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if readiness.is_ready() {
+        (StatusCode::OK, Json(json!({ "status": "ready" })))
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "warming_up" })),
+        )
+    }
+}
+
+// 生效规则导出查询参数
+#[derive(Debug, Default, Deserialize)]
+pub struct RulesParams {
+    // 跳过的规则数量
+    pub offset: Option<usize>,
+    // 返回的规则数量
+    pub limit: Option<usize>,
+    // 仅返回统计汇总，不列出规则
+    #[serde(default)]
+    pub summary: bool,
+}
+
+// 生效规则导出处理程序：返回当前路由引擎中合并后的规则及其按类型、动作与来源的统计
+pub async fn rules_handler(
+    State(handler): State<Arc<RequestHandler>>,
+    Query(params): Query<RulesParams>,
+) -> Json<serde_json::Value> {
+    let router = handler.router();
+    let counts = router.rule_counts();
+    let total: usize = counts.iter().map(|count| count.rules).sum();
+
+    let mut body = json!({
+        "total": total,
+        "default_upstream_group": router.default_upstream_group(),
+        "counts": counts,
+        "sources": router.rule_sources(),
+    });
+
+    if !params.summary {
+        let offset = params.offset.unwrap_or(0);
+        let limit = params
+            .limit
+            .unwrap_or(rule_listing_limits::DEFAULT_PAGE_SIZE)
+            .clamp(1, rule_listing_limits::MAX_PAGE_SIZE);
+        let rules: Vec<_> = router.rules().skip(offset).take(limit).collect();
+        body["offset"] = json!(offset);
+        body["limit"] = json!(limit);
+        body["rules"] = json!(rules);
+    }
+
+    Json(body)
+}
+
+// 规则重载处理程序：重新下载远程规则源并重建路由引擎，返回新规则的统计汇总
+pub async fn rules_reload_handler(
+    State((handler, rule_reloader)): State<(Arc<RequestHandler>, Arc<RuleReloader>)>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    match rule_reloader.reload(&handler).await {
+        Ok(router) => {
+            let counts = router.rule_counts();
+            let total: usize = counts.iter().map(|count| count.rules).sum();
+            Ok(Json(json!({
+                "status": "success",
+                "total": total,
+                "counts": counts,
+                "sources": router.rule_sources(),
+            })))
+        }
+        Err(e) => {
+            error!("Rule reload failed: {}", e);
+            let status = match e {
+                AppError::RuleReload(_) => StatusCode::BAD_GATEWAY,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            Err((
+                status,
+                Json(json!({
+                    "status": "error",
+                    "message": e.to_string()
+                })),
+            ))
+        }
+    }
+}
@@ -1,7 +1,6 @@
 use crate::error::AppError;
 use crate::r#const::shutdown_timeout;
 use clap::{ArgAction, Parser};
-use std::path::PathBuf;
 
 // DNS UDP/TCP to DoH 代理服务
 #[derive(Parser, Debug, Clone)]
@@ -37,9 +36,14 @@ GitHub: https://github.com/shengyanli1982/load-ants
 "#
 )]
 pub struct Args {
-    // 配置文件路径
-    #[arg(short, long, default_value = "./config.yaml")]
-    pub config: PathBuf,
+    // 配置来源（文件路径、`-` 表示标准输入，或 http(s):// URL）
+    #[arg(
+        short,
+        long,
+        default_value = "./config.yaml",
+        help = "Configuration source: file path, '-' for stdin, or an http(s):// URL"
+    )]
+    pub config: String,
 
     // 测试配置
     #[arg(
@@ -1,8 +1,13 @@
 use crate::config::UpstreamServerConfig;
 use crate::error::AppError;
+use crate::r#const::sticky_limits;
 use async_trait::async_trait;
+use moka::future::Cache;
 use rand::{seq::SliceRandom, thread_rng};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 // 负载均衡器特性
 #[async_trait]
@@ -10,8 +15,34 @@ pub trait LoadBalancer: Send + Sync {
     // 选择一个上游服务器
     async fn select_server(&self) -> Result<&UpstreamServerConfig, AppError>;
 
+    // 为指定客户端选择上游服务器，默认忽略客户端
+    async fn select_server_for_client(
+        &self,
+        _client: Option<IpAddr>,
+    ) -> Result<&UpstreamServerConfig, AppError> {
+        self.select_server().await
+    }
+
     // 报告服务器失败
     async fn report_failure(&self, server: &UpstreamServerConfig);
+
+    // 组内服务器数量
+    fn server_count(&self) -> usize;
+
+    // 选择一个与指定服务器不同的上游服务器（用于重试），组内只有一个服务器时返回该服务器
+    async fn select_server_excluding(
+        &self,
+        excluded: &UpstreamServerConfig,
+    ) -> Result<&UpstreamServerConfig, AppError> {
+        let mut selected = self.select_server().await?;
+        for _ in 1..self.server_count() {
+            if selected != excluded {
+                break;
+            }
+            selected = self.select_server().await?;
+        }
+        Ok(selected)
+    }
 }
 
 // 轮询负载均衡器
@@ -46,26 +77,30 @@ impl LoadBalancer for RoundRobinBalancer {
     async fn report_failure(&self, _server: &UpstreamServerConfig) {
         // 轮询策略下不需要特殊处理失败
     }
+
+    fn server_count(&self) -> usize {
+        self.servers.len()
+    }
 }
 
 // 加权轮询负载均衡器
 pub struct WeightedBalancer {
     // 服务器列表
     servers: Vec<UpstreamServerConfig>,
-    // 当前权重（原子操作）
-    current_weights: Vec<AtomicUsize>,
+    // 当前权重（原子操作，选中后会减去总权重，可能为负）
+    current_weights: Vec<AtomicIsize>,
     // 总权重
-    total_weight: usize,
+    total_weight: isize,
 }
 
 impl WeightedBalancer {
     // 创建新的加权轮询负载均衡器
     pub fn new(servers: Vec<UpstreamServerConfig>) -> Self {
         // 计算权重总和
-        let total_weight = servers.iter().map(|s| s.weight() as usize).sum();
+        let total_weight = servers.iter().map(|s| s.weight() as isize).sum();
 
         // 初始化当前权重为0
-        let current_weights = servers.iter().map(|_| AtomicUsize::new(0)).collect();
+        let current_weights = servers.iter().map(|_| AtomicIsize::new(0)).collect();
 
         Self {
             servers,
@@ -83,13 +118,13 @@ impl LoadBalancer for WeightedBalancer {
         }
 
         // 平滑加权轮询算法实现
-        let mut max_weight = 0;
+        let mut max_weight = isize::MIN;
         let mut max_index = 0;
 
         // 第一步：为每个服务器增加当前权重并选择最大的
         for (i, weight_atomic) in self.current_weights.iter().enumerate() {
             // 增加当前权重
-            let weight = self.servers[i].weight() as usize;
+            let weight = self.servers[i].weight() as isize;
             let current = weight_atomic.fetch_add(weight, Ordering::SeqCst) + weight;
 
             // 查找当前最大权重的服务器
@@ -109,6 +144,10 @@ impl LoadBalancer for WeightedBalancer {
     async fn report_failure(&self, _server: &UpstreamServerConfig) {
         // 加权轮询策略下不需要特殊处理失败
     }
+
+    fn server_count(&self) -> usize {
+        self.servers.len()
+    }
 }
 
 // 随机负载均衡器
@@ -141,4 +180,124 @@ impl LoadBalancer for RandomBalancer {
     async fn report_failure(&self, _server: &UpstreamServerConfig) {
         // 随机策略下不需要特殊处理失败
     }
+
+    fn server_count(&self) -> usize {
+        self.servers.len()
+    }
+
+    // 随机策略直接在其余服务器中随机选择，避免重复选中失败的服务器
+    async fn select_server_excluding(
+        &self,
+        excluded: &UpstreamServerConfig,
+    ) -> Result<&UpstreamServerConfig, AppError> {
+        let candidates: Vec<&UpstreamServerConfig> = self
+            .servers
+            .iter()
+            .filter(|server| *server != excluded)
+            .collect();
+        let selected = candidates.choose(&mut thread_rng()).copied();
+        match selected {
+            Some(server) => Ok(server),
+            None => self.select_server().await,
+        }
+    }
+}
+
+// 会话保持负载均衡器：同一客户端 IP 在绑定有效期内固定使用同一服务器
+pub struct StickyBalancer {
+    // 服务器列表
+    servers: Vec<UpstreamServerConfig>,
+    // 为新客户端分配服务器的轮询索引
+    current: AtomicUsize,
+    // 客户端 IP 到服务器索引的绑定，按有效期过期
+    pins: Cache<IpAddr, usize>,
+    // 各服务器不健康状态的截止时间
+    unhealthy_until: Vec<Mutex<Option<Instant>>>,
+    // 服务器失败后被视为不健康的时长
+    cooldown: Duration,
+}
+
+impl StickyBalancer {
+    // 创建新的会话保持负载均衡器
+    pub fn new(servers: Vec<UpstreamServerConfig>, affinity_ttl: Duration) -> Self {
+        let unhealthy_until = servers.iter().map(|_| Mutex::new(None)).collect();
+        Self {
+            servers,
+            current: AtomicUsize::new(0),
+            pins: Cache::builder()
+                .max_capacity(sticky_limits::MAX_PINNED_CLIENTS)
+                .time_to_live(affinity_ttl)
+                .build(),
+            unhealthy_until,
+            cooldown: Duration::from_secs(sticky_limits::UNHEALTHY_COOLDOWN),
+        }
+    }
+
+    // 服务器当前是否健康
+    fn is_healthy(&self, index: usize) -> bool {
+        let mut until = self.unhealthy_until[index]
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        match *until {
+            Some(deadline) if Instant::now() < deadline => false,
+            Some(_) => {
+                *until = None;
+                true
+            }
+            None => true,
+        }
+    }
+
+    // 轮询选择一个健康的服务器，全部不健康时按轮询返回任意服务器
+    fn next_healthy(&self) -> Result<usize, AppError> {
+        if self.servers.is_empty() {
+            return Err(AppError::NoUpstreamAvailable);
+        }
+        let start = self.current.fetch_add(1, Ordering::SeqCst);
+        let len = self.servers.len();
+        Ok((0..len)
+            .map(|offset| (start + offset) % len)
+            .find(|&index| self.is_healthy(index))
+            .unwrap_or(start % len))
+    }
+}
+
+#[async_trait]
+impl LoadBalancer for StickyBalancer {
+    async fn select_server(&self) -> Result<&UpstreamServerConfig, AppError> {
+        let index = self.next_healthy()?;
+        Ok(&self.servers[index])
+    }
+
+    async fn select_server_for_client(
+        &self,
+        client: Option<IpAddr>,
+    ) -> Result<&UpstreamServerConfig, AppError> {
+        let Some(client) = client else {
+            return self.select_server().await;
+        };
+
+        // 已绑定且服务器健康时沿用绑定，否则重新分配
+        if let Some(index) = self.pins.get(&client).await {
+            if self.is_healthy(index) {
+                return Ok(&self.servers[index]);
+            }
+        }
+        let index = self.next_healthy()?;
+        self.pins.insert(client, index).await;
+        Ok(&self.servers[index])
+    }
+
+    async fn report_failure(&self, server: &UpstreamServerConfig) {
+        // 标记失败的服务器在冷却期内不健康，绑定到它的客户端将被重新分配
+        if let Some(index) = self.servers.iter().position(|s| s == server) {
+            *self.unhealthy_until[index]
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()) = Some(Instant::now() + self.cooldown);
+        }
+    }
+
+    fn server_count(&self) -> usize {
+        self.servers.len()
+    }
 }
@@ -1,19 +1,29 @@
 use crate::error::AppError;
-use crate::metrics::METRICS;
-use crate::r#const::{cache_labels, cache_limits, ttl_source_labels};
+use crate::extended_error::{attach_option, extended_error_option};
+use crate::metrics::{self, CounterMetric, METRICS};
+use crate::r#const::{cache_labels, cache_limits, edns_defaults, ttl_source_labels};
+use dashmap::{mapref::entry::Entry, DashMap};
 use hickory_proto::{
-    op::{Message, ResponseCode},
-    rr::{DNSClass, RecordType},
+    op::{Edns, Message, ResponseCode},
+    rr::rdata::opt::EdnsOption,
+    rr::{DNSClass, Name, RecordType},
 };
 use moka::future::Cache;
 use moka::policy::Expiry;
 use rand::{seq::SliceRandom, thread_rng};
 use std::{
+    str::FromStr,
     sync::Arc,
     time::{Duration, Instant},
 };
+use tokio_graceful_shutdown::{IntoSubsystem, SubsystemHandle};
 use tracing::{debug, info};
 
+// 判断响应码是否为上游临时故障（SERVFAIL/REFUSED）
+fn is_transient_failure(code: ResponseCode) -> bool {
+    matches!(code, ResponseCode::ServFail | ResponseCode::Refused)
+}
+
 // DNS缓存键
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct CacheKey {
@@ -23,19 +33,33 @@ pub struct CacheKey {
     record_type: RecordType,
     // DNS类
     class: DNSClass,
+    // 缓存作用域：应答因客户端而异的查询按作用域隔离，None 表示共享缓存
+    scope: Option<Arc<str>>,
 }
 
 impl CacheKey {
-    // 从DNS查询消息创建缓存键
-    fn from_message(message: &Message) -> Option<Self> {
+    // 从DNS查询消息创建缓存键，lowercase 为 true 时域名按小写归一（DNS 名称不区分大小写）
+    fn from_message(message: &Message, lowercase: bool) -> Option<Self> {
         let query = message.queries().first()?;
+        let name = if lowercase {
+            query.name().to_lowercase().to_string()
+        } else {
+            query.name().to_string()
+        };
 
         Some(Self {
-            name: query.name().to_string(),
+            name,
             record_type: query.query_type(),
             class: query.query_class(),
+            scope: None,
         })
     }
+
+    // 设置缓存作用域
+    fn in_scope(mut self, scope: Option<&Arc<str>>) -> Self {
+        self.scope = scope.cloned();
+        self
+    }
 }
 
 // DNS缓存条目
@@ -47,23 +71,39 @@ struct CacheEntry {
     timestamp: Instant,
     // 缓存时长 (秒)
     _ttl: u32,
+    // 过期后仍可作为陈旧应答返回并触发后台重新验证的时长 (秒)
+    stale_window: u32,
+    // 本地附加的扩展错误选项，命中时写回新的 OPT 记录
+    extended_error: Option<EdnsOption>,
+}
+
+impl CacheEntry {
+    // 条目是否已超过缓存时长
+    fn is_expired(&self) -> bool {
+        self.timestamp.elapsed() >= Duration::from_secs(self._ttl as u64)
+    }
+
+    // 条目在缓存中的保留时长：缓存时长加上陈旧窗口
+    fn lifetime(&self) -> Duration {
+        Duration::from_secs(self._ttl as u64 + self.stale_window as u64)
+    }
 }
 
 struct CacheEntryExpiry;
 
-impl Expiry<CacheKey, CacheEntry> for CacheEntryExpiry {
+impl<K> Expiry<K, CacheEntry> for CacheEntryExpiry {
     fn expire_after_create(
         &self,
-        _key: &CacheKey,
+        _key: &K,
         value: &CacheEntry,
         _created_at: Instant,
     ) -> Option<Duration> {
-        Some(Duration::from_secs(value._ttl as u64))
+        Some(value.lifetime())
     }
 
     fn expire_after_read(
         &self,
-        _key: &CacheKey,
+        _key: &K,
         _value: &CacheEntry,
         _read_at: Instant,
         duration_until_expiry: Option<Duration>,
@@ -75,13 +115,13 @@ impl Expiry<CacheKey, CacheEntry> for CacheEntryExpiry {
 
     fn expire_after_update(
         &self,
-        _key: &CacheKey,
+        _key: &K,
         value: &CacheEntry,
         _updated_at: Instant,
         _duration_until_expiry: Option<Duration>,
     ) -> Option<Duration> {
         // 更新条目时，以新 value 的 ttl 重新计算过期时间。
-        Some(Duration::from_secs(value._ttl as u64))
+        Some(value.lifetime())
     }
 }
 
@@ -95,6 +135,30 @@ pub struct DnsCache {
     min_ttl: u32,
     // 负面缓存TTL (秒)
     negative_ttl: u32,
+    // SERVFAIL/REFUSED 响应缓存TTL (秒)，0 表示不缓存
+    servfail_ttl: u32,
+    // 缓存命中时返回给客户端的最小TTL (秒)
+    serve_ttl_floor: u32,
+    // 缓存命中时是否随机打乱 A/AAAA 记录顺序
+    shuffle_answers: bool,
+    // 客户端设置 CD 位时是否跳过缓存读写
+    bypass_on_cd: bool,
+    // 剩余TTL低于该值的条目视为未命中 (秒)
+    min_remaining_ttl: u32,
+    // 缓存键中的域名是否按小写归一
+    normalize_key_case: bool,
+    // 固定缓存的域名（含子域名）
+    pinned_domains: Vec<Name>,
+    // 固定缓存条目：不与普通条目竞争容量，按 TTL 过期，条目数受 MAX_PINNED_ENTRIES 限制
+    pinned: Cache<CacheKey, CacheEntry>,
+    // 是否启用 NXDOMAIN 截断（RFC 8020）：名称的 NXDOMAIN 同样适用于其所有子域名
+    nxdomain_cut: bool,
+    // NXDOMAIN 截断条目：键为小写的不存在名称，值为上游返回的 NXDOMAIN 应答
+    nxdomain_cuts: Cache<String, CacheEntry>,
+    // 陈旧窗口 (秒)：条目过期后在该时长内仍直接返回，同时由调用方在后台重新验证，0 表示禁用
+    stale_while_revalidate: u32,
+    // 正在后台重新验证的缓存键：None 表示进行中，Some 为上次失败后允许再次重新验证的时间
+    revalidating: DashMap<CacheKey, Option<Instant>>,
 }
 
 impl DnsCache {
@@ -121,6 +185,18 @@ impl DnsCache {
             // 过期时间为最大可能的TTL
             .time_to_live(Duration::from_secs(cache_limits::MAX_TTL as u64))
             .build();
+        // NXDOMAIN 截断条目与普通条目使用相同的容量上限与过期策略
+        let nxdomain_cuts = Cache::builder()
+            .max_capacity(size as u64)
+            .expire_after(CacheEntryExpiry)
+            .time_to_live(Duration::from_secs(cache_limits::MAX_TTL as u64))
+            .build();
+        // 固定缓存使用独立的容量上限，避免固定域名下的大量子域名无限占用内存
+        let pinned = Cache::builder()
+            .max_capacity(cache_limits::MAX_PINNED_ENTRIES)
+            .expire_after(CacheEntryExpiry)
+            .time_to_live(Duration::from_secs(cache_limits::MAX_TTL as u64))
+            .build();
 
         info!(
             "Creating DNS cache - Size: {}, Min TTL: {}s, Negative TTL: {}s",
@@ -136,40 +212,302 @@ impl DnsCache {
             size,
             min_ttl,
             negative_ttl,
+            servfail_ttl: cache_limits::DEFAULT_SERVFAIL_TTL,
+            serve_ttl_floor: cache_limits::MIN_TTL,
+            shuffle_answers: true,
+            bypass_on_cd: false,
+            min_remaining_ttl: 0,
+            normalize_key_case: true,
+            pinned_domains: Vec::new(),
+            pinned,
+            nxdomain_cut: false,
+            nxdomain_cuts,
+            stale_while_revalidate: 0,
+            revalidating: DashMap::new(),
         }
     }
 
+    // 设置 SERVFAIL/REFUSED 响应的缓存TTL，0 表示不缓存
+    pub fn with_servfail_ttl(mut self, servfail_ttl: u32) -> Self {
+        self.servfail_ttl = servfail_ttl.min(cache_limits::MAX_TTL);
+        self
+    }
+
+    // 设置缓存命中时返回给客户端的最小TTL
+    pub fn with_serve_ttl_floor(mut self, serve_ttl_floor: u32) -> Self {
+        self.serve_ttl_floor = serve_ttl_floor.clamp(cache_limits::MIN_TTL, cache_limits::MAX_TTL);
+        self
+    }
+
+    // 设置缓存命中时是否随机打乱 A/AAAA 记录顺序
+    pub fn with_shuffle_answers(mut self, shuffle_answers: bool) -> Self {
+        self.shuffle_answers = shuffle_answers;
+        self
+    }
+
+    // 设置客户端设置 CD 位时是否跳过缓存读写
+    pub fn with_bypass_on_cd(mut self, bypass_on_cd: bool) -> Self {
+        self.bypass_on_cd = bypass_on_cd;
+        self
+    }
+
+    // 设置命中所需的最小剩余TTL，剩余TTL更短的条目按未命中处理以触发重新解析
+    pub fn with_min_remaining_ttl(mut self, min_remaining_ttl: u32) -> Self {
+        self.min_remaining_ttl = min_remaining_ttl.min(cache_limits::MAX_TTL);
+        self
+    }
+
+    // 设置缓存键中的域名是否按小写归一，关闭时大小写不同的查询各自缓存
+    pub fn with_normalize_key_case(mut self, normalize_key_case: bool) -> Self {
+        self.normalize_key_case = normalize_key_case;
+        self
+    }
+
+    // 设置固定缓存的域名（含子域名），无法解析的域名会被忽略
+    pub fn with_pinned_domains(mut self, domains: &[String]) -> Self {
+        self.pinned_domains = domains
+            .iter()
+            .filter_map(|domain| {
+                let mut name = Name::from_str(domain).ok()?;
+                name.set_fqdn(true);
+                Some(name)
+            })
+            .collect();
+        self
+    }
+
+    // 设置是否启用 NXDOMAIN 截断：缓存的 NXDOMAIN 应答同样用于应答其子域名的查询
+    pub fn with_nxdomain_cut(mut self, nxdomain_cut: bool) -> Self {
+        self.nxdomain_cut = nxdomain_cut;
+        self
+    }
+
+    // 设置陈旧窗口：条目过期后在该时长内仍直接返回并标记为陈旧，0 表示禁用
+    pub fn with_stale_while_revalidate(mut self, stale_while_revalidate: u32) -> Self {
+        self.stale_while_revalidate = stale_while_revalidate.min(cache_limits::MAX_TTL);
+        self
+    }
+
+    // 检查查询名称是否属于固定缓存的域名
+    fn is_pinned(&self, query: &Message) -> bool {
+        query.queries().first().is_some_and(|q| {
+            self.pinned_domains
+                .iter()
+                .any(|domain| domain.zone_of(q.name()))
+        })
+    }
+
+    // 查找缓存条目：先查固定缓存，再查普通缓存
+    async fn lookup(&self, key: &CacheKey) -> Option<CacheEntry> {
+        match self.pinned.get(key).await {
+            Some(entry) => Some(entry),
+            None => self.cache.get(key).await,
+        }
+    }
+
+    // 查找覆盖查询名称的 NXDOMAIN 截断条目：从查询名称本身逐级向上检查祖先名称（不含根）
+    async fn lookup_nxdomain_cut(&self, query: &Message) -> Option<CacheEntry> {
+        if !self.nxdomain_cut {
+            return None;
+        }
+        let mut name = query.queries().first()?.name().to_lowercase();
+        while !name.is_root() {
+            if let Some(entry) = self.nxdomain_cuts.get(&name.to_string()).await {
+                debug!(
+                    "NXDOMAIN cut hit: {} is covered by cached NXDOMAIN for {}",
+                    query.queries()[0].name(),
+                    name
+                );
+                return Some(entry);
+            }
+            name = name.base_name();
+        }
+        None
+    }
+
+    // 记录或撤销 NXDOMAIN 截断：上游 NXDOMAIN（无应答记录）记录截断；
+    // 名称存在的应答（NOERROR）说明该名称及其所有祖先名称均存在，撤销相应的截断
+    async fn update_nxdomain_cuts(&self, query: &Message, entry: &CacheEntry) {
+        let Some(name) = query.queries().first().map(|q| q.name().to_lowercase()) else {
+            return;
+        };
+        match entry.message.response_code() {
+            // 应答中含 CNAME 时 NXDOMAIN 指的是别名目标，不代表查询名称不存在
+            ResponseCode::NXDomain if entry.message.answers().is_empty() => {
+                debug!("Recording NXDOMAIN cut for {}", name);
+                // 截断条目仅用于应答子域名，不参与陈旧应答
+                let cut = CacheEntry {
+                    stale_window: 0,
+                    ..entry.clone()
+                };
+                self.nxdomain_cuts.insert(name.to_string(), cut).await;
+            }
+            ResponseCode::NoError => {
+                let mut name = name;
+                while !name.is_root() {
+                    self.nxdomain_cuts.invalidate(&name.to_string()).await;
+                    name = name.base_name();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // 检查该请求是否应跳过缓存（启用 bypass_on_cd 且请求设置了 CD 位）
+    pub fn should_bypass(&self, request: &Message) -> bool {
+        self.bypass_on_cd && request.checking_disabled()
+    }
+
     // 检查缓存是否启用
     pub fn is_enabled(&self) -> bool {
         self.size > 0
     }
 
-    // 从缓存中获取响应
+    // 检查缓存中是否存在查询对应的条目（不影响命中统计）
+    pub fn contains(&self, query: &Message) -> bool {
+        CacheKey::from_message(query, self.normalize_key_case)
+            .is_some_and(|key| self.pinned.contains_key(&key) || self.cache.contains_key(&key))
+    }
+
+    // 从缓存中获取响应（陈旧窗口内的过期条目同样返回）
     pub async fn get(&self, query: &Message) -> Option<Message> {
+        let (response, stale) = self.get_with_staleness(query).await?;
+        if stale {
+            self.record_stale_hit();
+        }
+        Some(response)
+    }
+
+    // 从缓存中获取响应，并返回该响应是否为陈旧窗口内的过期条目（调用方应在后台重新验证）
+    pub async fn get_with_staleness(&self, query: &Message) -> Option<(Message, bool)> {
+        self.get_with_staleness_in(query, None).await
+    }
+
+    // 在指定缓存作用域中获取响应，scope 为 None 时查找共享缓存
+    pub async fn get_with_staleness_in(
+        &self,
+        query: &Message,
+        scope: Option<&Arc<str>>,
+    ) -> Option<(Message, bool)> {
         // 创建缓存键
-        let key = CacheKey::from_message(query)?;
+        let key = CacheKey::from_message(query, self.normalize_key_case)?.in_scope(scope);
 
-        // 从缓存中查找
-        let entry = self.cache.get(&key).await?;
+        // 从缓存中查找；共享缓存未命中时检查祖先名称的 NXDOMAIN 截断
+        let entry = match self.lookup(&key).await {
+            Some(entry) => entry,
+            None if scope.is_none() => self.lookup_nxdomain_cut(query).await?,
+            None => return None,
+        };
+        let stale = entry.is_expired();
+
+        // 即将过期的条目对客户端几乎无用，按未命中处理（陈旧条目由后台重新验证，不受此限制）
+        let remaining_ttl = entry
+            ._ttl
+            .saturating_sub(entry.timestamp.elapsed().as_secs() as u32);
+        if !stale && remaining_ttl < self.min_remaining_ttl {
+            debug!(
+                "Cache entry for {} has {}s remaining, below min_remaining_ttl {}s",
+                key.name, remaining_ttl, self.min_remaining_ttl
+            );
+            return None;
+        }
 
         // 创建响应的可变副本
         let mut response = entry.message.as_ref().clone();
 
+        // 问题部分回显客户端原样的查询（保留 0x20 随机大小写），应答记录保持不变
+        *response.queries_mut() = query.queries().to_vec();
+
         // 调整TTL
         self.adjust_message_ttl(&mut response, &entry);
 
-        // 如果是 A 或 AAAA 记录查询，对答案进行随机排序
-        if key.record_type == RecordType::A || key.record_type == RecordType::AAAA {
+        // 按当前客户端的 EDNS 参数重新生成 OPT 记录
+        Self::attach_client_edns(&mut response, query);
+        if let Some(option) = &entry.extended_error {
+            attach_option(&mut response, query, option.clone());
+        }
+
+        // 如果是 A 或 AAAA 记录查询且启用了随机排序，对答案进行随机排序
+        if self.shuffle_answers
+            && (key.record_type == RecordType::A || key.record_type == RecordType::AAAA)
+        {
             self.shuffle_message_records(&mut response, key.record_type);
         }
 
-        // 更新缓存命中指标
-        METRICS
-            .cache_operations_total()
-            .with_label_values(&[cache_labels::HIT])
-            .inc();
+        // 更新缓存命中指标；陈旧应答由调用方在实际返回时通过 record_stale_hit 计数
+        if stale {
+            debug!(
+                "Found stale cache entry - {} ({:?})",
+                key.name, key.record_type
+            );
+        } else {
+            metrics::backend().increment(CounterMetric::CacheOperations, &[cache_labels::HIT]);
+        }
 
-        Some(response)
+        Some((response, stale))
+    }
+
+    // 记录一次陈旧应答命中
+    pub fn record_stale_hit(&self) {
+        metrics::backend().increment(CounterMetric::CacheOperations, &[cache_labels::STALE_HIT]);
+    }
+
+    // 标记查询正在后台重新验证；已有进行中的重新验证，或上次失败后尚未到重试时间时返回 false
+    pub fn begin_revalidation(&self, query: &Message) -> bool {
+        let Some(key) = CacheKey::from_message(query, self.normalize_key_case) else {
+            return false;
+        };
+        match self.revalidating.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let retry_due =
+                    matches!(entry.get(), Some(retry_at) if *retry_at <= Instant::now());
+                if retry_due {
+                    entry.insert(None);
+                }
+                retry_due
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(None);
+                true
+            }
+        }
+    }
+
+    // 结束查询的后台重新验证；失败时在重试间隔内不再发起新的重新验证
+    pub fn end_revalidation(&self, query: &Message, refreshed: bool) {
+        let Some(key) = CacheKey::from_message(query, self.normalize_key_case) else {
+            return;
+        };
+        if refreshed {
+            self.revalidating.remove(&key);
+        } else {
+            let retry_at =
+                Instant::now() + Duration::from_secs(cache_limits::REVALIDATION_RETRY_INTERVAL);
+            self.revalidating.insert(key, Some(retry_at));
+        }
+    }
+
+    // 从缓存中移除查询对应的条目
+    pub async fn remove(&self, query: &Message) {
+        if let Some(key) = CacheKey::from_message(query, self.normalize_key_case) {
+            self.pinned.invalidate(&key).await;
+            self.cache.invalidate(&key).await;
+        }
+    }
+
+    // 为缓存命中的响应生成新的 OPT 记录：仅在客户端使用 EDNS 时附带，通告本地负载大小并回显 DO 位
+    fn attach_client_edns(response: &mut Message, query: &Message) {
+        match query.extensions() {
+            Some(client_edns) => {
+                let mut edns = Edns::new();
+                edns.set_max_payload(edns_defaults::ADVERTISED_PAYLOAD);
+                edns.set_dnssec_ok(client_edns.dnssec_ok());
+                response.set_edns(edns);
+            }
+            None => {
+                *response.extensions_mut() = None;
+            }
+        }
     }
 
     // 对 DNS 中指定类型的记录进行随机排序
@@ -200,8 +538,35 @@ impl DnsCache {
         answers[0..target_end].shuffle(&mut thread_rng());
     }
 
-    // 向缓存添加响应
+    // 向缓存添加上游响应
     pub async fn insert(&self, query: &Message, response: Message) -> Result<(), AppError> {
+        self.insert_entry(query, response, true, None).await
+    }
+
+    // 向缓存添加本地生成的响应（如拦截响应）：不代表名称在 DNS 中不存在，不参与 NXDOMAIN 截断
+    pub async fn insert_local(&self, query: &Message, response: Message) -> Result<(), AppError> {
+        self.insert_entry(query, response, false, None).await
+    }
+
+    // 向指定缓存作用域添加响应，local 为 true 表示本地生成的响应；作用域内的条目不参与 NXDOMAIN 截断
+    pub async fn insert_in(
+        &self,
+        query: &Message,
+        response: Message,
+        local: bool,
+        scope: Option<&Arc<str>>,
+    ) -> Result<(), AppError> {
+        self.insert_entry(query, response, !local, scope).await
+    }
+
+    // 向缓存添加响应，from_upstream 为 true 时据此更新共享缓存的 NXDOMAIN 截断
+    async fn insert_entry(
+        &self,
+        query: &Message,
+        mut response: Message,
+        from_upstream: bool,
+        scope: Option<&Arc<str>>,
+    ) -> Result<(), AppError> {
         // 检查是否可缓存
         if !self.is_cacheable(&response) {
             debug!("Response not cacheable");
@@ -209,15 +574,15 @@ impl DnsCache {
         }
 
         // 创建缓存键
-        let key = match CacheKey::from_message(query) {
-            Some(k) => k,
+        let key = match CacheKey::from_message(query, self.normalize_key_case) {
+            Some(k) => k.in_scope(scope),
             None => {
                 debug!("Cannot create cache key from query");
                 // 增加插入错误指标
-                METRICS
-                    .cache_operations_total()
-                    .with_label_values(&[cache_labels::INSERT_ERROR])
-                    .inc();
+                metrics::backend().increment(
+                    CounterMetric::CacheOperations,
+                    &[cache_labels::INSERT_ERROR],
+                );
                 return Err(AppError::Cache(
                     "Cannot create cache key from query".to_string(),
                 ));
@@ -235,22 +600,37 @@ impl DnsCache {
             .with_label_values(&[ttl_source_labels::ADJUSTED])
             .observe(ttl as f64);
 
+        // 移除上游的 OPT 记录，命中时按请求客户端重新生成（保留扩展错误选项）
+        let extended_error = extended_error_option(&response).cloned();
+        *response.extensions_mut() = None;
+
         // 创建缓存条目
         let entry = CacheEntry {
             message: Arc::new(response),
             timestamp: Instant::now(),
             _ttl: ttl,
+            stale_window: self.stale_while_revalidate,
+            extended_error,
         };
 
-        // 插入缓存
-        self.cache.insert(key.clone(), entry).await;
-        debug!("Added to cache - {} ({:?})", key.name, key.record_type);
+        if self.nxdomain_cut && from_upstream && scope.is_none() {
+            self.update_nxdomain_cuts(query, &entry).await;
+        }
+
+        // 插入缓存：固定域名的条目写入单独的固定缓存，避免被容量淘汰
+        if self.is_enabled() && self.is_pinned(query) {
+            debug!(
+                "Added to pinned cache - {} ({:?})",
+                key.name, key.record_type
+            );
+            self.pinned.insert(key, entry).await;
+        } else {
+            debug!("Added to cache - {} ({:?})", key.name, key.record_type);
+            self.cache.insert(key, entry).await;
+        }
 
         // 更新缓存指标
-        METRICS
-            .cache_operations_total()
-            .with_label_values(&[cache_labels::INSERT])
-            .inc();
+        metrics::backend().increment(CounterMetric::CacheOperations, &[cache_labels::INSERT]);
 
         Ok(())
     }
@@ -260,17 +640,16 @@ impl DnsCache {
     pub async fn clear(&self) {
         debug!("Clearing DNS cache");
         self.cache.invalidate_all();
+        self.pinned.invalidate_all();
+        self.nxdomain_cuts.invalidate_all();
 
         // 更新缓存指标
-        METRICS
-            .cache_operations_total()
-            .with_label_values(&[cache_labels::CLEAR])
-            .inc();
+        metrics::backend().increment(CounterMetric::CacheOperations, &[cache_labels::CLEAR]);
         METRICS.cache_entries().set(0);
     }
 
     // 检查响应是否可缓存
-    fn is_cacheable(&self, response: &Message) -> bool {
+    pub fn is_cacheable(&self, response: &Message) -> bool {
         // 所有响应都可以缓存，无论是成功响应还是错误响应
         // 但仍然需要确保查询部分存在
         if response.queries().is_empty() {
@@ -278,11 +657,33 @@ impl DnsCache {
             return false;
         }
 
+        // 临时故障响应默认不缓存，避免上游恢复后仍返回缓存的失败结果
+        if is_transient_failure(response.response_code()) && self.servfail_ttl == 0 {
+            debug!("Transient failure {} not cached", response.response_code());
+            return false;
+        }
+
         true
     }
 
     // 计算响应中所有记录的最小TTL
     fn calculate_min_ttl(&self, response: &Message) -> u32 {
+        // SERVFAIL/REFUSED 通常是上游的临时故障，使用单独的短TTL
+        if is_transient_failure(response.response_code()) {
+            METRICS
+                .cache_ttl_seconds()
+                .with_label_values(&[ttl_source_labels::SERVFAIL_TTL])
+                .observe(self.servfail_ttl as f64);
+
+            debug!(
+                "Using SERVFAIL cache TTL ({} seconds) for response code: {:?}",
+                self.servfail_ttl,
+                response.response_code()
+            );
+
+            return self.servfail_ttl;
+        }
+
         // 对于错误响应或没有答案的响应，使用负面缓存TTL
         if response.response_code() != ResponseCode::NoError || response.answer_count() == 0 {
             // 记录使用负面缓存TTL指标
@@ -342,6 +743,13 @@ impl DnsCache {
         min_ttl
     }
 
+    // 计算经过时间调整后的TTL，不低于 serve_ttl_floor
+    fn aged_ttl(&self, original_ttl: u32, elapsed_secs: u32) -> u32 {
+        original_ttl
+            .saturating_sub(elapsed_secs)
+            .max(self.serve_ttl_floor)
+    }
+
     // 调整响应消息中的TTL
     fn adjust_message_ttl(&self, message: &mut Message, entry: &CacheEntry) {
         // 计算经过的时间 (秒)
@@ -349,12 +757,7 @@ impl DnsCache {
 
         // 调整答案记录的TTL
         for record in message.answers_mut() {
-            let original_ttl = record.ttl();
-            let new_ttl = if original_ttl > elapsed_secs {
-                original_ttl - elapsed_secs
-            } else {
-                1 // 至少保留1秒TTL
-            };
+            let new_ttl = self.aged_ttl(record.ttl(), elapsed_secs);
             record.set_ttl(new_ttl);
 
             // 记录实际TTL指标（经过时间调整后）
@@ -366,12 +769,7 @@ impl DnsCache {
 
         // 调整权威记录的TTL
         for record in message.name_servers_mut() {
-            let original_ttl = record.ttl();
-            let new_ttl = if original_ttl > elapsed_secs {
-                original_ttl - elapsed_secs
-            } else {
-                1 // 至少保留1秒TTL
-            };
+            let new_ttl = self.aged_ttl(record.ttl(), elapsed_secs);
             record.set_ttl(new_ttl);
         }
 
@@ -382,26 +780,75 @@ impl DnsCache {
                 continue;
             }
 
-            let original_ttl = record.ttl();
-            let new_ttl = if original_ttl > elapsed_secs {
-                original_ttl - elapsed_secs
-            } else {
-                1 // 至少保留1秒TTL
-            };
+            let new_ttl = self.aged_ttl(record.ttl(), elapsed_secs);
             record.set_ttl(new_ttl);
         }
     }
 
-    // 获取缓存条目数量
+    // 移除已到重试时间的重新验证失败标记
+    pub fn sweep_revalidations(&self) {
+        let now = Instant::now();
+        self.revalidating
+            .retain(|_, retry_at| retry_at.is_none_or(|retry_at| retry_at > now));
+    }
+
+    // 获取缓存条目数量的近似值（不执行维护，开销固定）
+    pub fn entry_count(&self) -> usize {
+        (self.cache.entry_count() + self.pinned.entry_count()) as usize
+    }
+
+    // 执行缓存维护（淘汰过期与超出容量的条目）后获取缓存条目数量
     pub async fn len(&self) -> usize {
         self.cache.run_pending_tasks().await;
-        self.cache.entry_count() as usize
+        self.pinned.run_pending_tasks().await;
+        self.entry_count()
     }
 
     // 检查缓存是否为空
     #[allow(dead_code)]
     pub async fn is_empty(&self) -> bool {
-        self.cache.run_pending_tasks().await;
-        self.cache.entry_count() == 0
+        self.len().await == 0
+    }
+}
+
+// 缓存清理任务：moka 惰性淘汰过期条目，定期执行维护并校准缓存条目数指标
+pub struct CacheJanitor {
+    // 目标缓存
+    cache: Arc<DnsCache>,
+    // 执行间隔
+    interval: Duration,
+}
+
+impl CacheJanitor {
+    // 创建缓存清理任务
+    pub fn new(cache: Arc<DnsCache>, interval: Duration) -> Self {
+        Self { cache, interval }
+    }
+
+    // 执行一次维护并更新缓存条目数指标，返回当前条目数
+    pub async fn run_once(&self) -> usize {
+        self.cache.sweep_revalidations();
+        let entries = self.cache.len().await;
+        METRICS.cache_entries().set(entries as i64);
+        entries
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoSubsystem<AppError> for CacheJanitor {
+    async fn run(self, subsys: SubsystemHandle) -> Result<(), AppError> {
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = subsys.on_shutdown_requested() => break,
+                _ = ticker.tick() => {
+                    let entries = self.run_once().await;
+                    debug!("Cache janitor run completed, entries: {}", entries);
+                }
+            }
+        }
+        info!("Cache janitor stopped");
+        Ok(())
     }
 }
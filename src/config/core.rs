@@ -1,8 +1,14 @@
-use crate::config::{validate_idle_timeout, validate_keepalive, validate_socket_addr};
+use crate::config::{
+    validate_domain_list, validate_idle_timeout, validate_ipv6_socket_addr, validate_keepalive,
+    validate_query_types, validate_socket_addr,
+};
 use crate::r#const::{
-    cache_limits, dns_client_limits, http_client_limits, server_defaults, timeout_limits,
+    cache_limits, dns_client_limits, dnstap_limits, http_client_limits, http_headers,
+    metrics_defaults, server_defaults, timeout_limits,
 };
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::net::IpAddr;
 use validator::{Validate, ValidationError};
 
 // DNS Client 配置（传统 UDP/TCP 上游）
@@ -82,6 +88,51 @@ pub struct HttpClientConfig {
     pub keepalive: Option<u32>,
     // HTTP用户代理（可选）
     pub agent: Option<String>,
+    // 严格校验 DoH 上游响应 ID（可选，默认关闭）
+    #[serde(default)]
+    pub strict_id_check: bool,
+    // DoH 上游查询名称随机大小写（0x20 编码）并校验响应回显（可选，默认关闭）
+    #[serde(default)]
+    pub case_randomization: bool,
+    // DNS JSON 应答中无法转换为内置类型的记录是否保留（可选，默认丢弃）
+    #[serde(default)]
+    pub preserve_unknown_records: bool,
+    // JSON 格式的 DoH 请求被上游以 406/415 拒绝时，改用二进制格式重试同一服务器（可选，默认关闭）
+    #[serde(default)]
+    pub json_fallback: bool,
+    // 单次转发的总截止时间（秒），包含所有重试（可选）
+    #[serde(default)]
+    #[validate(range(
+        min = http_client_limits::MIN_FORWARD_DEADLINE,
+        max = http_client_limits::MAX_FORWARD_DEADLINE,
+        message = "Forward deadline must be between {} and {} seconds"
+    ))]
+    pub forward_deadline: Option<u64>,
+    // 上游连接使用的本地源地址（可选），用于多出口主机指定出口 IP
+    #[serde(default)]
+    pub bind_address: Option<IpAddr>,
+    // 上游连接是否启用 TCP Fast Open（可选，默认关闭）
+    #[serde(default)]
+    pub tcp_fastopen: bool,
+    // 不经协商直接使用 HTTP/2（可选，默认关闭），用于明文 h2c 上游；https 上游默认通过 ALPN 协商 HTTP/2
+    #[serde(default)]
+    pub http2_prior_knowledge: bool,
+    // HTTP/2 keepalive PING 间隔（秒）（可选），空闲连接同样发送，避免被中间设备静默回收
+    #[serde(default)]
+    #[validate(range(
+        min = http_client_limits::MIN_HTTP2_KEEPALIVE_INTERVAL,
+        max = http_client_limits::MAX_HTTP2_KEEPALIVE_INTERVAL,
+        message = "HTTP/2 keepalive interval must be between {} and {} seconds"
+    ))]
+    pub http2_keepalive_interval: Option<u64>,
+    // HTTP/2 keepalive PING 应答超时（秒）（可选），超时未应答则关闭连接
+    #[serde(default)]
+    #[validate(range(
+        min = http_client_limits::MIN_HTTP2_KEEPALIVE_TIMEOUT,
+        max = http_client_limits::MAX_HTTP2_KEEPALIVE_TIMEOUT,
+        message = "HTTP/2 keepalive timeout must be between {} and {} seconds"
+    ))]
+    pub http2_keepalive_timeout: Option<u64>,
 }
 
 impl Default for HttpClientConfig {
@@ -92,6 +143,16 @@ impl Default for HttpClientConfig {
             idle_timeout: Some(http_client_limits::DEFAULT_IDLE_TIMEOUT),
             keepalive: Some(http_client_limits::DEFAULT_KEEPALIVE),
             agent: None,
+            strict_id_check: false,
+            case_randomization: false,
+            preserve_unknown_records: false,
+            json_fallback: false,
+            forward_deadline: None,
+            bind_address: None,
+            tcp_fastopen: false,
+            http2_prior_knowledge: false,
+            http2_keepalive_interval: None,
+            http2_keepalive_timeout: None,
         }
     }
 }
@@ -138,6 +199,30 @@ pub struct ServerConfig {
         message = "Invalid TCP listen address format"
     ))]
     pub listen_tcp: String,
+    // 额外的 IPv6 UDP 监听地址（可选），以 IPV6_V6ONLY 绑定，可与 IPv4 监听地址使用同一端口
+    #[serde(default)]
+    #[validate(custom(
+        function = "validate_ipv6_socket_addr",
+        message = "listen_udp_v6 must be an IPv6 socket address"
+    ))]
+    pub listen_udp_v6: Option<String>,
+    // 额外的 IPv6 TCP 监听地址（可选），以 IPV6_V6ONLY 绑定，可与 IPv4 监听地址使用同一端口
+    #[serde(default)]
+    #[validate(custom(
+        function = "validate_ipv6_socket_addr",
+        message = "listen_tcp_v6 must be an IPv6 socket address"
+    ))]
+    pub listen_tcp_v6: Option<String>,
+    // IPv6 监听地址（listen_udp/listen_tcp）是否同时接收 IPv4 映射地址（IPV6_V6ONLY=false）
+    #[serde(default = "default_dual_stack")]
+    pub dual_stack: bool,
+    // UDP 响应大小上限（字节）：与客户端通告的 EDNS 缓冲区取较小值，超出时截断并由客户端改用 TCP（可选）
+    #[validate(range(
+        min = server_defaults::MIN_UDP_RESPONSE_SIZE,
+        max = server_defaults::MAX_UDP_RESPONSE_SIZE,
+        message = "udp_max_response_size must be between {} and {}"
+    ))]
+    pub udp_max_response_size: Option<u16>,
     // HTTP监听地址
     #[validate(custom(
         function = "validate_socket_addr",
@@ -160,6 +245,62 @@ pub struct ServerConfig {
         message = "HTTP timeout must be between 1 and 65535 seconds"
     ))]
     pub http_timeout: u64,
+    // DoH 服务器关闭时等待连接排空的超时（秒）
+    #[serde(default = "default_drain_timeout")]
+    #[validate(range(
+        min = timeout_limits::MIN_TIMEOUT,
+        max = timeout_limits::MAX_TIMEOUT,
+        message = "Drain timeout must be between 1 and 65535 seconds"
+    ))]
+    pub drain_timeout: u64,
+    // TCP监听套接字是否启用 SO_REUSEADDR
+    #[serde(default = "default_reuse_addr")]
+    pub reuse_addr: bool,
+    // TCP监听套接字是否启用 SO_REUSEPORT（仅 Unix）
+    #[serde(default)]
+    pub reuse_port: bool,
+    // TCP监听队列长度
+    #[serde(default = "default_tcp_backlog")]
+    #[validate(range(
+        min = server_defaults::MIN_TCP_BACKLOG,
+        max = server_defaults::MAX_TCP_BACKLOG,
+        message = "TCP backlog must be between 1 and 65535"
+    ))]
+    pub tcp_backlog: u32,
+    // 是否要求 UDP 与 TCP 都绑定成功，关闭后任一协议绑定失败时仅记录警告
+    #[serde(default = "default_require_both_protocols")]
+    pub require_both_protocols: bool,
+    // DNS over TCP 最大并发连接数（可选，不配置时不限制）
+    #[serde(default)]
+    #[validate(range(
+        min = server_defaults::MIN_MAX_CONNECTIONS,
+        max = server_defaults::MAX_MAX_CONNECTIONS,
+        message = "max_tcp_connections must be between {} and {}"
+    ))]
+    pub max_tcp_connections: Option<u32>,
+    // DoH 服务器最大并发连接数（可选，不配置时不限制）
+    #[serde(default)]
+    #[validate(range(
+        min = server_defaults::MIN_MAX_CONNECTIONS,
+        max = server_defaults::MAX_MAX_CONNECTIONS,
+        message = "max_http_connections must be between {} and {}"
+    ))]
+    pub max_http_connections: Option<u32>,
+    // RFC 8484 DoH 查询路径（GET 请求不带 dns 参数时按 JSON API 处理）
+    #[serde(default = "default_doh_path")]
+    #[validate(custom(function = "validate_http_path"))]
+    pub doh_path: String,
+    // Google JSON API 查询路径
+    #[serde(default = "default_json_path")]
+    #[validate(custom(function = "validate_http_path"))]
+    pub json_path: String,
+    // JSON API 响应的默认内容类型（请求可通过 ct 参数覆盖）
+    #[serde(default = "default_json_content_type")]
+    #[validate(custom(function = "validate_json_content_type"))]
+    pub json_content_type: String,
+    // 是否以 debug 级别记录无法解析的请求报文（客户端地址与报文长度）
+    #[serde(default)]
+    pub log_malformed_requests: bool,
 }
 
 fn default_tcp_timeout() -> u64 {
@@ -170,14 +311,88 @@ fn default_http_timeout() -> u64 {
     server_defaults::DEFAULT_HTTP_TIMEOUT
 }
 
+fn default_drain_timeout() -> u64 {
+    server_defaults::DEFAULT_DRAIN_TIMEOUT
+}
+
+fn default_reuse_addr() -> bool {
+    true
+}
+
+fn default_dual_stack() -> bool {
+    true
+}
+
+fn default_require_both_protocols() -> bool {
+    true
+}
+
+fn default_tcp_backlog() -> u32 {
+    server_defaults::DEFAULT_TCP_BACKLOG
+}
+
+fn default_doh_path() -> String {
+    server_defaults::DEFAULT_DOH_PATH.to_string()
+}
+
+fn default_json_path() -> String {
+    server_defaults::DEFAULT_JSON_PATH.to_string()
+}
+
+fn default_json_content_type() -> String {
+    http_headers::content_types::DNS_JSON.to_string()
+}
+
+// 自定义验证函数 - 验证 JSON API 响应内容类型
+pub fn validate_json_content_type(content_type: &str) -> Result<(), ValidationError> {
+    if !http_headers::content_types::JSON_TYPES.contains(&content_type) {
+        let mut err = ValidationError::new("invalid_json_content_type");
+        err.message = Some(Cow::from(format!(
+            "json_content_type must be one of {:?}, got '{}'",
+            http_headers::content_types::JSON_TYPES,
+            content_type
+        )));
+        return Err(err);
+    }
+    Ok(())
+}
+
+// 自定义验证函数 - 验证 HTTP 路径（必须以 '/' 开头且不含查询参数）
+pub fn validate_http_path(path: &str) -> Result<(), ValidationError> {
+    if !path.starts_with('/') || path.contains(['?', '#', ' ']) {
+        let mut err = ValidationError::new("invalid_http_path");
+        err.message = Some(Cow::from(format!(
+            "HTTP path must start with '/' and contain no query or fragment: '{}'",
+            path
+        )));
+        return Err(err);
+    }
+    Ok(())
+}
+
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             listen_udp: server_defaults::DEFAULT_DNS_LISTEN.to_string(),
             listen_tcp: server_defaults::DEFAULT_DNS_LISTEN.to_string(),
+            listen_udp_v6: None,
+            listen_tcp_v6: None,
+            dual_stack: default_dual_stack(),
+            udp_max_response_size: None,
             listen_http: None,
             tcp_timeout: default_tcp_timeout(),
             http_timeout: default_http_timeout(),
+            drain_timeout: default_drain_timeout(),
+            reuse_addr: default_reuse_addr(),
+            reuse_port: false,
+            tcp_backlog: default_tcp_backlog(),
+            require_both_protocols: default_require_both_protocols(),
+            max_tcp_connections: None,
+            max_http_connections: None,
+            doh_path: default_doh_path(),
+            json_path: default_json_path(),
+            json_content_type: default_json_content_type(),
+            log_malformed_requests: false,
         }
     }
 }
@@ -228,6 +443,88 @@ pub struct CacheConfig {
         message = "Negative cache TTL must be between 1 and 86400 seconds"
     ))]
     pub negative_ttl: u32,
+    // SERVFAIL/REFUSED 等临时故障响应的缓存TTL（秒），0 表示不缓存
+    #[serde(default = "default_servfail_ttl")]
+    #[validate(range(
+        max = cache_limits::MAX_TTL,
+        message = "SERVFAIL cache TTL must be between 0 and 86400 seconds"
+    ))]
+    pub servfail_ttl: u32,
+    // 缓存命中时返回给客户端的最小TTL（秒）
+    #[serde(default = "default_serve_ttl_floor")]
+    #[validate(range(
+        min = cache_limits::MIN_TTL,
+        max = cache_limits::MAX_TTL,
+        message = "Serve TTL floor must be between 1 and 86400 seconds"
+    ))]
+    pub serve_ttl_floor: u32,
+    // 缓存命中时是否随机打乱 A/AAAA 记录顺序
+    #[serde(default = "default_shuffle_answers")]
+    pub shuffle_answers: bool,
+    // 客户端设置 CD 位时是否跳过缓存读写
+    #[serde(default)]
+    pub bypass_on_cd: bool,
+    // 缓存命中所需的最小剩余TTL（秒），低于该值的条目视为未命中，0 表示不限制
+    #[serde(default)]
+    #[validate(range(
+        max = cache_limits::MAX_TTL,
+        message = "Minimum remaining TTL must be between 0 and 86400 seconds"
+    ))]
+    pub min_remaining_ttl: u32,
+    // 缓存清理任务间隔（秒）：定期执行淘汰维护并校准缓存条目数指标
+    #[serde(default = "default_janitor_interval")]
+    #[validate(range(
+        min = cache_limits::MIN_JANITOR_INTERVAL,
+        max = cache_limits::MAX_JANITOR_INTERVAL,
+        message = "Janitor interval must be between 1 and 3600 seconds"
+    ))]
+    pub janitor_interval: u64,
+    // 解析 A/AAAA 查询后是否异步预取同名的 AAAA/A 记录并写入缓存
+    #[serde(default)]
+    pub prefetch_companion: bool,
+    // 解析 A/AAAA 查询时是否同时向同一上游服务器并发查询同名的 AAAA/A 记录并写入缓存
+    #[serde(default)]
+    pub coalesce_companion: bool,
+    // 缓存键中的域名是否按小写归一，使大小写不同的同名查询共享缓存条目
+    #[serde(default = "default_normalize_key_case")]
+    pub normalize_key_case: bool,
+    // 是否缓存本地规则生成的拦截响应；默认不缓存，使规则变更立即生效
+    #[serde(default)]
+    pub cache_blocked: bool,
+    // 固定缓存的域名（含子域名）：其缓存条目不会因容量不足被淘汰，仅在 TTL 到期后失效
+    #[serde(default)]
+    #[validate(custom(function = "validate_domain_list"))]
+    pub pinned_domains: Vec<String>,
+    // 是否启用 NXDOMAIN 截断（RFC 8020）：缓存的 NXDOMAIN 同样用于应答其子域名的查询
+    #[serde(default)]
+    pub nxdomain_cut: bool,
+    // 陈旧窗口（秒）：条目过期后在该时长内仍立即返回，同时在后台向上游重新验证，0 表示禁用
+    #[serde(default)]
+    #[validate(range(
+        max = cache_limits::MAX_TTL,
+        message = "Stale-while-revalidate window must be between 0 and 86400 seconds"
+    ))]
+    pub stale_while_revalidate: u32,
+}
+
+fn default_janitor_interval() -> u64 {
+    cache_limits::DEFAULT_JANITOR_INTERVAL
+}
+
+fn default_servfail_ttl() -> u32 {
+    cache_limits::DEFAULT_SERVFAIL_TTL
+}
+
+fn default_serve_ttl_floor() -> u32 {
+    cache_limits::MIN_TTL
+}
+
+fn default_shuffle_answers() -> bool {
+    true
+}
+
+fn default_normalize_key_case() -> bool {
+    true
 }
 
 impl Default for CacheConfig {
@@ -238,6 +535,19 @@ impl Default for CacheConfig {
             min_ttl: cache_limits::MIN_TTL,
             max_ttl: cache_limits::MAX_TTL,
             negative_ttl: cache_limits::DEFAULT_NEGATIVE_TTL,
+            servfail_ttl: default_servfail_ttl(),
+            serve_ttl_floor: default_serve_ttl_floor(),
+            shuffle_answers: default_shuffle_answers(),
+            bypass_on_cd: false,
+            min_remaining_ttl: 0,
+            janitor_interval: default_janitor_interval(),
+            prefetch_companion: false,
+            coalesce_companion: false,
+            normalize_key_case: default_normalize_key_case(),
+            cache_blocked: false,
+            pinned_domains: Vec::new(),
+            nxdomain_cut: false,
+            stale_while_revalidate: 0,
         }
     }
 }
@@ -261,3 +571,157 @@ impl Default for AdminConfig {
         }
     }
 }
+
+// 请求追踪配置
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate, Default)]
+#[serde(rename_all = "lowercase")]
+pub struct TracingConfig {
+    // 是否将 DoH 请求携带的 W3C traceparent 头传递给 DoH 上游
+    #[serde(default)]
+    pub propagate: bool,
+}
+
+// 指标后端类型
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricsBackendKind {
+    // Prometheus（通过 admin 服务的 /metrics 拉取）
+    #[default]
+    Prometheus,
+    // StatsD / DogStatsD（通过 UDP 推送）
+    Statsd,
+}
+
+// 自定义验证函数 - 选择 statsd 后端时必须配置 statsd
+fn validate_metrics_backend(config: &MetricsConfig) -> Result<(), ValidationError> {
+    if config.backend == MetricsBackendKind::Statsd && config.statsd.is_none() {
+        return Err(ValidationError::new("missing_statsd_config"));
+    }
+    Ok(())
+}
+
+// 指标配置
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate, Default)]
+#[validate(schema(
+    function = "validate_metrics_backend",
+    message = "metrics.statsd is required when metrics.backend is 'statsd'"
+))]
+#[serde(rename_all = "lowercase")]
+pub struct MetricsConfig {
+    // 指标后端
+    #[serde(default)]
+    pub backend: MetricsBackendKind,
+    // StatsD 配置
+    #[serde(default)]
+    #[validate(nested)]
+    pub statsd: Option<StatsdConfig>,
+    // 延迟预算（毫秒）：总处理时长超过该值的请求计入 slo_breach_total（可选，不设置时不统计）
+    #[validate(range(
+        min = metrics_defaults::MIN_LATENCY_BUDGET_MS,
+        max = metrics_defaults::MAX_LATENCY_BUDGET_MS,
+        message = "latency_budget_ms must be between {} and {}"
+    ))]
+    pub latency_budget_ms: Option<u64>,
+}
+
+fn default_statsd_prefix() -> String {
+    metrics_defaults::DEFAULT_STATSD_PREFIX.to_string()
+}
+
+// StatsD 配置
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate)]
+#[serde(rename_all = "lowercase")]
+pub struct StatsdConfig {
+    // StatsD 服务器地址
+    #[validate(custom(
+        function = "validate_socket_addr",
+        message = "Invalid statsd address format"
+    ))]
+    pub address: String,
+    // 指标名称前缀
+    #[serde(default = "default_statsd_prefix")]
+    pub prefix: String,
+}
+
+// 默认预热查询类型
+fn default_warmup_types() -> Vec<String> {
+    vec!["A".to_string(), "AAAA".to_string()]
+}
+
+// 默认预热完成前不就绪
+fn default_gate_readiness() -> bool {
+    true
+}
+
+// 预热域名配置
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate)]
+#[serde(rename_all = "lowercase")]
+pub struct WarmupDomainConfig {
+    // 域名
+    #[validate(custom(function = "validate_domain_name"))]
+    pub name: String,
+    // 查询类型（默认为 A 与 AAAA）
+    #[serde(default = "default_warmup_types")]
+    #[validate(custom(function = "validate_query_types"))]
+    pub types: Vec<String>,
+}
+
+// 启动预热配置
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate)]
+#[serde(rename_all = "lowercase")]
+pub struct WarmupConfig {
+    // 启动后预先解析的域名
+    #[serde(default)]
+    #[validate(nested)]
+    pub domains: Vec<WarmupDomainConfig>,
+    // 预热完成前 /readyz 返回 503（默认为 true）
+    #[serde(default = "default_gate_readiness")]
+    pub gate_readiness: bool,
+}
+
+// 自定义验证函数 - 验证单个域名
+fn validate_domain_name(name: &str) -> Result<(), ValidationError> {
+    validate_domain_list(&[name.to_string()])
+}
+
+// 默认 dnstap 输出队列容量
+fn default_dnstap_queue_size() -> usize {
+    dnstap_limits::DEFAULT_QUEUE_SIZE
+}
+
+// 自定义验证函数 - dnstap 必须且只能配置一个输出目标
+fn validate_dnstap_target(config: &DnstapConfig) -> Result<(), ValidationError> {
+    if config.socket_path.is_some() == config.address.is_some() {
+        return Err(ValidationError::new("invalid_dnstap_target"));
+    }
+    Ok(())
+}
+
+// dnstap 输出配置
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate)]
+#[validate(schema(
+    function = "validate_dnstap_target",
+    message = "Exactly one of dnstap.socket_path and dnstap.address must be set"
+))]
+#[serde(rename_all = "lowercase")]
+pub struct DnstapConfig {
+    // Unix 套接字路径
+    #[validate(length(min = 1, message = "dnstap socket_path cannot be empty"))]
+    pub socket_path: Option<String>,
+    // TCP 地址
+    #[validate(custom(
+        function = "validate_socket_addr",
+        message = "Invalid dnstap address format"
+    ))]
+    pub address: Option<String>,
+    // 写入 dnstap 消息 identity 字段的服务器标识（可选）
+    pub identity: Option<String>,
+    // 输出队列容量（帧），队列已满时丢弃新帧
+    #[serde(default = "default_dnstap_queue_size")]
+    #[validate(range(
+        min = dnstap_limits::MIN_QUEUE_SIZE,
+        max = dnstap_limits::MAX_QUEUE_SIZE,
+        message = "queue_size must be between {} and {}"
+    ))]
+    pub queue_size: usize,
+}
@@ -1,19 +1,32 @@
 use crate::error::ConfigError;
-use crate::r#const::{http_client_limits, retry_limits, upstream_defaults};
+use crate::r#const::{
+    config_source, http_client_limits, retry_limits, router::wildcards, upstream_defaults,
+};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use std::{borrow::Cow, collections::HashSet, fs, net::SocketAddr, path::Path, str::FromStr};
-use tracing::debug;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs,
+    io::Read,
+    net::{SocketAddr, UdpSocket},
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
+use tracing::{debug, info, warn};
 use url::Url;
 use validator::{Validate, ValidationError, ValidationErrors};
 
 pub mod common;
 pub mod core;
+pub mod response;
 pub mod rule;
 pub mod upstream;
 
 pub use common::*;
 pub use core::*;
+pub use response::*;
 pub use rule::*;
 pub use upstream::*;
 
@@ -38,6 +51,14 @@ pub fn validate_socket_addr(addr: &str) -> Result<(), ValidationError> {
     }
 }
 
+// 自定义验证函数 - 验证IPv6 Socket地址格式
+pub fn validate_ipv6_socket_addr(addr: &str) -> Result<(), ValidationError> {
+    match SocketAddr::from_str(addr) {
+        Ok(addr) if addr.is_ipv6() => Ok(()),
+        _ => Err(ValidationError::new("invalid_ipv6_socket_addr")),
+    }
+}
+
 // 自定义验证函数 - 验证URL格式
 pub fn validate_url(url_str: &str) -> Result<(), ValidationError> {
     match Url::parse(url_str) {
@@ -83,10 +104,21 @@ pub fn validate_group_references(config: &Config) -> Result<(), ValidationError>
                 if let Some(target) = &rule.target {
                     forward_targets.push(target.clone());
                 }
+                forward_targets.extend(rule.weighted_targets.iter().map(|t| t.group.clone()));
+                forward_targets.extend(rule.fallback.iter().cloned());
             }
         }
     }
 
+    // 收集默认上游组
+    if let Some(group) = config
+        .routing
+        .as_ref()
+        .and_then(|r| r.default_upstream_group.as_ref())
+    {
+        forward_targets.push(group.clone());
+    }
+
     // 收集远程规则中的 Forward targets
     for rule in &config.remote_rules {
         if let RouteAction::Forward = rule.action {
@@ -132,6 +164,58 @@ pub fn validate_group_references(config: &Config) -> Result<(), ValidationError>
     Ok(())
 }
 
+// 自定义验证函数 - 验证影子上游组存在
+pub fn validate_shadow_group(config: &Config) -> Result<(), ValidationError> {
+    let Some(shadow) = &config.shadow else {
+        return Ok(());
+    };
+
+    let exists = config
+        .upstream_groups
+        .as_ref()
+        .is_some_and(|groups| groups.iter().any(|g| g.name == shadow.group));
+    if !exists {
+        let mut err = ValidationError::new("non_existent_shadow_group");
+        err.message = Some(Cow::from(format!(
+            "Shadow config references non-existent upstream group: '{}'",
+            shadow.group
+        )));
+        return Err(err);
+    }
+    Ok(())
+}
+
+// 自定义验证函数 - 严格白名单模式下不允许存在隐式兜底（全局通配符 forward 规则或默认上游组）
+pub fn validate_strict_allowlist(config: &Config) -> Result<(), ValidationError> {
+    let Some(routing) = config.routing.as_ref().filter(|r| r.strict_allowlist) else {
+        return Ok(());
+    };
+
+    if routing.default_upstream_group.is_some() {
+        let mut err = ValidationError::new("strict_allowlist_with_default_group");
+        err.message = Some(Cow::from(
+            "routing.strict_allowlist cannot be combined with routing.default_upstream_group",
+        ));
+        return Err(err);
+    }
+
+    let has_catch_all = config.static_rules.as_ref().is_some_and(|rules| {
+        rules.iter().any(|rule| {
+            rule.match_type == MatchType::Wildcard
+                && rule.action == RouteAction::Forward
+                && rule.patterns.iter().any(|p| p == wildcards::GLOBAL)
+        })
+    });
+    if has_catch_all {
+        let mut err = ValidationError::new("strict_allowlist_with_global_wildcard");
+        err.message = Some(Cow::from(
+            "routing.strict_allowlist cannot be combined with a global wildcard '*' forward rule",
+        ));
+        return Err(err);
+    }
+    Ok(())
+}
+
 // 自定义验证函数 - 验证重试配置
 pub fn validate_retry_config(retry: &RetryConfig) -> Result<(), ValidationError> {
     if retry.attempts < retry_limits::MIN_ATTEMPTS || retry.attempts > retry_limits::MAX_ATTEMPTS {
@@ -169,6 +253,8 @@ pub fn validate_keepalive(value: u32) -> Result<(), ValidationError> {
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate)]
 #[validate(schema(function = "validate_unique_group_names"))]
 #[validate(schema(function = "validate_group_references"))]
+#[validate(schema(function = "validate_shadow_group"))]
+#[validate(schema(function = "validate_strict_allowlist"))]
 #[serde(rename_all = "lowercase")]
 pub struct Config {
     // 服务器配置
@@ -190,10 +276,42 @@ pub struct Config {
     #[serde(default)]
     #[validate(nested)]
     pub dns_client: Option<DnsClientConfig>,
+    // 响应处理配置（可选）
+    #[serde(default)]
+    #[validate(nested)]
+    pub response: Option<ResponseConfig>,
+    // DNS64 配置（可选）
+    #[serde(default)]
+    #[validate(nested)]
+    pub dns64: Option<Dns64Config>,
+    // DNS 重绑定防护配置（可选）
+    #[serde(default)]
+    #[validate(nested)]
+    pub rebind_protection: Option<RebindProtectionConfig>,
+    // DNSSEC 验证配置（可选）
+    #[serde(default)]
+    #[validate(nested)]
+    pub dnssec: Option<DnssecConfig>,
     // 上游组配置（可选）
     #[serde(default)]
     #[validate(nested)]
     pub upstream_groups: Option<Vec<UpstreamGroupConfig>>,
+    // 影子上游配置（可选）
+    #[serde(default)]
+    #[validate(nested)]
+    pub shadow: Option<ShadowConfig>,
+    // 指标配置（可选）
+    #[serde(default)]
+    #[validate(nested)]
+    pub metrics: Option<MetricsConfig>,
+    // 请求追踪配置（可选）
+    #[serde(default)]
+    #[validate(nested)]
+    pub tracing: Option<TracingConfig>,
+    // 路由配置（可选）
+    #[serde(default)]
+    #[validate(nested)]
+    pub routing: Option<RoutingConfig>,
     // 路由规则配置（可选）
     #[serde(default)]
     #[validate(nested)]
@@ -202,6 +320,31 @@ pub struct Config {
     #[serde(default)]
     #[validate(nested)]
     pub remote_rules: Vec<RemoteRuleConfig>,
+    // 启动预热配置（可选）
+    #[serde(default)]
+    #[validate(nested)]
+    pub warmup: Option<WarmupConfig>,
+    // dnstap 输出配置（可选）
+    #[serde(default)]
+    #[validate(nested)]
+    pub dnstap: Option<DnstapConfig>,
+}
+
+// 脱敏配置来源：远程 URL 去除用户信息、查询参数与片段，其余来源原样返回
+pub fn redact_config_source(source: &str) -> String {
+    if !(source.starts_with("http://") || source.starts_with("https://")) {
+        return source.to_string();
+    }
+    match Url::parse(source) {
+        Ok(mut url) => {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.set_query(None);
+            url.set_fragment(None);
+            url.to_string()
+        }
+        Err(_) => config_source::REDACTED_URL.to_string(),
+    }
 }
 
 impl Config {
@@ -209,17 +352,157 @@ impl Config {
     pub fn from_file<P: AsRef<Path>>(path: P) -> ConfigResult<Self> {
         debug!("Loading configuration file: {:?}", path.as_ref());
         let content = fs::read_to_string(path).map_err(ConfigError::LoadError)?;
-        let config: Config = serde_yaml::from_str(&content).map_err(ConfigError::ParseError)?;
+        Self::from_yaml(&content)
+    }
+
+    // 从 YAML 文本解析并验证配置
+    pub fn from_yaml(content: &str) -> ConfigResult<Self> {
+        let mut config: Config = serde_yaml::from_str(content).map_err(ConfigError::ParseError)?;
+        config.dedupe_rule_targets()?;
+        config.apply_implicit_default_group();
         config.validate()?;
         Ok(config)
     }
 
+    // 从任意读取器（例如标准输入）加载配置
+    pub fn from_reader<R: Read>(mut reader: R) -> ConfigResult<Self> {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .map_err(ConfigError::LoadError)?;
+        Self::from_yaml(&content)
+    }
+
+    // 从 HTTP(S) URL 下载配置（仅在启动时下载一次）
+    pub async fn from_url(url: &str, timeout: Duration) -> ConfigResult<Self> {
+        // URL 中可能携带凭据或令牌，日志与错误信息只使用脱敏后的形式
+        let redacted = redact_config_source(url);
+        debug!("Fetching configuration from URL: {}", redacted);
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| ConfigError::FetchError(e.without_url().to_string()))?;
+        let mut response = client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| ConfigError::FetchError(e.without_url().to_string()))?;
+        if !response.status().is_success() {
+            return Err(ConfigError::FetchError(format!(
+                "HTTP status {} from {}",
+                response.status(),
+                redacted
+            )));
+        }
+
+        // 限制配置大小：先检查声明的长度，再在读取过程中累计检查
+        let too_large = || {
+            ConfigError::FetchError(format!(
+                "Configuration from {} exceeds {} bytes",
+                redacted,
+                config_source::MAX_SIZE
+            ))
+        };
+        if response
+            .content_length()
+            .is_some_and(|length| length > config_source::MAX_SIZE as u64)
+        {
+            return Err(too_large());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| ConfigError::FetchError(e.without_url().to_string()))?
+        {
+            if body.len() + chunk.len() > config_source::MAX_SIZE {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        let content = String::from_utf8(body).map_err(|_| {
+            ConfigError::FetchError(format!(
+                "Configuration from {} is not valid UTF-8",
+                redacted
+            ))
+        })?;
+        Self::from_yaml(&content)
+    }
+
+    // 根据来源加载配置：`-` 表示标准输入，`http(s)://` 表示远程 URL，其余视为文件路径
+    pub async fn load(source: &str) -> ConfigResult<Self> {
+        if source == config_source::STDIN {
+            debug!("Loading configuration from stdin");
+            return Self::from_reader(std::io::stdin().lock());
+        }
+        if source.starts_with("http://") || source.starts_with("https://") {
+            return Self::from_url(source, Duration::from_secs(config_source::FETCH_TIMEOUT)).await;
+        }
+        Self::from_file(source)
+    }
+
     // 创建一个带有默认值的配置
     #[allow(dead_code)]
     pub fn new_with_defaults() -> Self {
         Self::default()
     }
 
+    // 规则目标列表中重复的上游组：默认保留首次出现并记录警告，strict_targets 时拒绝
+    pub fn dedupe_rule_targets(&mut self) -> ConfigResult<()> {
+        let strict = self.routing.as_ref().is_some_and(|r| r.strict_targets);
+        for rule in self.static_rules.iter_mut().flatten() {
+            let mut seen = HashSet::new();
+            let mut duplicates = Vec::new();
+            rule.weighted_targets.retain(|target| {
+                if seen.insert(target.group.clone()) {
+                    true
+                } else {
+                    duplicates.push(target.group.clone());
+                    false
+                }
+            });
+            for group in duplicates {
+                if strict {
+                    return Err(ConfigError::ValidationError(format!(
+                        "Rule {:?} lists upstream group '{}' more than once in weighted_targets",
+                        rule.patterns, group
+                    )));
+                }
+                warn!(
+                    "Rule {:?} lists upstream group '{}' more than once in weighted_targets, keeping the first entry",
+                    rule.patterns, group
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // 仅配置了一个上游组且没有任何路由规则时，将其作为默认上游组，所有查询都转发到该组
+    pub fn apply_implicit_default_group(&mut self) {
+        let has_rules = self
+            .static_rules
+            .as_ref()
+            .is_some_and(|rules| !rules.is_empty())
+            || !self.remote_rules.is_empty();
+        let has_routing_policy = self
+            .routing
+            .as_ref()
+            .is_some_and(|r| r.default_upstream_group.is_some() || r.strict_allowlist);
+        if has_rules || has_routing_policy {
+            return;
+        }
+
+        if let Some([group]) = self.upstream_groups.as_deref() {
+            info!(
+                "No routing rules configured, forwarding all queries to the only upstream group '{}'",
+                group.name
+            );
+            self.routing
+                .get_or_insert_with(RoutingConfig::default)
+                .default_upstream_group = Some(group.name.clone());
+        }
+    }
+
     // 验证配置有效性
     pub fn validate(&self) -> ConfigResult<()> {
         // 使用 validator 库进行验证
@@ -238,10 +521,14 @@ impl Config {
     pub fn validate_runtime_requirements(&self) -> ConfigResult<()> {
         let static_rules_count = self.static_rules.as_ref().map_or(0, |rules| rules.len());
         let remote_rules_count = self.remote_rules.len();
+        let has_default_group = self
+            .routing
+            .as_ref()
+            .is_some_and(|r| r.default_upstream_group.is_some());
 
-        if static_rules_count == 0 && remote_rules_count == 0 {
+        if static_rules_count == 0 && remote_rules_count == 0 && !has_default_group {
             return Err(ConfigError::ValidationError(
-                "No routing rules configured: please configure 'static_rules' and/or 'remote_rules'"
+                "No routing rules configured: please configure 'static_rules', 'remote_rules' and/or 'routing.default_upstream_group'"
                     .to_string(),
             ));
         }
@@ -256,13 +543,23 @@ impl Config {
             .iter()
             .any(|rule| matches!(rule.action, RouteAction::Forward));
 
-        if !(has_forward_in_static || has_forward_in_remote) {
+        if !(has_forward_in_static || has_forward_in_remote || has_default_group) {
             return Err(ConfigError::ValidationError(
-                "No forward rules configured: please add at least one rule with action 'forward'"
+                "No forward rules configured: please add at least one rule with action 'forward' or set 'routing.default_upstream_group'"
                     .to_string(),
             ));
         }
 
+        // 上游源地址必须是本机地址，否则所有上游连接都会失败
+        if let Some(addr) = self.http_client.as_ref().and_then(|c| c.bind_address) {
+            UdpSocket::bind(SocketAddr::new(addr, 0)).map_err(|e| {
+                ConfigError::ValidationError(format!(
+                    "http_client.bind_address '{}' is not a local address: {}",
+                    addr, e
+                ))
+            })?;
+        }
+
         Ok(())
     }
 }
@@ -320,6 +617,14 @@ impl Default for Config {
             cache: Some(CacheConfig::default()),
             http_client: Some(HttpClientConfig::default()),
             dns_client: Some(DnsClientConfig::default()),
+            response: Some(ResponseConfig::default()),
+            dns64: None,
+            rebind_protection: None,
+            dnssec: None,
+            shadow: None,
+            metrics: None,
+            tracing: None,
+            routing: None,
             upstream_groups: Some(vec![UpstreamGroupConfig {
                 name: upstream_defaults::DEFAULT_GROUP_NAME.to_string(),
                 scheme: UpstreamScheme::Doh,
@@ -336,14 +641,24 @@ impl Default for Config {
                     delay: retry_limits::DEFAULT_DELAY,
                 }),
                 proxy: None,
+                affinity_ttl: None,
+                max_connections: None,
+                headers: HashMap::new(),
             }]),
             static_rules: Some(vec![RouteRuleConfig {
                 match_type: MatchType::Wildcard,
                 patterns: vec!["*".to_string()],
                 action: RouteAction::Forward,
                 target: Some(upstream_defaults::DEFAULT_GROUP_NAME.to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
             }]),
             remote_rules: Vec::new(),
+            warmup: None,
+            dnstap: None,
         }
     }
 }
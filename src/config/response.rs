@@ -0,0 +1,361 @@
+use crate::dnssec::parse_trust_anchor;
+use crate::r#const::{dns64_defaults, log_throttle_limits, response_limits};
+use hickory_proto::op::ResponseCode;
+use hickory_proto::rr::{Name, RecordType};
+use ipnet::{Ipv4Net, Ipv6Net};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+use validator::{Validate, ValidationError};
+
+// 应答排序模式
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AnswerSort {
+    // 不排序（保持上游或缓存给出的顺序）
+    #[default]
+    None,
+    // 与客户端同一子网（IPv4 /24，IPv6 /64）的地址优先
+    Subnet,
+}
+
+// 上游超时时返回给客户端的响应码
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamTimeoutRcode {
+    // SERVFAIL（默认）
+    #[default]
+    ServFail,
+    // REFUSED：多数解析器不会缓存，客户端会立即改用其他解析器重试
+    Refused,
+}
+
+impl UpstreamTimeoutRcode {
+    // 对应的 DNS 响应码
+    pub fn response_code(self) -> ResponseCode {
+        match self {
+            Self::ServFail => ResponseCode::ServFail,
+            Self::Refused => ResponseCode::Refused,
+        }
+    }
+}
+
+// 根域名或仅含顶级域名（如 "." 或 "com."）查询的处理方式
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RootTldAction {
+    // 按路由规则正常处理（默认，下游验证型解析器需要查询根与顶级域的 NS/DS 记录）
+    #[default]
+    Route,
+    // 返回 REFUSED
+    Refused,
+    // 返回 NXDOMAIN
+    NxDomain,
+}
+
+impl RootTldAction {
+    // 对应的 DNS 响应码，按路由处理时为空
+    pub fn response_code(self) -> Option<ResponseCode> {
+        match self {
+            Self::Route => None,
+            Self::Refused => Some(ResponseCode::Refused),
+            Self::NxDomain => Some(ResponseCode::NXDomain),
+        }
+    }
+}
+
+// 响应处理配置
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate)]
+#[serde(rename_all = "lowercase")]
+pub struct ResponseConfig {
+    // A/AAAA 应答排序模式
+    #[serde(default)]
+    pub answer_sort: AnswerSort,
+    // 允许跟随的最大 CNAME 链长度
+    #[serde(default = "default_max_cname_chain")]
+    #[validate(range(
+        min = response_limits::MIN_CNAME_CHAIN,
+        max = response_limits::MAX_CNAME_CHAIN,
+        message = "max_cname_chain must be between {} and {}"
+    ))]
+    pub max_cname_chain: usize,
+    // 是否将 ANAME 别名展开为目标的 A/AAAA 记录
+    #[serde(default)]
+    pub flatten_aname: bool,
+    // 拦截日志汇总间隔（秒）：同一域名首次拦截记录日志，之后按间隔汇总
+    #[serde(default = "default_block_log_interval")]
+    #[validate(range(
+        min = log_throttle_limits::MIN_BLOCK_LOG_INTERVAL,
+        max = log_throttle_limits::MAX_BLOCK_LOG_INTERVAL,
+        message = "block_log_interval must be between {} and {} seconds"
+    ))]
+    pub block_log_interval: u64,
+    // 被拦截域名的 TXT 查询是否返回说明拦截原因的 TXT 记录
+    #[serde(default)]
+    pub block_explain: bool,
+    // 拦截响应（包括其 SOA 记录）的 TTL（秒），0 表示客户端不缓存
+    #[serde(default = "default_block_ttl")]
+    #[validate(range(
+        max = response_limits::MAX_BLOCK_TTL,
+        message = "block_ttl must be between 0 and 86400 seconds"
+    ))]
+    pub block_ttl: u32,
+    // 是否在客户端与上游之间透传未知的 EDNS0 选项
+    #[serde(default)]
+    pub edns_passthrough: bool,
+    // 上游超时时返回的响应码
+    #[serde(default)]
+    pub upstream_timeout_rcode: UpstreamTimeoutRcode,
+    // 是否将应答中所有记录的 TTL 统一为最小值
+    #[serde(default)]
+    pub normalize_ttl: bool,
+    // 根域名或仅含顶级域名查询的处理方式
+    #[serde(default)]
+    pub root_tld_action: RootTldAction,
+    // 是否在本地生成的错误与拦截响应中附带扩展 DNS 错误（RFC 8914）
+    #[serde(default)]
+    pub extended_errors: bool,
+    // 允许的查询类型（为空时不限制）
+    #[serde(default)]
+    #[validate(custom(function = "validate_query_types"))]
+    pub allowed_query_types: Vec<String>,
+    // 拒绝的查询类型，优先于 allowed_query_types
+    #[serde(default)]
+    #[validate(custom(function = "validate_query_types"))]
+    pub denied_query_types: Vec<String>,
+    // 本地 PTR 映射：反向解析名称（或 IP 地址）到主机名，命中时直接应答，不经过路由与上游
+    #[serde(default)]
+    #[validate(custom(function = "validate_local_ptr"))]
+    pub local_ptr: HashMap<String, String>,
+}
+
+impl Default for ResponseConfig {
+    fn default() -> Self {
+        Self {
+            answer_sort: AnswerSort::default(),
+            max_cname_chain: default_max_cname_chain(),
+            flatten_aname: false,
+            block_log_interval: default_block_log_interval(),
+            block_explain: false,
+            block_ttl: default_block_ttl(),
+            edns_passthrough: false,
+            upstream_timeout_rcode: UpstreamTimeoutRcode::default(),
+            normalize_ttl: false,
+            root_tld_action: RootTldAction::default(),
+            extended_errors: false,
+            allowed_query_types: Vec::new(),
+            denied_query_types: Vec::new(),
+            local_ptr: HashMap::new(),
+        }
+    }
+}
+
+fn default_max_cname_chain() -> usize {
+    response_limits::DEFAULT_MAX_CNAME_CHAIN
+}
+
+fn default_block_ttl() -> u32 {
+    response_limits::DEFAULT_BLOCK_TTL
+}
+
+fn default_block_log_interval() -> u64 {
+    log_throttle_limits::DEFAULT_BLOCK_LOG_INTERVAL
+}
+
+fn default_dns64_prefix() -> String {
+    dns64_defaults::DEFAULT_PREFIX.to_string()
+}
+
+// 解析查询类型名称（不区分大小写，例如 "AXFR"、"https"），也支持 RFC 3597 的 "TYPE<n>" 形式
+pub fn parse_query_type(name: &str) -> Option<RecordType> {
+    let name = name.to_ascii_uppercase();
+    match name.as_str() {
+        // hickory 的类型名称解析不包含 IXFR 与 OPT
+        "IXFR" => Some(RecordType::IXFR),
+        "OPT" => Some(RecordType::OPT),
+        _ => match name.strip_prefix("TYPE") {
+            Some(code) => code.parse::<u16>().ok().map(RecordType::from),
+            None => RecordType::from_str(&name).ok(),
+        },
+    }
+}
+
+// 自定义验证函数 - 验证查询类型列表
+pub fn validate_query_types(types: &[String]) -> Result<(), ValidationError> {
+    for name in types {
+        if parse_query_type(name).is_none() {
+            let mut err = ValidationError::new("invalid_query_type");
+            err.message = Some(Cow::from(format!("Invalid query type: '{}'", name)));
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+// 解析本地 PTR 映射的键：IP 地址转换为反向解析名称，否则须为 in-addr.arpa 或 ip6.arpa 下的名称
+pub fn parse_ptr_name(key: &str) -> Option<Name> {
+    if let Ok(addr) = IpAddr::from_str(key) {
+        return Some(Name::from(addr));
+    }
+    let mut name = Name::from_str(key).ok()?;
+    name.set_fqdn(true);
+    let in_addr = Name::from_ascii("in-addr.arpa.").ok()?;
+    let ip6 = Name::from_ascii("ip6.arpa.").ok()?;
+    (in_addr.zone_of(&name) || ip6.zone_of(&name)).then_some(name)
+}
+
+// 自定义验证函数 - 验证本地 PTR 映射
+pub fn validate_local_ptr(mappings: &HashMap<String, String>) -> Result<(), ValidationError> {
+    for (key, target) in mappings {
+        if parse_ptr_name(key).is_none() {
+            let mut err = ValidationError::new("invalid_ptr_name");
+            err.message = Some(Cow::from(format!(
+                "Invalid local PTR name '{}': must be an IP address or a name under in-addr.arpa/ip6.arpa",
+                key
+            )));
+            return Err(err);
+        }
+        if target.is_empty() || Name::from_str(target).is_err() {
+            let mut err = ValidationError::new("invalid_ptr_target");
+            err.message = Some(Cow::from(format!(
+                "Invalid local PTR target '{}' for '{}'",
+                target, key
+            )));
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+// 自定义验证函数 - 验证 NAT64 前缀（RFC 6052）
+pub fn validate_dns64_prefix(prefix: &str) -> Result<(), ValidationError> {
+    let net =
+        Ipv6Net::from_str(prefix).map_err(|_| ValidationError::new("invalid_dns64_prefix"))?;
+    if !dns64_defaults::PREFIX_LENGTHS.contains(&net.prefix_len()) {
+        let mut err = ValidationError::new("invalid_dns64_prefix_length");
+        err.message = Some(Cow::from(format!(
+            "DNS64 prefix length must be one of {:?}, got /{}",
+            dns64_defaults::PREFIX_LENGTHS,
+            net.prefix_len()
+        )));
+        return Err(err);
+    }
+    // RFC 6052 要求地址第 64-71 位（u 字节）为 0
+    if net.network().octets()[8] != 0 {
+        let mut err = ValidationError::new("invalid_dns64_prefix_u_octet");
+        err.message = Some(Cow::from(format!(
+            "DNS64 prefix '{}' must have bits 64-71 set to zero",
+            prefix
+        )));
+        return Err(err);
+    }
+    Ok(())
+}
+
+// 自定义验证函数 - 验证 IPv4 CIDR 列表
+pub fn validate_ipv4_cidrs(cidrs: &[String]) -> Result<(), ValidationError> {
+    for cidr in cidrs {
+        if Ipv4Net::from_str(cidr).is_err() {
+            let mut err = ValidationError::new("invalid_ipv4_cidr");
+            err.message = Some(Cow::from(format!("Invalid IPv4 CIDR: '{}'", cidr)));
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+// DNS64 配置（RFC 6147）
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate)]
+#[serde(rename_all = "lowercase")]
+pub struct Dns64Config {
+    // 是否启用 DNS64
+    #[serde(default)]
+    pub enabled: bool,
+    // NAT64 前缀
+    #[serde(default = "default_dns64_prefix")]
+    #[validate(custom(function = "validate_dns64_prefix"))]
+    pub prefix: String,
+    // 不参与合成的 IPv4 地址范围（CIDR）
+    #[serde(default)]
+    #[validate(custom(function = "validate_ipv4_cidrs"))]
+    pub exclude: Vec<String>,
+}
+
+impl Default for Dns64Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            prefix: default_dns64_prefix(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+// 公网域名应答中出现私有地址时的处理方式
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RebindAction {
+    // 移除私有地址记录，保留其余应答（默认）
+    #[default]
+    Strip,
+    // 返回 REFUSED
+    Refuse,
+}
+
+// 自定义验证函数 - 验证域名列表
+pub fn validate_domain_list(domains: &[String]) -> Result<(), ValidationError> {
+    for domain in domains {
+        if domain.is_empty() || Name::from_str(domain).is_err() {
+            let mut err = ValidationError::new("invalid_domain");
+            err.message = Some(Cow::from(format!("Invalid domain name: '{}'", domain)));
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+// DNS 重绑定防护配置
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate, Default)]
+#[serde(rename_all = "lowercase")]
+pub struct RebindProtectionConfig {
+    // 是否启用重绑定防护
+    #[serde(default)]
+    pub enabled: bool,
+    // 命中私有地址时的处理方式
+    #[serde(default)]
+    pub action: RebindAction,
+    // 允许返回私有地址的域名（含子域名），例如内部区域
+    #[serde(default)]
+    #[validate(custom(function = "validate_domain_list"))]
+    pub allowlist: Vec<String>,
+}
+
+// 自定义验证函数 - 验证 DNSSEC 信任锚（DS 格式）
+pub fn validate_trust_anchors(anchors: &[String]) -> Result<(), ValidationError> {
+    for anchor in anchors {
+        if let Err(e) = parse_trust_anchor(anchor) {
+            let mut err = ValidationError::new("invalid_trust_anchor");
+            err.message = Some(Cow::from(format!(
+                "Invalid trust anchor '{}': {}",
+                anchor, e
+            )));
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+// DNSSEC 验证配置
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate, Default)]
+#[serde(rename_all = "lowercase")]
+pub struct DnssecConfig {
+    // 是否启用 DNSSEC 验证
+    #[serde(default)]
+    pub enabled: bool,
+    // 信任锚列表（DS 格式：`<zone> <key_tag> <algorithm> <digest_type> <digest>`），为空时使用根区信任锚
+    #[serde(default)]
+    #[validate(custom(function = "validate_trust_anchors"))]
+    pub trust_anchors: Vec<String>,
+}
@@ -1,8 +1,13 @@
-use crate::config::validate_url;
-use crate::r#const::remote_rule_limits;
+use crate::config::{validate_domain_list, validate_url};
+use crate::r#const::{remote_rule_limits, routing_limits};
+use crate::router::label_wildcard_regex;
+use ipnet::IpNet;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
 use validator::{Validate, ValidationError};
 
 use super::common::{AuthConfig, RetryConfig};
@@ -88,6 +93,81 @@ pub struct RemoteRuleConfig {
     pub max_size: usize,
 }
 
+// 规则数量超出上限时的处理方式
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MaxRulesAction {
+    // 中止远程规则加载并报错
+    #[default]
+    Error,
+    // 截断超出部分并告警
+    Truncate,
+}
+
+// 路由配置
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate, Default)]
+pub struct RoutingConfig {
+    // 内存中规则（模式）数量上限（可选，不设置时不限制）
+    #[validate(range(
+        min = routing_limits::MIN_MAX_RULES,
+        max = routing_limits::MAX_MAX_RULES,
+        message = "max_rules must be between {} and {}"
+    ))]
+    pub max_rules: Option<usize>,
+    // 超出上限时的处理方式（默认为 error）
+    #[serde(default)]
+    pub on_max_rules: MaxRulesAction,
+    // 默认上游组：没有任何规则（包括 block 规则）匹配时转发到该组（可选）
+    #[validate(length(min = 1, message = "Default upstream group cannot be empty"))]
+    pub default_upstream_group: Option<String>,
+    // 规则重载宽限期（秒）：重载后新规则无匹配时，在该时间内回退到旧规则，0 表示不回退
+    #[serde(default)]
+    #[validate(range(
+        max = routing_limits::MAX_RELOAD_GRACE_PERIOD,
+        message = "reload_grace_period must be between 0 and 3600 seconds"
+    ))]
+    pub reload_grace_period: u64,
+    // 远程规则最大陈旧时长（秒）：任一规则源距上次成功加载超过该时长时 /health/rules 报告不健康（可选）
+    #[validate(range(
+        min = routing_limits::MIN_MAX_STALENESS,
+        max = routing_limits::MAX_MAX_STALENESS,
+        message = "max_staleness must be between {} and {} seconds"
+    ))]
+    pub max_staleness: Option<u64>,
+    // 严格白名单模式：只解析显式列出的域名，未命中任何规则的查询返回 REFUSED
+    #[serde(default)]
+    pub strict_allowlist: bool,
+    // 规则的目标列表中重复出现同一上游组时拒绝启动（默认去重并记录警告）
+    #[serde(default)]
+    pub strict_targets: bool,
+    // 查询名称最大标签数：超过时跳过规则匹配，转发到默认上游组或返回 REFUSED（可选，不设置时不限制）
+    #[validate(range(
+        min = routing_limits::MIN_MAX_LABELS,
+        max = routing_limits::MAX_MAX_LABELS,
+        message = "max_labels must be between {} and {}"
+    ))]
+    pub max_labels: Option<usize>,
+    // 远程规则源最大并发下载数（可选，默认为 4）
+    #[validate(range(
+        min = routing_limits::MIN_MAX_CONCURRENT_RULE_DOWNLOADS,
+        max = routing_limits::MAX_MAX_CONCURRENT_RULE_DOWNLOADS,
+        message = "max_concurrent_rule_downloads must be between {} and {}"
+    ))]
+    pub max_concurrent_rule_downloads: Option<usize>,
+    // 仅有 IPv4 地址的域名（含子域名）：其 AAAA 查询直接返回 NODATA，不转发上游
+    #[serde(default)]
+    #[validate(custom(function = "validate_domain_list"))]
+    pub synthesize_empty_aaaa: Vec<String>,
+}
+
+impl RoutingConfig {
+    // 实际生效的远程规则并发下载数
+    pub fn rule_download_concurrency(&self) -> usize {
+        self.max_concurrent_rule_downloads
+            .unwrap_or(routing_limits::DEFAULT_MAX_CONCURRENT_RULE_DOWNLOADS)
+    }
+}
+
 // 路由匹配类型枚举
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -108,6 +188,8 @@ pub enum RouteAction {
     Forward,
     // 拦截请求
     Block,
+    // 放行请求：豁免所有拦截规则，继续按转发规则路由
+    Allow,
 }
 
 // 自定义验证函数 - 验证规则匹配模式非空
@@ -118,22 +200,66 @@ fn validate_patterns_not_empty(patterns: &[String]) -> Result<(), ValidationErro
     Ok(())
 }
 
+// 解析客户端网段，单个 IP 地址视为 /32 或 /128 网段
+pub fn parse_client_cidr(cidr: &str) -> Option<IpNet> {
+    IpNet::from_str(cidr)
+        .or_else(|_| IpAddr::from_str(cidr).map(IpNet::from))
+        .ok()
+}
+
+// 自定义验证函数 - 验证客户端网段格式
+fn validate_client_cidrs(cidrs: &[String]) -> Result<(), ValidationError> {
+    for cidr in cidrs {
+        if parse_client_cidr(cidr).is_none() {
+            let mut err = ValidationError::new("invalid_client_cidr");
+            err.message = Some(Cow::from(format!("Invalid client CIDR: '{}'", cidr)));
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
 // 自定义验证函数 - 校验 RouteRuleConfig 的 patterns 与 match_type 语义一致
 fn validate_route_rule_patterns(rule: &RouteRuleConfig) -> Result<(), ValidationError> {
     match rule.match_type {
-        MatchType::Exact => Ok(()),
-        MatchType::Wildcard => {
+        MatchType::Exact => {
             for pattern in &rule.patterns {
-                if pattern == "*" {
-                    continue;
-                }
-                let Some(suffix) = pattern.strip_prefix("*.") else {
-                    let mut err = ValidationError::new("invalid_wildcard_pattern");
+                // 允许单个尾随 '.'（完全限定域名写法），Router 构建时会去除
+                let domain = pattern.strip_suffix('.').unwrap_or(pattern);
+                if domain.is_empty()
+                    || domain.starts_with('.')
+                    || domain.ends_with('.')
+                    || domain.contains("..")
+                {
+                    let mut err = ValidationError::new("invalid_exact_pattern");
                     err.message = Some(Cow::from(format!(
-                        "Invalid wildcard pattern '{}': expected '*' or '*.domain.tld'",
+                        "Invalid exact pattern '{}': invalid domain name",
                         pattern
                     )));
                     return Err(err);
+                }
+            }
+            Ok(())
+        }
+        MatchType::Wildcard => {
+            for pattern in &rule.patterns {
+                if pattern == "*" {
+                    continue;
+                }
+                let Some(suffix) = pattern
+                    .strip_prefix("*.")
+                    .filter(|suffix| !suffix.contains('*'))
+                else {
+                    // 任意标签位置的通配符（如 "ads.*.example.com"）会被转换为正则表达式，需在加载时校验
+                    if label_wildcard_regex(pattern).is_none() {
+                        let mut err = ValidationError::new("invalid_wildcard_pattern");
+                        err.message = Some(Cow::from(format!(
+                            "Invalid wildcard pattern '{}': expected '*', '*.domain.tld' or '*' as whole labels",
+                            pattern
+                        )));
+                        return Err(err);
+                    }
+                    continue;
                 };
 
                 // 允许配置里带尾随 '.'，与 Router 的 normalize 行为对齐
@@ -165,14 +291,36 @@ fn validate_route_rule_patterns(rule: &RouteRuleConfig) -> Result<(), Validation
     }
 }
 
-// 自定义验证函数 - 验证静态规则的Forward动作时必须有target
+// 自定义验证函数 - 验证静态规则的Forward动作时必须有target或weighted_targets
 fn validate_static_forward_target(rule: &RouteRuleConfig) -> Result<(), ValidationError> {
-    if matches!(rule.action, RouteAction::Forward) && rule.target.is_none() {
+    if matches!(rule.action, RouteAction::Forward)
+        && rule.target.is_none()
+        && rule.weighted_targets.is_empty()
+    {
         return Err(ValidationError::new("missing_target_for_forward"));
     }
     Ok(())
 }
 
+// 自定义验证函数 - target 与 weighted_targets 不能同时配置
+fn validate_weighted_targets_exclusive(rule: &RouteRuleConfig) -> Result<(), ValidationError> {
+    if rule.target.is_some() && !rule.weighted_targets.is_empty() {
+        return Err(ValidationError::new("conflicting_targets"));
+    }
+    Ok(())
+}
+
+// 加权目标上游组配置
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate)]
+pub struct WeightedTargetConfig {
+    // 目标上游组
+    #[validate(length(min = 1, message = "Weighted target group cannot be empty"))]
+    pub group: String,
+    // 权重
+    #[validate(range(min = 1, message = "Weighted target weight must be greater than 0"))]
+    pub weight: u32,
+}
+
 // 路由规则配置
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate)]
 #[validate(schema(
@@ -183,6 +331,10 @@ fn validate_static_forward_target(rule: &RouteRuleConfig) -> Result<(), Validati
     function = "validate_route_rule_patterns",
     message = "Invalid route rule patterns"
 ))]
+#[validate(schema(
+    function = "validate_weighted_targets_exclusive",
+    message = "target and weighted_targets cannot be used together"
+))]
 #[serde(rename_all = "lowercase")]
 pub struct RouteRuleConfig {
     // 匹配类型
@@ -196,6 +348,23 @@ pub struct RouteRuleConfig {
     pub patterns: Vec<String>,
     // 路由动作
     pub action: RouteAction,
-    // 目标上游组（当action为Forward时必须提供）
+    // 目标上游组（当action为Forward时必须提供target或weighted_targets）
     pub target: Option<String>,
+    // 按权重分流的目标上游组（可选）
+    #[serde(default)]
+    #[validate(nested)]
+    pub weighted_targets: Vec<WeightedTargetConfig>,
+    // 备用上游组：目标上游组转发失败时按顺序尝试（可选）
+    #[serde(default)]
+    pub fallback: Vec<String>,
+    // 目标上游组返回 NXDOMAIN 时同样尝试下一个备用上游组
+    #[serde(default)]
+    pub fallback_on_nxdomain: bool,
+    // 客户端网段：非空时规则仅对来自这些网段的客户端生效（可选）
+    #[serde(default)]
+    #[validate(custom(function = "validate_client_cidrs"))]
+    pub client_cidrs: Vec<String>,
+    // 规则来源：远程规则源 URL，静态规则为 None（加载规则时填充，不参与序列化）
+    #[serde(skip)]
+    pub source: Option<Arc<str>>,
 }
@@ -1,10 +1,12 @@
-use crate::r#const::weight_limits;
+use crate::r#const::{shadow_limits, sticky_limits, upstream_connection_limits, weight_limits};
+use reqwest::header::{HeaderName, HeaderValue};
 use reqwest::Url;
 use serde::{
     de::{self, Deserializer},
     Deserialize, Serialize,
 };
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use validator::{Validate, ValidationError, ValidationErrors};
 
@@ -20,6 +22,8 @@ pub enum LoadBalancingStrategy {
     Weighted,
     // 随机策略
     Random,
+    // 会话保持策略：同一客户端 IP 在绑定有效期内固定使用同一服务器
+    Sticky,
 }
 
 // 上游组 scheme
@@ -266,6 +270,13 @@ fn validate_group_scheme(group: &UpstreamGroupConfig) -> Result<(), ValidationEr
                 ));
                 return Err(err);
             }
+            if !group.headers.is_empty() {
+                let mut err = ValidationError::new("dns_group_headers_not_supported");
+                err.message = Some(Cow::from(
+                    "Upstream group scheme 'dns' does not support 'headers'".to_string(),
+                ));
+                return Err(err);
+            }
             for server in &group.servers {
                 if server.as_dns().is_none() {
                     let mut err = ValidationError::new("invalid_server_variant_for_scheme");
@@ -314,4 +325,66 @@ pub struct UpstreamGroupConfig {
 
     // 代理（可选）
     pub proxy: Option<String>,
+
+    // sticky 策略下客户端绑定有效期（秒，可选）
+    #[serde(default)]
+    #[validate(range(
+        min = sticky_limits::MIN_AFFINITY_TTL,
+        max = sticky_limits::MAX_AFFINITY_TTL,
+        message = "affinity_ttl must be between {} and {} seconds"
+    ))]
+    pub affinity_ttl: Option<u64>,
+
+    // 该组的最大并发上游请求数（可选），同时限制每个主机保留的空闲连接数
+    #[serde(default)]
+    #[validate(range(
+        min = upstream_connection_limits::MIN_CONNECTIONS,
+        max = upstream_connection_limits::MAX_CONNECTIONS,
+        message = "max_connections must be between {} and {}"
+    ))]
+    pub max_connections: Option<usize>,
+
+    // 附加到该组每个 DoH 请求的自定义请求头（可选），同名时覆盖内置请求头
+    #[serde(default)]
+    #[validate(custom(function = "validate_headers"))]
+    pub headers: HashMap<String, String>,
+}
+
+// 自定义验证函数 - 验证自定义请求头的名称与值
+pub fn validate_headers(headers: &HashMap<String, String>) -> Result<(), ValidationError> {
+    for (name, value) in headers {
+        if HeaderName::from_bytes(name.as_bytes()).is_err() {
+            let mut err = ValidationError::new("invalid_header_name");
+            err.message = Some(Cow::from(format!("Invalid header name: '{}'", name)));
+            return Err(err);
+        }
+        if HeaderValue::from_str(value).is_err() {
+            let mut err = ValidationError::new("invalid_header_value");
+            err.message = Some(Cow::from(format!("Invalid value for header '{}'", name)));
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+fn default_shadow_max_concurrency() -> usize {
+    shadow_limits::DEFAULT_MAX_CONCURRENCY
+}
+
+// 影子上游配置：将查询副本发送到指定组并比较应答，结果不返回给客户端
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Validate)]
+#[serde(rename_all = "lowercase")]
+pub struct ShadowConfig {
+    // 影子上游组名称
+    #[validate(length(min = 1, message = "Shadow group name cannot be empty"))]
+    pub group: String,
+
+    // 最大并发影子请求数，超出时直接丢弃影子请求
+    #[serde(default = "default_shadow_max_concurrency")]
+    #[validate(range(
+        min = shadow_limits::MIN_CONCURRENCY,
+        max = shadow_limits::MAX_CONCURRENCY,
+        message = "Shadow max_concurrency must be between {} and {}"
+    ))]
+    pub max_concurrency: usize,
 }
@@ -0,0 +1,181 @@
+use crate::metrics::METRICS;
+use crate::r#const::server_defaults;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, warn};
+
+// 监听器并发连接数限制器
+//
+// 达到上限后不再 accept 新连接，新连接停留在内核监听队列中等待，形成背压
+#[derive(Clone)]
+pub struct ConnectionLimiter {
+    // 连接许可（None 表示不限制）
+    semaphore: Option<Arc<Semaphore>>,
+    // 监听器标签（用于活跃连接指标）
+    listener: &'static str,
+}
+
+impl ConnectionLimiter {
+    // 创建连接数限制器
+    pub fn new(max_connections: Option<usize>, listener: &'static str) -> Self {
+        Self {
+            semaphore: max_connections.map(|max| Arc::new(Semaphore::new(max))),
+            listener,
+        }
+    }
+
+    // 等待可用的连接许可，连接建立后通过 ConnectionPermit::connected 计入活跃连接
+    pub async fn acquire(&self) -> ConnectionPermit {
+        let permit = match &self.semaphore {
+            Some(semaphore) => {
+                if semaphore.available_permits() == 0 {
+                    debug!(
+                        "Connection limit reached on {} listener, delaying accept",
+                        self.listener
+                    );
+                }
+                // 信号量不会被关闭，获取许可不会失败
+                semaphore.clone().acquire_owned().await.ok()
+            }
+            None => None,
+        };
+        ConnectionPermit {
+            permit,
+            listener: self.listener,
+        }
+    }
+}
+
+// 尚未建立连接的许可：accept 失败时复用，不计入活跃连接
+pub struct ConnectionPermit {
+    permit: Option<OwnedSemaphorePermit>,
+    listener: &'static str,
+}
+
+impl ConnectionPermit {
+    // accept 成功后转换为活跃连接守卫
+    pub fn connected(self) -> ConnectionGuard {
+        METRICS
+            .active_connections()
+            .with_label_values(&[self.listener])
+            .inc();
+        ConnectionGuard {
+            _permit: self.permit,
+            listener: self.listener,
+        }
+    }
+}
+
+// 活跃连接守卫：持有连接许可并维护活跃连接指标，连接关闭时释放
+pub struct ConnectionGuard {
+    _permit: Option<OwnedSemaphorePermit>,
+    listener: &'static str,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        METRICS
+            .active_connections()
+            .with_label_values(&[self.listener])
+            .dec();
+    }
+}
+
+// 带连接数限制的 TCP 监听器，供 axum 服务使用
+pub struct LimitedListener {
+    inner: TcpListener,
+    limiter: ConnectionLimiter,
+}
+
+impl LimitedListener {
+    // 包装 TCP 监听器
+    pub fn new(inner: TcpListener, limiter: ConnectionLimiter) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+impl axum::serve::Listener for LimitedListener {
+    type Io = LimitedStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        // 先获取许可再 accept，超出上限的连接在监听队列中等待
+        let permit = self.limiter.acquire().await;
+        loop {
+            match self.inner.accept().await {
+                Ok((stream, addr)) => {
+                    return (
+                        LimitedStream {
+                            stream,
+                            _guard: permit.connected(),
+                        },
+                        addr,
+                    )
+                }
+                Err(e) => {
+                    warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(std::time::Duration::from_millis(
+                        server_defaults::ACCEPT_ERROR_BACKOFF_MS,
+                    ))
+                    .await;
+                }
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.inner.local_addr()
+    }
+}
+
+// 持有连接许可的 TCP 连接，连接释放时归还许可
+pub struct LimitedStream {
+    stream: TcpStream,
+    _guard: ConnectionGuard,
+}
+
+impl AsyncRead for LimitedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for LimitedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
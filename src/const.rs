@@ -24,10 +24,24 @@ pub mod cache_limits {
     pub const MAX_SIZE: usize = 1000000;
     // 默认负面缓存TTL值（秒）
     pub const DEFAULT_NEGATIVE_TTL: u32 = 300;
+    // 默认SERVFAIL/REFUSED响应缓存TTL值（秒），0 表示默认不缓存
+    pub const DEFAULT_SERVFAIL_TTL: u32 = 0;
     // 最小TTL值（秒）
     pub const MIN_TTL: u32 = 1;
     // 最大TTL值（秒）
     pub const MAX_TTL: u32 = 86400;
+    // 默认缓存清理任务间隔（秒）
+    pub const DEFAULT_JANITOR_INTERVAL: u64 = 30;
+    // 最小缓存清理任务间隔（秒）
+    pub const MIN_JANITOR_INTERVAL: u64 = 1;
+    // 最大缓存清理任务间隔（秒）
+    pub const MAX_JANITOR_INTERVAL: u64 = 3600;
+    // 伴随查询预取的最大并发数
+    pub const MAX_PREFETCH_CONCURRENCY: usize = 64;
+    // 固定缓存的最大条目数
+    pub const MAX_PINNED_ENTRIES: u64 = 10000;
+    // 陈旧条目重新验证失败后再次重新验证的间隔（秒）
+    pub const REVALIDATION_RETRY_INTERVAL: u64 = 5;
 }
 
 // HTTP客户端配置限制
@@ -56,6 +70,18 @@ pub mod http_client_limits {
     pub const MIN_KEEPALIVE: u32 = 5;
     // 最大keepalive时间（秒）
     pub const MAX_KEEPALIVE: u32 = 600;
+    // 最小转发截止时间（秒）
+    pub const MIN_FORWARD_DEADLINE: u64 = 1;
+    // 最大转发截止时间（秒）
+    pub const MAX_FORWARD_DEADLINE: u64 = 1200;
+    // 最小 HTTP/2 keepalive PING 间隔（秒）
+    pub const MIN_HTTP2_KEEPALIVE_INTERVAL: u64 = 1;
+    // 最大 HTTP/2 keepalive PING 间隔（秒）
+    pub const MAX_HTTP2_KEEPALIVE_INTERVAL: u64 = 3600;
+    // 最小 HTTP/2 keepalive PING 应答超时（秒）
+    pub const MIN_HTTP2_KEEPALIVE_TIMEOUT: u64 = 1;
+    // 最大 HTTP/2 keepalive PING 应答超时（秒）
+    pub const MAX_HTTP2_KEEPALIVE_TIMEOUT: u64 = 300;
 }
 
 // DNS Client（传统 UDP/TCP 上游）配置限制
@@ -112,6 +138,18 @@ pub mod remote_rule_limits {
     pub const MAX_SIZE: usize = 50 * 1024 * 1024;
 }
 
+// 配置来源
+pub mod config_source {
+    // 从标准输入读取配置的标记
+    pub const STDIN: &str = "-";
+    // 远程配置下载超时（秒）
+    pub const FETCH_TIMEOUT: u64 = 10;
+    // 远程配置最大大小（字节）- 4MB
+    pub const MAX_SIZE: usize = 4 * 1024 * 1024;
+    // 无法解析的远程配置 URL 在日志中的占位文本
+    pub const REDACTED_URL: &str = "<redacted url>";
+}
+
 // 端口限制
 pub mod timeout_limits {
     // 最小超时
@@ -120,6 +158,93 @@ pub mod timeout_limits {
     pub const MAX_TIMEOUT: u64 = 65535;
 }
 
+// DNSSEC 验证默认值
+pub mod dnssec_defaults {
+    // 根区信任锚（KSK-2017，DS 格式）
+    pub const ROOT_TRUST_ANCHOR: &str =
+        ". 20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D";
+    // 信任链最大验证深度
+    pub const MAX_VALIDATION_DEPTH: usize = 16;
+    // 已验证 DNSKEY 的最长缓存时间（秒）
+    pub const KEY_CACHE_MAX_TTL: u64 = 3600;
+    // 区域密钥缓存的最大条目数
+    pub const KEY_CACHE_SIZE: u64 = 10000;
+    // 内部 DNSKEY/DS 查询的 EDNS 负载大小
+    pub const EDNS_PAYLOAD: u16 = 4096;
+}
+
+// 响应处理限制
+pub mod response_limits {
+    // 默认最大 CNAME 链长度
+    pub const DEFAULT_MAX_CNAME_CHAIN: usize = 16;
+    // 最小 CNAME 链长度
+    pub const MIN_CNAME_CHAIN: usize = 1;
+    // 最大 CNAME 链长度
+    pub const MAX_CNAME_CHAIN: usize = 64;
+    // 默认拦截响应 TTL（秒）
+    pub const DEFAULT_BLOCK_TTL: u32 = 60;
+    // 最大拦截响应 TTL（秒）
+    pub const MAX_BLOCK_TTL: u32 = 86400;
+    // 拦截响应 SOA 记录的主服务器名称
+    pub const BLOCK_SOA_MNAME: &str = "blocked.load-ants.";
+    // 拦截响应 SOA 记录的管理员邮箱
+    pub const BLOCK_SOA_RNAME: &str = "hostmaster.load-ants.";
+    // 拦截响应 SOA 记录的刷新、重试与过期时间（秒）
+    pub const BLOCK_SOA_REFRESH: i32 = 3600;
+    pub const BLOCK_SOA_RETRY: i32 = 600;
+    pub const BLOCK_SOA_EXPIRE: i32 = 86400;
+    // 本地 PTR 应答的 TTL（秒）
+    pub const LOCAL_PTR_TTL: u32 = 300;
+    // 本地合成的空 AAAA 应答（NODATA）的否定缓存 TTL（秒）
+    pub const EMPTY_AAAA_TTL: u32 = 300;
+}
+
+// 客户端查询统计限制
+pub mod client_stats_limits {
+    // 最大跟踪客户端数量
+    pub const MAX_TRACKED_CLIENTS: usize = 4096;
+    // 默认返回的 Top-N 数量
+    pub const DEFAULT_TOP_N: usize = 10;
+    // 最大返回的 Top-N 数量
+    pub const MAX_TOP_N: usize = 1000;
+    // 计数衰减间隔（秒）
+    pub const DECAY_INTERVAL: u64 = 300;
+    // 计数表维护间隔（毫秒）：淘汰与衰减在维护时批量执行
+    pub const MAINTENANCE_INTERVAL_MS: u64 = 1000;
+    // 两次维护之间允许跟踪的客户端数量相对容量的倍数
+    pub const OVERFLOW_FACTOR: usize = 2;
+}
+
+// 生效规则导出限制
+pub mod rule_listing_limits {
+    // 默认每页返回的规则数量
+    pub const DEFAULT_PAGE_SIZE: usize = 1000;
+    // 每页最多返回的规则数量
+    pub const MAX_PAGE_SIZE: usize = 10000;
+}
+
+// 日志节流限制
+pub mod log_throttle_limits {
+    // 默认拦截日志汇总间隔（秒）
+    pub const DEFAULT_BLOCK_LOG_INTERVAL: u64 = 60;
+    // 最小拦截日志汇总间隔（秒）
+    pub const MIN_BLOCK_LOG_INTERVAL: u64 = 1;
+    // 最大拦截日志汇总间隔（秒）
+    pub const MAX_BLOCK_LOG_INTERVAL: u64 = 3600;
+    // 最大跟踪键数量
+    pub const MAX_TRACKED_KEYS: usize = 4096;
+}
+
+// 上游延迟直方图参数
+pub mod latency_histogram {
+    // 第一个桶的上界（毫秒）
+    pub const FIRST_BOUND_MS: f64 = 0.5;
+    // 相邻桶上界的增长倍数
+    pub const GROWTH_FACTOR: f64 = 1.5;
+    // 有界桶数量（另有一个溢出桶）
+    pub const BUCKETS: usize = 32;
+}
+
 //
 // 指标标签常量
 //
@@ -130,6 +255,8 @@ pub mod protocol_labels {
     pub const UDP: &str = "udp";
     // TCP协议
     pub const TCP: &str = "tcp";
+    // DoH协议
+    pub const DOH: &str = "doh";
     // 未知协议
     #[allow(dead_code)]
     pub const UNKNOWN: &str = "unknown";
@@ -175,14 +302,34 @@ pub mod error_labels {
     pub const HANDLER_ERROR: &str = "handler_error";
     // 选择错误
     pub const SELECT_ERROR: &str = "select_error";
+    // 上游组初始化失败，不可用
+    pub const GROUP_UNAVAILABLE: &str = "group_unavailable";
     // 请求错误
     pub const REQUEST_ERROR: &str = "request_error";
+    // CNAME 链过长
+    pub const CNAME_CHAIN_TOO_LONG: &str = "cname_chain_too_long";
+    // DNSSEC 验证失败
+    pub const DNSSEC_BOGUS: &str = "dnssec_bogus";
+    // 上游响应 ID 不匹配
+    pub const ID_MISMATCH: &str = "id_mismatch";
+    // 上游响应问题名称大小写不匹配（0x20）
+    pub const CASE_MISMATCH: &str = "case_mismatch";
+    // 超过转发截止时间
+    pub const DEADLINE_EXCEEDED: &str = "deadline_exceeded";
+    // 等待上游组并发许可超时
+    pub const CONNECTION_LIMIT: &str = "connection_limit";
+    // 上游响应报文格式异常
+    pub const MALFORMED_RESPONSE: &str = "malformed_response";
+    // 查询名称标签数过多
+    pub const TOO_MANY_LABELS: &str = "too_many_labels";
 }
 
 // 缓存操作标签
 pub mod cache_labels {
     // 缓存命中
     pub const HIT: &str = "hit";
+    // 陈旧窗口内的过期条目命中
+    pub const STALE_HIT: &str = "stale_hit";
     // 缓存未命中
     #[allow(dead_code)]
     pub const MISS: &str = "miss";
@@ -210,17 +357,51 @@ pub mod ttl_source_labels {
     pub const ADJUSTED: &str = "adjusted";
     // 负面缓存TTL
     pub const NEGATIVE_TTL: &str = "negative_ttl";
+    // SERVFAIL缓存TTL
+    pub const SERVFAIL_TTL: &str = "servfail_ttl";
+}
+
+// 陈旧条目后台重新验证结果标签
+pub mod revalidation_labels {
+    // 已用上游应答刷新缓存
+    pub const REFRESHED: &str = "refreshed";
+    // 上游查询失败或应答不可缓存，保留陈旧条目
+    pub const FAILED: &str = "failed";
 }
 
 // 上游标签
 pub mod upstream_labels {
     // 未知上游
     pub const UNKNOWN: &str = "unknown";
+    // 未经上游转发（缓存命中或本地生成的应答）
+    pub const NONE: &str = "none";
     // 重试
     #[allow(dead_code)]
     pub const RETRY: &str = "retry";
 }
 
+// 负载均衡策略标签
+pub mod strategy_labels {
+    // 轮询
+    pub const ROUND_ROBIN: &str = "roundrobin";
+    // 加权轮询
+    pub const WEIGHTED: &str = "weighted";
+    // 随机
+    pub const RANDOM: &str = "random";
+    // 会话保持
+    pub const STICKY: &str = "sticky";
+}
+
+// 延迟预算超限阶段标签
+pub mod slo_phase_labels {
+    // 缓存查询
+    pub const CACHE: &str = "cache";
+    // 路由匹配
+    pub const ROUTING: &str = "routing";
+    // 上游转发
+    pub const UPSTREAM: &str = "upstream";
+}
+
 // 上游协议标签
 pub mod upstream_protocol_labels {
     // DoH 上游
@@ -253,6 +434,10 @@ pub mod rule_type_labels {
     pub const REGEX: &str = "regex";
     // 未指定目标
     pub const NO_TARGET: &str = "none";
+    // 按权重分流的目标
+    pub const WEIGHTED_TARGET: &str = "weighted";
+    // 默认上游组兜底
+    pub const DEFAULT: &str = "default";
 }
 
 // 规则来源标签
@@ -265,6 +450,8 @@ pub mod rule_source_labels {
 
 // 规则动作标签
 pub mod rule_action_labels {
+    // 放行动作（豁免拦截规则）
+    pub const ALLOW: &str = "allow";
     // 转发动作
     pub const FORWARD: &str = "forward";
     // 阻止动作
@@ -279,6 +466,12 @@ pub mod subsystem_names {
     pub const ADMIN_SERVER: &str = "admin_server";
     // DoH服务器子系统
     pub const DOH_SERVER: &str = "doh_server";
+    // 缓存清理子系统
+    pub const CACHE_JANITOR: &str = "cache_janitor";
+    // 启动预热子系统
+    pub const WARMUP: &str = "warmup";
+    // dnstap 输出子系统
+    pub const DNSTAP: &str = "dnstap";
 }
 
 // 服务器默认值
@@ -287,8 +480,32 @@ pub mod server_defaults {
     pub const DEFAULT_TCP_TIMEOUT: u64 = 10;
     // 默认HTTP超时（秒）
     pub const DEFAULT_HTTP_TIMEOUT: u64 = 30;
+    // 默认 DoH 连接排空超时（秒）
+    pub const DEFAULT_DRAIN_TIMEOUT: u64 = 5;
+    // 默认TCP监听队列长度
+    pub const DEFAULT_TCP_BACKLOG: u32 = 1024;
+    // 最小TCP监听队列长度
+    pub const MIN_TCP_BACKLOG: u32 = 1;
+    // 最大TCP监听队列长度
+    pub const MAX_TCP_BACKLOG: u32 = 65535;
+    // 最小并发连接数上限
+    pub const MIN_MAX_CONNECTIONS: u32 = 1;
+    // 最大并发连接数上限
+    pub const MAX_MAX_CONNECTIONS: u32 = 1_000_000;
+    // accept 失败后重试前的等待时间（毫秒），避免文件描述符耗尽等持续性错误时空转
+    pub const ACCEPT_ERROR_BACKOFF_MS: u64 = 50;
+    // 单个 DNS over TCP 连接上同时处理的流水线查询数上限
+    pub const MAX_TCP_PIPELINED_QUERIES: usize = 16;
+    // UDP 响应大小上限的最小值（RFC 1035）
+    pub const MIN_UDP_RESPONSE_SIZE: u16 = 512;
+    // UDP 响应大小上限的最大值
+    pub const MAX_UDP_RESPONSE_SIZE: u16 = 65535;
     // 默认DNS监听地址
     pub const DEFAULT_DNS_LISTEN: &str = "127.0.0.1:53";
+    // 默认 RFC 8484 DoH 查询路径
+    pub const DEFAULT_DOH_PATH: &str = "/dns-query";
+    // 默认 Google JSON API 查询路径
+    pub const DEFAULT_JSON_PATH: &str = "/resolve";
     // 默认HTTP监听地址
     pub const DEFAULT_HTTP_LISTEN: &str = "127.0.0.1:8080";
     // 默认管理服务器监听地址
@@ -303,6 +520,138 @@ pub mod upstream_defaults {
     pub const DEFAULT_DOH_SERVER: &str = "https://dns.google/dns-query";
     // 默认权重
     pub const DEFAULT_WEIGHT: u32 = 1;
+    // DoH 出站查询使用的事务 ID（RFC 8484 §4.1）
+    pub const DOH_QUERY_ID: u16 = 0;
+}
+
+// 上游组并发连接限制
+pub mod upstream_connection_limits {
+    // 最小并发连接数
+    pub const MIN_CONNECTIONS: usize = 1;
+    // 最大并发连接数
+    pub const MAX_CONNECTIONS: usize = 10000;
+    // 未配置转发截止时间时等待并发许可的最长时间（秒）
+    pub const PERMIT_WAIT_TIMEOUT: u64 = 5;
+}
+
+// DNS 报文格式限制（RFC 1035）
+pub mod wire_limits {
+    // 报文头长度
+    pub const HEADER_LEN: usize = 12;
+    // 名称最大长度（含长度字节）
+    pub const MAX_NAME_LEN: usize = 255;
+    // 单个名称允许跟随的最大压缩指针次数
+    pub const MAX_POINTER_HOPS: usize = 64;
+}
+
+// 影子上游限制
+pub mod shadow_limits {
+    // 默认最大并发影子请求数
+    pub const DEFAULT_MAX_CONCURRENCY: usize = 32;
+    // 最小并发影子请求数
+    pub const MIN_CONCURRENCY: usize = 1;
+    // 最大并发影子请求数
+    pub const MAX_CONCURRENCY: usize = 1024;
+}
+
+// 指标后端默认值
+pub mod metrics_defaults {
+    // 默认 StatsD 指标名称前缀
+    pub const DEFAULT_STATSD_PREFIX: &str = "loadants";
+    // 延迟预算下限（毫秒）
+    pub const MIN_LATENCY_BUDGET_MS: u64 = 1;
+    // 延迟预算上限（毫秒）
+    pub const MAX_LATENCY_BUDGET_MS: u64 = 60_000;
+}
+
+// dnstap 输出限制
+pub mod dnstap_limits {
+    // 默认输出队列容量（帧）
+    pub const DEFAULT_QUEUE_SIZE: usize = 4096;
+    // 最小输出队列容量
+    pub const MIN_QUEUE_SIZE: usize = 16;
+    // 最大输出队列容量
+    pub const MAX_QUEUE_SIZE: usize = 1_000_000;
+    // 连接断开后的重连间隔（秒）
+    pub const RECONNECT_DELAY: u64 = 1;
+    // Frame Streams 内容类型
+    pub const CONTENT_TYPE: &str = "protobuf:dnstap.Dnstap";
+}
+
+// 会话保持（sticky）负载均衡限制
+pub mod sticky_limits {
+    // 默认客户端绑定有效期（秒）
+    pub const DEFAULT_AFFINITY_TTL: u64 = 300;
+    // 最小客户端绑定有效期（秒）
+    pub const MIN_AFFINITY_TTL: u64 = 1;
+    // 最大客户端绑定有效期（秒）
+    pub const MAX_AFFINITY_TTL: u64 = 86400;
+    // 每个上游组最多保存的客户端绑定数
+    pub const MAX_PINNED_CLIENTS: u64 = 100_000;
+    // 服务器失败后被视为不健康的时长（秒）
+    pub const UNHEALTHY_COOLDOWN: u64 = 30;
+}
+
+// 路由规则数量限制
+pub mod routing_limits {
+    // 最小规则数量上限
+    pub const MIN_MAX_RULES: usize = 1;
+    // 最大规则数量上限
+    pub const MAX_MAX_RULES: usize = 100_000_000;
+    // 规则重载宽限期上限（秒）
+    pub const MAX_RELOAD_GRACE_PERIOD: u64 = 3600;
+    // 远程规则最大陈旧时长下限（秒）
+    pub const MIN_MAX_STALENESS: u64 = 60;
+    // 远程规则最大陈旧时长上限（秒，30 天）
+    pub const MAX_MAX_STALENESS: u64 = 2_592_000;
+    // 查询名称最大标签数下限
+    pub const MIN_MAX_LABELS: usize = 2;
+    // 查询名称最大标签数上限（255 字节名称最多包含 127 个标签）
+    pub const MAX_MAX_LABELS: usize = 127;
+    // 默认远程规则并发下载数
+    pub const DEFAULT_MAX_CONCURRENT_RULE_DOWNLOADS: usize = 4;
+    // 远程规则并发下载数下限
+    pub const MIN_MAX_CONCURRENT_RULE_DOWNLOADS: usize = 1;
+    // 远程规则并发下载数上限
+    pub const MAX_MAX_CONCURRENT_RULE_DOWNLOADS: usize = 64;
+}
+
+// 规则数量超限处理方式标签
+pub mod rule_limit_labels {
+    // 中止加载
+    pub const ERROR: &str = "error";
+    // 截断规则
+    pub const TRUNCATE: &str = "truncate";
+}
+
+// DNS64 默认值
+pub mod dns64_defaults {
+    // 默认 NAT64 前缀（RFC 6052 知名前缀）
+    pub const DEFAULT_PREFIX: &str = "64:ff9b::/96";
+    // 允许的前缀长度（RFC 6052）
+    pub const PREFIX_LENGTHS: [u8; 6] = [32, 40, 48, 56, 64, 96];
+}
+
+// EDNS 默认值
+pub mod edns_defaults {
+    // 向客户端通告的 UDP 负载大小（DNS Flag Day 2020 建议值）
+    pub const ADVERTISED_PAYLOAD: u16 = 1232;
+}
+
+// 扩展 DNS 错误（RFC 8914）
+pub mod extended_errors {
+    // EDE 选项代码
+    pub const OPTION_CODE: u16 = 15;
+    // 其他错误
+    pub const OTHER: u16 = 0;
+    // DNSSEC 验证失败
+    pub const DNSSEC_BOGUS: u16 = 6;
+    // 按策略过滤
+    pub const FILTERED: u16 = 17;
+    // 无法访问权威服务器（上游超时）
+    pub const NO_REACHABLE_AUTHORITY: u16 = 22;
+    // 网络错误（上游请求失败）
+    pub const NETWORK_ERROR: u16 = 23;
 }
 
 // 路由器常量
@@ -324,8 +673,21 @@ pub mod http_headers {
     pub const CONTENT_TYPE: &str = "Content-Type";
     // Accept 头
     pub const ACCEPT: &str = "Accept";
+    // Accept-Encoding 头
+    pub const ACCEPT_ENCODING: &str = "Accept-Encoding";
     // Authorization 头
     pub const AUTHORIZATION: &str = "Authorization";
+    // W3C Trace Context 头
+    pub const TRACEPARENT: &str = "traceparent";
+    // 调试日志中需要脱敏的请求头（小写）
+    pub const SENSITIVE_HEADERS: &[&str] = &["authorization", "proxy-authorization", "cookie"];
+    // 调试日志中原样输出的 URL 查询参数，其余参数值均脱敏
+    pub const LOGGABLE_QUERY_PARAMS: &[&str] =
+        &["dns", "name", "type", "cd", "do", "ct", "dnssec_data"];
+    // 脱敏后的占位符
+    pub const REDACTED: &str = "***";
+    // 调试日志中错误响应体的最大输出字节数
+    pub const DEBUG_BODY_SNIPPET_LEN: usize = 256;
 
     // 内容类型常量
     pub mod content_types {
@@ -333,6 +695,18 @@ pub mod http_headers {
         pub const DNS_MESSAGE: &str = "application/dns-message";
         // DNS JSON内容类型
         pub const DNS_JSON: &str = "application/dns-json";
+        // Google JSON API 早期使用的 JSON 内容类型
+        pub const X_JAVASCRIPT: &str = "application/x-javascript";
+        // 通用 JSON 内容类型
+        pub const JSON: &str = "application/json";
+        // JSON API 响应可用的内容类型
+        pub const JSON_TYPES: &[&str] = &[DNS_JSON, X_JAVASCRIPT, JSON];
+    }
+
+    // 内容编码常量
+    pub mod encodings {
+        // 不压缩
+        pub const IDENTITY: &str = "identity";
     }
 
     // 认证常量
@@ -0,0 +1,100 @@
+// src/dns64.rs
+
+use crate::config::Dns64Config;
+use crate::error::ConfigError;
+use hickory_proto::op::{Message, ResponseCode};
+use hickory_proto::rr::rdata::AAAA;
+use hickory_proto::rr::{RData, Record, RecordType};
+use ipnet::{Ipv4Net, Ipv6Net};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+// DNS64 合成器（RFC 6147 / RFC 6052）
+#[derive(Debug, Clone)]
+pub struct Dns64 {
+    // NAT64 前缀
+    prefix: Ipv6Net,
+    // 不参与合成的 IPv4 地址范围
+    exclude: Vec<Ipv4Net>,
+}
+
+impl Dns64 {
+    // 根据配置创建 DNS64 合成器
+    pub fn from_config(config: &Dns64Config) -> Result<Self, ConfigError> {
+        let prefix = Ipv6Net::from_str(&config.prefix).map_err(|e| {
+            ConfigError::InvalidDns64Config(format!("invalid prefix '{}': {}", config.prefix, e))
+        })?;
+
+        let exclude = config
+            .exclude
+            .iter()
+            .map(|cidr| {
+                Ipv4Net::from_str(cidr).map_err(|e| {
+                    ConfigError::InvalidDns64Config(format!("invalid exclude '{}': {}", cidr, e))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { prefix, exclude })
+    }
+
+    // 将 IPv4 地址嵌入 NAT64 前缀（RFC 6052 第 2.2 节），跳过第 64-71 位（u 字节）
+    pub fn synthesize_addr(&self, v4: Ipv4Addr) -> Ipv6Addr {
+        let mut octets = self.prefix.network().octets();
+        let mut index = (self.prefix.prefix_len() / 8) as usize;
+        for byte in v4.octets() {
+            if index == 8 {
+                index += 1;
+            }
+            octets[index] = byte;
+            index += 1;
+        }
+        Ipv6Addr::from(octets)
+    }
+
+    // 判断 IPv4 地址是否被排除
+    fn is_excluded(&self, v4: &Ipv4Addr) -> bool {
+        self.exclude.iter().any(|net| net.contains(v4))
+    }
+
+    // 判断 AAAA 响应是否需要合成：NOERROR 且不包含任何 AAAA 记录
+    pub fn needs_synthesis(response: &Message) -> bool {
+        response.response_code() == ResponseCode::NoError
+            && !response
+                .answers()
+                .iter()
+                .any(|record| record.record_type() == RecordType::AAAA)
+    }
+
+    // 根据 A 响应合成 AAAA 响应；若没有可用的 A 记录则返回 None
+    pub fn synthesize(&self, aaaa_response: &Message, a_response: &Message) -> Option<Message> {
+        let mut answers: Vec<Record> = Vec::new();
+        let mut synthesized = 0;
+
+        for record in a_response.answers() {
+            match record.data() {
+                // 保留 CNAME 链，保证客户端能看到完整的别名关系
+                Some(RData::CNAME(_)) => answers.push(record.clone()),
+                Some(RData::A(a)) if !self.is_excluded(&a.0) => {
+                    answers.push(Record::from_rdata(
+                        record.name().clone(),
+                        record.ttl(),
+                        RData::AAAA(AAAA(self.synthesize_addr(a.0))),
+                    ));
+                    synthesized += 1;
+                }
+                _ => {}
+            }
+        }
+
+        if synthesized == 0 {
+            return None;
+        }
+
+        let mut response = aaaa_response.clone();
+        response.insert_answers(answers);
+        // NODATA 响应中的 SOA 不再适用
+        response.insert_name_servers(Vec::new());
+        Some(response)
+    }
+}
@@ -0,0 +1,629 @@
+// src/dnssec.rs
+
+use crate::config::DnssecConfig;
+use crate::error::ConfigError;
+use crate::r#const::dnssec_defaults;
+use crate::UpstreamManager;
+use hickory_proto::op::{Edns, Message, MessageType, Query};
+use hickory_proto::rr::dnssec::rdata::{DNSSECRData, DNSKEY, DS, NSEC3, RRSIG};
+use hickory_proto::rr::dnssec::{Algorithm, DigestType, Verifier};
+use hickory_proto::rr::{DNSClass, Name, RData, Record, RecordType};
+use moka::future::Cache;
+use moka::policy::Expiry;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+// DNSSEC 验证结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnssecStatus {
+    // 所有应答 RRset 均通过信任链验证
+    Secure,
+    // 响应未签名或信任链未覆盖（例如父区没有 DS）
+    Insecure,
+    // 签名验证失败
+    Bogus(String),
+}
+
+// 解析 DS 格式的信任锚：`<zone> <key_tag> <algorithm> <digest_type> <digest>`
+pub fn parse_trust_anchor(anchor: &str) -> Result<(Name, DS), String> {
+    let fields: Vec<&str> = anchor.split_whitespace().collect();
+    let [zone, key_tag, algorithm, digest_type, digest] = fields.as_slice() else {
+        return Err("expected '<zone> <key_tag> <algorithm> <digest_type> <digest>'".to_string());
+    };
+
+    let zone = Name::from_ascii(zone).map_err(|e| format!("invalid zone: {}", e))?;
+    let key_tag = key_tag
+        .parse::<u16>()
+        .map_err(|e| format!("invalid key tag: {}", e))?;
+    let algorithm = algorithm
+        .parse::<u8>()
+        .map(Algorithm::from_u8)
+        .map_err(|e| format!("invalid algorithm: {}", e))?;
+    let digest_type = digest_type
+        .parse::<u8>()
+        .map_err(|e| format!("invalid digest type: {}", e))
+        .and_then(|t| DigestType::from_u8(t).map_err(|e| e.to_string()))?;
+    let digest = decode_hex(digest).ok_or_else(|| "invalid hex digest".to_string())?;
+
+    let mut zone = zone;
+    zone.set_fqdn(true);
+    Ok((zone, DS::new(key_tag, algorithm, digest_type, digest)))
+}
+
+// 解码十六进制字符串
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// 解码 base32hex（RFC 4648，NSEC3 哈希所有者名称使用的编码）
+fn decode_base32hex(label: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(label.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in label.chars() {
+        buffer = (buffer << 5) | c.to_digit(32)?;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+// 已验证的区域密钥缓存条目
+#[derive(Clone)]
+struct CachedKeys {
+    // 区域密钥（None 表示该区域未签名委派）
+    keys: Option<Vec<DNSKEY>>,
+    // 缓存时长（秒）
+    ttl: u64,
+}
+
+// 区域密钥缓存过期策略：按条目各自的缓存时长过期
+struct CachedKeysExpiry;
+
+impl Expiry<Name, CachedKeys> for CachedKeysExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &Name,
+        value: &CachedKeys,
+        _created_at: Instant,
+    ) -> Option<Duration> {
+        Some(Duration::from_secs(value.ttl))
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &Name,
+        value: &CachedKeys,
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(Duration::from_secs(value.ttl))
+    }
+}
+
+// 区域密钥查找结果
+type KeysResult = Result<Option<Vec<DNSKEY>>, String>;
+
+// DNSSEC 验证器：从信任锚出发，通过同一上游组获取 DNSKEY/DS 构建信任链并验证应答签名
+pub struct DnssecValidator {
+    // 信任锚（区域 -> DS）
+    anchors: Vec<(Name, DS)>,
+    // 已验证的区域密钥缓存
+    key_cache: Cache<Name, CachedKeys>,
+    // 名称所属区域顶点的缓存，用于检查未签名的 RRset
+    zone_apexes: Cache<Name, Name>,
+}
+
+impl DnssecValidator {
+    // 根据配置创建 DNSSEC 验证器
+    pub fn from_config(config: &DnssecConfig) -> Result<Self, ConfigError> {
+        let anchors = if config.trust_anchors.is_empty() {
+            vec![dnssec_defaults::ROOT_TRUST_ANCHOR.to_string()]
+        } else {
+            config.trust_anchors.clone()
+        };
+
+        let anchors = anchors
+            .iter()
+            .map(|anchor| {
+                parse_trust_anchor(anchor).map_err(|e| {
+                    ConfigError::InvalidDnssecConfig(format!(
+                        "invalid trust anchor '{}': {}",
+                        anchor, e
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            anchors,
+            key_cache: Cache::builder()
+                .max_capacity(dnssec_defaults::KEY_CACHE_SIZE)
+                .expire_after(CachedKeysExpiry)
+                .build(),
+            zone_apexes: Cache::builder()
+                .max_capacity(dnssec_defaults::KEY_CACHE_SIZE)
+                .time_to_live(Duration::from_secs(dnssec_defaults::KEY_CACHE_MAX_TTL))
+                .build(),
+        })
+    }
+
+    // 判断响应应答部分是否携带 RRSIG
+    pub fn has_signatures(response: &Message) -> bool {
+        response
+            .answers()
+            .iter()
+            .any(|record| record.record_type() == RecordType::RRSIG)
+    }
+
+    // 验证响应应答部分的所有 RRset
+    pub async fn validate(
+        &self,
+        response: &Message,
+        upstream: &UpstreamManager,
+        group: &str,
+    ) -> DnssecStatus {
+        let rrsets = group_rrsets(response.answers());
+        if rrsets.is_empty() {
+            return DnssecStatus::Insecure;
+        }
+
+        let mut secure = true;
+        for (name, record_type, records) in rrsets {
+            let sigs = covering_sigs(response.answers(), &name, record_type);
+            let result = if sigs.is_empty() {
+                // 未签名的 RRset 仅在所属区域被证明为未签名委派时接受，否则视为签名被剥离
+                self.check_unsigned(&name, upstream, group).await
+            } else {
+                self.verify_rrset(&name, &records, &sigs, upstream, group, 0)
+                    .await
+            };
+            match result {
+                Ok(true) => {}
+                Ok(false) => secure = false,
+                Err(reason) => {
+                    return DnssecStatus::Bogus(format!("{} {}: {}", name, record_type, reason))
+                }
+            }
+        }
+
+        if secure {
+            DnssecStatus::Secure
+        } else {
+            DnssecStatus::Insecure
+        }
+    }
+
+    // 检查未签名的 RRset：所属区域为未签名委派时返回 Ok(false)，区域已签名时返回错误
+    async fn check_unsigned(
+        &self,
+        name: &Name,
+        upstream: &UpstreamManager,
+        group: &str,
+    ) -> Result<bool, String> {
+        let apex = self.zone_apex(name, upstream, group).await?;
+        match self.zone_keys(&apex, upstream, group, 1).await? {
+            Some(_) => Err(format!("missing RRSIG in signed zone {}", apex)),
+            None => Ok(false),
+        }
+    }
+
+    // 通过 SOA 查询确定名称所属区域的顶点：应答中名称自身的 SOA，或权威部分中祖先区域的 SOA
+    async fn zone_apex(
+        &self,
+        name: &Name,
+        upstream: &UpstreamManager,
+        group: &str,
+    ) -> Result<Name, String> {
+        if let Some(apex) = self.zone_apexes.get(name).await {
+            return Ok(apex);
+        }
+        let response = query(upstream, group, name, RecordType::SOA).await?;
+        let apex = response
+            .answers()
+            .iter()
+            .chain(response.name_servers())
+            .find(|record| record.record_type() == RecordType::SOA && record.name().zone_of(name))
+            .map(|record| record.name().clone())
+            .ok_or_else(|| format!("cannot determine the zone of {}", name))?;
+        self.zone_apexes.insert(name.clone(), apex.clone()).await;
+        Ok(apex)
+    }
+
+    // 使用签名者区域的密钥验证 RRset；Ok(false) 表示签名者区域不在信任链内
+    fn verify_rrset<'a>(
+        &'a self,
+        name: &'a Name,
+        records: &'a [Record],
+        sigs: &'a [RRSIG],
+        upstream: &'a UpstreamManager,
+        group: &'a str,
+        depth: usize,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, String>> + Send + 'a>> {
+        Box::pin(async move {
+            let mut last_error = "no usable RRSIG".to_string();
+            for sig in sigs {
+                if !sig.signer_name().zone_of(name) {
+                    last_error = format!("signer {} is not a parent zone", sig.signer_name());
+                    continue;
+                }
+                if !signature_in_validity_period(sig) {
+                    last_error = "RRSIG outside validity period".to_string();
+                    continue;
+                }
+
+                let Some(keys) = self
+                    .zone_keys(sig.signer_name(), upstream, group, depth + 1)
+                    .await?
+                else {
+                    return Ok(false);
+                };
+
+                let verified = keys.iter().any(|key| {
+                    key_matches_sig(key, sig)
+                        && key.verify_rrsig(name, DNSClass::IN, sig, records).is_ok()
+                });
+                if verified {
+                    return Ok(true);
+                }
+                last_error = "signature verification failed".to_string();
+            }
+            Err(last_error)
+        })
+    }
+
+    // 获取并验证区域的 DNSKEY 集合；Ok(None) 表示该区域为未签名委派
+    fn zone_keys<'a>(
+        &'a self,
+        zone: &'a Name,
+        upstream: &'a UpstreamManager,
+        group: &'a str,
+        depth: usize,
+    ) -> Pin<Box<dyn Future<Output = KeysResult> + Send + 'a>> {
+        Box::pin(async move {
+            if depth > dnssec_defaults::MAX_VALIDATION_DEPTH {
+                return Err("exceeded maximum validation depth".to_string());
+            }
+            if let Some(cached) = self.key_cache.get(zone).await {
+                return Ok(cached.keys);
+            }
+
+            // 确定可信 DS：信任锚优先，否则向父区查询并验证 DS
+            let anchors: Vec<DS> = self
+                .anchors
+                .iter()
+                .filter(|(anchor_zone, _)| anchor_zone == zone)
+                .map(|(_, ds)| ds.clone())
+                .collect();
+            let trusted_ds = if !anchors.is_empty() {
+                anchors
+            } else if zone.is_root() {
+                return Err("no trust anchor for the root zone".to_string());
+            } else {
+                let ds_response = query(upstream, group, zone, RecordType::DS).await?;
+                let ds_records = rrset_records(ds_response.answers(), zone, RecordType::DS);
+                if ds_records.is_empty() {
+                    // 没有 DS 时必须由父区签名的 NSEC/NSEC3 证明其不存在，否则视为降级攻击
+                    let ttl = self
+                        .verify_no_ds(zone, &ds_response, upstream, group, depth)
+                        .await?;
+                    debug!("No DS record for {}, treating zone as insecure", zone);
+                    self.cache_keys(zone, None, ttl).await;
+                    return Ok(None);
+                }
+                let ds_sigs = covering_sigs(ds_response.answers(), zone, RecordType::DS);
+                if !self
+                    .verify_rrset(zone, &ds_records, &ds_sigs, upstream, group, depth)
+                    .await?
+                {
+                    return Ok(None);
+                }
+                ds_records
+                    .iter()
+                    .filter_map(|record| match record.data() {
+                        Some(RData::DNSSEC(DNSSECRData::DS(ds))) => Some(ds.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            };
+
+            // 获取 DNSKEY 集合，并要求其由 DS 认证的密钥签名
+            let key_response = query(upstream, group, zone, RecordType::DNSKEY).await?;
+            let key_records = rrset_records(key_response.answers(), zone, RecordType::DNSKEY);
+            let keys: Vec<DNSKEY> = key_records
+                .iter()
+                .filter_map(|record| match record.data() {
+                    Some(RData::DNSSEC(DNSSECRData::DNSKEY(key))) => Some(key.clone()),
+                    _ => None,
+                })
+                .collect();
+            if keys.is_empty() {
+                return Err(format!("no DNSKEY for {}", zone));
+            }
+
+            let entry_keys: Vec<&DNSKEY> = keys
+                .iter()
+                .filter(|key| {
+                    trusted_ds.iter().any(|ds| {
+                        key.calculate_key_tag().ok() == Some(ds.key_tag())
+                            && key.algorithm() == ds.algorithm()
+                            && ds.covers(zone, key).unwrap_or(false)
+                    })
+                })
+                .collect();
+            if entry_keys.is_empty() {
+                return Err(format!("no DNSKEY for {} matches a trusted DS", zone));
+            }
+
+            let key_sigs = covering_sigs(key_response.answers(), zone, RecordType::DNSKEY);
+            let self_signed = key_sigs.iter().any(|sig| {
+                signature_in_validity_period(sig)
+                    && entry_keys.iter().any(|key| {
+                        key_matches_sig(key, sig)
+                            && key
+                                .verify_rrsig(zone, DNSClass::IN, sig, &key_records)
+                                .is_ok()
+                    })
+            });
+            if !self_signed {
+                return Err(format!(
+                    "DNSKEY set for {} is not signed by a trusted key",
+                    zone
+                ));
+            }
+
+            let zone_keys: Vec<DNSKEY> = keys
+                .into_iter()
+                .filter(|key| key.zone_key() && !key.revoke())
+                .collect();
+            let ttl = key_records
+                .iter()
+                .map(|record| u64::from(record.ttl()))
+                .min()
+                .unwrap_or(0)
+                .min(dnssec_defaults::KEY_CACHE_MAX_TTL);
+            self.cache_keys(zone, Some(zone_keys.clone()), ttl).await;
+            Ok(Some(zone_keys))
+        })
+    }
+
+    // 验证父区对 DS 不存在的否定证明（NSEC 或 NSEC3，含 opt-out），返回否定应答的缓存时长
+    async fn verify_no_ds(
+        &self,
+        zone: &Name,
+        response: &Message,
+        upstream: &UpstreamManager,
+        group: &str,
+        depth: usize,
+    ) -> Result<u64, String> {
+        let authority = response.name_servers();
+        let proofs: Vec<(Name, RecordType, Vec<Record>)> = group_rrsets(authority)
+            .into_iter()
+            .filter(|(_, record_type, _)| {
+                matches!(record_type, RecordType::NSEC | RecordType::NSEC3)
+            })
+            .collect();
+        if proofs.is_empty() {
+            return Err(format!("no DS for {} and no NSEC/NSEC3 proof", zone));
+        }
+
+        for (owner, record_type, records) in &proofs {
+            let sigs = covering_sigs(authority, owner, *record_type);
+            if !self
+                .verify_rrset(owner, records, &sigs, upstream, group, depth)
+                .await?
+            {
+                // 父区本身不在信任链内，子区同样不安全
+                return Ok(negative_ttl(authority));
+            }
+        }
+
+        let records: Vec<&Record> = proofs.iter().flat_map(|(_, _, records)| records).collect();
+        if nsec_denies_ds(zone, &records) || nsec3_denies_ds(zone, &records) {
+            Ok(negative_ttl(authority))
+        } else {
+            Err(format!(
+                "NSEC/NSEC3 records do not prove absence of DS for {}",
+                zone
+            ))
+        }
+    }
+
+    // 写入区域密钥缓存
+    async fn cache_keys(&self, zone: &Name, keys: Option<Vec<DNSKEY>>, ttl: u64) {
+        if ttl == 0 {
+            return;
+        }
+        self.key_cache
+            .insert(zone.clone(), CachedKeys { keys, ttl })
+            .await;
+    }
+}
+
+// 委派点的类型位图：存在 NS、不存在 DS，且不是子区顶点（SOA）
+fn proves_insecure_delegation(types: &[RecordType]) -> bool {
+    types.contains(&RecordType::NS)
+        && !types.contains(&RecordType::DS)
+        && !types.contains(&RecordType::SOA)
+}
+
+// NSEC 证明：所有者名称为委派点且类型位图中没有 DS
+fn nsec_denies_ds(zone: &Name, records: &[&Record]) -> bool {
+    records.iter().any(|record| match record.data() {
+        Some(RData::DNSSEC(DNSSECRData::NSEC(nsec))) => {
+            record.name() == zone && proves_insecure_delegation(nsec.type_bit_maps())
+        }
+        _ => false,
+    })
+}
+
+// NSEC3 证明（RFC 5155 8.6）：委派点哈希精确匹配且位图中没有 DS，
+// 或最近祖先证明存在且覆盖“下一更近名称”的 NSEC3 设置了 opt-out
+fn nsec3_denies_ds(zone: &Name, records: &[&Record]) -> bool {
+    let nsec3s: Vec<(Vec<u8>, &NSEC3)> = records
+        .iter()
+        .filter_map(|record| match record.data() {
+            Some(RData::DNSSEC(DNSSECRData::NSEC3(nsec3))) => {
+                let label = record.name().iter().next()?;
+                let hash = decode_base32hex(std::str::from_utf8(label).ok()?)?;
+                Some((hash, nsec3))
+            }
+            _ => None,
+        })
+        .collect();
+    let Some((_, params)) = nsec3s.first() else {
+        return false;
+    };
+    let hash = |name: &Name| -> Option<Vec<u8>> {
+        params
+            .hash_algorithm()
+            .hash(params.salt(), name, params.iterations())
+            .ok()
+            .map(|digest| digest.as_ref().to_vec())
+    };
+    let matching = |name: &Name| {
+        let hashed = hash(name)?;
+        nsec3s
+            .iter()
+            .find(|(owner, _)| *owner == hashed)
+            .map(|(_, nsec3)| *nsec3)
+    };
+
+    if let Some(nsec3) = matching(zone) {
+        return proves_insecure_delegation(nsec3.type_bit_maps());
+    }
+
+    // 查找最近祖先，并要求覆盖下一更近名称的 NSEC3 设置 opt-out
+    let mut encloser = zone.base_name();
+    loop {
+        if matching(&encloser).is_some() {
+            let next_closer = zone.trim_to(encloser.num_labels() as usize + 1);
+            let Some(hashed) = hash(&next_closer) else {
+                return false;
+            };
+            return nsec3s.iter().any(|(owner, nsec3)| {
+                let next = nsec3.next_hashed_owner_name();
+                let covered = if owner.as_slice() < next {
+                    owner < &hashed && hashed.as_slice() < next
+                } else {
+                    owner < &hashed || hashed.as_slice() < next
+                };
+                covered && nsec3.opt_out()
+            });
+        }
+        if encloser.is_root() {
+            return false;
+        }
+        encloser = encloser.base_name();
+    }
+}
+
+// 否定应答的缓存时长：NSEC/NSEC3 记录 TTL 与 SOA 最小 TTL 中的较小值
+fn negative_ttl(authority: &[Record]) -> u64 {
+    authority
+        .iter()
+        .filter_map(|record| match record.data() {
+            Some(RData::SOA(soa)) => Some(record.ttl().min(soa.minimum())),
+            Some(RData::DNSSEC(DNSSECRData::NSEC(_)))
+            | Some(RData::DNSSEC(DNSSECRData::NSEC3(_))) => Some(record.ttl()),
+            _ => None,
+        })
+        .min()
+        .map(u64::from)
+        .unwrap_or(0)
+        .min(dnssec_defaults::KEY_CACHE_MAX_TTL)
+}
+
+// 向上游组发起设置 DO 位的内部查询
+async fn query(
+    upstream: &UpstreamManager,
+    group: &str,
+    name: &Name,
+    record_type: RecordType,
+) -> Result<Message, String> {
+    let mut request = Message::new();
+    request.set_id(rand::random());
+    request.set_message_type(MessageType::Query);
+    request.set_recursion_desired(true);
+    request.add_query(Query::query(name.clone(), record_type));
+
+    let mut edns = Edns::new();
+    edns.set_dnssec_ok(true);
+    edns.set_max_payload(dnssec_defaults::EDNS_PAYLOAD);
+    request.set_edns(edns);
+
+    upstream
+        .forward(&request, group)
+        .await
+        .map_err(|e| format!("{} {} lookup failed: {}", name, record_type, e))
+}
+
+// 按名称和类型对记录分组（忽略 RRSIG）
+fn group_rrsets(records: &[Record]) -> Vec<(Name, RecordType, Vec<Record>)> {
+    let mut rrsets: Vec<(Name, RecordType, Vec<Record>)> = Vec::new();
+    for record in records {
+        if record.record_type() == RecordType::RRSIG {
+            continue;
+        }
+        match rrsets.iter_mut().find(|(name, record_type, _)| {
+            name == record.name() && *record_type == record.record_type()
+        }) {
+            Some((_, _, members)) => members.push(record.clone()),
+            None => rrsets.push((
+                record.name().clone(),
+                record.record_type(),
+                vec![record.clone()],
+            )),
+        }
+    }
+    rrsets
+}
+
+// 提取指定名称和类型的记录
+fn rrset_records(records: &[Record], name: &Name, record_type: RecordType) -> Vec<Record> {
+    records
+        .iter()
+        .filter(|record| record.name() == name && record.record_type() == record_type)
+        .cloned()
+        .collect()
+}
+
+// 提取覆盖指定 RRset 的 RRSIG
+fn covering_sigs(records: &[Record], name: &Name, record_type: RecordType) -> Vec<RRSIG> {
+    records
+        .iter()
+        .filter(|record| record.name() == name)
+        .filter_map(|record| match record.data() {
+            Some(RData::DNSSEC(DNSSECRData::RRSIG(sig))) if sig.type_covered() == record_type => {
+                Some(sig.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// 判断密钥是否与签名的密钥标签和算法一致
+fn key_matches_sig(key: &DNSKEY, sig: &RRSIG) -> bool {
+    key.algorithm() == sig.algorithm() && key.calculate_key_tag().ok() == Some(sig.key_tag())
+}
+
+// 判断签名是否处于有效期内（RFC 1982 序列号算术）
+fn signature_in_validity_period(sig: &RRSIG) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0);
+    (now.wrapping_sub(sig.sig_inception()) as i32) >= 0
+        && (sig.sig_expiration().wrapping_sub(now) as i32) >= 0
+}
@@ -0,0 +1,364 @@
+use crate::config::DnstapConfig;
+use crate::error::AppError;
+use crate::protocol_labels;
+use crate::r#const::dnstap_limits;
+use hickory_proto::op::Message;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio_graceful_shutdown::{IntoSubsystem, SubsystemHandle};
+use tracing::{debug, info, warn};
+
+// dnstap 消息类型（dnstap.proto 中的 Message.Type）
+const CLIENT_QUERY: u64 = 5;
+const CLIENT_RESPONSE: u64 = 6;
+// dnstap 顶层类型 MESSAGE
+const DNSTAP_TYPE_MESSAGE: u64 = 1;
+// 套接字地址族
+const SOCKET_FAMILY_INET: u64 = 1;
+const SOCKET_FAMILY_INET6: u64 = 2;
+// 套接字协议
+const SOCKET_PROTOCOL_UDP: u64 = 1;
+const SOCKET_PROTOCOL_TCP: u64 = 2;
+const SOCKET_PROTOCOL_DOH: u64 = 4;
+
+// Frame Streams 控制帧类型
+pub const CONTROL_ACCEPT: u32 = 1;
+pub const CONTROL_START: u32 = 2;
+pub const CONTROL_STOP: u32 = 3;
+pub const CONTROL_READY: u32 = 4;
+pub const CONTROL_FINISH: u32 = 5;
+// Frame Streams 控制帧字段：内容类型
+const CONTROL_FIELD_CONTENT_TYPE: u32 = 1;
+
+// 写入 protobuf varint
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+// 写入 varint 类型字段
+fn put_varint_field(buf: &mut Vec<u8>, field: u32, value: u64) {
+    put_varint(buf, (field as u64) << 3);
+    put_varint(buf, value);
+}
+
+// 写入 fixed32 类型字段
+fn put_fixed32_field(buf: &mut Vec<u8>, field: u32, value: u32) {
+    put_varint(buf, ((field as u64) << 3) | 5);
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+// 写入长度前缀类型字段（bytes 或嵌套消息）
+fn put_bytes_field(buf: &mut Vec<u8>, field: u32, value: &[u8]) {
+    put_varint(buf, ((field as u64) << 3) | 2);
+    put_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+// 编码 Frame Streams 控制帧，START 与 READY 携带内容类型
+pub fn control_frame(control_type: u32) -> Vec<u8> {
+    let mut body = control_type.to_be_bytes().to_vec();
+    if matches!(control_type, CONTROL_ACCEPT | CONTROL_START | CONTROL_READY) {
+        let content_type = dnstap_limits::CONTENT_TYPE.as_bytes();
+        body.extend_from_slice(&CONTROL_FIELD_CONTENT_TYPE.to_be_bytes());
+        body.extend_from_slice(&(content_type.len() as u32).to_be_bytes());
+        body.extend_from_slice(content_type);
+    }
+    let mut frame = Vec::with_capacity(body.len() + 8);
+    frame.extend_from_slice(&0u32.to_be_bytes());
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&body);
+    frame
+}
+
+// 编码 Frame Streams 数据帧
+fn data_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 4);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// 读取一个 Frame Streams 控制帧，返回控制帧类型
+pub async fn read_control_frame<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<u32> {
+    if stream.read_u32().await? != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expected Frame Streams control frame",
+        ));
+    }
+    let len = stream.read_u32().await? as usize;
+    if len < 4 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Frame Streams control frame too short",
+        ));
+    }
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body).await?;
+    Ok(u32::from_be_bytes([body[0], body[1], body[2], body[3]]))
+}
+
+// 将协议标签映射为 dnstap 套接字协议
+fn socket_protocol(protocol: Option<&str>) -> Option<u64> {
+    match protocol? {
+        protocol_labels::UDP => Some(SOCKET_PROTOCOL_UDP),
+        protocol_labels::TCP => Some(SOCKET_PROTOCOL_TCP),
+        protocol_labels::DOH => Some(SOCKET_PROTOCOL_DOH),
+        _ => None,
+    }
+}
+
+// 时间拆分为秒与纳秒
+fn split_time(time: SystemTime) -> (u64, u32) {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (since_epoch.as_secs(), since_epoch.subsec_nanos())
+}
+
+// dnstap 输出目标
+#[derive(Debug, Clone)]
+pub enum DnstapTarget {
+    // Unix 套接字
+    Unix(String),
+    // TCP 地址
+    Tcp(SocketAddr),
+}
+
+impl std::fmt::Display for DnstapTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnstapTarget::Unix(path) => write!(f, "unix:{}", path),
+            DnstapTarget::Tcp(addr) => write!(f, "tcp:{}", addr),
+        }
+    }
+}
+
+// 查询来源：客户端地址与协议
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DnstapPeer<'a> {
+    // 客户端地址
+    pub client: Option<SocketAddr>,
+    // 协议标签（udp/tcp/doh）
+    pub protocol: Option<&'a str>,
+}
+
+// dnstap 记录器：在请求处理路径上编码帧并放入输出队列，队列已满时丢弃
+#[derive(Debug, Clone)]
+pub struct DnstapLogger {
+    // 输出队列
+    sender: mpsc::Sender<Vec<u8>>,
+    // identity 字段
+    identity: Option<Vec<u8>>,
+}
+
+impl DnstapLogger {
+    // 创建 dnstap 记录器与对应的输出任务
+    pub fn new(config: &DnstapConfig) -> Result<(Self, DnstapOutput), AppError> {
+        let target = match (&config.socket_path, &config.address) {
+            (Some(path), _) => DnstapTarget::Unix(path.clone()),
+            (None, Some(address)) => DnstapTarget::Tcp(address.parse()?),
+            (None, None) => {
+                return Err(AppError::MissingRequiredConfig(
+                    "dnstap.socket_path or dnstap.address".to_string(),
+                ))
+            }
+        };
+        let (sender, receiver) = mpsc::channel(config.queue_size);
+        Ok((
+            Self {
+                sender,
+                identity: config.identity.as_ref().map(|id| id.as_bytes().to_vec()),
+            },
+            DnstapOutput { target, receiver },
+        ))
+    }
+
+    // 记录客户端查询（CLIENT_QUERY）
+    pub fn log_query(&self, peer: DnstapPeer<'_>, query: &Message, query_time: SystemTime) {
+        self.log(peer, CLIENT_QUERY, query, query_time, None);
+    }
+
+    // 记录客户端响应（CLIENT_RESPONSE）
+    pub fn log_response(
+        &self,
+        peer: DnstapPeer<'_>,
+        query: &Message,
+        query_time: SystemTime,
+        response: &Message,
+        response_time: SystemTime,
+    ) {
+        self.log(
+            peer,
+            CLIENT_RESPONSE,
+            query,
+            query_time,
+            Some((response, response_time)),
+        );
+    }
+
+    // 编码 dnstap 消息并放入输出队列
+    fn log(
+        &self,
+        peer: DnstapPeer<'_>,
+        message_type: u64,
+        query: &Message,
+        query_time: SystemTime,
+        response: Option<(&Message, SystemTime)>,
+    ) {
+        let mut message = Vec::with_capacity(256);
+        put_varint_field(&mut message, 1, message_type);
+        if let Some(client) = peer.client {
+            let (family, address) = match client.ip() {
+                IpAddr::V4(ip) => (SOCKET_FAMILY_INET, ip.octets().to_vec()),
+                IpAddr::V6(ip) => (SOCKET_FAMILY_INET6, ip.octets().to_vec()),
+            };
+            put_varint_field(&mut message, 2, family);
+            if let Some(protocol) = socket_protocol(peer.protocol) {
+                put_varint_field(&mut message, 3, protocol);
+            }
+            put_bytes_field(&mut message, 4, &address);
+            put_varint_field(&mut message, 6, client.port() as u64);
+        }
+        let (secs, nanos) = split_time(query_time);
+        put_varint_field(&mut message, 8, secs);
+        put_fixed32_field(&mut message, 9, nanos);
+        match response {
+            None => match query.to_vec() {
+                Ok(bytes) => put_bytes_field(&mut message, 10, &bytes),
+                Err(e) => debug!("Failed to encode query for dnstap: {}", e),
+            },
+            Some((response, response_time)) => {
+                let (secs, nanos) = split_time(response_time);
+                put_varint_field(&mut message, 12, secs);
+                put_fixed32_field(&mut message, 13, nanos);
+                match response.to_vec() {
+                    Ok(bytes) => put_bytes_field(&mut message, 14, &bytes),
+                    Err(e) => debug!("Failed to encode response for dnstap: {}", e),
+                }
+            }
+        }
+
+        let mut dnstap = Vec::with_capacity(message.len() + 64);
+        if let Some(identity) = &self.identity {
+            put_bytes_field(&mut dnstap, 1, identity);
+        }
+        put_bytes_field(
+            &mut dnstap,
+            2,
+            concat!("load-ants ", env!("CARGO_PKG_VERSION")).as_bytes(),
+        );
+        put_bytes_field(&mut dnstap, 14, &message);
+        put_varint_field(&mut dnstap, 15, DNSTAP_TYPE_MESSAGE);
+
+        // 输出端处理不过来或未连接时丢弃，不阻塞请求处理
+        if self.sender.try_send(data_frame(&dnstap)).is_err() {
+            debug!("dnstap output queue full or closed, dropping frame");
+        }
+    }
+}
+
+// 输出连接：Unix 套接字或 TCP 流
+trait DnstapStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> DnstapStream for T {}
+
+// dnstap 输出任务：维护到采集端的 Frame Streams 连接并写出队列中的帧
+pub struct DnstapOutput {
+    // 输出目标
+    target: DnstapTarget,
+    // 输出队列
+    receiver: mpsc::Receiver<Vec<u8>>,
+}
+
+impl DnstapOutput {
+    // 连接采集端并完成双向 Frame Streams 握手（READY/ACCEPT/START）
+    async fn connect(&self) -> io::Result<Box<dyn DnstapStream>> {
+        let mut stream: Box<dyn DnstapStream> = match &self.target {
+            #[cfg(unix)]
+            DnstapTarget::Unix(path) => Box::new(tokio::net::UnixStream::connect(path).await?),
+            #[cfg(not(unix))]
+            DnstapTarget::Unix(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Unix sockets are not supported on this platform",
+                ))
+            }
+            DnstapTarget::Tcp(addr) => Box::new(tokio::net::TcpStream::connect(addr).await?),
+        };
+        stream.write_all(&control_frame(CONTROL_READY)).await?;
+        let control_type = read_control_frame(&mut stream).await?;
+        if control_type != CONTROL_ACCEPT {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected ACCEPT control frame, got {}", control_type),
+            ));
+        }
+        stream.write_all(&control_frame(CONTROL_START)).await?;
+        stream.flush().await?;
+        Ok(stream)
+    }
+
+    // 发送 STOP 并等待 FINISH，结束当前连接
+    async fn finish(stream: &mut Box<dyn DnstapStream>) -> io::Result<()> {
+        stream.write_all(&control_frame(CONTROL_STOP)).await?;
+        stream.flush().await?;
+        let _ = tokio::time::timeout(Duration::from_secs(1), read_control_frame(stream)).await;
+        Ok(())
+    }
+
+    // 持续写出队列中的帧直到 shutdown 完成，连接失败或断开时按固定间隔重连
+    pub async fn serve(mut self, shutdown: impl Future<Output = ()>) {
+        tokio::pin!(shutdown);
+        let reconnect_delay = Duration::from_secs(dnstap_limits::RECONNECT_DELAY);
+        'connect: loop {
+            let mut stream = tokio::select! {
+                _ = &mut shutdown => break,
+                connected = self.connect() => match connected {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("Failed to connect to dnstap receiver {}: {}", self.target, e);
+                        tokio::select! {
+                            _ = &mut shutdown => break,
+                            _ = tokio::time::sleep(reconnect_delay) => continue,
+                        }
+                    }
+                },
+            };
+            info!("dnstap output connected to {}", self.target);
+
+            loop {
+                tokio::select! {
+                    _ = &mut shutdown => {
+                        if let Err(e) = Self::finish(&mut stream).await {
+                            debug!("Failed to finish dnstap stream: {}", e);
+                        }
+                        break 'connect;
+                    }
+                    frame = self.receiver.recv() => {
+                        let Some(frame) = frame else { break 'connect };
+                        if let Err(e) = stream.write_all(&frame).await {
+                            warn!("dnstap output to {} failed, reconnecting: {}", self.target, e);
+                            continue 'connect;
+                        }
+                    }
+                }
+            }
+        }
+        info!("dnstap output stopped");
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoSubsystem<AppError> for DnstapOutput {
+    async fn run(self, subsys: SubsystemHandle) -> Result<(), AppError> {
+        self.serve(subsys.on_shutdown_requested()).await;
+        Ok(())
+    }
+}
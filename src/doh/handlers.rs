@@ -2,12 +2,13 @@
 
 use crate::doh::json::SerializableDnsMessage;
 use crate::doh::state::AppState;
-use crate::metrics::METRICS;
+use crate::handler::RequestContext;
+use crate::metrics::{self, CounterMetric, HistogramMetric};
 use crate::r#const::{http_headers, processing_labels, protocol_labels};
 use axum::{
     body::Bytes,
     extract::{ConnectInfo, Query, State},
-    http::{header, HeaderMap, StatusCode},
+    http::{header, HeaderMap, StatusCode, Uri},
     response::{IntoResponse, Json, Response},
 };
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
@@ -24,7 +25,6 @@ use tracing::{error, info, warn};
 type DohError = (StatusCode, &'static str);
 type DohQueryType = Cow<'static, str>;
 type DohHandlerError = (StatusCode, &'static str, DohQueryType);
-type DohBinaryHandlerResult = Result<(HeaderMap, Vec<u8>), DohHandlerError>;
 type DohResponseHandlerResult = Result<Response, DohHandlerError>;
 
 /// 根据记录类型高效地返回一个 Cow<'static, str>
@@ -70,7 +70,7 @@ pub struct DohJsonGetParams {
     #[serde(rename = "do", default)]
     pub do_flag: Option<String>,
     /// 内容类型选项，用于指定响应的内容类型
-    /// 使用 ct=application/dns-message 接收二进制 DNS 消息；使用 ct=application/x-javascript 或不提供 ct 参数接收 JSON 文本
+    /// 使用 ct=application/dns-message 接收二进制 DNS 消息；使用 ct=application/x-javascript 等 JSON 类型时按该类型返回 JSON 文本，不提供 ct 参数时使用配置的默认类型
     #[serde(default)]
     pub ct: Option<String>,
 }
@@ -79,9 +79,24 @@ pub struct DohJsonGetParams {
 ///
 /// 这是一个内部辅助函数，用于处理 DNS 消息并生成响应，被 GET 和 POST 处理函数共用
 #[inline(always)]
-async fn process_dns_message(state: &AppState, dns_message: &Message) -> Result<Message, DohError> {
-    // 处理 DNS 请求
-    match state.handler.handle_request(dns_message).await {
+async fn process_dns_message(
+    state: &AppState,
+    dns_message: &Message,
+    client_addr: SocketAddr,
+    headers: &HeaderMap,
+) -> Result<Message, DohError> {
+    // 处理 DNS 请求，携带客户端的追踪上下文
+    let traceparent = headers
+        .get(http_headers::TRACEPARENT)
+        .and_then(|value| value.to_str().ok());
+    let context = RequestContext::from_client(client_addr)
+        .with_protocol(protocol_labels::DOH)
+        .with_traceparent(traceparent);
+    match state
+        .handler
+        .handle_request_with_context(dns_message, &context)
+        .await
+    {
         Ok(resp) => Ok(resp),
         Err(_) => {
             // 注意：这里的具体错误已经在 handler 内部记录，这里只向上传递错误类型
@@ -99,11 +114,15 @@ async fn process_dns_message(state: &AppState, dns_message: &Message) -> Result<
 pub async fn handle_doh_get(
     State(state): State<AppState>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Query(params): Query<DohGetParams>,
 ) -> impl IntoResponse {
     let start_time = Instant::now();
 
-    let result: DohBinaryHandlerResult = async {
+    // 记录客户端查询统计
+    state.handler.record_client(addr.ip());
+
+    let result: DohResponseHandlerResult = async {
         // 提取 DNS 查询参数
         let dns_param = &params.dns;
 
@@ -115,6 +134,7 @@ pub async fn handle_doh_get(
                 Cow::from(protocol_labels::UNKNOWN),
             )
         })?;
+        record_request_size(dns_bytes.len());
 
         // 解析 DNS 消息
         let dns_message = Message::from_vec(&dns_bytes).map_err(|_| {
@@ -133,35 +153,22 @@ pub async fn handle_doh_get(
             .unwrap_or(Cow::from(protocol_labels::UNKNOWN));
 
         // 处理 DNS 消息
-        let response = process_dns_message(&state, &dns_message)
+        let response = process_dns_message(&state, &dns_message, addr, &headers)
             .await
             .map_err(|(status, err_type)| (status, err_type, query_type.clone()))?;
 
-        // 编码 DNS 响应消息
-        let response_bytes = response.to_vec().map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                processing_labels::error_types::MESSAGE_ENCODE_ERROR,
-                query_type.clone(),
-            )
-        })?;
-
-        // 构建 HTTP 响应
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static(http_headers::content_types::DNS_MESSAGE),
-        );
+        // 按 Accept 头编码 DNS 响应消息
+        let response = encode_doh_response(&response, accepts_dns_json(&headers), &query_type)?;
 
         // 记录成功的指标
         record_doh_metrics(start_time, &query_type, addr, &Ok(StatusCode::OK), None);
 
-        Ok((headers, response_bytes))
+        Ok(response)
     }
     .await;
 
     match result {
-        Ok((headers, body)) => (StatusCode::OK, headers, body).into_response(),
+        Ok(response) => response,
         Err((status, error_type, query_type)) => {
             // 记录失败的指标
             record_doh_metrics(
@@ -176,6 +183,33 @@ pub async fn handle_doh_get(
     }
 }
 
+/// 处理 DoH 路径上的 GET 请求
+///
+/// 带 `dns` 参数时按 RFC 8484 处理，否则带 `name` 参数时按 Google JSON API 处理
+pub async fn handle_doh_or_json_get(
+    state: State<AppState>,
+    connect_info: ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    let rejection = match Query::<DohGetParams>::try_from_uri(&uri) {
+        Ok(params) => {
+            return handle_doh_get(state, connect_info, headers, params)
+                .await
+                .into_response()
+        }
+        Err(rejection) => rejection,
+    };
+
+    match Query::<DohJsonGetParams>::try_from_uri(&uri) {
+        Ok(params) => handle_json_get(state, connect_info, headers, params)
+            .await
+            .into_response(),
+        // 两种参数都不匹配时返回 RFC 8484 的参数错误
+        Err(_) => rejection.into_response(),
+    }
+}
+
 /// 处理 RFC 8484 DoH POST 请求
 ///
 /// 处理 DNS 查询，其中 DNS 消息在 HTTP 请求体中传递
@@ -187,7 +221,10 @@ pub async fn handle_doh_post(
 ) -> impl IntoResponse {
     let start_time = Instant::now();
 
-    let result: DohBinaryHandlerResult = async {
+    // 记录客户端查询统计
+    state.handler.record_client(addr.ip());
+
+    let result: DohResponseHandlerResult = async {
         // 验证内容类型
         if let Some(content_type) = headers.get(header::CONTENT_TYPE) {
             if content_type != http_headers::content_types::DNS_MESSAGE {
@@ -205,6 +242,8 @@ pub async fn handle_doh_post(
             ));
         }
 
+        record_request_size(body.len());
+
         // 解析 DNS 消息
         let dns_message = Message::from_vec(&body).map_err(|_| {
             (
@@ -222,34 +261,22 @@ pub async fn handle_doh_post(
             .unwrap_or(Cow::from(protocol_labels::UNKNOWN));
 
         // 处理 DNS 消息
-        let response = process_dns_message(&state, &dns_message)
+        let response = process_dns_message(&state, &dns_message, addr, &headers)
             .await
             .map_err(|(status, err_type)| (status, err_type, query_type.clone()))?;
 
-        // 编码 DNS 响应消息
-        let response_bytes = response.to_vec().map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                processing_labels::error_types::MESSAGE_ENCODE_ERROR,
-                query_type.clone(),
-            )
-        })?;
+        // 按 Accept 头编码 DNS 响应消息
+        let response = encode_doh_response(&response, accepts_dns_json(&headers), &query_type)?;
 
-        // 构建 HTTP 响应
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static(http_headers::content_types::DNS_MESSAGE),
-        );
         // 记录成功的指标
         record_doh_metrics(start_time, &query_type, addr, &Ok(StatusCode::OK), None);
 
-        Ok((headers, response_bytes))
+        Ok(response)
     }
     .await;
 
     match result {
-        Ok((headers, body)) => (StatusCode::OK, headers, body).into_response(),
+        Ok(response) => response,
         Err((status, error_type, query_type)) => {
             // 记录失败的指标
             record_doh_metrics(
@@ -270,10 +297,14 @@ pub async fn handle_doh_post(
 pub async fn handle_json_get(
     State(state): State<AppState>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request_headers: HeaderMap,
     Query(params): Query<DohJsonGetParams>,
 ) -> impl IntoResponse {
     let start_time = Instant::now();
 
+    // 记录客户端查询统计
+    state.handler.record_client(addr.ip());
+
     let result: DohResponseHandlerResult = async {
         // 提取必要的查询参数
         let name = &params.name;
@@ -342,7 +373,7 @@ pub async fn handle_json_get(
         query.add_query(q);
 
         // 处理 DNS 请求
-        let response = process_dns_message(&state, &query)
+        let response = process_dns_message(&state, &query, addr, &request_headers)
             .await
             .map_err(|(status, err_type)| (status, err_type, query_type.clone()))?;
 
@@ -366,15 +397,21 @@ pub async fn handle_json_get(
                         query_type.clone(),
                     )
                 })?;
+                record_response_size(response_bytes.len());
 
                 (headers, response_bytes).into_response()
             }
-            _ => {
-                // JSON 响应（默认）
-                headers.insert(
-                    header::CONTENT_TYPE,
-                    header::HeaderValue::from_static(http_headers::content_types::DNS_JSON),
-                );
+            ct => {
+                // JSON 响应：优先使用请求指定的 JSON 内容类型，否则使用配置的默认值
+                let content_type = ct
+                    .and_then(|ct| {
+                        http_headers::content_types::JSON_TYPES
+                            .iter()
+                            .find(|json_type| json_type.eq_ignore_ascii_case(ct))
+                    })
+                    .map(|json_type| header::HeaderValue::from_static(json_type))
+                    .unwrap_or_else(|| state.json_content_type.clone());
+                headers.insert(header::CONTENT_TYPE, content_type);
                 (headers, Json(SerializableDnsMessage(&response))).into_response()
             }
         };
@@ -402,6 +439,74 @@ pub async fn handle_json_get(
     }
 }
 
+/// 判断客户端是否通过 Accept 头请求 JSON 格式的响应
+fn accepts_dns_json(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| {
+            media_type
+                .split(';')
+                .next()
+                .is_some_and(|t| t.trim() == http_headers::content_types::DNS_JSON)
+        })
+}
+
+/// 编码 RFC 8484 端点的响应
+///
+/// 客户端请求 `application/dns-json` 时返回可读的 JSON（便于调试），否则返回二进制 DNS 消息
+fn encode_doh_response(
+    response: &Message,
+    json: bool,
+    query_type: &DohQueryType,
+) -> DohResponseHandlerResult {
+    let mut headers = HeaderMap::new();
+    if json {
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static(http_headers::content_types::DNS_JSON),
+        );
+        return Ok((headers, Json(SerializableDnsMessage(response))).into_response());
+    }
+
+    let response_bytes = response.to_vec().map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            processing_labels::error_types::MESSAGE_ENCODE_ERROR,
+            query_type.clone(),
+        )
+    })?;
+    record_response_size(response_bytes.len());
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static(http_headers::content_types::DNS_MESSAGE),
+    );
+    Ok((headers, response_bytes).into_response())
+}
+
+/// 记录 DoH 二进制 DNS 请求报文大小
+#[inline]
+fn record_request_size(size: usize) {
+    metrics::backend().observe(
+        HistogramMetric::DnsRequestBytes,
+        &[protocol_labels::DOH],
+        size as f64,
+    );
+}
+
+/// 记录 DoH 二进制 DNS 响应报文大小
+#[inline]
+fn record_response_size(size: usize) {
+    metrics::backend().observe(
+        HistogramMetric::DnsResponseBytes,
+        &[protocol_labels::DOH],
+        size as f64,
+    );
+}
+
 /// 记录 DoH 请求的指标和日志
 #[inline]
 fn record_doh_metrics(
@@ -418,18 +523,16 @@ fn record_doh_metrics(
     let status_str = status_code.as_str();
 
     // 记录请求总数和时长
-    METRICS
-        .http_requests_total()
-        .with_label_values(&[status_str])
-        .inc();
+    metrics::backend().increment(CounterMetric::HttpRequests, &[status_str]);
 
     // 根据结果记录日志和错误总数
     match result {
         Ok(status) => {
-            METRICS
-                .http_request_duration_seconds()
-                .with_label_values(&[query_type, status_str])
-                .observe(duration);
+            metrics::backend().observe(
+                HistogramMetric::HttpRequestDuration,
+                &[query_type, status_str],
+                duration,
+            );
             info!(
                 client_ip = %client_addr,
                 status_code = %status,
@@ -439,10 +542,7 @@ fn record_doh_metrics(
         }
         Err(status) => {
             if let Some(err_type) = error_type {
-                METRICS
-                    .http_request_errors_total()
-                    .with_label_values(&[err_type])
-                    .inc();
+                metrics::backend().increment(CounterMetric::HttpRequestErrors, &[err_type]);
                 error!(
                     client_ip = %client_addr,
                     status_code = %status,
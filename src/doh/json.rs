@@ -197,11 +197,21 @@ fn rdata_to_string(rdata: Option<&RData>) -> String {
         }
         RData::TXT(txt) => {
             // from_utf8_lossy 是高效的，只有在需要修复非UTF8序列时才会分配
-            txt.txt_data()
+            let strings: Vec<_> = txt
+                .txt_data()
                 .iter()
                 .map(|bytes| String::from_utf8_lossy(bytes))
-                .collect::<Vec<_>>()
-                .join(" ") // Google JSON 通常将多个 TXT 块合并为一个字符串
+                .collect();
+            if strings.len() == 1 {
+                strings[0].to_string()
+            } else {
+                // 多个 character-string 分别加引号，保留字符串边界
+                strings
+                    .iter()
+                    .map(|s| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }
         }
         // 对于其他所有类型，返回一个标准的字符串表示
         _ => rdata.to_string(),
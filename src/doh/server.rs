@@ -1,19 +1,20 @@
 // src/doh/server.rs
 
-use crate::doh::handlers::{handle_doh_get, handle_doh_post, handle_json_get};
+use crate::connection_limit::{ConnectionLimiter, LimitedListener};
+use crate::doh::handlers::{handle_doh_or_json_get, handle_doh_post, handle_json_get};
 use crate::doh::state::AppState;
 use crate::error::AppError;
 use crate::handler::RequestHandler;
-use axum::{routing::get, Router};
+use crate::r#const::{http_headers, protocol_labels, server_defaults};
+use axum::{http::HeaderValue, routing::get, serve::ListenerExt, Router};
+use std::future::IntoFuture;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::sync::oneshot;
 use tokio_graceful_shutdown::SubsystemHandle;
-use tracing::{error, info};
-
-const DOH_QUERY_PATH: &str = "/dns-query";
-const JSON_QUERY_PATH: &str = "/resolve";
+use tracing::{error, info, warn};
 
 /// DoH 服务器结构体
 pub struct DoHServer {
@@ -21,6 +22,16 @@ pub struct DoHServer {
     bind_addr: SocketAddr,
     /// DNS 请求处理器
     handler: Arc<RequestHandler>,
+    /// 关闭时等待连接排空的超时
+    drain_timeout: Duration,
+    /// 最大并发连接数（None 表示不限制）
+    max_connections: Option<usize>,
+    /// RFC 8484 DoH 查询路径
+    doh_path: String,
+    /// Google JSON API 查询路径
+    json_path: String,
+    /// JSON API 响应的默认内容类型
+    json_content_type: HeaderValue,
     /// 关闭信号发送端
     shutdown_tx: oneshot::Sender<()>,
     /// 关闭信号接收端
@@ -35,26 +46,76 @@ impl DoHServer {
         Self {
             bind_addr,
             handler,
+            drain_timeout: Duration::from_secs(server_defaults::DEFAULT_DRAIN_TIMEOUT),
+            max_connections: None,
+            doh_path: server_defaults::DEFAULT_DOH_PATH.to_string(),
+            json_path: server_defaults::DEFAULT_JSON_PATH.to_string(),
+            json_content_type: HeaderValue::from_static(http_headers::content_types::DNS_JSON),
             shutdown_tx,
             shutdown_rx,
         }
     }
 
+    /// 设置关闭时等待连接排空的超时
+    ///
+    /// 独立于全局 `shutdown_timeout`，避免长连接（keep-alive）拖慢整体关闭
+    pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    /// 设置最大并发连接数
+    ///
+    /// 达到上限后暂停接受新连接，新连接在监听队列中等待直到已有连接关闭
+    pub fn with_max_connections(mut self, max_connections: Option<usize>) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// 设置 DoH 与 JSON API 的查询路径
+    ///
+    /// DoH 路径上不带 `dns` 参数的 GET 请求同样按 JSON API 处理
+    pub fn with_paths(mut self, doh_path: impl Into<String>, json_path: impl Into<String>) -> Self {
+        self.doh_path = doh_path.into();
+        self.json_path = json_path.into();
+        self
+    }
+
+    /// 设置 JSON API 响应的默认内容类型
+    ///
+    /// 请求通过 `ct` 参数指定 JSON 内容类型时以请求为准
+    pub fn with_json_content_type(mut self, json_content_type: &str) -> Self {
+        match HeaderValue::from_str(json_content_type) {
+            Ok(value) => self.json_content_type = value,
+            Err(_) => warn!(
+                "Invalid JSON content type '{}', keeping {:?}",
+                json_content_type, self.json_content_type
+            ),
+        }
+        self
+    }
+
     /// 创建应用路由
     fn create_router(&self) -> Router {
         // 创建应用程序状态
-        let app_state = AppState {
-            handler: self.handler.clone(),
+        let app_state = AppState::new(self.handler.clone())
+            .with_json_content_type(self.json_content_type.clone());
+
+        // 创建路由：RFC 8484 DoH 端点（GET 同时兼容 JSON API 参数）
+        let router = Router::new().route(
+            &self.doh_path,
+            get(handle_doh_or_json_get).post(handle_doh_post),
+        );
+
+        // Google JSON DoH 端点（与 DoH 路径相同时无需重复注册）
+        let router = if self.json_path != self.doh_path {
+            router.route(&self.json_path, get(handle_json_get))
+        } else {
+            router
         };
 
-        // 创建路由
-        Router::new()
-            // RFC 8484 DoH 端点
-            .route(DOH_QUERY_PATH, get(handle_doh_get).post(handle_doh_post))
-            // Google JSON DoH 端点
-            .route(JSON_QUERY_PATH, get(handle_json_get))
-            // 添加应用程序状态
-            .with_state(app_state)
+        // 添加应用程序状态
+        router.with_state(app_state)
     }
 
     /// 启动 DoH 服务器
@@ -74,19 +135,34 @@ impl DoHServer {
             }
         };
 
+        // 包装监听器以限制并发连接数并统计活跃连接（tap_io 使 ConnectInfo<SocketAddr> 可用）
+        if let Some(max_connections) = self.max_connections {
+            info!("DoH server connections limited to {}", max_connections);
+        }
+        let listener = LimitedListener::new(
+            listener,
+            ConnectionLimiter::new(self.max_connections, protocol_labels::DOH),
+        )
+        .tap_io(|_| {});
+
         // 获取关闭信号接收端
         let shutdown_rx = self.shutdown_rx;
+        let drain_timeout = self.drain_timeout;
 
         // 启动 HTTP 服务器
+        let server = axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(async {
+            let _ = shutdown_rx.await;
+            info!("DoH server received shutdown signal");
+        })
+        .into_future();
+        tokio::pin!(server);
+
         tokio::select! {
-            result = axum::serve(
-                listener,
-                app.into_make_service_with_connect_info::<SocketAddr>()
-            )
-            .with_graceful_shutdown(async {
-                let _ = shutdown_rx.await;
-                info!("DoH server received shutdown signal");
-            }) => {
+            result = &mut server => {
                 if let Err(e) = result {
                     error!("DoH server error: {}", e);
                 } else {
@@ -95,8 +171,18 @@ impl DoHServer {
                 Ok(())
             }
             _ = subsys.on_shutdown_requested() => {
-                info!("Shutdown requested, stopping DoH server");
+                info!("Shutdown requested, draining DoH server connections");
                 let _ = self.shutdown_tx.send(());
+
+                // 在排空超时内等待现有连接完成，超时后直接停止
+                match tokio::time::timeout(drain_timeout, &mut server).await {
+                    Ok(Ok(())) => info!("DoH server drained and stopped"),
+                    Ok(Err(e)) => error!("DoH server error during drain: {}", e),
+                    Err(_) => warn!(
+                        "DoH server drain timed out after {:?}, closing remaining connections",
+                        drain_timeout
+                    ),
+                }
                 Ok(())
             }
         }
@@ -1,6 +1,8 @@
 // src/doh/state.rs
 
 use crate::handler::RequestHandler;
+use crate::r#const::http_headers;
+use axum::http::HeaderValue;
 use std::sync::Arc;
 
 /// 应用程序状态结构体
@@ -8,4 +10,22 @@ use std::sync::Arc;
 pub struct AppState {
     /// DNS 请求处理器
     pub handler: Arc<RequestHandler>,
+    /// JSON API 响应的默认内容类型（请求未通过 `ct` 参数指定时使用）
+    pub json_content_type: HeaderValue,
+}
+
+impl AppState {
+    /// 创建应用程序状态
+    pub fn new(handler: Arc<RequestHandler>) -> Self {
+        Self {
+            handler,
+            json_content_type: HeaderValue::from_static(http_headers::content_types::DNS_JSON),
+        }
+    }
+
+    /// 设置 JSON API 响应的默认内容类型
+    pub fn with_json_content_type(mut self, json_content_type: HeaderValue) -> Self {
+        self.json_content_type = json_content_type;
+        self
+    }
 }
@@ -34,6 +34,24 @@ pub enum AppError {
     #[error("Upstream error: {0}")]
     Upstream(String),
 
+    #[error("Upstream server returned error: {0}")]
+    UpstreamStatus(reqwest::StatusCode),
+
+    #[error("Upstream response ID mismatch: expected {expected}, got {actual}")]
+    UpstreamIdMismatch { expected: u16, actual: u16 },
+
+    #[error("Upstream response question case mismatch: expected {expected}, got {actual}")]
+    UpstreamCaseMismatch { expected: String, actual: String },
+
+    #[error("Malformed upstream response: {0}")]
+    MalformedUpstreamResponse(String),
+
+    #[error("Rule limit exceeded: more than {0} rules loaded")]
+    RuleLimitExceeded(usize),
+
+    #[error("Rule reload failed: {0}")]
+    RuleReload(String),
+
     #[error("Router error: {0}")]
     #[allow(dead_code)]
     Router(String),
@@ -42,7 +60,6 @@ pub enum AppError {
     Cache(String),
 
     #[error("Timeout error")]
-    #[allow(dead_code)]
     Timeout,
 
     #[error("No available upstream servers")]
@@ -51,6 +68,9 @@ pub enum AppError {
     #[error("Upstream group not found: {0}")]
     UpstreamGroupNotFound(String),
 
+    #[error("Upstream group unavailable: {0}")]
+    UpstreamGroupUnavailable(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 
@@ -60,6 +80,9 @@ pub enum AppError {
     #[error("No matching route rule: {0}")]
     NoRouteMatch(String),
 
+    #[error("Query name has too many labels: {0}")]
+    TooManyLabels(String),
+
     #[error("Invalid proxy configuration: {0}")]
     InvalidProxy(#[from] InvalidProxyConfig),
 
@@ -77,10 +100,28 @@ pub enum AppError {
     #[error("Invalid shutdown timeout: must be between minimum and maximum values")]
     InvalidShutdownTimeout,
 
+    #[error("Failed to bind {protocol} listener on {addr}: {source}")]
+    Bind {
+        protocol: &'static str,
+        addr: std::net::SocketAddr,
+        source: io::Error,
+    },
+
     #[error("Feature not implemented: {0}")]
     NotImplemented(String),
 }
 
+impl AppError {
+    // 是否为上游超时错误（包括 HTTP 请求超时与转发截止时间超时）
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Self::Timeout => true,
+            Self::Http(e) => e.is_timeout(),
+            _ => false,
+        }
+    }
+}
+
 impl From<reqwest_middleware::Error> for AppError {
     fn from(err: reqwest_middleware::Error) -> Self {
         match err {
@@ -108,6 +149,9 @@ pub enum ConfigError {
     #[error("Failed to load configuration file: {0}")]
     LoadError(#[from] io::Error),
 
+    #[error("Failed to fetch configuration: {0}")]
+    FetchError(String),
+
     #[error("YAML parsing error: {0}")]
     ParseError(#[from] serde_yaml::Error),
 
@@ -145,6 +189,15 @@ pub enum ConfigError {
     #[error("Invalid HTTP client configuration: {0}")]
     InvalidHttpClientConfig(String),
 
+    #[error("Invalid DNS64 configuration: {0}")]
+    InvalidDns64Config(String),
+
+    #[error("Invalid rebind protection configuration: {0}")]
+    InvalidRebindConfig(String),
+
+    #[error("Invalid DNSSEC configuration: {0}")]
+    InvalidDnssecConfig(String),
+
     #[error("Duplicate upstream group name: {0}")]
     DuplicateGroupName(String),
 
@@ -0,0 +1,76 @@
+// src/extended_error.rs
+
+use crate::r#const::{edns_defaults, extended_errors};
+use hickory_proto::op::{Edns, Message};
+use hickory_proto::rr::rdata::opt::{EdnsCode, EdnsOption};
+
+// 扩展 DNS 错误（RFC 8914）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedError {
+    // INFO-CODE
+    pub info_code: u16,
+    // EXTRA-TEXT
+    pub extra_text: String,
+}
+
+impl ExtendedError {
+    // 创建扩展错误
+    pub fn new(info_code: u16, extra_text: impl Into<String>) -> Self {
+        Self {
+            info_code,
+            extra_text: extra_text.into(),
+        }
+    }
+
+    // 编码为 EDNS 选项：2 字节 INFO-CODE 后接 UTF-8 EXTRA-TEXT
+    pub fn to_option(&self) -> EdnsOption {
+        let mut data = Vec::with_capacity(2 + self.extra_text.len());
+        data.extend_from_slice(&self.info_code.to_be_bytes());
+        data.extend_from_slice(self.extra_text.as_bytes());
+        EdnsOption::Unknown(extended_errors::OPTION_CODE, data)
+    }
+
+    // 从报文的 OPT 记录中读取扩展错误
+    pub fn from_message(message: &Message) -> Option<Self> {
+        let option = extended_error_option(message)?;
+        let EdnsOption::Unknown(_, data) = option else {
+            return None;
+        };
+        if data.len() < 2 {
+            return None;
+        }
+        Some(Self {
+            info_code: u16::from_be_bytes([data[0], data[1]]),
+            extra_text: String::from_utf8_lossy(&data[2..]).into_owned(),
+        })
+    }
+}
+
+// 获取报文 OPT 记录中的扩展错误选项
+pub fn extended_error_option(message: &Message) -> Option<&EdnsOption> {
+    message
+        .extensions()
+        .as_ref()?
+        .option(EdnsCode::from(extended_errors::OPTION_CODE))
+}
+
+// 向响应附加扩展错误；客户端查询未使用 EDNS 时不附加 OPT 记录（RFC 6891）
+pub fn attach_extended_error(response: &mut Message, request: &Message, error: &ExtendedError) {
+    attach_option(response, request, error.to_option());
+}
+
+// 向响应的 OPT 记录写入选项，响应没有 OPT 记录时按客户端查询生成
+pub fn attach_option(response: &mut Message, request: &Message, option: EdnsOption) {
+    let Some(client_edns) = request.extensions() else {
+        return;
+    };
+    if response.extensions().is_none() {
+        let mut edns = Edns::new();
+        edns.set_max_payload(edns_defaults::ADVERTISED_PAYLOAD);
+        edns.set_dnssec_ok(client_edns.dnssec_ok());
+        response.set_edns(edns);
+    }
+    if let Some(edns) = response.extensions_mut() {
+        edns.options_mut().insert(option);
+    }
+}
@@ -1,20 +1,240 @@
 use crate::{
-    cache_labels, error_labels, metrics::METRICS, processing_labels, protocol_labels, AppError,
-    DnsCache, RouteAction, Router, UpstreamManager,
+    cache_labels,
+    config::{
+        parse_ptr_name, parse_query_type, AnswerSort, ResponseConfig, ShadowConfig, TracingConfig,
+    },
+    dns64::Dns64,
+    dnssec::{DnssecStatus, DnssecValidator},
+    dnstap::{DnstapLogger, DnstapPeer},
+    error_labels,
+    extended_error::{attach_extended_error, ExtendedError},
+    log_throttle::{LogDecision, LogThrottle},
+    metrics::{self, CounterMetric, HistogramMetric, METRICS},
+    processing_labels, protocol_labels,
+    r#const::{
+        cache_limits, extended_errors, log_throttle_limits, response_limits, revalidation_labels,
+        slo_phase_labels,
+    },
+    rebind::RebindGuard,
+    rule_action_labels,
+    stats::ClientStats,
+    upstream::ForwardContext,
+    AppError, DnsCache, RouteAction, Router, UpstreamManager,
 };
+use arc_swap::{ArcSwap, ArcSwapOption};
+use hickory_proto::op::Query;
 use hickory_proto::op::{Message, MessageType, ResponseCode};
-use std::sync::Arc;
-use std::time::Instant;
-use tracing::{debug, error, info, warn};
+use hickory_proto::rr::rdata::opt::{EdnsCode, EdnsOption};
+use hickory_proto::rr::rdata::{PTR, SOA, TXT};
+use hickory_proto::rr::{Name, RData, Record, RecordType};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::Semaphore;
+use tracing::{debug, debug_span, error, info, warn, Instrument};
+
+// 请求上下文
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    // 客户端地址
+    pub client_addr: Option<SocketAddr>,
+    // 客户端请求携带的 W3C traceparent 头（仅保留格式合法的值）
+    pub traceparent: Option<String>,
+    // 客户端请求使用的协议标签（udp/tcp/doh）
+    pub protocol: Option<&'static str>,
+    // 实际转发查询的上游组（缓存命中或本地应答时为空）
+    upstream_group: OnceLock<String>,
+}
+
+impl RequestContext {
+    // 根据客户端地址创建请求上下文
+    pub fn from_client(client_addr: SocketAddr) -> Self {
+        Self {
+            client_addr: Some(client_addr),
+            ..Default::default()
+        }
+    }
+
+    // 设置客户端请求使用的协议标签
+    pub fn with_protocol(mut self, protocol: &'static str) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    // 设置 traceparent 头，格式不合法的值将被忽略
+    pub fn with_traceparent(mut self, traceparent: Option<&str>) -> Self {
+        self.traceparent = traceparent
+            .filter(|value| trace_id_from_traceparent(value).is_some())
+            .map(str::to_string);
+        self
+    }
+
+    // 实际转发查询的上游组
+    pub fn upstream_group(&self) -> Option<&str> {
+        self.upstream_group.get().map(String::as_str)
+    }
+
+    // 追踪 ID（traceparent 中的 trace-id 字段）
+    pub fn trace_id(&self) -> Option<&str> {
+        self.traceparent
+            .as_deref()
+            .and_then(trace_id_from_traceparent)
+    }
+}
+
+// 规则重载后在宽限期内保留的旧路由引擎
+struct PreviousRouter {
+    // 旧路由引擎
+    router: Arc<Router>,
+    // 宽限期截止时间
+    expires_at: Instant,
+}
+
+// 影子上游：接收查询副本，仅用于比较应答
+struct Shadow {
+    // 影子上游组名称
+    group: String,
+    // 并发影子请求许可
+    permits: Arc<Semaphore>,
+}
+
+// 请求各处理阶段的耗时，用于延迟预算超限归因
+#[derive(Debug, Default)]
+struct PhaseTimings {
+    // 缓存查询耗时
+    cache: Duration,
+    // 路由匹配耗时
+    routing: Duration,
+    // 上游转发耗时（包括 DNSSEC 验证、ANAME 展开等后续上游查询）
+    upstream: Duration,
+}
+
+impl PhaseTimings {
+    // 耗时最长的阶段
+    fn dominant_phase(&self) -> &'static str {
+        if self.upstream >= self.cache && self.upstream >= self.routing {
+            slo_phase_labels::UPSTREAM
+        } else if self.routing >= self.cache {
+            slo_phase_labels::ROUTING
+        } else {
+            slo_phase_labels::CACHE
+        }
+    }
+}
+
+// 后台查询（伴随查询、陈旧条目重新验证）应答的缓存写入：按主查询的应答处理规则处理后写入缓存
+struct BackgroundStore {
+    cache: Arc<DnsCache>,
+    rebind: Option<Arc<RebindGuard>>,
+    edns_passthrough: bool,
+    normalize_ttl: bool,
+    max_cname_chain: usize,
+}
+
+impl BackgroundStore {
+    // 按主查询的应答处理规则处理后台查询应答，返回 CNAME 链是否未超限
+    fn prepare(&self, request: &Message, response: &mut Message) -> bool {
+        if !self.edns_passthrough {
+            strip_unknown_edns_options(response);
+        }
+        if let Some(rebind) = &self.rebind {
+            rebind.filter(response);
+        }
+        if self.normalize_ttl {
+            normalize_answer_ttls(response);
+        }
+        cname_chain_length(response.answers(), request.queries()[0].name())
+            .is_some_and(|length| length <= self.max_cname_chain)
+    }
+
+    // 写入缓存，返回是否已写入
+    async fn insert(&self, request: &Message, response: Message) -> bool {
+        let query = &request.queries()[0];
+        match self.cache.insert(request, response).await {
+            Ok(()) => {
+                debug!(
+                    "Cached background answer {} {}",
+                    query.name().to_utf8(),
+                    query.query_type()
+                );
+                true
+            }
+            Err(e) => {
+                debug!("Background answer cache insertion failed: {}", e);
+                false
+            }
+        }
+    }
+
+    // 处理伴随查询应答并写入缓存，仅缓存 NOERROR 且 CNAME 链未超限的应答
+    async fn store(&self, request: &Message, mut response: Message) {
+        if self.prepare(request, &mut response) && response.response_code() == ResponseCode::NoError
+        {
+            self.insert(request, response).await;
+        }
+    }
+
+    // 处理陈旧条目的重新验证应答：任意响应码的可缓存应答都写回缓存，CNAME 链超限时移除陈旧条目；
+    // 返回缓存是否已更新（不可缓存的临时故障应答保留陈旧条目）
+    async fn refresh(&self, request: &Message, mut response: Message) -> bool {
+        if !self.prepare(request, &mut response) {
+            self.cache.remove(request).await;
+            return true;
+        }
+        self.cache.is_cacheable(&response) && self.insert(request, response).await
+    }
+}
 
 // DNS 请求处理器
 pub struct RequestHandler {
     // DNS 缓存
     cache: Arc<DnsCache>,
-    // 路由引擎
-    router: Arc<Router>,
+    // 路由引擎（支持热重载）
+    router: ArcSwap<Router>,
+    // 重载宽限期内的旧路由引擎
+    previous_router: ArcSwapOption<PreviousRouter>,
+    // 规则重载宽限期
+    reload_grace_period: Duration,
+    // 严格白名单模式：未命中任何规则的查询返回 REFUSED
+    strict_allowlist: bool,
     // 上游管理器
     upstream: Arc<UpstreamManager>,
+    // 客户端查询统计
+    client_stats: Arc<ClientStats>,
+    // 响应处理配置
+    response_config: ResponseConfig,
+    // 允许的查询类型（为空时不限制）
+    allowed_query_types: HashSet<RecordType>,
+    // 拒绝的查询类型
+    denied_query_types: HashSet<RecordType>,
+    // 本地 PTR 映射：反向解析名称到主机名
+    local_ptr: HashMap<Name, Name>,
+    // 仅有 IPv4 地址的域名（含子域名），其 AAAA 查询直接返回 NODATA
+    empty_aaaa_domains: HashSet<Name>,
+    // DNS64 合成器
+    dns64: Option<Dns64>,
+    // DNSSEC 验证器
+    dnssec: Option<DnssecValidator>,
+    // DNS 重绑定防护
+    rebind: Option<Arc<RebindGuard>>,
+    // 伴随查询预取并发许可（未启用时为空）
+    prefetch_permits: Option<Arc<Semaphore>>,
+    // 是否与主查询并发转发 A/AAAA 伴随查询
+    coalesce_companion: bool,
+    // 是否缓存本地拦截响应
+    cache_blocked: bool,
+    // 影子上游
+    shadow: Option<Shadow>,
+    // 拦截日志节流器
+    block_log: LogThrottle,
+    // 是否向上游传递 traceparent 头
+    propagate_trace: bool,
+    // 延迟预算：总处理时长超过该值时计入 slo_breach_total
+    latency_budget: Option<Duration>,
+    // dnstap 查询/响应日志
+    dnstap: Option<DnstapLogger>,
 }
 
 impl RequestHandler {
@@ -22,13 +242,263 @@ impl RequestHandler {
     pub fn new(cache: Arc<DnsCache>, router: Arc<Router>, upstream: Arc<UpstreamManager>) -> Self {
         Self {
             cache,
-            router,
+            router: ArcSwap::new(router),
+            previous_router: ArcSwapOption::empty(),
+            reload_grace_period: Duration::ZERO,
+            strict_allowlist: false,
             upstream,
+            client_stats: Arc::new(ClientStats::default()),
+            response_config: ResponseConfig::default(),
+            allowed_query_types: HashSet::new(),
+            denied_query_types: HashSet::new(),
+            local_ptr: HashMap::new(),
+            empty_aaaa_domains: HashSet::new(),
+            dns64: None,
+            dnssec: None,
+            rebind: None,
+            prefetch_permits: None,
+            coalesce_companion: false,
+            cache_blocked: false,
+            shadow: None,
+            block_log: LogThrottle::new(Duration::from_secs(
+                log_throttle_limits::DEFAULT_BLOCK_LOG_INTERVAL,
+            )),
+            propagate_trace: false,
+            latency_budget: None,
+            dnstap: None,
+        }
+    }
+
+    // 设置响应处理配置
+    pub fn with_response_config(mut self, response_config: ResponseConfig) -> Self {
+        self.block_log = LogThrottle::new(Duration::from_secs(response_config.block_log_interval));
+        let parse_types = |types: &[String]| -> HashSet<RecordType> {
+            types.iter().filter_map(|t| parse_query_type(t)).collect()
+        };
+        self.allowed_query_types = parse_types(&response_config.allowed_query_types);
+        self.denied_query_types = parse_types(&response_config.denied_query_types);
+        self.local_ptr = response_config
+            .local_ptr
+            .iter()
+            .filter_map(|(key, target)| {
+                let mut target = Name::from_str(target).ok()?;
+                target.set_fqdn(true);
+                Some((parse_ptr_name(key)?, target))
+            })
+            .collect();
+        self.response_config = response_config;
+        self
+    }
+
+    // 启用 DNS64 合成
+    pub fn with_dns64(mut self, dns64: Dns64) -> Self {
+        self.dns64 = Some(dns64);
+        self
+    }
+
+    // 启用 DNSSEC 验证
+    pub fn with_dnssec(mut self, dnssec: DnssecValidator) -> Self {
+        self.dnssec = Some(dnssec);
+        self
+    }
+
+    // 启用 DNS 重绑定防护
+    pub fn with_rebind_protection(mut self, rebind: RebindGuard) -> Self {
+        self.rebind = Some(Arc::new(rebind));
+        self
+    }
+
+    // 设置是否预取 A/AAAA 伴随查询
+    pub fn with_prefetch_companion(mut self, enabled: bool) -> Self {
+        self.prefetch_permits =
+            enabled.then(|| Arc::new(Semaphore::new(cache_limits::MAX_PREFETCH_CONCURRENCY)));
+        self
+    }
+
+    // 设置是否与主查询并发转发 A/AAAA 伴随查询（同一上游服务器）
+    pub fn with_coalesce_companion(mut self, enabled: bool) -> Self {
+        self.coalesce_companion = enabled;
+        self
+    }
+
+    // 设置是否缓存本地拦截响应
+    pub fn with_cache_blocked(mut self, enabled: bool) -> Self {
+        self.cache_blocked = enabled;
+        self
+    }
+
+    // 启用影子上游
+    pub fn with_shadow(mut self, config: &ShadowConfig) -> Self {
+        self.shadow = Some(Shadow {
+            group: config.group.clone(),
+            permits: Arc::new(Semaphore::new(config.max_concurrency)),
+        });
+        self
+    }
+
+    // 设置请求追踪配置
+    pub fn with_tracing(mut self, config: &TracingConfig) -> Self {
+        self.propagate_trace = config.propagate;
+        self
+    }
+
+    // 设置延迟预算
+    pub fn with_latency_budget(mut self, budget: Duration) -> Self {
+        self.latency_budget = Some(budget);
+        self
+    }
+
+    // 设置 dnstap 记录器，记录客户端查询与响应
+    pub fn with_dnstap(mut self, dnstap: DnstapLogger) -> Self {
+        self.dnstap = Some(dnstap);
+        self
+    }
+
+    // 设置规则重载宽限期
+    pub fn with_reload_grace_period(mut self, grace_period: Duration) -> Self {
+        self.reload_grace_period = grace_period;
+        self
+    }
+
+    // 设置严格白名单模式
+    pub fn with_strict_allowlist(mut self, strict_allowlist: bool) -> Self {
+        self.strict_allowlist = strict_allowlist;
+        self
+    }
+
+    // 设置仅有 IPv4 地址的域名，其 AAAA 查询直接返回 NODATA
+    pub fn with_empty_aaaa_domains(mut self, domains: &[String]) -> Self {
+        self.empty_aaaa_domains = domains
+            .iter()
+            .filter_map(|domain| {
+                let mut name = Name::from_str(domain).ok()?;
+                name.set_fqdn(true);
+                Some(name)
+            })
+            .collect();
+        self
+    }
+
+    // 获取当前路由引擎
+    pub fn router(&self) -> Arc<Router> {
+        self.router.load_full()
+    }
+
+    // 替换路由引擎；宽限期内新路由无匹配时回退到旧路由
+    pub fn reload_router(&self, router: Arc<Router>) {
+        let previous = self.router.swap(router);
+        if self.reload_grace_period.is_zero() {
+            self.previous_router.store(None);
+        } else {
+            self.previous_router.store(Some(Arc::new(PreviousRouter {
+                router: previous,
+                expires_at: Instant::now() + self.reload_grace_period,
+            })));
         }
     }
 
+    // 在当前路由中查找匹配；无匹配时在重载宽限期内回退到旧路由
+    fn match_route(&self, query_name: &Name) -> Result<crate::router::RouteMatch, AppError> {
+        let result = self.router.load().find_match(query_name);
+        if !matches!(result, Err(AppError::NoRouteMatch(_))) {
+            return result;
+        }
+        let previous = self.previous_router.load();
+        match previous.as_deref() {
+            Some(previous) if Instant::now() < previous.expires_at => {
+                debug!(
+                    "No route match in reloaded rules, falling back to previous rules: {}",
+                    query_name.to_utf8()
+                );
+                previous.router.find_match(query_name)
+            }
+            Some(_) => {
+                // 宽限期已过，释放旧路由（期间若发生新的重载则保留新的旧路由）
+                self.previous_router.compare_and_swap(&previous, None);
+                result
+            }
+            None => result,
+        }
+    }
+
+    // 是否透传未知的 EDNS 选项
+    pub fn edns_passthrough(&self) -> bool {
+        self.response_config.edns_passthrough
+    }
+
+    // 获取客户端查询统计
+    pub fn client_stats(&self) -> Arc<ClientStats> {
+        self.client_stats.clone()
+    }
+
+    // 记录客户端查询
+    pub fn record_client(&self, client: IpAddr) {
+        self.client_stats.record(client);
+    }
+
     // 处理 DNS 请求
     pub async fn handle_request(&self, request: &Message) -> Result<Message, AppError> {
+        self.handle_request_with_context(request, &RequestContext::default())
+            .await
+    }
+
+    // 处理带有请求上下文的 DNS 请求
+    pub async fn handle_request_with_context(
+        &self,
+        request: &Message,
+        context: &RequestContext,
+    ) -> Result<Message, AppError> {
+        // 每个请求一个追踪 span，携带客户端地址与追踪 ID
+        let span = debug_span!(
+            "dns_request",
+            id = request.id(),
+            client = ?context.client_addr,
+            trace_id = context.trace_id().unwrap_or_default(),
+        );
+        let start_time = Instant::now();
+        let query_time = SystemTime::now();
+        let peer = DnstapPeer {
+            client: context.client_addr,
+            protocol: context.protocol,
+        };
+        if let Some(dnstap) = &self.dnstap {
+            dnstap.log_query(peer, request, query_time);
+        }
+        let mut timings = PhaseTimings::default();
+        let result = self
+            .process_request(request, context, &mut timings)
+            .instrument(span)
+            .await;
+        self.record_slo_breach(start_time.elapsed(), &timings);
+        if let (Some(dnstap), Ok(response)) = (&self.dnstap, &result) {
+            dnstap.log_response(peer, request, query_time, response, SystemTime::now());
+        }
+        result
+    }
+
+    // 总处理时长超过延迟预算时，按耗时最长的阶段计入超限指标
+    fn record_slo_breach(&self, elapsed: Duration, timings: &PhaseTimings) {
+        let Some(budget) = self.latency_budget else {
+            return;
+        };
+        if elapsed <= budget {
+            return;
+        }
+        let phase = timings.dominant_phase();
+        debug!(
+            "Request exceeded latency budget ({:?} > {:?}), dominant phase: {}",
+            elapsed, budget, phase
+        );
+        metrics::backend().increment(CounterMetric::SloBreaches, &[phase]);
+    }
+
+    // 处理 DNS 请求：缓存、路由、转发
+    async fn process_request(
+        &self,
+        request: &Message,
+        context: &RequestContext,
+        timings: &mut PhaseTimings,
+    ) -> Result<Message, AppError> {
         // 记录请求开始时间
         let start_time = Instant::now();
 
@@ -39,10 +509,10 @@ impl RequestHandler {
         let query_class = query.query_class();
 
         // 记录查询类型指标
-        METRICS
-            .dns_query_type_total()
-            .with_label_values(&[query_type.to_string().as_str()])
-            .inc();
+        metrics::backend().increment(
+            CounterMetric::DnsQueryTypes,
+            &[query_type.to_string().as_str()],
+        );
 
         debug!(
             "Processing DNS query: {} ({} {})",
@@ -51,49 +521,355 @@ impl RequestHandler {
             query_class
         );
 
+        // 按查询类型策略拒绝查询，不参与缓存与路由；区域传送返回 NOTIMP
+        if !self.query_type_permitted(query_type) {
+            let rcode = if matches!(query_type, RecordType::AXFR | RecordType::IXFR) {
+                ResponseCode::NotImp
+            } else {
+                ResponseCode::Refused
+            };
+            debug!(
+                "Rejecting disallowed query type {} for {} with {}",
+                query_type,
+                query_name.to_utf8(),
+                rcode
+            );
+            let response = self.create_error_response(request, rcode)?;
+            return Ok(self.finalize_response(response, context));
+        }
+
+        // 根域名或仅含顶级域名的查询按配置直接应答，不参与缓存与路由
+        if query_name.num_labels() <= 1 {
+            if let Some(rcode) = self.response_config.root_tld_action.response_code() {
+                debug!(
+                    "Answering root/TLD-only query {} with {}",
+                    query_name.to_utf8(),
+                    rcode
+                );
+                let response = self.create_error_response(request, rcode)?;
+                return Ok(self.finalize_response(response, context));
+            }
+        }
+
+        // 本地 PTR 映射直接应答，不参与缓存与路由
+        if query_type == RecordType::PTR {
+            if let Some(target) = self.local_ptr.get(query_name) {
+                debug!(
+                    "Answering PTR {} locally with {}",
+                    query_name.to_utf8(),
+                    target.to_utf8()
+                );
+                let mut response = self.create_error_response(request, ResponseCode::NoError)?;
+                response.set_authoritative(true);
+                response.add_answer(Record::from_rdata(
+                    query_name.clone(),
+                    response_limits::LOCAL_PTR_TTL,
+                    RData::PTR(PTR(target.clone())),
+                ));
+                return Ok(self.finalize_response(response, context));
+            }
+        }
+
+        // 已知仅有 IPv4 地址的域名，AAAA 查询直接返回 NODATA，省去一次上游往返
+        if query_type == RecordType::AAAA && self.is_empty_aaaa_domain(query_name) {
+            debug!(
+                "Synthesizing empty AAAA response for {}",
+                query_name.to_utf8()
+            );
+            let response = self.create_empty_aaaa_response(request)?;
+            return Ok(self.finalize_response(response, context));
+        }
+
+        // 客户端网段规则优先匹配；命中时应答因客户端而异，缓存条目按网段规则组隔离
+        let phase_time = Instant::now();
+        let client_route = context
+            .client_addr
+            .and_then(|addr| self.router.load().find_client_match(query_name, addr.ip()));
+        timings.routing = phase_time.elapsed();
+
         // 尝试从缓存获取响应
-        if let Some(response) = self
-            .check_cache(request, query_name, query_type, &start_time)
-            .await
-        {
-            return Ok(response);
+        let phase_time = Instant::now();
+        let scope = client_route
+            .as_ref()
+            .and_then(|route_match| route_match.client_scope.as_ref());
+        let cached = self
+            .check_cache(request, query_name, query_type, &start_time, scope)
+            .await;
+        timings.cache = phase_time.elapsed();
+        if let Some(response) = cached {
+            return Ok(self.finalize_response(response, context));
         }
 
-        // 查找路由规则
-        let route_match = self.find_route_match(query_name).await?;
+        // 查找路由规则；严格白名单模式下未命中任何规则的查询直接拒绝（不缓存）
+        let phase_time = Instant::now();
+        let route_result = match client_route {
+            Some(route_match) => Ok(route_match),
+            None => self.find_route_match(query_name).await,
+        };
+        timings.routing += phase_time.elapsed();
+        let mut route_match = match route_result {
+            Ok(route_match) => route_match,
+            Err(AppError::NoRouteMatch(_)) if self.strict_allowlist => {
+                debug!(
+                    "Refusing unlisted domain in strict allowlist mode: {}",
+                    query_name.to_utf8()
+                );
+                let response = self.create_error_response(request, ResponseCode::Refused)?;
+                return Ok(self.finalize_response(response, context));
+            }
+            Err(AppError::TooManyLabels(_)) => {
+                debug!(
+                    "Refusing query name with too many labels: {}",
+                    query_name.to_utf8()
+                );
+                metrics::backend().increment(
+                    CounterMetric::DnsRequestErrors,
+                    &[error_labels::TOO_MANY_LABELS],
+                );
+                let response = self.create_error_response(request, ResponseCode::Refused)?;
+                return Ok(self.finalize_response(response, context));
+            }
+            Err(e) => return Err(e),
+        };
 
         // 根据路由动作处理请求
-        let response = match route_match.action {
-            RouteAction::Forward => {
-                self.handle_forward(request, &route_match, query_name)
-                    .await?
+        let phase_time = Instant::now();
+        let mut response = match route_match.action {
+            // allow 规则只用于豁免拦截规则，路由引擎返回的匹配按转发处理
+            RouteAction::Forward | RouteAction::Allow => {
+                // 按权重选定本次查询的目标上游组，后续 DNSSEC/DNS64 查询使用同一组
+                route_match.select_weighted_target();
+                if let Some(group) = &route_match.target {
+                    let _ = context.upstream_group.set(group.clone());
+                }
+                let forward_context = ForwardContext {
+                    client: context.client_addr.map(|addr| addr.ip()),
+                    traceparent: context
+                        .traceparent
+                        .as_deref()
+                        .filter(|_| self.propagate_trace),
+                };
+                let response = self
+                    .handle_forward(request, &route_match, query_name, forward_context)
+                    .await?;
+                if !self.cname_chain_within_limit(&response) {
+                    let mut response =
+                        self.create_error_response(request, ResponseCode::ServFail)?;
+                    self.attach_extended_error(
+                        request,
+                        &mut response,
+                        extended_errors::OTHER,
+                        "CNAME chain too long",
+                    );
+                    response
+                } else if let Some(response) =
+                    self.apply_dnssec(request, response, &route_match).await
+                {
+                    let response = self.flatten_aname(request, response, &route_match).await;
+                    let response = self.apply_dns64(request, response, &route_match).await;
+                    self.prefetch_companion(request, &response, &route_match);
+                    response
+                } else {
+                    let mut response =
+                        self.create_error_response(request, ResponseCode::ServFail)?;
+                    self.attach_extended_error(
+                        request,
+                        &mut response,
+                        extended_errors::DNSSEC_BOGUS,
+                        "DNSSEC validation failed",
+                    );
+                    response
+                }
             }
             RouteAction::Block => {
-                debug!("Blocking domain: {}", query_name.to_utf8());
-                self.create_error_response(request, ResponseCode::NXDomain)?
+                self.log_blocked(query_name);
+                let mut response =
+                    if self.response_config.block_explain && query_type == RecordType::TXT {
+                        self.create_block_explanation(request, &route_match)?
+                    } else {
+                        self.create_block_response(request)?
+                    };
+                self.attach_extended_error(
+                    request,
+                    &mut response,
+                    extended_errors::FILTERED,
+                    &format!(
+                        "Blocked by {} rule '{}'",
+                        route_match.rule_type, route_match.pattern
+                    ),
+                );
+                response
             }
         };
+        if route_match.action != RouteAction::Block {
+            timings.upstream = phase_time.elapsed();
+        }
 
-        // 缓存响应
-        self.cache_response(request, response.clone(), query_name)
-            .await;
+        // DNS 重绑定防护：处理公网域名应答中的私有地址
+        if let Some(rebind) = &self.rebind {
+            let filtered = rebind.filter(&mut response);
+            if filtered > 0 {
+                debug!(
+                    "Rebind protection filtered {} private address(es) for {}",
+                    filtered,
+                    query_name.to_utf8()
+                );
+            }
+        }
+
+        // 将应答记录的 TTL 统一为最小值
+        if self.response_config.normalize_ttl {
+            normalize_answer_ttls(&mut response);
+        }
+
+        // 缓存响应（SERVFAIL/REFUSED 是否缓存由 cache.servfail_ttl 决定）
+        // 本地拦截响应重新匹配规则的开销很小，默认不缓存，使规则重载后立即生效
+        if route_match.action != RouteAction::Block || self.cache_blocked {
+            let local = route_match.action == RouteAction::Block;
+            let scope = route_match.client_scope.as_ref();
+            self.cache_response(request, response.clone(), query_name, local, scope)
+                .await;
+        }
 
         // 记录请求处理时间
         let duration = start_time.elapsed();
-        METRICS
-            .dns_request_duration_seconds()
-            .with_label_values(&[processing_labels::RESOLVED, query_type.to_string().as_str()])
-            .observe(duration.as_secs_f64());
-
-        info!(
-            "DNS request processed in {:?} - {}",
-            duration,
-            query_name.to_utf8()
+        metrics::backend().observe(
+            HistogramMetric::DnsRequestDuration,
+            &[processing_labels::RESOLVED, query_type.to_string().as_str()],
+            duration.as_secs_f64(),
         );
 
+        // 拦截请求已由节流日志记录，避免逐条输出
+        if route_match.action == RouteAction::Block {
+            debug!(
+                "DNS request processed in {:?} - {}",
+                duration,
+                query_name.to_utf8()
+            );
+        } else {
+            info!(
+                "DNS request processed in {:?} - {}",
+                duration,
+                query_name.to_utf8()
+            );
+        }
+
+        Ok(self.finalize_response(response, context))
+    }
+
+    // 查询类型是否被允许：拒绝列表优先，允许列表为空时不限制
+    fn query_type_permitted(&self, query_type: RecordType) -> bool {
+        !self.denied_query_types.contains(&query_type)
+            && (self.allowed_query_types.is_empty()
+                || self.allowed_query_types.contains(&query_type))
+    }
+
+    // 记录拦截日志：同一域名首次拦截时记录，之后按间隔输出汇总
+    fn log_blocked(&self, query_name: &Name) {
+        let domain = query_name.to_utf8();
+        match self.block_log.record(&domain, rule_action_labels::BLOCK) {
+            LogDecision::First => info!("Blocking domain: {}", domain),
+            LogDecision::Summary { count, elapsed } => {
+                info!("Blocked {} {} times in last {:?}", domain, count, elapsed)
+            }
+            LogDecision::Suppressed => debug!("Blocking domain: {}", domain),
+        }
+    }
+
+    // 为被拦截域名的 TXT 查询生成说明拦截原因（匹配的规则类型与模式）的应答
+    fn create_block_explanation(
+        &self,
+        request: &Message,
+        route_match: &crate::router::RouteMatch,
+    ) -> Result<Message, AppError> {
+        let mut response = self.create_error_response(request, ResponseCode::NoError)?;
+        if let Some(query) = request.queries().first() {
+            let explanation = format!(
+                "Blocked by load-ants: {} rule '{}'",
+                route_match.rule_type, route_match.pattern
+            );
+            response.add_answer(Record::from_rdata(
+                query.name().clone(),
+                self.response_config.block_ttl,
+                RData::TXT(TXT::new(vec![explanation])),
+            ));
+        }
         Ok(response)
     }
 
+    // 为被拦截域名生成 NXDOMAIN 应答，权威部分附带 TTL 为 block_ttl 的 SOA 记录，
+    // 使客户端的否定缓存时长（RFC 2308）跟随配置，策略变更后尽快重新查询
+    fn create_block_response(&self, request: &Message) -> Result<Message, AppError> {
+        let mut response = self.create_error_response(request, ResponseCode::NXDomain)?;
+        if let Some(query) = request.queries().first() {
+            let block_ttl = self.response_config.block_ttl;
+            let soa = SOA::new(
+                Name::from_ascii(response_limits::BLOCK_SOA_MNAME)?,
+                Name::from_ascii(response_limits::BLOCK_SOA_RNAME)?,
+                1,
+                response_limits::BLOCK_SOA_REFRESH,
+                response_limits::BLOCK_SOA_RETRY,
+                response_limits::BLOCK_SOA_EXPIRE,
+                block_ttl,
+            );
+            response.add_name_server(Record::from_rdata(
+                query.name().clone(),
+                block_ttl,
+                RData::SOA(soa),
+            ));
+        }
+        Ok(response)
+    }
+
+    // 名称或其任一上级域名是否配置为仅有 IPv4 地址
+    fn is_empty_aaaa_domain(&self, name: &Name) -> bool {
+        if self.empty_aaaa_domains.is_empty() {
+            return false;
+        }
+        let mut name = name.clone();
+        loop {
+            if self.empty_aaaa_domains.contains(&name) {
+                return true;
+            }
+            if name.is_root() {
+                return false;
+            }
+            name = name.base_name();
+        }
+    }
+
+    // 生成 NODATA 应答（NOERROR 且无应答记录），权威部分附带 SOA 记录以便客户端按 RFC 2308 缓存
+    fn create_empty_aaaa_response(&self, request: &Message) -> Result<Message, AppError> {
+        let mut response = self.create_error_response(request, ResponseCode::NoError)?;
+        if let Some(query) = request.queries().first() {
+            let soa = SOA::new(
+                Name::from_ascii(response_limits::BLOCK_SOA_MNAME)?,
+                Name::from_ascii(response_limits::BLOCK_SOA_RNAME)?,
+                1,
+                response_limits::BLOCK_SOA_REFRESH,
+                response_limits::BLOCK_SOA_RETRY,
+                response_limits::BLOCK_SOA_EXPIRE,
+                response_limits::EMPTY_AAAA_TTL,
+            );
+            response.add_name_server(Record::from_rdata(
+                query.name().clone(),
+                response_limits::EMPTY_AAAA_TTL,
+                RData::SOA(soa),
+            ));
+        }
+        Ok(response)
+    }
+
+    // 响应返回客户端前的最终处理
+    fn finalize_response(&self, mut response: Message, context: &RequestContext) -> Message {
+        if self.response_config.answer_sort == AnswerSort::Subnet {
+            if let Some(client_addr) = context.client_addr {
+                sort_answers_by_subnet(response.answers_mut(), client_addr.ip());
+            }
+        }
+        response
+    }
+
     // 验证请求有效性并获取查询
     fn validate_request<'a>(
         &self,
@@ -109,10 +885,10 @@ impl RequestHandler {
             Some(q) => Ok(q),
             None => {
                 // 记录错误指标
-                METRICS
-                    .dns_request_errors_total()
-                    .with_label_values(&[error_labels::EMPTY_QUERY])
-                    .inc();
+                metrics::backend().increment(
+                    CounterMetric::DnsRequestErrors,
+                    &[error_labels::EMPTY_QUERY],
+                );
                 Err(AppError::Internal("Empty query".to_string()))
             }
         }
@@ -125,18 +901,37 @@ impl RequestHandler {
         query_name: &hickory_proto::rr::Name,
         query_type: hickory_proto::rr::RecordType,
         start_time: &Instant,
+        scope: Option<&Arc<str>>,
     ) -> Option<Message> {
         if !self.cache.is_enabled() {
             return None;
         }
 
+        // 自行验证 DNSSEC 的客户端（CD=1）直接查询上游
+        if self.cache.should_bypass(request) {
+            debug!("Bypassing cache for CD query: {}", query_name.to_utf8());
+            return None;
+        }
+
         // 检查是否有查询
         if request.queries().is_empty() {
             return None;
         }
 
         let cache_check_time = Instant::now();
-        if let Some(cached_response) = self.cache.get(request).await {
+        let cached = match self.cache.get_with_staleness_in(request, scope).await {
+            // 陈旧条目在后台重新验证期间直接返回，无法重新验证时按未命中处理（客户端网段作用域的条目不在后台重新验证）
+            Some((response, true))
+                if scope.is_none() && self.revalidate_stale(request, query_name) =>
+            {
+                self.cache.record_stale_hit();
+                Some(response)
+            }
+            Some((_, true)) => None,
+            Some((response, false)) => Some(response),
+            None => None,
+        };
+        if let Some(cached_response) = cached {
             debug!("Cache hit: {} ({})", query_name.to_utf8(), query_type);
 
             // 设置响应ID与请求ID相匹配
@@ -145,10 +940,11 @@ impl RequestHandler {
 
             // 记录请求处理时间
             let duration = start_time.elapsed();
-            METRICS
-                .dns_request_duration_seconds()
-                .with_label_values(&[processing_labels::CACHED, query_type.to_string().as_str()])
-                .observe(duration.as_secs_f64());
+            metrics::backend().observe(
+                HistogramMetric::DnsRequestDuration,
+                &[processing_labels::CACHED, query_type.to_string().as_str()],
+                duration.as_secs_f64(),
+            );
 
             info!(
                 "Cache hit: {} processed in {:?}",
@@ -159,10 +955,7 @@ impl RequestHandler {
             return Some(response);
         } else {
             // 记录缓存未命中指标
-            METRICS
-                .cache_operations_total()
-                .with_label_values(&[cache_labels::MISS])
-                .inc();
+            metrics::backend().increment(CounterMetric::CacheOperations, &[cache_labels::MISS]);
             info!(
                 "Cache check for {} took {:?}",
                 query_name.to_utf8(),
@@ -179,16 +972,20 @@ impl RequestHandler {
         query_name: &hickory_proto::rr::Name,
     ) -> Result<crate::router::RouteMatch, AppError> {
         let route_match_time = Instant::now();
-        let route_match = match self.router.find_match(query_name) {
+        let route_match = match self.match_route(query_name) {
             Ok(m) => m,
+            // 严格白名单模式下未命中属于预期行为，交由调用方拒绝
+            Err(e @ AppError::NoRouteMatch(_)) if self.strict_allowlist => return Err(e),
+            // 标签数超限属于防护行为，交由调用方拒绝
+            Err(e @ AppError::TooManyLabels(_)) => return Err(e),
             Err(e) => {
                 warn!("Route matching failed: {} - {}", query_name.to_utf8(), e);
 
                 // 记录路由失败指标
-                METRICS
-                    .dns_request_errors_total()
-                    .with_label_values(&[error_labels::ROUTE_ERROR])
-                    .inc();
+                metrics::backend().increment(
+                    CounterMetric::DnsRequestErrors,
+                    &[error_labels::ROUTE_ERROR],
+                );
 
                 return Err(AppError::Internal(format!("Route matching failed: {}", e)));
             }
@@ -217,6 +1014,7 @@ impl RequestHandler {
         request: &Message,
         route_match: &crate::router::RouteMatch,
         query_name: &hickory_proto::rr::Name,
+        forward_context: ForwardContext<'_>,
     ) -> Result<Message, AppError> {
         // 获取目标上游组
         let target_group = match &route_match.target {
@@ -228,54 +1026,561 @@ impl RequestHandler {
                 );
 
                 // 记录错误指标
-                METRICS
-                    .dns_request_errors_total()
-                    .with_label_values(&[error_labels::MISSING_TARGET])
-                    .inc();
+                metrics::backend().increment(
+                    CounterMetric::DnsRequestErrors,
+                    &[error_labels::MISSING_TARGET],
+                );
 
                 return self.create_error_response(request, ResponseCode::ServFail);
             }
         };
 
-        // 转发到上游
+        // 未启用透传时，转发前移除未知的 EDNS 选项
+        let stripped;
+        let upstream_request =
+            if !self.response_config.edns_passthrough && has_unknown_edns_options(request) {
+                let mut message = request.clone();
+                strip_unknown_edns_options(&mut message);
+                stripped = message;
+                &stripped
+            } else {
+                request
+            };
+
+        // 依次尝试目标上游组与备用上游组，直到得到可用的应答或没有更多上游组
+        let fallback = route_match.fallback.as_deref();
+        let mut fallback_groups = fallback
+            .into_iter()
+            .flat_map(|f| f.groups.iter().map(String::as_str));
+        let mut group = target_group.as_str();
+        let (target_group, result) = loop {
+            let result = self
+                .forward_to_group(
+                    request,
+                    upstream_request,
+                    group,
+                    query_name,
+                    forward_context,
+                    route_match.client_scope.is_none(),
+                )
+                .await;
+            let Some(next) = fallback_groups.next() else {
+                break (group, result);
+            };
+            match &result {
+                Ok(response)
+                    if response.response_code() == ResponseCode::NXDomain
+                        && fallback.is_some_and(|f| f.on_nxdomain) =>
+                {
+                    debug!(
+                        "Upstream group {} returned NXDOMAIN for {}, trying fallback group {}",
+                        group,
+                        query_name.to_utf8(),
+                        next
+                    );
+                }
+                Ok(_) => break (group, result),
+                Err(e) => {
+                    warn!(
+                        "Upstream request failed: {} - {}, trying fallback group {}",
+                        group, e, next
+                    );
+                }
+            }
+            group = next;
+        };
+
+        match result {
+            Ok(mut response) => {
+                if !self.response_config.edns_passthrough {
+                    strip_unknown_edns_options(&mut response);
+                }
+                self.mirror_to_shadow(upstream_request, &response, target_group);
+                Ok(response)
+            }
+            Err(e) => {
+                error!("Upstream request failed: {} - {}", target_group, e);
+
+                // 记录错误指标
+                metrics::backend().increment(
+                    CounterMetric::DnsRequestErrors,
+                    &[error_labels::UPSTREAM_ERROR],
+                );
+
+                let (rcode, info_code, extra_text) = if e.is_timeout() {
+                    (
+                        self.response_config.upstream_timeout_rcode.response_code(),
+                        extended_errors::NO_REACHABLE_AUTHORITY,
+                        "Upstream request timed out",
+                    )
+                } else {
+                    (
+                        ResponseCode::ServFail,
+                        extended_errors::NETWORK_ERROR,
+                        "Upstream request failed",
+                    )
+                };
+                let mut response = self.create_error_response(request, rcode)?;
+                self.attach_extended_error(request, &mut response, info_code, extra_text);
+                Ok(response)
+            }
+        }
+    }
+
+    // 转发到指定上游组；启用合并时与伴随查询并发发往同一上游服务器（应答因客户端而异时不合并）
+    async fn forward_to_group(
+        &self,
+        request: &Message,
+        upstream_request: &Message,
+        target_group: &str,
+        query_name: &hickory_proto::rr::Name,
+        forward_context: ForwardContext<'_>,
+        cache_companion: bool,
+    ) -> Result<Message, AppError> {
         let upstream_time = Instant::now();
-        let result = self.upstream.forward(request, target_group).await;
+        let companion = (self.coalesce_companion && cache_companion)
+            .then(|| self.companion_request(request))
+            .flatten();
+        let result = match companion {
+            Some(companion) => {
+                let (result, companion_result) = self
+                    .upstream
+                    .forward_pair(upstream_request, &companion, target_group, forward_context)
+                    .await;
+                // 伴随应答在后台写入缓存，不延迟主查询的响应
+                match companion_result {
+                    Ok(response) => {
+                        let store = self.background_store();
+                        tokio::spawn(async move { store.store(&companion, response).await });
+                    }
+                    Err(e) => debug!("Coalesced companion query failed: {}", e),
+                }
+                result
+            }
+            None => {
+                self.upstream
+                    .forward_with_context(upstream_request, target_group, forward_context)
+                    .await
+            }
+        };
         info!(
             "Upstream forwarding to {} for {} took {:?}",
             target_group,
             query_name.to_utf8(),
             upstream_time.elapsed()
         );
+        result
+    }
 
-        match result {
-            Ok(response) => Ok(response),
+    // 将查询副本异步发送到影子上游并比较应答，不影响客户端延迟与返回结果
+    fn mirror_to_shadow(&self, upstream_request: &Message, response: &Message, target_group: &str) {
+        let Some(shadow) = &self.shadow else {
+            return;
+        };
+        if shadow.group == target_group {
+            return;
+        }
+        let Ok(permit) = shadow.permits.clone().try_acquire_owned() else {
+            debug!(
+                "Shadow concurrency limit reached, dropping shadow query to {}",
+                shadow.group
+            );
+            return;
+        };
+
+        let upstream = self.upstream.clone();
+        let group = shadow.group.clone();
+        // 影子上游收到与主上游相同的处理后报文（例如已剥离未知 EDNS 选项）
+        let request = upstream_request.clone();
+        let primary = answer_fingerprint(response);
+        tokio::spawn(async move {
+            let _permit = permit;
+            match upstream.forward(&request, &group).await {
+                Ok(shadow_response) => {
+                    if answer_fingerprint(&shadow_response) != primary {
+                        debug!(
+                            "Shadow group {} diverged for {:?}",
+                            group,
+                            request.queries().first().map(|q| q.name().to_utf8())
+                        );
+                        metrics::backend()
+                            .increment(CounterMetric::ShadowDivergences, &[group.as_str()]);
+                    }
+                }
+                Err(e) => debug!("Shadow query to {} failed: {}", group, e),
+            }
+        });
+    }
+
+    // 构造 A/AAAA 查询的伴随查询（AAAA/A），不需要预取时返回 None
+    fn companion_request(&self, request: &Message) -> Option<Message> {
+        let query = request.queries().first()?;
+        let companion_type = match query.query_type() {
+            RecordType::A => RecordType::AAAA,
+            RecordType::AAAA => RecordType::A,
+            _ => return None,
+        };
+        if !self.cache.is_enabled() || self.cache.should_bypass(request) {
+            return None;
+        }
+        // 需要二次处理（DNSSEC 验证、ANAME 展开、DNS64 合成）的应答不预取，避免缓存未经处理的上游应答
+        if self.dnssec.is_some()
+            || self.response_config.flatten_aname
+            || (companion_type == RecordType::AAAA && self.dns64.is_some())
+        {
+            return None;
+        }
+
+        let mut companion = request.clone();
+        let mut companion_query = Query::query(query.name().clone(), companion_type);
+        companion_query.set_query_class(query.query_class());
+        *companion.queries_mut() = vec![companion_query];
+        if !self.response_config.edns_passthrough {
+            strip_unknown_edns_options(&mut companion);
+        }
+        (!self.cache.contains(&companion)).then_some(companion)
+    }
+
+    // 后台查询应答的缓存写入器
+    fn background_store(&self) -> BackgroundStore {
+        BackgroundStore {
+            cache: self.cache.clone(),
+            rebind: self.rebind.clone(),
+            edns_passthrough: self.response_config.edns_passthrough,
+            normalize_ttl: self.response_config.normalize_ttl,
+            max_cname_chain: self.response_config.max_cname_chain,
+        }
+    }
+
+    // 异步预取 A/AAAA 伴随查询并写入缓存，使客户端随后的伴随查询直接命中缓存
+    fn prefetch_companion(
+        &self,
+        request: &Message,
+        response: &Message,
+        route_match: &crate::router::RouteMatch,
+    ) {
+        let (Some(permits), Some(target_group)) = (&self.prefetch_permits, &route_match.target)
+        else {
+            return;
+        };
+        if route_match.client_scope.is_some() {
+            return;
+        }
+        if response.response_code() != ResponseCode::NoError {
+            return;
+        }
+        let Some(companion) = self.companion_request(request) else {
+            return;
+        };
+        let Ok(permit) = permits.clone().try_acquire_owned() else {
+            let query = &companion.queries()[0];
+            debug!(
+                "Companion prefetch concurrency limit reached, skipping {} {}",
+                query.name().to_utf8(),
+                query.query_type()
+            );
+            return;
+        };
+
+        let upstream = self.upstream.clone();
+        let store = self.background_store();
+        let group = target_group.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            match upstream.forward(&companion, &group).await {
+                Ok(response) => store.store(&companion, response).await,
+                Err(e) => {
+                    let query = &companion.queries()[0];
+                    debug!(
+                        "Companion prefetch for {} {} failed: {}",
+                        query.name().to_utf8(),
+                        query.query_type(),
+                        e
+                    )
+                }
+            }
+        });
+    }
+
+    // 在后台向上游重新验证陈旧的缓存条目，无法重新验证时返回 false
+    fn revalidate_stale(&self, request: &Message, query_name: &Name) -> bool {
+        // 需要二次处理（DNSSEC 验证、ANAME 展开、DNS64 合成）的应答无法在后台重新生成
+        if self.dnssec.is_some() || self.response_config.flatten_aname || self.dns64.is_some() {
+            return false;
+        }
+        let Ok(mut route_match) = self.match_route(query_name) else {
+            return false;
+        };
+        if route_match.action == RouteAction::Block {
+            return false;
+        }
+        route_match.select_weighted_target();
+        let Some(target_group) = route_match.target else {
+            return false;
+        };
+        // 同一条目已有进行中（或刚失败）的重新验证，直接返回陈旧应答
+        if !self.cache.begin_revalidation(request) {
+            return true;
+        }
+
+        // 与正常转发相同，依次尝试目标上游组与备用上游组
+        let fallback = route_match.fallback;
+        let on_nxdomain = fallback.as_ref().is_some_and(|f| f.on_nxdomain);
+        let groups: Vec<String> = std::iter::once(target_group)
+            .chain(fallback.iter().flat_map(|f| f.groups.iter().cloned()))
+            .collect();
+
+        let mut upstream_request = request.clone();
+        if !self.response_config.edns_passthrough {
+            strip_unknown_edns_options(&mut upstream_request);
+        }
+        let upstream = self.upstream.clone();
+        let store = self.background_store();
+        debug!(
+            "Revalidating stale cache entry in background: {}",
+            query_name.to_utf8()
+        );
+        tokio::spawn(async move {
+            let mut result = Err(AppError::Internal("no upstream group".to_string()));
+            for (index, group) in groups.iter().enumerate() {
+                result = upstream.forward(&upstream_request, group).await;
+                let is_last = index + 1 == groups.len();
+                match &result {
+                    Ok(response)
+                        if on_nxdomain
+                            && !is_last
+                            && response.response_code() == ResponseCode::NXDomain => {}
+                    Ok(_) => break,
+                    Err(e) => debug!("Stale cache entry revalidation via {} failed: {}", group, e),
+                }
+            }
+            let refreshed = match result {
+                Ok(response) => store.refresh(&upstream_request, response).await,
+                Err(_) => false,
+            };
+            let label = if refreshed {
+                revalidation_labels::REFRESHED
+            } else {
+                revalidation_labels::FAILED
+            };
+            metrics::backend().increment(CounterMetric::CacheStaleRevalidations, &[label]);
+            store.cache.end_revalidation(&upstream_request, refreshed);
+        });
+        true
+    }
+
+    // DNSSEC：客户端设置 DO 位且未设置 CD 位时验证应答签名并设置 AD 位，验证失败返回 None
+    async fn apply_dnssec(
+        &self,
+        request: &Message,
+        mut response: Message,
+        route_match: &crate::router::RouteMatch,
+    ) -> Option<Message> {
+        let (Some(dnssec), Some(target_group)) = (&self.dnssec, &route_match.target) else {
+            return Some(response);
+        };
+        let dnssec_ok = request
+            .extensions()
+            .as_ref()
+            .is_some_and(|edns| edns.dnssec_ok());
+        if !dnssec_ok || request.checking_disabled() {
+            return Some(response);
+        }
+
+        match dnssec
+            .validate(&response, &self.upstream, target_group)
+            .await
+        {
+            DnssecStatus::Secure => {
+                response.set_authentic_data(true);
+                Some(response)
+            }
+            DnssecStatus::Insecure => {
+                response.set_authentic_data(false);
+                Some(response)
+            }
+            DnssecStatus::Bogus(reason) => {
+                warn!(
+                    "DNSSEC validation failed for {:?}: {}",
+                    request.queries().first().map(|q| q.name().to_utf8()),
+                    reason
+                );
+                metrics::backend().increment(
+                    CounterMetric::DnsRequestErrors,
+                    &[error_labels::DNSSEC_BOGUS],
+                );
+                None
+            }
+        }
+    }
+
+    // ANAME 展开：A/AAAA 查询只返回 ANAME 别名时，解析别名目标并以查询名称返回其地址记录
+    async fn flatten_aname(
+        &self,
+        request: &Message,
+        response: Message,
+        route_match: &crate::router::RouteMatch,
+    ) -> Message {
+        if !self.response_config.flatten_aname {
+            return response;
+        }
+        let Some(target_group) = &route_match.target else {
+            return response;
+        };
+        let Some(query) = request.queries().first() else {
+            return response;
+        };
+        let query_type = query.query_type();
+        if query_type != RecordType::A && query_type != RecordType::AAAA {
+            return response;
+        }
+        // 已有目标类型的地址记录时无需展开
+        if response
+            .answers()
+            .iter()
+            .any(|record| record.record_type() == query_type)
+        {
+            return response;
+        }
+        let Some((alias, alias_ttl)) =
+            response
+                .answers()
+                .iter()
+                .find_map(|record| match record.data() {
+                    Some(RData::ANAME(aname)) if record.name() == query.name() => {
+                        Some((aname.0.clone(), record.ttl()))
+                    }
+                    _ => None,
+                })
+        else {
+            return response;
+        };
+
+        // 以相同类型向同一上游组解析别名目标
+        let mut alias_request = request.clone();
+        let mut alias_query = Query::query(alias.clone(), query_type);
+        alias_query.set_query_class(query.query_class());
+        *alias_request.queries_mut() = vec![alias_query];
+
+        match self.upstream.forward(&alias_request, target_group).await {
+            Ok(alias_response) if !self.cname_chain_within_limit(&alias_response) => response,
+            Ok(alias_response) => {
+                // 地址记录改写为查询名称，TTL 取别名与目标记录中的较小值
+                let records: Vec<Record> = alias_response
+                    .answers()
+                    .iter()
+                    .filter(|record| record.record_type() == query_type)
+                    .map(|record| {
+                        let mut record = record.clone();
+                        record.set_name(query.name().clone());
+                        record.set_ttl(record.ttl().min(alias_ttl));
+                        record
+                    })
+                    .collect();
+                if records.is_empty() {
+                    return response;
+                }
+
+                debug!(
+                    "Flattened ANAME {} -> {} for {}",
+                    query.name().to_utf8(),
+                    alias.to_utf8(),
+                    query_type
+                );
+                let mut flattened = response;
+                *flattened.answers_mut() = records;
+                flattened
+            }
             Err(e) => {
-                error!("Upstream request failed: {} - {}", target_group, e);
+                warn!("ANAME target lookup failed for {}: {}", alias.to_utf8(), e);
+                response
+            }
+        }
+    }
 
-                // 记录错误指标
-                METRICS
-                    .dns_request_errors_total()
-                    .with_label_values(&[error_labels::UPSTREAM_ERROR])
-                    .inc();
+    // DNS64：AAAA 查询无结果时根据 A 记录合成 AAAA
+    async fn apply_dns64(
+        &self,
+        request: &Message,
+        response: Message,
+        route_match: &crate::router::RouteMatch,
+    ) -> Message {
+        let (Some(dns64), Some(target_group)) = (&self.dns64, &route_match.target) else {
+            return response;
+        };
+        let Some(query) = request.queries().first() else {
+            return response;
+        };
+        if query.query_type() != RecordType::AAAA || !Dns64::needs_synthesis(&response) {
+            return response;
+        }
+
+        // 以相同名称向同一上游组发起 A 查询
+        let mut a_request = request.clone();
+        let mut a_query = Query::query(query.name().clone(), RecordType::A);
+        a_query.set_query_class(query.query_class());
+        *a_request.queries_mut() = vec![a_query];
 
-                self.create_error_response(request, ResponseCode::ServFail)
+        match self.upstream.forward(&a_request, target_group).await {
+            Ok(a_response) if !self.cname_chain_within_limit(&a_response) => response,
+            Ok(a_response) => match dns64.synthesize(&response, &a_response) {
+                Some(synthesized) => {
+                    debug!("DNS64 synthesized AAAA for {}", query.name().to_utf8());
+                    synthesized
+                }
+                None => response,
+            },
+            Err(e) => {
+                warn!(
+                    "DNS64 A lookup failed for {}: {}",
+                    query.name().to_utf8(),
+                    e
+                );
+                response
             }
         }
     }
 
-    // 缓存响应
+    // 检查响应中的 CNAME 链是否在允许范围内（出现环路视为超限）
+    fn cname_chain_within_limit(&self, response: &Message) -> bool {
+        let Some(query) = response.queries().first() else {
+            return true;
+        };
+        let limit = self.response_config.max_cname_chain;
+        match cname_chain_length(response.answers(), query.name()) {
+            Some(length) if length <= limit => true,
+            length => {
+                warn!(
+                    "CNAME chain for {} exceeds limit {} ({}), aborting",
+                    query.name().to_utf8(),
+                    limit,
+                    length.map_or_else(|| "loop detected".to_string(), |l| l.to_string())
+                );
+                metrics::backend().increment(
+                    CounterMetric::DnsRequestErrors,
+                    &[error_labels::CNAME_CHAIN_TOO_LONG],
+                );
+                false
+            }
+        }
+    }
+
+    // 缓存响应，local 为 true 表示本地生成的响应（如拦截响应），scope 为客户端网段规则的缓存作用域
     async fn cache_response(
         &self,
         request: &Message,
         response: Message,
         query_name: &hickory_proto::rr::Name,
+        local: bool,
+        scope: Option<&Arc<str>>,
     ) {
-        if !self.cache.is_enabled() {
+        if !self.cache.is_enabled() || self.cache.should_bypass(request) {
             return;
         }
 
         let cache_insert_time = Instant::now();
-        if let Err(e) = self.cache.insert(request, response).await {
+        let result = self.cache.insert_in(request, response, local, scope).await;
+        if let Err(e) = result {
             warn!("Cache insertion failed: {}", e);
         } else {
             info!(
@@ -286,7 +1591,24 @@ impl RequestHandler {
         }
 
         // 更新缓存条目计数
-        METRICS.cache_entries().set(self.cache.len().await as i64);
+        METRICS.cache_entries().set(self.cache.entry_count() as i64);
+    }
+
+    // 启用扩展错误时向本地生成的响应附加 EDE 选项
+    fn attach_extended_error(
+        &self,
+        request: &Message,
+        response: &mut Message,
+        info_code: u16,
+        extra_text: &str,
+    ) {
+        if self.response_config.extended_errors {
+            attach_extended_error(
+                response,
+                request,
+                &ExtendedError::new(info_code, extra_text),
+            );
+        }
     }
 
     // 创建错误响应
@@ -312,6 +1634,144 @@ impl RequestHandler {
     }
 }
 
+// 按客户端子网对地址记录排序：与客户端同一 /24（IPv4）或 /64（IPv6）的地址排在前面
+// 仅调整 A/AAAA 记录之间的相对顺序，CNAME 等其他记录保持原位置
+fn sort_answers_by_subnet(answers: &mut [Record], client: IpAddr) {
+    // 收集地址记录所在位置
+    let positions: Vec<usize> = answers
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| matches!(record.data(), Some(RData::A(_)) | Some(RData::AAAA(_))))
+        .map(|(index, _)| index)
+        .collect();
+    if positions.len() < 2 {
+        return;
+    }
+
+    // 稳定排序，同一优先级内保持原有（可能已随机化的）顺序
+    let mut records: Vec<Record> = positions.iter().map(|&i| answers[i].clone()).collect();
+    records.sort_by_key(|record| !in_client_subnet(record, client));
+
+    for (position, record) in positions.into_iter().zip(records) {
+        answers[position] = record;
+    }
+}
+
+// 生成应答指纹（响应码与忽略 TTL 和顺序的应答记录集合），用于比较影子上游结果
+fn answer_fingerprint(response: &Message) -> (ResponseCode, BTreeSet<String>) {
+    let answers = response
+        .answers()
+        .iter()
+        .filter(|record| record.record_type() != RecordType::RRSIG)
+        .map(|record| {
+            format!(
+                "{} {} {}",
+                record.name().to_lowercase(),
+                record.record_type(),
+                record
+                    .data()
+                    .map(|data| data.to_string())
+                    .unwrap_or_default()
+            )
+        })
+        .collect();
+    (response.response_code(), answers)
+}
+
+// 从查询名称开始沿 CNAME 记录跟随，返回链长度；出现环路时返回 None
+pub fn cname_chain_length(answers: &[Record], name: &Name) -> Option<usize> {
+    let mut visited = HashSet::new();
+    let mut current = name.clone();
+    let mut length = 0;
+
+    while let Some(target) = answers.iter().find_map(|record| match record.data() {
+        Some(RData::CNAME(cname)) if record.name() == &current => Some(cname.0.clone()),
+        _ => None,
+    }) {
+        if !visited.insert(current) {
+            return None;
+        }
+        length += 1;
+        current = target;
+    }
+
+    Some(length)
+}
+
+// 解析 W3C traceparent 头（`version-trace_id-parent_id-flags`），返回 trace-id
+pub fn trace_id_from_traceparent(traceparent: &str) -> Option<&str> {
+    let mut parts = traceparent.split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let parent_id = parts.next()?;
+    let flags = parts.next()?;
+
+    let is_hex = |s: &str, len: usize| {
+        s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    };
+    // 版本 ff 无效；版本 00 不允许额外字段
+    if !is_hex(version, 2) || version == "ff" || (version == "00" && parts.next().is_some()) {
+        return None;
+    }
+    // trace-id 与 parent-id 不能全为 0
+    if !is_hex(trace_id, 32)
+        || !is_hex(parent_id, 16)
+        || !is_hex(flags, 2)
+        || trace_id.bytes().all(|b| b == b'0')
+        || parent_id.bytes().all(|b| b == b'0')
+    {
+        return None;
+    }
+    Some(trace_id)
+}
+
+// 将应答部分所有记录的 TTL 改写为其中的最小值
+fn normalize_answer_ttls(response: &mut Message) {
+    let Some(min_ttl) = response.answers().iter().map(Record::ttl).min() else {
+        return;
+    };
+    for record in response.answers_mut() {
+        record.set_ttl(min_ttl);
+    }
+}
+
+// 判断报文的 OPT 记录中是否包含未知的 EDNS 选项
+fn has_unknown_edns_options(message: &Message) -> bool {
+    message.extensions().as_ref().is_some_and(|edns| {
+        edns.options()
+            .as_ref()
+            .values()
+            .any(|option| matches!(option, EdnsOption::Unknown(..)))
+    })
+}
+
+// 移除报文 OPT 记录中的未知 EDNS 选项，保留已识别的选项
+fn strip_unknown_edns_options(message: &mut Message) {
+    if let Some(edns) = message.extensions_mut() {
+        let unknown: Vec<EdnsCode> = edns
+            .options()
+            .as_ref()
+            .iter()
+            .filter(|(_, option)| matches!(option, EdnsOption::Unknown(..)))
+            .map(|(code, _)| *code)
+            .collect();
+        for code in unknown {
+            edns.options_mut().remove(code);
+        }
+    }
+}
+
+// 判断地址记录是否与客户端处于同一子网
+fn in_client_subnet(record: &Record, client: IpAddr) -> bool {
+    match (record.data(), client) {
+        (Some(RData::A(a)), IpAddr::V4(client)) => a.0.octets()[..3] == client.octets()[..3],
+        (Some(RData::AAAA(aaaa)), IpAddr::V6(client)) => {
+            aaaa.0.segments()[..4] == client.segments()[..4]
+        }
+        _ => false,
+    }
+}
+
 // 处理DNS请求（仅用于测试）
 #[allow(dead_code)]
 pub async fn handle_request(
@@ -320,11 +1780,8 @@ pub async fn handle_request(
 ) -> Result<Message, AppError> {
     debug!("Received DNS request: {:?}", request);
 
-    // 记录DNS请求总数
-    METRICS
-        .dns_requests_total()
-        .with_label_values(&[protocol_labels::UNKNOWN]) // 此函数无法获知请求协议
-        .inc();
+    // 记录DNS请求总数（此函数无法获知请求协议）
+    metrics::backend().increment(CounterMetric::DnsRequests, &[protocol_labels::UNKNOWN]);
 
     let response = handler.handle_request(&request).await?;
 
@@ -3,20 +3,32 @@ pub mod args;
 pub mod balancer;
 pub mod cache;
 pub mod config;
+pub mod connection_limit;
 pub mod r#const;
+pub mod dns64;
+pub mod dnssec;
+pub mod dnstap;
 pub mod doh;
 pub mod error;
+pub mod extended_error;
 pub mod handler;
+pub mod log_throttle;
 pub mod metrics;
+pub mod rebind;
 pub mod remote_rule;
 pub mod router;
 pub mod server;
+pub mod stats;
+pub mod statsd;
 pub mod upstream;
+pub mod warmup;
 
 // 重导出常用组件
 pub use admin::AdminServer;
 pub use args::Args;
-pub use balancer::{LoadBalancer, RandomBalancer, RoundRobinBalancer, WeightedBalancer};
+pub use balancer::{
+    LoadBalancer, RandomBalancer, RoundRobinBalancer, StickyBalancer, WeightedBalancer,
+};
 pub use cache::DnsCache;
 pub use config::Config;
 pub use doh::DoHServer;
@@ -37,5 +49,5 @@ pub use r#const::{
 // 重导出常用配置类型
 pub use config::{
     AdminConfig, CacheConfig, DnsClientConfig, HttpClientConfig, MatchType, RemoteRuleConfig,
-    RouteAction, RouteRuleConfig, ServerConfig, UpstreamGroupConfig,
+    ResponseConfig, RouteAction, RouteRuleConfig, ServerConfig, UpstreamGroupConfig,
 };
@@ -0,0 +1,86 @@
+// src/log_throttle.rs
+
+use crate::r#const::log_throttle_limits;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// 日志节流判定结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogDecision {
+    // 首次出现，应记录完整日志
+    First,
+    // 汇总间隔已到，应记录汇总日志（间隔内出现次数与实际经过的时间）
+    Summary { count: u64, elapsed: Duration },
+    // 已被汇总，不记录日志
+    Suppressed,
+}
+
+// 节流窗口状态
+struct ThrottleWindow {
+    // 窗口开始时间
+    started: Instant,
+    // 窗口内出现次数
+    count: u64,
+}
+
+// 按 (键, 动作) 节流的日志辅助器：首次出现时记录一次，之后每个间隔最多输出一条汇总
+pub struct LogThrottle {
+    // 各键的窗口状态
+    windows: Mutex<HashMap<(String, &'static str), ThrottleWindow>>,
+    // 汇总间隔
+    interval: Duration,
+    // 最大跟踪键数量
+    capacity: usize,
+}
+
+impl LogThrottle {
+    // 创建日志节流器
+    pub fn new(interval: Duration) -> Self {
+        Self::with_capacity(interval, log_throttle_limits::MAX_TRACKED_KEYS)
+    }
+
+    // 创建指定最大跟踪键数量的日志节流器
+    pub fn with_capacity(interval: Duration, capacity: usize) -> Self {
+        Self {
+            windows: Mutex::new(HashMap::new()),
+            interval,
+            capacity: capacity.max(1),
+        }
+    }
+
+    // 记录一次出现并返回是否需要输出日志
+    pub fn record(&self, key: &str, action: &'static str) -> LogDecision {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(window) = windows.get_mut(&(key.to_string(), action)) {
+            window.count += 1;
+            let elapsed = now.duration_since(window.started);
+            if elapsed < self.interval {
+                return LogDecision::Suppressed;
+            }
+            let count = window.count;
+            window.started = now;
+            window.count = 0;
+            return LogDecision::Summary { count, elapsed };
+        }
+
+        // 表满时先清理已过期的窗口，仍然已满则整体重置
+        if windows.len() >= self.capacity {
+            let interval = self.interval;
+            windows.retain(|_, window| now.duration_since(window.started) < interval);
+            if windows.len() >= self.capacity {
+                windows.clear();
+            }
+        }
+        windows.insert(
+            (key.to_string(), action),
+            ThrottleWindow {
+                started: now,
+                count: 0,
+            },
+        );
+        LogDecision::First
+    }
+}
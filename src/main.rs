@@ -1,11 +1,25 @@
 use loadants::{
-    doh::server::DoHServer, metrics::METRICS, r#const::server_defaults, rule_source_labels,
-    rule_type_labels, server::DnsServerConfig, subsystem_names, AdminServer, AppError, Args,
-    Config, DnsCache, DnsServer, MatchType, RequestHandler, Router, UpstreamManager,
+    cache::CacheJanitor,
+    config::{redact_config_source, MetricsBackendKind},
+    dns64::Dns64,
+    dnssec::DnssecValidator,
+    dnstap::{DnstapLogger, DnstapOutput},
+    doh::server::DoHServer,
+    metrics,
+    r#const::server_defaults,
+    rebind::RebindGuard,
+    remote_rule::{MergedRules, RuleReloader},
+    server::{probe_tcp_bind, DnsServerConfig, TcpListenerOptions},
+    stats::RuleSourceStats,
+    statsd::StatsdBackend,
+    subsystem_names,
+    warmup::{Readiness, Warmup},
+    AdminServer, AppError, Args, Config, DnsCache, DnsServer, RequestHandler, UpstreamManager,
 };
 use mimalloc::MiMalloc;
 use std::process;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio_graceful_shutdown::{IntoSubsystem, SubsystemBuilder, Toplevel};
 use tracing::{error, info, warn};
 
@@ -45,9 +59,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("Starting Load Ants DNS UDP/TCP to DoH Proxy");
 
     // 加载配置
-    let config = match Config::from_file(&args.config) {
+    let config = match Config::load(&args.config).await {
         Ok(config) => {
-            info!("Successfully loaded configuration: {:?}", args.config);
+            info!(
+                "Successfully loaded configuration: {}",
+                redact_config_source(&args.config)
+            );
             config
         }
         Err(e) => {
@@ -97,6 +114,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 move |s| async move { doh_server.run(s).await },
             ));
         }
+        // 启动缓存清理子系统
+        if let Some(cache_janitor) = components.cache_janitor {
+            s.start(SubsystemBuilder::new(
+                subsystem_names::CACHE_JANITOR,
+                cache_janitor.into_subsystem(),
+            ));
+        }
+        // 启动 dnstap 输出子系统
+        if let Some(dnstap_output) = components.dnstap_output {
+            s.start(SubsystemBuilder::new(
+                subsystem_names::DNSTAP,
+                dnstap_output.into_subsystem(),
+            ));
+        }
+        // 启动预热子系统
+        if let Some(warmup) = components.warmup {
+            s.start(SubsystemBuilder::new(
+                subsystem_names::WARMUP,
+                warmup.into_subsystem(),
+            ));
+        }
     });
 
     // 等待关闭
@@ -125,10 +163,30 @@ struct AppComponents {
     dns_server: DnsServer,
     // 管理服务器
     admin_server: AdminServer,
+    // 缓存清理任务
+    cache_janitor: Option<CacheJanitor>,
+    // 启动预热任务
+    warmup: Option<Warmup>,
+    // dnstap 输出任务
+    dnstap_output: Option<DnstapOutput>,
 }
 
 // 创建应用组件
 async fn create_components(config: Config) -> Result<AppComponents, AppError> {
+    // 安装指标后端（默认 Prometheus）
+    if let Some(metrics_config) = &config.metrics {
+        if metrics_config.backend == MetricsBackendKind::Statsd {
+            if let Some(statsd_config) = &metrics_config.statsd {
+                let address = statsd_config.address.parse()?;
+                metrics::install_backend(Box::new(StatsdBackend::new(
+                    address,
+                    &statsd_config.prefix,
+                )?));
+                info!("StatsD metrics backend enabled, sending to {}", address);
+            }
+        }
+    }
+
     // 创建 DNS 缓存
     let cache = if let Some(cache_config) = &config.cache {
         let cache_size = if cache_config.enabled {
@@ -136,11 +194,22 @@ async fn create_components(config: Config) -> Result<AppComponents, AppError> {
         } else {
             0
         };
-        let cache = Arc::new(DnsCache::new(
-            cache_size,
-            cache_config.min_ttl,
-            Some(cache_config.negative_ttl),
-        ));
+        let cache = Arc::new(
+            DnsCache::new(
+                cache_size,
+                cache_config.min_ttl,
+                Some(cache_config.negative_ttl),
+            )
+            .with_servfail_ttl(cache_config.servfail_ttl)
+            .with_serve_ttl_floor(cache_config.serve_ttl_floor)
+            .with_shuffle_answers(cache_config.shuffle_answers)
+            .with_bypass_on_cd(cache_config.bypass_on_cd)
+            .with_min_remaining_ttl(cache_config.min_remaining_ttl)
+            .with_normalize_key_case(cache_config.normalize_key_case)
+            .with_pinned_domains(&cache_config.pinned_domains)
+            .with_nxdomain_cut(cache_config.nxdomain_cut)
+            .with_stale_while_revalidate(cache_config.stale_while_revalidate),
+        );
         if cache_config.enabled {
             info!(
                 "DNS cache enabled, size: {}, min TTL: {}s, negative TTL: {}s",
@@ -156,6 +225,18 @@ async fn create_components(config: Config) -> Result<AppComponents, AppError> {
         Arc::new(DnsCache::new(0, 0, Some(0)))
     };
 
+    // 缓存启用时定期执行维护并校准缓存条目数指标
+    let cache_janitor = config
+        .cache
+        .as_ref()
+        .filter(|cache_config| cache_config.enabled)
+        .map(|cache_config| {
+            CacheJanitor::new(
+                Arc::clone(&cache),
+                Duration::from_secs(cache_config.janitor_interval),
+            )
+        });
+
     // 创建管理服务器
     let admin_listen_addr = match &config.admin {
         Some(admin_config) => admin_config.listen.parse()?,
@@ -192,116 +273,53 @@ async fn create_components(config: Config) -> Result<AppComponents, AppError> {
         }
     };
 
+    // 上游统计供管理服务器使用
+    let upstream_stats = upstream.stats();
+
     // 获取静态规则（如果有）
     let static_rules = config.static_rules.clone().unwrap_or_default();
+    let routing_config = config.routing.clone().unwrap_or_default();
+
+    // 远程规则源新鲜度统计供管理服务器使用
+    let rule_source_stats = Arc::new(RuleSourceStats::new(
+        routing_config.max_staleness.map(Duration::from_secs),
+    ));
+
+    // 规则重载器：启动时加载规则，之后由管理接口按需重载
+    let rule_reloader = Arc::new(
+        RuleReloader::new(
+            config.remote_rules.clone(),
+            static_rules.clone(),
+            http_client_config.clone(),
+            routing_config.clone(),
+        )
+        .with_source_stats(Arc::clone(&rule_source_stats)),
+    );
 
     // 加载远程规则并与静态规则合并
-    let rules = if !config.remote_rules.is_empty() {
+    let merged = if !config.remote_rules.is_empty() {
         info!(
             "Loading {} remote rule sources...",
             config.remote_rules.len()
         );
-        match loadants::remote_rule::load_and_merge_rules(
-            &config.remote_rules,
-            &static_rules,
-            &http_client_config,
-        )
-        .await
-        {
-            Ok(merged_rules) => merged_rules,
+        match rule_reloader.load_rules().await {
+            Ok(merged) => merged,
             Err(e) => {
                 error!(
                     "Failed to load remote rules: {}, falling back to static rules only",
                     e
                 );
-                static_rules.clone()
+                MergedRules::static_only(&static_rules)
             }
         }
     } else {
         // 没有远程规则，直接使用静态规则
-        static_rules.clone()
+        MergedRules::static_only(&static_rules)
     };
 
     // 创建路由引擎 - 使用合并后的规则
-    let router = match Router::new(rules.clone()) {
-        Ok(router) => {
-            // 设置路由规则数量指标 - 考虑每个规则中的多个模式
-            let mut exact_count_static = 0;
-            let mut wildcard_count_static = 0;
-            let mut regex_count_static = 0;
-            let mut exact_count_remote = 0;
-            let mut wildcard_count_remote = 0;
-            let mut regex_count_remote = 0;
-
-            // 静态规则数量
-            for rule in &static_rules {
-                match &rule.match_type {
-                    MatchType::Exact => exact_count_static += rule.patterns.len(),
-                    MatchType::Wildcard => wildcard_count_static += rule.patterns.len(),
-                    MatchType::Regex => regex_count_static += rule.patterns.len(),
-                }
-            }
-
-            // 远程规则数量
-            let static_rules_len = static_rules.len();
-            if static_rules_len < rules.len() {
-                // 计算远程规则中各类型的数量
-                for rule in rules.iter().skip(static_rules_len) {
-                    match &rule.match_type {
-                        MatchType::Exact => exact_count_remote += rule.patterns.len(),
-                        MatchType::Wildcard => wildcard_count_remote += rule.patterns.len(),
-                        MatchType::Regex => regex_count_remote += rule.patterns.len(),
-                    }
-                }
-            }
-
-            // 设置静态规则指标
-            METRICS
-                .route_rules_count()
-                .with_label_values(&[rule_type_labels::EXACT, rule_source_labels::STATIC])
-                .set(exact_count_static as i64);
-
-            METRICS
-                .route_rules_count()
-                .with_label_values(&[rule_type_labels::WILDCARD, rule_source_labels::STATIC])
-                .set(wildcard_count_static as i64);
-
-            METRICS
-                .route_rules_count()
-                .with_label_values(&[rule_type_labels::REGEX, rule_source_labels::STATIC])
-                .set(regex_count_static as i64);
-
-            // 设置远程规则指标
-            let remote_rules_count = rules.len() - static_rules_len;
-            if remote_rules_count > 0 {
-                METRICS
-                    .route_rules_count()
-                    .with_label_values(&[rule_type_labels::EXACT, rule_source_labels::REMOTE])
-                    .set(exact_count_remote as i64);
-
-                METRICS
-                    .route_rules_count()
-                    .with_label_values(&[rule_type_labels::WILDCARD, rule_source_labels::REMOTE])
-                    .set(wildcard_count_remote as i64);
-
-                METRICS
-                    .route_rules_count()
-                    .with_label_values(&[rule_type_labels::REGEX, rule_source_labels::REMOTE])
-                    .set(regex_count_remote as i64);
-            }
-
-            info!(
-                "Routing engine initialized successfully with {} rules ({} static, {} remote): {} exact, {} wildcard, {} regex",
-                rules.len(),
-                static_rules_len,
-                remote_rules_count,
-                exact_count_static + exact_count_remote,
-                wildcard_count_static + wildcard_count_remote,
-                regex_count_static + regex_count_remote
-            );
-
-            Arc::new(router)
-        }
+    let router = match rule_reloader.build_router(merged) {
+        Ok(router) => Arc::new(router),
         Err(e) => {
             error!("Failed to initialize routing engine: {}", e);
             return Err(AppError::Config(e));
@@ -309,7 +327,87 @@ async fn create_components(config: Config) -> Result<AppComponents, AppError> {
     };
 
     // 创建请求处理器
-    let handler = Arc::new(RequestHandler::new(cache, router, upstream));
+    let mut handler = RequestHandler::new(cache, router, upstream)
+        .with_response_config(config.response.clone().unwrap_or_default())
+        .with_reload_grace_period(Duration::from_secs(routing_config.reload_grace_period))
+        .with_strict_allowlist(routing_config.strict_allowlist)
+        .with_empty_aaaa_domains(&routing_config.synthesize_empty_aaaa)
+        .with_prefetch_companion(
+            config
+                .cache
+                .as_ref()
+                .is_some_and(|c| c.enabled && c.prefetch_companion),
+        )
+        .with_coalesce_companion(
+            config
+                .cache
+                .as_ref()
+                .is_some_and(|c| c.enabled && c.coalesce_companion),
+        )
+        .with_cache_blocked(config.cache.as_ref().is_some_and(|c| c.cache_blocked));
+    if let Some(budget) = config.metrics.as_ref().and_then(|m| m.latency_budget_ms) {
+        info!("Latency budget enabled: {}ms", budget);
+        handler = handler.with_latency_budget(Duration::from_millis(budget));
+    }
+    if let Some(dns64_config) = config.dns64.as_ref().filter(|c| c.enabled) {
+        info!("DNS64 enabled with prefix {}", dns64_config.prefix);
+        handler = handler.with_dns64(Dns64::from_config(dns64_config)?);
+    }
+    if let Some(rebind_config) = config.rebind_protection.as_ref().filter(|c| c.enabled) {
+        info!(
+            "DNS rebind protection enabled with {} allowlisted domain(s)",
+            rebind_config.allowlist.len()
+        );
+        handler = handler.with_rebind_protection(RebindGuard::from_config(rebind_config)?);
+    }
+    if let Some(dnssec_config) = config.dnssec.as_ref().filter(|c| c.enabled) {
+        info!(
+            "DNSSEC validation enabled with {} trust anchor(s)",
+            dnssec_config.trust_anchors.len().max(1)
+        );
+        handler = handler.with_dnssec(DnssecValidator::from_config(dnssec_config)?);
+    }
+    if let Some(shadow_config) = &config.shadow {
+        info!(
+            "Shadow upstream enabled: mirroring queries to group {}",
+            shadow_config.group
+        );
+        handler = handler.with_shadow(shadow_config);
+    }
+    if let Some(tracing_config) = config.tracing.as_ref().filter(|c| c.propagate) {
+        info!("Trace context propagation to DoH upstreams enabled");
+        handler = handler.with_tracing(tracing_config);
+    }
+    let mut dnstap_output = None;
+    if let Some(dnstap_config) = &config.dnstap {
+        let (logger, output) = DnstapLogger::new(dnstap_config)?;
+        info!(
+            "dnstap output enabled, queue size: {}",
+            dnstap_config.queue_size
+        );
+        handler = handler.with_dnstap(logger);
+        dnstap_output = Some(output);
+    }
+    let handler = Arc::new(handler);
+
+    // 配置了预热域名时创建预热任务，按需在预热完成前报告未就绪
+    let warmup_config = config.warmup.as_ref().filter(|w| !w.domains.is_empty());
+    let readiness = Arc::new(match warmup_config {
+        Some(warmup_config) if warmup_config.gate_readiness => Readiness::pending(),
+        _ => Readiness::ready(),
+    });
+    let warmup = warmup_config.map(|warmup_config| {
+        Warmup::new(Arc::clone(&handler), warmup_config, Arc::clone(&readiness))
+    });
+
+    // 管理服务器挂载客户端查询统计、上游统计、远程规则源新鲜度统计、生效规则导出与规则重载
+    let admin_server = admin_server
+        .with_client_stats(handler.client_stats())
+        .with_upstream_stats(upstream_stats)
+        .with_rule_source_stats(rule_source_stats)
+        .with_handler(Arc::clone(&handler))
+        .with_rule_reloader(rule_reloader)
+        .with_readiness(readiness);
 
     // 创建DNS服务器配置
     let server_config = DnsServerConfig {
@@ -324,8 +422,35 @@ async fn create_components(config: Config) -> Result<AppComponents, AppError> {
             .unwrap_or("127.0.0.1:0")
             .parse()?,
         http_timeout: config.server.http_timeout,
+        tcp_listener: TcpListenerOptions {
+            reuse_addr: config.server.reuse_addr,
+            reuse_port: config.server.reuse_port,
+            backlog: config.server.tcp_backlog,
+            only_v6: None,
+        },
+        max_tcp_connections: config.server.max_tcp_connections.map(|max| max as usize),
+        require_both_protocols: config.server.require_both_protocols,
+        log_malformed_requests: config.server.log_malformed_requests,
+        udp_v6_bind_addr: config
+            .server
+            .listen_udp_v6
+            .as_deref()
+            .map(str::parse)
+            .transpose()?,
+        tcp_v6_bind_addr: config
+            .server
+            .listen_tcp_v6
+            .as_deref()
+            .map(str::parse)
+            .transpose()?,
+        dual_stack: config.server.dual_stack,
+        udp_max_response_size: config.server.udp_max_response_size.map(usize::from),
     };
 
+    // 在启动子系统前探测所有监听地址，端口冲突或权限不足时直接返回错误
+    server_config.probe_bind()?;
+    probe_tcp_bind(admin_listen_addr, &TcpListenerOptions::default())?;
+
     // 创建 DNS 服务器
     let dns_server = DnsServer::new(server_config, handler.clone());
 
@@ -335,12 +460,16 @@ async fn create_components(config: Config) -> Result<AppComponents, AppError> {
             "DNS server initialized with UDP: {:?}, TCP: {:?}, HTTP: {:?}",
             config.server.listen_udp, config.server.listen_tcp, config.server.listen_http
         );
+        let http_bind_addr = listen_http.parse()?;
+        probe_tcp_bind(http_bind_addr, &TcpListenerOptions::default())?;
         // 创建 DoH 服务器
-        Some(DoHServer::new(
-            listen_http.parse()?,
-            config.server.http_timeout,
-            handler,
-        ))
+        Some(
+            DoHServer::new(http_bind_addr, config.server.http_timeout, handler)
+                .with_drain_timeout(Duration::from_secs(config.server.drain_timeout))
+                .with_max_connections(config.server.max_http_connections.map(|max| max as usize))
+                .with_paths(&config.server.doh_path, &config.server.json_path)
+                .with_json_content_type(&config.server.json_content_type),
+        )
     } else {
         info!(
             "DNS server initialized with UDP: {:?}, TCP: {:?}",
@@ -354,5 +483,8 @@ async fn create_components(config: Config) -> Result<AppComponents, AppError> {
         doh_server,
         dns_server,
         admin_server,
+        cache_janitor,
+        warmup,
+        dnstap_output,
     })
 }
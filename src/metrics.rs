@@ -1,11 +1,202 @@
 use axum::http::{header, StatusCode};
 use axum::{routing::get, Router};
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use prometheus::{opts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Registry};
 
 // 全局静态指标实例
 pub static METRICS: Lazy<DnsMetrics> = Lazy::new(DnsMetrics::new);
 
+// 全局指标后端（未安装时使用 Prometheus）
+static BACKEND: OnceCell<Box<dyn MetricsBackend>> = OnceCell::new();
+
+// 默认的 Prometheus 指标后端
+static PROMETHEUS_BACKEND: PrometheusBackend = PrometheusBackend;
+
+// 请求热路径上的计数器指标
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterMetric {
+    DnsRequests,
+    DnsRequestErrors,
+    DnsMalformedRequests,
+    DnsQueryTypes,
+    DnsResponseCodes,
+    SloBreaches,
+    TruncatedResponses,
+    HttpRequests,
+    HttpRequestErrors,
+    CacheOperations,
+    CacheStaleRevalidations,
+    UpstreamRequests,
+    UpstreamErrors,
+    UpstreamRetries,
+    UpstreamDroppedRecords,
+    UpstreamSelections,
+    ShadowDivergences,
+    RouteMatches,
+}
+
+impl CounterMetric {
+    // 指标名称（不含 `loadants_` 前缀）
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DnsRequests => "dns_requests_total",
+            Self::DnsRequestErrors => "dns_request_errors_total",
+            Self::DnsMalformedRequests => "dns_malformed_requests_total",
+            Self::DnsQueryTypes => "dns_query_type_total",
+            Self::DnsResponseCodes => "dns_response_codes_total",
+            Self::SloBreaches => "slo_breach_total",
+            Self::TruncatedResponses => "truncated_responses_total",
+            Self::HttpRequests => "http_requests_total",
+            Self::HttpRequestErrors => "http_request_errors_total",
+            Self::CacheOperations => "cache_operations_total",
+            Self::CacheStaleRevalidations => "cache_stale_revalidations_total",
+            Self::UpstreamRequests => "upstream_requests_total",
+            Self::UpstreamErrors => "upstream_errors_total",
+            Self::UpstreamRetries => "upstream_retries_total",
+            Self::UpstreamDroppedRecords => "upstream_dropped_records_total",
+            Self::UpstreamSelections => "upstream_selections_total",
+            Self::ShadowDivergences => "shadow_divergence_total",
+            Self::RouteMatches => "route_matches_total",
+        }
+    }
+
+    // 标签名称，顺序与 Prometheus 指标定义一致
+    pub fn label_names(self) -> &'static [&'static str] {
+        match self {
+            Self::DnsRequests => &["protocol"],
+            Self::DnsRequestErrors | Self::HttpRequestErrors => &["error_type"],
+            Self::DnsMalformedRequests => &["protocol"],
+            Self::DnsQueryTypes => &["type"],
+            Self::DnsResponseCodes => &["rcode"],
+            Self::SloBreaches => &["phase"],
+            Self::TruncatedResponses => &["group"],
+            Self::HttpRequests => &["status_code"],
+            Self::CacheOperations => &["operation"],
+            Self::CacheStaleRevalidations => &["result"],
+            Self::UpstreamRequests => {
+                &["upstream_protocol", "upstream_transport", "group", "server"]
+            }
+            Self::UpstreamErrors => &[
+                "upstream_protocol",
+                "upstream_transport",
+                "error_type",
+                "group",
+                "server",
+            ],
+            Self::UpstreamRetries => &["group", "server"],
+            Self::UpstreamDroppedRecords => &["record_type"],
+            Self::UpstreamSelections => &["group", "server", "strategy"],
+            Self::ShadowDivergences => &["group"],
+            Self::RouteMatches => &["rule_type", "target_group", "rule_source", "action"],
+        }
+    }
+}
+
+// 请求热路径上的直方图指标
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistogramMetric {
+    DnsRequestDuration,
+    HttpRequestDuration,
+    UpstreamDuration,
+    DnsRequestBytes,
+    DnsResponseBytes,
+}
+
+impl HistogramMetric {
+    // 指标名称（不含 `loadants_` 前缀）
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DnsRequestDuration => "dns_request_duration_seconds",
+            Self::HttpRequestDuration => "http_request_duration_seconds",
+            Self::UpstreamDuration => "upstream_duration_seconds",
+            Self::DnsRequestBytes => "dns_request_bytes",
+            Self::DnsResponseBytes => "dns_response_bytes",
+        }
+    }
+
+    // 标签名称，顺序与 Prometheus 指标定义一致
+    pub fn label_names(self) -> &'static [&'static str] {
+        match self {
+            Self::DnsRequestDuration => &["protocol", "query_type"],
+            Self::HttpRequestDuration => &["query_type", "status_code"],
+            Self::UpstreamDuration => {
+                &["upstream_protocol", "upstream_transport", "group", "server"]
+            }
+            Self::DnsRequestBytes | Self::DnsResponseBytes => &["protocol"],
+        }
+    }
+
+    // 是否为时长指标（单位：秒）
+    pub fn is_duration(self) -> bool {
+        matches!(
+            self,
+            Self::DnsRequestDuration | Self::HttpRequestDuration | Self::UpstreamDuration
+        )
+    }
+}
+
+// 指标后端：请求热路径上的指标通过该特性上报
+pub trait MetricsBackend: Send + Sync {
+    // 计数器加一
+    fn increment(&self, metric: CounterMetric, labels: &[&str]);
+
+    // 记录一次直方图观测值
+    fn observe(&self, metric: HistogramMetric, labels: &[&str], value: f64);
+}
+
+// Prometheus 指标后端：写入全局 METRICS 注册表
+pub struct PrometheusBackend;
+
+impl MetricsBackend for PrometheusBackend {
+    fn increment(&self, metric: CounterMetric, labels: &[&str]) {
+        let counter = match metric {
+            CounterMetric::DnsRequests => METRICS.dns_requests_total(),
+            CounterMetric::DnsRequestErrors => METRICS.dns_request_errors_total(),
+            CounterMetric::DnsMalformedRequests => METRICS.dns_malformed_requests_total(),
+            CounterMetric::DnsQueryTypes => METRICS.dns_query_type_total(),
+            CounterMetric::DnsResponseCodes => METRICS.dns_response_codes_total(),
+            CounterMetric::SloBreaches => METRICS.slo_breach_total(),
+            CounterMetric::TruncatedResponses => METRICS.truncated_responses_total(),
+            CounterMetric::HttpRequests => METRICS.http_requests_total(),
+            CounterMetric::HttpRequestErrors => METRICS.http_request_errors_total(),
+            CounterMetric::CacheOperations => METRICS.cache_operations_total(),
+            CounterMetric::CacheStaleRevalidations => METRICS.cache_stale_revalidations_total(),
+            CounterMetric::UpstreamRequests => METRICS.upstream_requests_total(),
+            CounterMetric::UpstreamErrors => METRICS.upstream_errors_total(),
+            CounterMetric::UpstreamRetries => METRICS.upstream_retries_total(),
+            CounterMetric::UpstreamDroppedRecords => METRICS.upstream_dropped_records_total(),
+            CounterMetric::UpstreamSelections => METRICS.upstream_selections_total(),
+            CounterMetric::ShadowDivergences => METRICS.shadow_divergence_total(),
+            CounterMetric::RouteMatches => METRICS.route_matches_total(),
+        };
+        counter.with_label_values(labels).inc();
+    }
+
+    fn observe(&self, metric: HistogramMetric, labels: &[&str], value: f64) {
+        let histogram = match metric {
+            HistogramMetric::DnsRequestDuration => METRICS.dns_request_duration_seconds(),
+            HistogramMetric::HttpRequestDuration => METRICS.http_request_duration_seconds(),
+            HistogramMetric::UpstreamDuration => METRICS.upstream_duration_seconds(),
+            HistogramMetric::DnsRequestBytes => METRICS.dns_request_bytes(),
+            HistogramMetric::DnsResponseBytes => METRICS.dns_response_bytes(),
+        };
+        histogram.with_label_values(labels).observe(value);
+    }
+}
+
+// 安装全局指标后端，只能安装一次；已安装时返回 false
+pub fn install_backend(backend: Box<dyn MetricsBackend>) -> bool {
+    BACKEND.set(backend).is_ok()
+}
+
+// 获取全局指标后端
+pub fn backend() -> &'static dyn MetricsBackend {
+    match BACKEND.get() {
+        Some(backend) => backend.as_ref(),
+        None => &PROMETHEUS_BACKEND,
+    }
+}
+
 // DNS 代理性能指标
 pub struct DnsMetrics {
     registry: Registry,
@@ -17,12 +208,18 @@ pub struct DnsMetrics {
     http_requests_total: IntCounterVec,
     http_request_duration_seconds: HistogramVec,
     http_request_errors_total: IntCounterVec,
+    dns_request_bytes: HistogramVec,
+    dns_response_bytes: HistogramVec,
+    active_connections: IntGaugeVec,
+    slo_breach_total: IntCounterVec,
+    dns_malformed_requests_total: IntCounterVec,
 
     // 2. 缓存效率和状态指标
     cache_entries: IntGauge,
     cache_capacity: IntGauge,
     cache_operations_total: IntCounterVec,
     cache_ttl_seconds: HistogramVec,
+    cache_stale_revalidations_total: IntCounterVec,
 
     // 3. DNS 查询统计指标
     dns_query_type_total: IntCounterVec,
@@ -32,10 +229,18 @@ pub struct DnsMetrics {
     upstream_requests_total: IntCounterVec,
     upstream_errors_total: IntCounterVec,
     upstream_duration_seconds: HistogramVec,
+    upstream_retries_total: IntCounterVec,
+    upstream_selections_total: IntCounterVec,
+    upstream_dropped_records_total: IntCounterVec,
+    truncated_responses_total: IntCounterVec,
 
     // 5. 路由策略指标
     route_matches_total: IntCounterVec,
     route_rules_count: IntGaugeVec,
+    rule_limit_exceeded_total: IntCounterVec,
+
+    // 6. 影子上游指标
+    shadow_divergence_total: IntCounterVec,
 }
 
 impl Default for DnsMetrics {
@@ -106,6 +311,53 @@ impl DnsMetrics {
         )
         .unwrap();
 
+        let dns_request_bytes = HistogramVec::new(
+            prometheus::histogram_opts!(
+                "loadants_dns_request_bytes",
+                "Size of incoming DNS queries in bytes, classified by protocol (UDP/TCP/DoH)",
+                vec![64.0, 128.0, 256.0, 512.0, 1232.0, 4096.0]
+            ),
+            &["protocol"],
+        )
+        .unwrap();
+
+        let dns_response_bytes = HistogramVec::new(
+            prometheus::histogram_opts!(
+                "loadants_dns_response_bytes",
+                "Size of outgoing DNS responses in bytes, classified by protocol (UDP/TCP/DoH)",
+                vec![64.0, 128.0, 256.0, 512.0, 1232.0, 4096.0]
+            ),
+            &["protocol"],
+        )
+        .unwrap();
+
+        let active_connections = IntGaugeVec::new(
+            opts!(
+                "loadants_active_connections",
+                "Current number of active client connections, classified by listener (TCP/DoH)"
+            ),
+            &["listener"],
+        )
+        .unwrap();
+
+        let slo_breach_total = IntCounterVec::new(
+            opts!(
+                "loadants_slo_breach_total",
+                "Total DNS requests exceeding the configured latency budget, classified by the dominant phase (cache, routing, upstream)"
+            ),
+            &["phase"],
+        )
+        .unwrap();
+
+        let dns_malformed_requests_total = IntCounterVec::new(
+            opts!(
+                "loadants_dns_malformed_requests_total",
+                "Total incoming DNS packets that could not be parsed, classified by protocol (UDP/TCP)"
+            ),
+            &["protocol"],
+        )
+        .unwrap();
+
         // 2. 缓存效率和状态指标
         let cache_entries = IntGauge::new(
             "loadants_cache_entries",
@@ -122,7 +374,7 @@ impl DnsMetrics {
         let cache_operations_total = IntCounterVec::new(
             opts!(
                 "loadants_cache_operations_total",
-                "Total cache operations, classified by operation type (hit, miss, insert, insert_error, clear)"
+                "Total cache operations, classified by operation type (hit, stale_hit, miss, insert, insert_error, clear)"
             ),
             &["operation"]
         ).unwrap();
@@ -137,6 +389,15 @@ impl DnsMetrics {
         )
         .unwrap();
 
+        let cache_stale_revalidations_total = IntCounterVec::new(
+            opts!(
+                "loadants_cache_stale_revalidations_total",
+                "Total background revalidations of stale cache entries, classified by result"
+            ),
+            &["result"],
+        )
+        .unwrap();
+
         // 3. DNS 查询统计指标
         let dns_query_type_total = IntCounterVec::new(
             opts!(
@@ -191,6 +452,42 @@ impl DnsMetrics {
         )
         .unwrap();
 
+        let upstream_retries_total = IntCounterVec::new(
+            opts!(
+                "loadants_upstream_retries_total",
+                "Total retry attempts sent to upstream DoH resolvers after a failed attempt, classified by group and server"
+            ),
+            &["group", "server"],
+        )
+        .unwrap();
+
+        let upstream_selections_total = IntCounterVec::new(
+            opts!(
+                "loadants_upstream_selections_total",
+                "Total upstream servers chosen by the load balancer, including retry re-selections and selections that later failed, classified by group, server and strategy"
+            ),
+            &["group", "server", "strategy"],
+        )
+        .unwrap();
+
+        let upstream_dropped_records_total = IntCounterVec::new(
+            opts!(
+                "loadants_upstream_dropped_records_total",
+                "Total records dropped from upstream DNS JSON responses because they could not be parsed, classified by record type"
+            ),
+            &["record_type"],
+        )
+        .unwrap();
+
+        let truncated_responses_total = IntCounterVec::new(
+            opts!(
+                "loadants_truncated_responses_total",
+                "Total UDP responses truncated because they exceeded the client's advertised buffer size, classified by upstream group"
+            ),
+            &["group"],
+        )
+        .unwrap();
+
         // 5. 路由策略指标
         let route_matches_total = IntCounterVec::new(
             opts!("loadants_route_matches_total", "Total routing rule matches, classified by rule type, target group, rule source and action"),
@@ -206,6 +503,25 @@ impl DnsMetrics {
         )
         .unwrap();
 
+        let rule_limit_exceeded_total = IntCounterVec::new(
+            opts!(
+                "loadants_rule_limit_exceeded_total",
+                "Total times the configured max_rules limit was exceeded while loading rules, classified by action"
+            ),
+            &["action"],
+        )
+        .unwrap();
+
+        // 6. 影子上游指标
+        let shadow_divergence_total = IntCounterVec::new(
+            opts!(
+                "loadants_shadow_divergence_total",
+                "Total shadow upstream answers that differ from the primary answer, classified by shadow group"
+            ),
+            &["group"],
+        )
+        .unwrap();
+
         // 创建指标实例
         let metrics = DnsMetrics {
             registry,
@@ -215,17 +531,29 @@ impl DnsMetrics {
             http_requests_total,
             http_request_duration_seconds,
             http_request_errors_total,
+            dns_request_bytes,
+            dns_response_bytes,
+            active_connections,
+            slo_breach_total,
+            dns_malformed_requests_total,
             cache_entries,
             cache_capacity,
             cache_operations_total,
             cache_ttl_seconds,
+            cache_stale_revalidations_total,
             dns_query_type_total,
             dns_response_codes_total,
             upstream_requests_total,
             upstream_errors_total,
             upstream_duration_seconds,
+            upstream_retries_total,
+            upstream_selections_total,
+            upstream_dropped_records_total,
+            truncated_responses_total,
             route_matches_total,
             route_rules_count,
+            rule_limit_exceeded_total,
+            shadow_divergence_total,
         };
 
         // 注册所有指标
@@ -255,6 +583,21 @@ impl DnsMetrics {
         self.registry
             .register(Box::new(self.http_request_errors_total.clone()))
             .unwrap();
+        self.registry
+            .register(Box::new(self.dns_request_bytes.clone()))
+            .unwrap();
+        self.registry
+            .register(Box::new(self.dns_response_bytes.clone()))
+            .unwrap();
+        self.registry
+            .register(Box::new(self.active_connections.clone()))
+            .unwrap();
+        self.registry
+            .register(Box::new(self.slo_breach_total.clone()))
+            .unwrap();
+        self.registry
+            .register(Box::new(self.dns_malformed_requests_total.clone()))
+            .unwrap();
 
         // 2. 缓存效率和状态指标
         self.registry
@@ -269,6 +612,9 @@ impl DnsMetrics {
         self.registry
             .register(Box::new(self.cache_ttl_seconds.clone()))
             .unwrap();
+        self.registry
+            .register(Box::new(self.cache_stale_revalidations_total.clone()))
+            .unwrap();
 
         // 3. DNS 查询统计指标
         self.registry
@@ -288,6 +634,18 @@ impl DnsMetrics {
         self.registry
             .register(Box::new(self.upstream_duration_seconds.clone()))
             .unwrap();
+        self.registry
+            .register(Box::new(self.upstream_retries_total.clone()))
+            .unwrap();
+        self.registry
+            .register(Box::new(self.upstream_selections_total.clone()))
+            .unwrap();
+        self.registry
+            .register(Box::new(self.upstream_dropped_records_total.clone()))
+            .unwrap();
+        self.registry
+            .register(Box::new(self.truncated_responses_total.clone()))
+            .unwrap();
 
         // 5. 路由策略指标
         self.registry
@@ -296,6 +654,14 @@ impl DnsMetrics {
         self.registry
             .register(Box::new(self.route_rules_count.clone()))
             .unwrap();
+        self.registry
+            .register(Box::new(self.rule_limit_exceeded_total.clone()))
+            .unwrap();
+
+        // 6. 影子上游指标
+        self.registry
+            .register(Box::new(self.shadow_divergence_total.clone()))
+            .unwrap();
     }
 
     // 获取 Prometheus 注册表
@@ -340,6 +706,26 @@ impl DnsMetrics {
         &self.http_request_errors_total
     }
 
+    pub fn dns_request_bytes(&self) -> &HistogramVec {
+        &self.dns_request_bytes
+    }
+
+    pub fn dns_response_bytes(&self) -> &HistogramVec {
+        &self.dns_response_bytes
+    }
+
+    pub fn active_connections(&self) -> &IntGaugeVec {
+        &self.active_connections
+    }
+
+    pub fn slo_breach_total(&self) -> &IntCounterVec {
+        &self.slo_breach_total
+    }
+
+    pub fn dns_malformed_requests_total(&self) -> &IntCounterVec {
+        &self.dns_malformed_requests_total
+    }
+
     // 2. 缓存效率和状态指标
     pub fn cache_entries(&self) -> &IntGauge {
         &self.cache_entries
@@ -357,6 +743,10 @@ impl DnsMetrics {
         &self.cache_ttl_seconds
     }
 
+    pub fn cache_stale_revalidations_total(&self) -> &IntCounterVec {
+        &self.cache_stale_revalidations_total
+    }
+
     // 3. DNS 查询统计指标
     pub fn dns_query_type_total(&self) -> &IntCounterVec {
         &self.dns_query_type_total
@@ -379,6 +769,22 @@ impl DnsMetrics {
         &self.upstream_duration_seconds
     }
 
+    pub fn upstream_retries_total(&self) -> &IntCounterVec {
+        &self.upstream_retries_total
+    }
+
+    pub fn upstream_selections_total(&self) -> &IntCounterVec {
+        &self.upstream_selections_total
+    }
+
+    pub fn upstream_dropped_records_total(&self) -> &IntCounterVec {
+        &self.upstream_dropped_records_total
+    }
+
+    pub fn truncated_responses_total(&self) -> &IntCounterVec {
+        &self.truncated_responses_total
+    }
+
     // 5. 路由策略指标
     pub fn route_matches_total(&self) -> &IntCounterVec {
         &self.route_matches_total
@@ -387,6 +793,15 @@ impl DnsMetrics {
     pub fn route_rules_count(&self) -> &IntGaugeVec {
         &self.route_rules_count
     }
+
+    pub fn rule_limit_exceeded_total(&self) -> &IntCounterVec {
+        &self.rule_limit_exceeded_total
+    }
+
+    // 6. 影子上游指标
+    pub fn shadow_divergence_total(&self) -> &IntCounterVec {
+        &self.shadow_divergence_total
+    }
 }
 
 // 提供指标导出路由
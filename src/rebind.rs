@@ -0,0 +1,115 @@
+// src/rebind.rs
+
+use crate::config::{RebindAction, RebindProtectionConfig};
+use crate::error::ConfigError;
+use hickory_proto::op::{Message, ResponseCode};
+use hickory_proto::rr::{Name, RData};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+// DNS 重绑定防护：公网域名的应答中不允许出现私有、环回或链路本地地址
+#[derive(Debug, Clone)]
+pub struct RebindGuard {
+    // 命中私有地址时的处理方式
+    action: RebindAction,
+    // 允许返回私有地址的域名（含子域名）
+    allowlist: Vec<Name>,
+}
+
+impl RebindGuard {
+    // 根据配置创建重绑定防护
+    pub fn from_config(config: &RebindProtectionConfig) -> Result<Self, ConfigError> {
+        let allowlist = config
+            .allowlist
+            .iter()
+            .map(|domain| {
+                Name::from_str(domain)
+                    .map(|name| name.to_lowercase())
+                    .map_err(|e| {
+                        ConfigError::InvalidRebindConfig(format!(
+                            "invalid allowlist domain '{}': {}",
+                            domain, e
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            action: config.action,
+            allowlist,
+        })
+    }
+
+    // 判断域名是否在白名单中（域名本身或其子域名）
+    pub fn is_allowed(&self, name: &Name) -> bool {
+        self.allowlist.iter().any(|zone| zone.zone_of(name))
+    }
+
+    // 过滤应答：返回被过滤的私有地址记录数，未命中时原样返回
+    pub fn filter(&self, response: &mut Message) -> usize {
+        let Some(query) = response.queries().first() else {
+            return 0;
+        };
+        if self.is_allowed(query.name()) {
+            return 0;
+        }
+
+        let private = response
+            .answers()
+            .iter()
+            .filter(|record| record.data().is_some_and(is_private_rdata))
+            .count();
+        if private == 0 {
+            return 0;
+        }
+
+        match self.action {
+            RebindAction::Strip => {
+                response
+                    .answers_mut()
+                    .retain(|record| !record.data().is_some_and(is_private_rdata));
+            }
+            RebindAction::Refuse => {
+                response.answers_mut().clear();
+                response.name_servers_mut().clear();
+                response.additionals_mut().clear();
+                response.set_response_code(ResponseCode::Refused);
+            }
+        }
+        private
+    }
+}
+
+// 判断记录是否为指向私有地址的 A/AAAA 记录
+fn is_private_rdata(rdata: &RData) -> bool {
+    match rdata {
+        RData::A(a) => is_private_ipv4(&a.0),
+        RData::AAAA(aaaa) => is_private_ipv6(&aaaa.0),
+        _ => false,
+    }
+}
+
+// 判断地址是否属于私有、环回、链路本地或未指定地址范围
+pub fn is_private_address(addr: IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(v4) => is_private_ipv4(&v4),
+        IpAddr::V6(v6) => is_private_ipv6(&v6),
+    }
+}
+
+// RFC 1918、127.0.0.0/8、169.254.0.0/16、0.0.0.0/8
+fn is_private_ipv4(addr: &Ipv4Addr) -> bool {
+    addr.is_private() || addr.is_loopback() || addr.is_link_local() || addr.octets()[0] == 0
+}
+
+// ::1、::、fe80::/10、fc00::/7，以及映射到私有 IPv4 的地址
+fn is_private_ipv6(addr: &Ipv6Addr) -> bool {
+    if let Some(v4) = addr.to_ipv4_mapped() {
+        return is_private_ipv4(&v4);
+    }
+    let first = addr.segments()[0];
+    addr.is_loopback()
+        || addr.is_unspecified()
+        || first & 0xffc0 == 0xfe80
+        || first & 0xfe00 == 0xfc00
+}
@@ -6,6 +6,7 @@ use crate::r#const::{retry_limits, rule_action_labels};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 use retry_policies::Jitter;
+use std::sync::Arc;
 use std::time::Duration;
 use tracing::{debug, info};
 
@@ -194,8 +195,12 @@ impl RemoteRuleLoader {
         let action_label = match self.config.action {
             crate::config::RouteAction::Forward => rule_action_labels::FORWARD,
             crate::config::RouteAction::Block => rule_action_labels::BLOCK,
+            crate::config::RouteAction::Allow => rule_action_labels::ALLOW,
         };
 
+        // 规则来源标识，随规则一起导出
+        let source: Arc<str> = self.config.url.as_str().into();
+
         // 创建精确匹配规则（如果有）
         if !exact_patterns.is_empty() {
             route_rules.push(RouteRuleConfig {
@@ -203,6 +208,11 @@ impl RemoteRuleLoader {
                 patterns: exact_patterns,
                 action: self.config.action,
                 target: self.config.target.clone(),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: Some(source.clone()),
             });
         }
 
@@ -213,6 +223,11 @@ impl RemoteRuleLoader {
                 patterns: wildcard_patterns,
                 action: self.config.action,
                 target: self.config.target.clone(),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: Some(source.clone()),
             });
         }
 
@@ -223,6 +238,11 @@ impl RemoteRuleLoader {
                 patterns: regex_patterns,
                 action: self.config.action,
                 target: self.config.target.clone(),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: Some(source.clone()),
             });
         }
 
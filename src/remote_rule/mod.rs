@@ -1,52 +1,258 @@
 mod loader;
 mod parser;
+mod reload;
 
 pub use self::loader::RemoteRuleLoader;
 pub use self::parser::{ClashRuleParser, RuleParser, V2RayRuleParser};
+pub use self::reload::RuleReloader;
 
-use crate::config::{HttpClientConfig, RemoteRuleConfig, RouteRuleConfig};
+use crate::config::{
+    HttpClientConfig, MaxRulesAction, RemoteRuleConfig, RouteRuleConfig, RoutingConfig,
+};
 use crate::error::AppError;
-use tracing::error;
+use crate::metrics::METRICS;
+use crate::r#const::{rule_limit_labels, rule_source_labels};
+use crate::router::RuleSourceSummary;
+use crate::stats::RuleSourceStats;
+use futures_util::stream::{self, StreamExt};
+use tracing::{error, warn};
 
 // 类型别名，简化远程规则加载结果类型
 pub type RemoteRuleResult = Result<Vec<RouteRuleConfig>, AppError>;
 
+// 合并后的规则集：规则列表及各规则源贡献的规则（模式）数量
+#[derive(Debug, Clone)]
+pub struct MergedRules {
+    // 合并后的规则（静态规则在前，远程规则按配置顺序在后）
+    pub rules: Vec<RouteRuleConfig>,
+    // 规则来源汇总
+    pub sources: Vec<RuleSourceSummary>,
+    // 加载失败而被跳过的远程规则源
+    pub failed_sources: Vec<String>,
+}
+
+impl MergedRules {
+    // 仅包含静态规则的规则集
+    pub fn static_only(static_rules: &[RouteRuleConfig]) -> Self {
+        Self {
+            rules: static_rules.to_vec(),
+            sources: vec![RuleSourceSummary {
+                source: rule_source_labels::STATIC.to_string(),
+                rules: count_patterns(static_rules),
+            }],
+            failed_sources: Vec::new(),
+        }
+    }
+}
+
 /// 加载所有远程规则并与本地规则合并
 pub async fn load_and_merge_rules(
     remote_configs: &[RemoteRuleConfig],
     static_rules: &[RouteRuleConfig],
     http_config: &HttpClientConfig,
+    routing_config: &RoutingConfig,
 ) -> RemoteRuleResult {
-    // 创建一个规则列表，预先分配足够的空间
-    let mut merged_rules = Vec::with_capacity(static_rules.len() + remote_configs.len() * 3);
+    load_and_merge_rules_with_stats(
+        remote_configs,
+        static_rules,
+        http_config,
+        routing_config,
+        None,
+    )
+    .await
+}
+
+/// 加载所有远程规则并与本地规则合并，同时记录每个规则源的成功加载时间
+pub async fn load_and_merge_rules_with_stats(
+    remote_configs: &[RemoteRuleConfig],
+    static_rules: &[RouteRuleConfig],
+    http_config: &HttpClientConfig,
+    routing_config: &RoutingConfig,
+    source_stats: Option<&RuleSourceStats>,
+) -> RemoteRuleResult {
+    load_rule_set(
+        remote_configs,
+        static_rules,
+        http_config,
+        routing_config,
+        source_stats,
+    )
+    .await
+    .map(|merged| merged.rules)
+}
+
+/// 加载所有远程规则并与本地规则合并，同时返回各规则源贡献的规则数量
+pub async fn load_rule_set(
+    remote_configs: &[RemoteRuleConfig],
+    static_rules: &[RouteRuleConfig],
+    http_config: &HttpClientConfig,
+    routing_config: &RoutingConfig,
+    source_stats: Option<&RuleSourceStats>,
+) -> Result<MergedRules, AppError> {
+    // 先登记所有规则源，使加载失败的规则源同样出现在状态报告中
+    if let Some(stats) = source_stats {
+        for config in remote_configs {
+            stats.register(&config.url);
+        }
+    }
 
     // 首先添加静态规则（通过克隆）
-    merged_rules.extend_from_slice(static_rules);
-
-    // 加载每个远程规则
-    for config in remote_configs {
-        match RemoteRuleLoader::new(config.clone(), http_config.clone()) {
-            Ok(loader) => {
-                match loader.load().await {
-                    Ok(remote_rules) => {
-                        // 将远程规则添加到合并规则列表，避免不必要的克隆
-                        merged_rules.extend(remote_rules);
-                    }
-                    Err(e) => {
-                        // 记录错误但继续处理其他规则
-                        error!("Failed to load domains from {:?}: {}", config.url, e);
-                    }
-                }
+    let mut merged = MergedRules::static_only(static_rules);
+    merged
+        .rules
+        .reserve(static_rules.len() + remote_configs.len() * 3);
+    let mut rule_count = merged.sources[0].rules;
+
+    // 静态规则本身已超出上限时同样按 on_max_rules 处理，截断时跳过所有远程规则源
+    if let Some(max_rules) = routing_config.max_rules {
+        if rule_count > max_rules {
+            let static_rules = std::mem::take(&mut merged.rules);
+            merged.sources.clear();
+            return handle_rule_limit(
+                merged,
+                rule_source_labels::STATIC,
+                static_rules,
+                max_rules,
+                0,
+                routing_config.on_max_rules,
+            );
+        }
+    }
+
+    // 并发加载远程规则（受并发上限约束），结果按配置顺序合并
+    // 每个下载任务持有配置副本，使返回的 Future 满足 Send（可在管理接口等多线程任务中调用）
+    let mut results: Vec<_> = stream::iter(remote_configs.iter().cloned().enumerate())
+        .map(|(index, config)| {
+            let http_config = http_config.clone();
+            async move { (index, load_remote_rules(&config, &http_config).await) }
+        })
+        .buffer_unordered(routing_config.rule_download_concurrency())
+        .collect()
+        .await;
+    results.sort_unstable_by_key(|(index, _)| *index);
+
+    // 应用规则数量上限之前记录所有下载成功的规则源：
+    // 截断模式下超出上限后的规则源虽被跳过，但下载并未失败，不应被判定为陈旧
+    if let Some(stats) = source_stats {
+        for (index, remote_rules) in &results {
+            if remote_rules.is_some() {
+                stats.record_success(&remote_configs[*index].url);
             }
-            Err(e) => {
-                // 记录错误但继续处理其他规则
-                error!(
-                    "Failed to create remote rule loader for {}: {}",
-                    config.url, e
+        }
+    }
+
+    // 加载失败的规则源已记录错误，直接跳过并记入失败列表
+    for (index, remote_rules) in results {
+        let config = &remote_configs[index];
+        let Some(remote_rules) = remote_rules else {
+            merged.failed_sources.push(config.url.clone());
+            continue;
+        };
+        let remote_count = count_patterns(&remote_rules);
+
+        // 检查规则数量上限
+        if let Some(max_rules) = routing_config.max_rules {
+            if rule_count + remote_count > max_rules {
+                return handle_rule_limit(
+                    merged,
+                    &config.url,
+                    remote_rules,
+                    max_rules,
+                    rule_count,
+                    routing_config.on_max_rules,
                 );
             }
         }
+
+        // 将远程规则添加到合并规则列表，避免不必要的克隆
+        rule_count += remote_count;
+        merged.rules.extend(remote_rules);
+        merged.sources.push(RuleSourceSummary {
+            source: config.url.clone(),
+            rules: remote_count,
+        });
     }
 
-    Ok(merged_rules)
+    Ok(merged)
+}
+
+// 加载单个远程规则源
+// 加载失败时记录错误并返回 None，由调用方跳过该规则源
+async fn load_remote_rules(
+    config: &RemoteRuleConfig,
+    http_config: &HttpClientConfig,
+) -> Option<Vec<RouteRuleConfig>> {
+    let loader = match RemoteRuleLoader::new(config.clone(), http_config.clone()) {
+        Ok(loader) => loader,
+        Err(e) => {
+            error!(
+                "Failed to create remote rule loader for {}: {}",
+                config.url, e
+            );
+            return None;
+        }
+    };
+    match loader.load().await {
+        Ok(rules) => Some(rules),
+        Err(e) => {
+            error!("Failed to load domains from {:?}: {}", config.url, e);
+            None
+        }
+    }
+}
+
+// 统计规则中的模式数量（每个模式对应一条内存中的规则）
+fn count_patterns(rules: &[RouteRuleConfig]) -> usize {
+    rules.iter().map(|rule| rule.patterns.len()).sum()
+}
+
+// 处理规则数量超出上限：报错中止或截断后停止加载后续远程规则
+fn handle_rule_limit(
+    mut merged: MergedRules,
+    source: &str,
+    remote_rules: Vec<RouteRuleConfig>,
+    max_rules: usize,
+    rule_count: usize,
+    action: MaxRulesAction,
+) -> Result<MergedRules, AppError> {
+    match action {
+        MaxRulesAction::Error => {
+            METRICS
+                .rule_limit_exceeded_total()
+                .with_label_values(&[rule_limit_labels::ERROR])
+                .inc();
+            error!(
+                "Rule limit exceeded: loading would exceed max_rules ({}), aborting rule load",
+                max_rules
+            );
+            Err(AppError::RuleLimitExceeded(max_rules))
+        }
+        MaxRulesAction::Truncate => {
+            METRICS
+                .rule_limit_exceeded_total()
+                .with_label_values(&[rule_limit_labels::TRUNCATE])
+                .inc();
+            warn!(
+                "Rule limit exceeded: truncating rules to max_rules ({}), remaining remote sources are skipped",
+                max_rules
+            );
+
+            let mut remaining = max_rules.saturating_sub(rule_count);
+            let mut kept = 0;
+            for mut rule in remote_rules {
+                if remaining == 0 {
+                    break;
+                }
+                rule.patterns.truncate(remaining);
+                remaining -= rule.patterns.len();
+                kept += rule.patterns.len();
+                merged.rules.push(rule);
+            }
+            merged.sources.push(RuleSourceSummary {
+                source: source.to_string(),
+                rules: kept,
+            });
+            Ok(merged)
+        }
+    }
 }
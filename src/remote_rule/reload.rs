@@ -0,0 +1,148 @@
+use super::{load_rule_set, MergedRules};
+use crate::config::{
+    HttpClientConfig, MatchType, RemoteRuleConfig, RouteRuleConfig, RoutingConfig,
+};
+use crate::error::{AppError, ConfigError};
+use crate::handler::RequestHandler;
+use crate::metrics::METRICS;
+use crate::r#const::{rule_source_labels, rule_type_labels};
+use crate::router::Router;
+use crate::stats::RuleSourceStats;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+
+// 规则重载器：重新下载远程规则源并重建路由引擎，不影响上游与缓存
+pub struct RuleReloader {
+    // 远程规则源配置
+    remote_configs: Vec<RemoteRuleConfig>,
+    // 静态规则
+    static_rules: Vec<RouteRuleConfig>,
+    // 下载远程规则使用的 HTTP 客户端配置
+    http_config: HttpClientConfig,
+    // 路由配置
+    routing_config: RoutingConfig,
+    // 远程规则源新鲜度统计
+    source_stats: Option<Arc<RuleSourceStats>>,
+    // 串行化重载，避免并发下载同一批规则源
+    reload_lock: Mutex<()>,
+}
+
+impl RuleReloader {
+    // 创建规则重载器
+    pub fn new(
+        remote_configs: Vec<RemoteRuleConfig>,
+        static_rules: Vec<RouteRuleConfig>,
+        http_config: HttpClientConfig,
+        routing_config: RoutingConfig,
+    ) -> Self {
+        Self {
+            remote_configs,
+            static_rules,
+            http_config,
+            routing_config,
+            source_stats: None,
+            reload_lock: Mutex::new(()),
+        }
+    }
+
+    // 设置远程规则源新鲜度统计
+    pub fn with_source_stats(mut self, source_stats: Arc<RuleSourceStats>) -> Self {
+        self.source_stats = Some(source_stats);
+        self
+    }
+
+    // 加载远程规则并与静态规则合并
+    pub async fn load_rules(&self) -> Result<MergedRules, AppError> {
+        load_rule_set(
+            &self.remote_configs,
+            &self.static_rules,
+            &self.http_config,
+            &self.routing_config,
+            self.source_stats.as_deref(),
+        )
+        .await
+    }
+
+    // 使用合并后的规则构建路由引擎，并更新路由规则数量指标
+    pub fn build_router(&self, merged: MergedRules) -> Result<Router, ConfigError> {
+        let MergedRules { rules, sources, .. } = merged;
+
+        // 静态规则在前，其后均为远程规则
+        let static_len = self.static_rules.len().min(rules.len());
+        let (static_counts, remote_counts) = (
+            count_by_match_type(&rules[..static_len]),
+            count_by_match_type(&rules[static_len..]),
+        );
+
+        let router = Router::new(rules)?
+            .with_routing_config(&self.routing_config)
+            .with_rule_sources(sources);
+
+        // 设置路由规则数量指标 - 考虑每个规则中的多个模式
+        let labels = [
+            rule_type_labels::EXACT,
+            rule_type_labels::WILDCARD,
+            rule_type_labels::REGEX,
+        ];
+        for (i, label) in labels.into_iter().enumerate() {
+            METRICS
+                .route_rules_count()
+                .with_label_values(&[label, rule_source_labels::STATIC])
+                .set(static_counts[i] as i64);
+            METRICS
+                .route_rules_count()
+                .with_label_values(&[label, rule_source_labels::REMOTE])
+                .set(remote_counts[i] as i64);
+        }
+
+        info!(
+            "Routing engine initialized successfully with {} rules ({} static, {} remote): {} exact, {} wildcard, {} regex",
+            static_counts.iter().sum::<usize>() + remote_counts.iter().sum::<usize>(),
+            static_counts.iter().sum::<usize>(),
+            remote_counts.iter().sum::<usize>(),
+            static_counts[0] + remote_counts[0],
+            static_counts[1] + remote_counts[1],
+            static_counts[2] + remote_counts[2]
+        );
+
+        Ok(router)
+    }
+
+    // 重新下载远程规则源并替换请求处理器的路由引擎
+    // 任一远程规则源加载失败时放弃本次重载，保留当前生效的规则
+    pub async fn reload(&self, handler: &RequestHandler) -> Result<Arc<Router>, AppError> {
+        let _guard = self.reload_lock.lock().await;
+
+        info!(
+            "Reloading rules from {} remote rule sources...",
+            self.remote_configs.len()
+        );
+        let merged = self.load_rules().await?;
+        if !merged.failed_sources.is_empty() {
+            return Err(AppError::RuleReload(format!(
+                "failed to load remote rule sources: {}",
+                merged.failed_sources.join(", ")
+            )));
+        }
+
+        let router = Arc::new(self.build_router(merged)?);
+        handler.reload_router(Arc::clone(&router));
+        info!("Routing rules reloaded");
+        Ok(router)
+    }
+}
+
+// 按匹配类型（精确、通配符、正则）统计规则中的模式数量
+fn count_by_match_type(rules: &[RouteRuleConfig]) -> [usize; 3] {
+    let mut counts = [0; 3];
+    for rule in rules {
+        let index = match rule.match_type {
+            MatchType::Exact => 0,
+            MatchType::Wildcard => 1,
+            MatchType::Regex => 2,
+        };
+        counts[index] += rule.patterns.len();
+    }
+    counts
+}
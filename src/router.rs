@@ -1,19 +1,22 @@
 use crate::{
-    error::ConfigError, metrics::METRICS, r#const::router::wildcards, rule_action_labels,
-    rule_source_labels, rule_type_labels, AppError, MatchType, RouteAction, RouteRuleConfig,
+    config::{parse_client_cidr, RoutingConfig, WeightedTargetConfig},
+    error::ConfigError,
+    metrics::{self, CounterMetric, METRICS},
+    r#const::router::wildcards,
+    rule_action_labels, rule_source_labels, rule_type_labels, AppError, MatchType, RouteAction,
+    RouteRuleConfig,
 };
+use aho_corasick::AhoCorasick;
 use hickory_proto::rr::Name;
-use lazy_static::lazy_static;
+use ipnet::IpNet;
+use rand::{seq::SliceRandom, thread_rng};
 use regex::Regex;
+use regex_syntax::hir::{Hir, HirKind};
+use serde::{Serialize, Serializer};
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
 use std::sync::Arc;
-use tracing::debug;
-
-// 定义正则表达式特殊字符常量
-lazy_static! {
-    static ref REGEX_SPECIAL_CHARS: [char; 15] =
-        ['\\', '^', '$', '.', '|', '?', '*', '+', '(', ')', '[', ']', '{', '}', '-',];
-}
+use tracing::{debug, warn};
 
 // 编译后的正则表达式规则
 struct CompiledRegexRule {
@@ -25,15 +28,311 @@ struct CompiledRegexRule {
     #[allow(dead_code)]
     action: RouteAction,
     // 目标上游组
-    target: Option<Arc<String>>,
+    target: RuleTarget,
 }
 
 #[derive(Clone)]
 struct WildcardRule {
-    target: Option<Arc<String>>,
+    target: RuleTarget,
     pattern: String,
 }
 
+// 规则目标：单一上游组或按权重分流的多个上游组
+#[derive(Clone)]
+struct RuleTarget {
+    group: Option<Arc<String>>,
+    weighted: Option<Arc<WeightedTargets>>,
+    fallback: Option<Arc<Fallback>>,
+    // 规则来源：远程规则源 URL，静态规则为 None
+    source: Option<Arc<str>>,
+}
+
+impl RuleTarget {
+    // 单一目标上游组名称
+    fn group_name(&self) -> Option<String> {
+        self.group.as_ref().map(|arc_str| arc_str.to_string())
+    }
+
+    // 用于日志与指标的目标标签
+    fn label(&self) -> &str {
+        match (&self.group, &self.weighted) {
+            (Some(group), _) => group.as_str(),
+            (None, Some(_)) => rule_type_labels::WEIGHTED_TARGET,
+            (None, None) => rule_type_labels::NO_TARGET,
+        }
+    }
+
+    // 用于指标的规则来源标签
+    fn source_label(&self) -> &'static str {
+        if self.source.is_some() {
+            rule_source_labels::REMOTE
+        } else {
+            rule_source_labels::STATIC
+        }
+    }
+}
+
+// 备用上游组链：目标上游组失败（可选地包括返回 NXDOMAIN）时按顺序尝试
+#[derive(Debug, PartialEq, Eq)]
+pub struct Fallback {
+    // 备用上游组（按尝试顺序）
+    pub groups: Vec<String>,
+    // 返回 NXDOMAIN 时是否尝试下一个上游组
+    pub on_nxdomain: bool,
+}
+
+impl Fallback {
+    // 从配置创建，未配置备用上游组时返回 None
+    fn from_config(groups: Vec<String>, on_nxdomain: bool) -> Option<Self> {
+        if groups.is_empty() {
+            return None;
+        }
+        Some(Self {
+            groups,
+            on_nxdomain,
+        })
+    }
+}
+
+// 按权重分流的目标上游组
+#[derive(Debug, PartialEq, Eq)]
+pub struct WeightedTargets {
+    // (上游组, 权重)
+    targets: Vec<(String, u32)>,
+}
+
+impl WeightedTargets {
+    // 从配置创建，未配置加权目标时返回 None
+    fn from_config(configs: Vec<WeightedTargetConfig>) -> Option<Self> {
+        if configs.is_empty() {
+            return None;
+        }
+        Some(Self {
+            targets: configs.into_iter().map(|t| (t.group, t.weight)).collect(),
+        })
+    }
+
+    // 按权重随机选择一个上游组
+    pub fn select(&self) -> &str {
+        self.targets
+            .choose_weighted(&mut thread_rng(), |(_, weight)| *weight)
+            .map(|(group, _)| group.as_str())
+            .unwrap_or(self.targets[0].0.as_str())
+    }
+
+    // 获取所有上游组名称
+    pub fn groups(&self) -> impl Iterator<Item = &str> {
+        self.targets.iter().map(|(group, _)| group.as_str())
+    }
+}
+
+// 序列化为 {上游组: 权重} 映射
+impl Serialize for WeightedTargets {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.targets.iter().map(|(group, weight)| (group, weight)))
+    }
+}
+
+// 将任意标签位置带 `*` 的通配符模式转换为锚定的正则表达式
+// - 首个标签为 `*` 时匹配一个或多个标签（与 "*.domain.tld" 语义一致）
+// - 其余位置的 `*` 恰好匹配一个标签
+// 模式无效（空标签、标签内部含 `*`、没有字面标签或不含 `*`）时返回 None
+pub fn label_wildcard_regex(pattern: &str) -> Option<String> {
+    let pattern = pattern.trim_end_matches(wildcards::DOT).to_lowercase();
+    let labels: Vec<&str> = pattern.split(wildcards::DOT).collect();
+
+    if labels.iter().any(|label| label.is_empty())
+        || labels
+            .iter()
+            .any(|label| *label != wildcards::GLOBAL && label.contains('*'))
+        || labels.iter().all(|label| *label == wildcards::GLOBAL)
+        || !labels.contains(&wildcards::GLOBAL)
+    {
+        return None;
+    }
+
+    let mut expr = String::with_capacity(pattern.len() * 2 + 16);
+    expr.push('^');
+    for (i, label) in labels.iter().enumerate() {
+        if i > 0 {
+            expr.push_str("\\.");
+        }
+        match (i, *label) {
+            (0, wildcards::GLOBAL) => expr.push_str("(?:[^.]+\\.)*[^.]+"),
+            (_, wildcards::GLOBAL) => expr.push_str("[^.]+"),
+            (_, literal) => expr.push_str(&regex::escape(literal)),
+        }
+    }
+    expr.push('$');
+
+    Some(expr)
+}
+
+// 正则表达式预筛选器
+// 从每条规则中提取所有必须出现在匹配文本中的字面片段作为关键词，
+// 查询时一次扫描找出域名中出现的全部关键词，只有关键词全部出现的规则才需要执行完整的正则匹配；
+// 提取不到关键词的规则始终作为候选，确保不会漏掉任何可能匹配的规则
+#[derive(Debug, Default)]
+pub struct RegexPrefilter {
+    // 所有关键词的多模式匹配器
+    matcher: Option<AhoCorasick>,
+    // 规则索引 -> 该规则的全部关键词编号
+    rule_keywords: Vec<Vec<usize>>,
+    // 关键词编号 -> 以该关键词为索引键（规则中最长的关键词）的规则
+    keyword_rules: Vec<Vec<usize>>,
+    // 没有关键词、每次都需要匹配的规则
+    unfiltered: Vec<usize>,
+}
+
+impl RegexPrefilter {
+    // 关键词最小长度，过短的片段几乎与所有域名重合，没有筛选效果
+    const MIN_KEYWORD_LEN: usize = 2;
+
+    // 按规则顺序构建预筛选器，规则索引与传入模式的位置一致
+    pub fn build<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut keyword_ids: HashMap<String, usize> = HashMap::new();
+        let mut keywords = Vec::new();
+        let mut prefilter = Self::default();
+
+        for (i, pattern) in patterns.into_iter().enumerate() {
+            let mut literals = Self::required_literals(pattern);
+            literals.sort_unstable_by_key(|literal| std::cmp::Reverse(literal.len()));
+
+            let ids: Vec<usize> = literals
+                .into_iter()
+                .map(|literal| {
+                    *keyword_ids.entry(literal).or_insert_with_key(|literal| {
+                        keywords.push(literal.clone());
+                        prefilter.keyword_rules.push(Vec::new());
+                        keywords.len() - 1
+                    })
+                })
+                .collect();
+
+            // 以最长（最具体）的关键词作为索引键
+            match ids.first() {
+                Some(&id) => prefilter.keyword_rules[id].push(i),
+                None => prefilter.unfiltered.push(i),
+            }
+            prefilter.rule_keywords.push(ids);
+        }
+
+        if !keywords.is_empty() {
+            match AhoCorasick::new(&keywords) {
+                Ok(matcher) => prefilter.matcher = Some(matcher),
+                Err(e) => {
+                    // 无法构建匹配器时退化为逐条匹配全部规则
+                    warn!(
+                        "Failed to build regex prefilter, checking all regex rules: {}",
+                        e
+                    );
+                    prefilter.unfiltered = (0..prefilter.rule_keywords.len()).collect();
+                    prefilter.keyword_rules.iter_mut().for_each(Vec::clear);
+                }
+            }
+        }
+
+        prefilter
+    }
+
+    // 返回需要执行完整正则匹配的候选规则索引（升序、去重）
+    // domain 须已做小写归一化
+    pub fn candidates(&self, domain: &str) -> Vec<usize> {
+        let mut candidates = self.unfiltered.clone();
+
+        if let Some(matcher) = &self.matcher {
+            let mut present: Vec<usize> = matcher
+                .find_overlapping_iter(domain)
+                .map(|found| found.pattern().as_usize())
+                .collect();
+            present.sort_unstable();
+            present.dedup();
+
+            for &keyword in &present {
+                candidates.extend(self.keyword_rules[keyword].iter().copied().filter(|&rule| {
+                    self.rule_keywords[rule]
+                        .iter()
+                        .all(|id| present.binary_search(id).is_ok())
+                }));
+            }
+        }
+
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
+
+    // 提取匹配文本中必然出现的字面片段（已转为小写）
+    // 只在能确定片段必然出现时提取：分支、可选重复与字符类都会截断片段，解析失败时返回空列表
+    fn required_literals(pattern: &str) -> Vec<String> {
+        let Ok(hir) = regex_syntax::Parser::new().parse(pattern) else {
+            return Vec::new();
+        };
+
+        let mut literals = Vec::new();
+        let mut run = Vec::new();
+        Self::collect_literals(&hir, &mut run, &mut literals);
+        Self::flush_literal(&mut run, &mut literals);
+
+        literals.sort_unstable();
+        literals.dedup();
+        literals
+    }
+
+    // 递归收集必然出现的字面片段，`run` 为当前连续的字面字节
+    fn collect_literals(hir: &Hir, run: &mut Vec<u8>, literals: &mut Vec<String>) {
+        match hir.kind() {
+            HirKind::Literal(literal) => run.extend_from_slice(&literal.0),
+            // 零宽断言不消耗字符，不打断连续的字面片段
+            HirKind::Empty | HirKind::Look(_) => {}
+            HirKind::Capture(capture) => Self::collect_literals(&capture.sub, run, literals),
+            HirKind::Concat(subs) => {
+                for sub in subs {
+                    Self::collect_literals(sub, run, literals);
+                }
+            }
+            // 至少出现一次的重复：其中的字面片段必然出现，但与前后内容不再连续
+            HirKind::Repetition(repetition) if repetition.min > 0 => {
+                Self::flush_literal(run, literals);
+                Self::collect_literals(&repetition.sub, run, literals);
+                Self::flush_literal(run, literals);
+            }
+            HirKind::Repetition(_) | HirKind::Class(_) | HirKind::Alternation(_) => {
+                Self::flush_literal(run, literals);
+            }
+        }
+    }
+
+    // 结束当前字面片段，长度足够时作为关键词
+    fn flush_literal(run: &mut Vec<u8>, literals: &mut Vec<String>) {
+        if run.len() >= Self::MIN_KEYWORD_LEN {
+            if let Ok(literal) = std::str::from_utf8(run) {
+                literals.push(literal.to_lowercase());
+            }
+        }
+        run.clear();
+    }
+}
+
+// 客户端网段规则：仅对来自指定网段的客户端生效，内部为独立的路由引擎
+struct ClientRules {
+    // 客户端网段
+    cidrs: Vec<IpNet>,
+    // 网段的原始配置（供管理接口导出）
+    labels: Vec<String>,
+    // 缓存作用域：网段原始配置拼接而成，规则重载后保持不变
+    scope: Arc<str>,
+    // 网段内生效的规则
+    router: Router,
+}
+
+impl ClientRules {
+    // 客户端地址是否位于网段内
+    fn contains(&self, client: &IpAddr) -> bool {
+        self.cidrs.iter().any(|cidr| cidr.contains(client))
+    }
+}
+
 // 添加类型别名用于简化复杂类型
 /// 路由规则元组类型，包含(模式, 动作, 目标)
 pub type RouteRuleTuple = (Option<String>, RouteAction, Option<Arc<String>>);
@@ -42,29 +341,118 @@ pub type RouteRuleTuple = (Option<String>, RouteAction, Option<Arc<String>>);
 // 实现特点：
 // 1. 分离存储：block规则和forward规则分开存储，确保block规则始终具有更高优先级
 // 2. 查询优化：在匹配算法中，始终先检查所有类型的block规则，再检查forward规则
-// 3. 规则排序：维持了原有的精确匹配>通配符匹配>正则匹配>全局通配符的类型优先级
+// 3. 规则排序：维持了原有的精确匹配>通配符匹配>标签通配符匹配>正则匹配>全局通配符的类型优先级
 // 4. 性能保障：保留了高效的查询机制，如使用HashMap进行精确匹配，BTreeMap进行后缀树匹配，以及正则表达式预筛选
 pub struct Router {
-    // 精确匹配规则 - 分离block和forward规则
-    exact_block_rules: HashMap<String, Option<Arc<String>>>,
-    exact_forward_rules: HashMap<String, Option<Arc<String>>>,
-
-    // 通配符匹配规则树 - 分离block和forward规则
+    // 是否存在 allow 规则
+    has_allow_rules: bool,
+    // 精确匹配规则 - 分离allow、block和forward规则
+    exact_allow_rules: HashMap<String, RuleTarget>,
+    exact_block_rules: HashMap<String, RuleTarget>,
+    exact_forward_rules: HashMap<String, RuleTarget>,
+
+    // 通配符匹配规则树 - 分离allow、block和forward规则
     // 键为反转后的域名后缀，值为(目标,原始模式)
+    wildcard_allow_rules: BTreeMap<String, WildcardRule>,
     wildcard_block_rules: BTreeMap<String, WildcardRule>,
     wildcard_forward_rules: BTreeMap<String, WildcardRule>,
 
-    // 全局通配符规则（模式为 "*"）- 分离block和forward规则
+    // 全局通配符规则（模式为 "*"）- 分离allow、block和forward规则
+    global_wildcard_allow_rule: Option<WildcardRule>,
     global_wildcard_block_rule: Option<WildcardRule>,
     global_wildcard_forward_rule: Option<WildcardRule>,
 
-    // 正则表达式匹配规则 - 分离block和forward规则
+    // 标签通配符规则（如 "ads.*.example.com"），内部转换为锚定的正则表达式 - 分离allow、block和forward规则
+    label_wildcard_allow_rules: Vec<CompiledRegexRule>,
+    label_wildcard_block_rules: Vec<CompiledRegexRule>,
+    label_wildcard_forward_rules: Vec<CompiledRegexRule>,
+
+    // 标签通配符预筛选映射：键为模式中最长的字面标签
+    label_wildcard_allow_prefilter: HashMap<String, HashSet<usize>>,
+    label_wildcard_block_prefilter: HashMap<String, HashSet<usize>>,
+    label_wildcard_forward_prefilter: HashMap<String, HashSet<usize>>,
+
+    // 正则表达式匹配规则 - 分离allow、block和forward规则
+    regex_allow_rules: Vec<CompiledRegexRule>,
     regex_block_rules: Vec<CompiledRegexRule>,
     regex_forward_rules: Vec<CompiledRegexRule>,
 
     // 正则表达式预筛选映射
-    regex_block_prefilter: HashMap<String, HashSet<usize>>,
-    regex_forward_prefilter: HashMap<String, HashSet<usize>>,
+    regex_allow_prefilter: RegexPrefilter,
+    regex_block_prefilter: RegexPrefilter,
+    regex_forward_prefilter: RegexPrefilter,
+
+    // 默认上游组：没有任何规则匹配时的兜底转发目标
+    default_upstream_group: Option<String>,
+
+    // 严格白名单模式：忽略全局通配符 forward 规则与默认上游组，只解析显式列出的域名
+    strict_allowlist: bool,
+
+    // 查询名称最大标签数：超过时跳过规则匹配
+    max_labels: Option<usize>,
+
+    // 规则来源汇总：各规则源贡献的规则（模式）数量
+    rule_sources: Vec<RuleSourceSummary>,
+
+    // 客户端网段规则（按配置中首次出现的顺序），优先于不限客户端的规则
+    client_rules: Vec<ClientRules>,
+}
+
+// 规则来源汇总：规则源（static 或远程规则 URL）及其合并进路由引擎的规则数量
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuleSourceSummary {
+    pub source: String,
+    pub rules: usize,
+}
+
+// 按匹配类型与动作分组的规则数量
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuleCount {
+    #[serde(rename = "type")]
+    pub match_type: &'static str,
+    pub action: &'static str,
+    pub rules: usize,
+}
+
+// 生效规则条目（供管理接口导出）
+#[derive(Debug, Serialize)]
+pub struct RuleEntry<'a> {
+    #[serde(rename = "type")]
+    pub match_type: &'static str,
+    pub action: &'static str,
+    pub pattern: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weighted_targets: Option<&'a WeightedTargets>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cidrs: Option<&'a [String]>,
+    pub source: &'a str,
+}
+
+impl<'a> RuleEntry<'a> {
+    fn new(
+        match_type: &'static str,
+        action: RouteAction,
+        pattern: &'a str,
+        target: &'a RuleTarget,
+    ) -> Self {
+        Self {
+            match_type,
+            action: action.into(),
+            pattern,
+            target: target.group.as_deref().map(String::as_str),
+            weighted_targets: target.weighted.as_deref(),
+            fallback: target.fallback.as_deref().map(|f| f.groups.as_slice()),
+            client_cidrs: None,
+            source: target
+                .source
+                .as_deref()
+                .unwrap_or(rule_source_labels::STATIC),
+        }
+    }
 }
 
 // 路由匹配结果
@@ -76,10 +464,27 @@ pub struct RouteMatch {
     pub action: RouteAction,
     // 目标上游组
     pub target: Option<String>,
+    // 按权重分流的目标上游组
+    pub weighted_targets: Option<Arc<WeightedTargets>>,
+    // 备用上游组链
+    pub fallback: Option<Arc<Fallback>>,
     // 匹配规则类型
     pub rule_type: &'static str,
     // 匹配的模式
     pub pattern: String,
+    // 匹配的客户端网段规则组（应答因客户端而异，缓存条目按该作用域隔离），None 表示不限客户端的规则
+    pub client_scope: Option<Arc<str>>,
+    // 匹配规则的来源标签（static 或 remote）
+    pub rule_source: &'static str,
+}
+
+impl RouteMatch {
+    // 配置了加权目标时，为本次查询按权重选定目标上游组
+    pub fn select_weighted_target(&mut self) {
+        if let Some(weighted) = &self.weighted_targets {
+            self.target = Some(weighted.select().to_string());
+        }
+    }
 }
 
 impl Router {
@@ -126,39 +531,27 @@ impl Router {
         reversed
     }
 
-    // 构建正则表达式预筛选映射
-    fn build_regex_prefilter(rules: &[CompiledRegexRule]) -> HashMap<String, HashSet<usize>> {
+    // 规则模式列表
+    fn patterns(rules: &[CompiledRegexRule]) -> impl Iterator<Item = &str> {
+        rules.iter().map(|rule| rule.pattern.as_str())
+    }
+
+    // 构建标签通配符预筛选映射：以模式中最长的字面标签为键
+    fn build_label_wildcard_prefilter(
+        rules: &[CompiledRegexRule],
+    ) -> HashMap<String, HashSet<usize>> {
         let mut prefilter = HashMap::new();
 
-        // 为每个正则表达式规则提取关键词
         for (i, rule) in rules.iter().enumerate() {
-            // 提取不包含正则表达式特殊字符的子字符串作为预筛选关键词
-            let pattern = &rule.pattern;
-            let mut current_segment = String::with_capacity(pattern.len() / 2);
-            let mut segments = Vec::with_capacity(5);
-
-            for c in pattern.chars() {
-                if REGEX_SPECIAL_CHARS.contains(&c) {
-                    if !current_segment.is_empty() {
-                        segments.push(std::mem::take(&mut current_segment));
-                    }
-                } else {
-                    current_segment.push(c);
-                }
-            }
-
-            if !current_segment.is_empty() {
-                segments.push(current_segment);
-            }
-
-            // 选择最长的子字符串作为预筛选关键词（更具体更好）
-            if let Some(longest_segment) = segments
-                .iter()
-                .filter(|s| s.len() >= 2) // 忽略太短的字符串
-                .max_by_key(|s| s.len())
+            // 模式在构建时已校验，至少包含一个字面标签
+            if let Some(label) = rule
+                .pattern
+                .split(wildcards::DOT)
+                .filter(|label| *label != wildcards::GLOBAL)
+                .max_by_key(|label| label.len())
             {
                 prefilter
-                    .entry(longest_segment.to_lowercase())
+                    .entry(label.to_string())
                     .or_insert_with(HashSet::new)
                     .insert(i);
             }
@@ -169,19 +562,91 @@ impl Router {
 
     // 构建新的路由引擎
     pub fn new(rules: Vec<RouteRuleConfig>) -> Result<Self, ConfigError> {
+        // 配置了客户端网段的规则按网段集合分组，各自构建独立的路由引擎
+        let mut general_rules = Vec::new();
+        let mut client_groups: Vec<(Vec<String>, Vec<RouteRuleConfig>)> = Vec::new();
+        for mut rule in rules {
+            if rule.client_cidrs.is_empty() {
+                general_rules.push(rule);
+                continue;
+            }
+            let cidrs = std::mem::take(&mut rule.client_cidrs);
+            match client_groups
+                .iter_mut()
+                .find(|(labels, _)| *labels == cidrs)
+            {
+                Some((_, group)) => group.push(rule),
+                None => client_groups.push((cidrs, vec![rule])),
+            }
+        }
+
+        let mut router = Self::build(general_rules)?;
+        for (labels, rules) in client_groups {
+            let cidrs = labels
+                .iter()
+                .map(|cidr| {
+                    parse_client_cidr(cidr).ok_or_else(|| {
+                        ConfigError::InvalidRouteRule(format!("Invalid client CIDR: '{}'", cidr))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            router.client_rules.push(ClientRules {
+                cidrs,
+                scope: labels.join(",").into(),
+                labels,
+                router: Self::build(rules)?,
+            });
+        }
+
+        // 更新路由规则数量指标（包含客户端网段规则）
+        for match_type in [
+            rule_type_labels::EXACT,
+            rule_type_labels::WILDCARD,
+            rule_type_labels::REGEX,
+        ] {
+            let count: usize = router
+                .rule_counts()
+                .iter()
+                .filter(|count| count.match_type == match_type)
+                .map(|count| count.rules)
+                .sum();
+            METRICS
+                .route_rules_count()
+                .with_label_values(&[match_type, rule_source_labels::STATIC])
+                .set(count as i64);
+        }
+
+        Ok(router)
+    }
+
+    // 由不限客户端的规则构建路由引擎
+    fn build(rules: Vec<RouteRuleConfig>) -> Result<Self, ConfigError> {
+        let mut exact_allow_rules = HashMap::new();
         let mut exact_block_rules = HashMap::new();
         let mut exact_forward_rules = HashMap::new();
+        let mut wildcard_allow_rules = BTreeMap::new();
         let mut wildcard_block_rules = BTreeMap::new();
         let mut wildcard_forward_rules = BTreeMap::new();
+        let mut global_wildcard_allow_rule = None;
         let mut global_wildcard_block_rule = None;
         let mut global_wildcard_forward_rule = None;
+        let mut label_wildcard_allow_rules = Vec::new();
+        let mut label_wildcard_block_rules = Vec::new();
+        let mut label_wildcard_forward_rules = Vec::new();
+        let mut regex_allow_rules = Vec::new();
         let mut regex_block_rules = Vec::new();
         let mut regex_forward_rules = Vec::new();
 
         // 处理所有规则
         for rule in rules {
-            // 将目标字符串转换为 Arc 以便共享
-            let target = rule.target.map(Arc::new);
+            // 将目标转换为 Arc 以便共享
+            let target = RuleTarget {
+                group: rule.target.map(Arc::new),
+                weighted: WeightedTargets::from_config(rule.weighted_targets).map(Arc::new),
+                fallback: Fallback::from_config(rule.fallback, rule.fallback_on_nxdomain)
+                    .map(Arc::new),
+                source: rule.source,
+            };
 
             match rule.match_type {
                 MatchType::Exact => {
@@ -189,6 +654,9 @@ impl Router {
                     for pattern in rule.patterns {
                         let pattern = Self::normalize_domain_like(pattern);
                         match rule.action {
+                            RouteAction::Allow => {
+                                exact_allow_rules.insert(pattern, target.clone());
+                            }
                             RouteAction::Block => {
                                 exact_block_rules.insert(pattern, target.clone());
                             }
@@ -203,6 +671,15 @@ impl Router {
                         if pattern == wildcards::GLOBAL {
                             // 根据动作类型存储全局通配符规则
                             match rule.action {
+                                RouteAction::Allow => {
+                                    if global_wildcard_allow_rule.is_some() {
+                                        debug!("Multiple definitions of global wildcard allow rule '*', using the last one");
+                                    }
+                                    global_wildcard_allow_rule = Some(WildcardRule {
+                                        target: target.clone(),
+                                        pattern,
+                                    });
+                                }
                                 RouteAction::Block => {
                                     if global_wildcard_block_rule.is_some() {
                                         debug!("Multiple definitions of global wildcard block rule '*', using the last one");
@@ -222,21 +699,27 @@ impl Router {
                                     });
                                 }
                             }
-                        } else {
+                        } else if let Some(suffix) = pattern
+                            .strip_prefix(wildcards::PREFIX)
+                            .filter(|suffix| !suffix.contains('*'))
+                        {
                             // 处理特定通配符规则：*.domain.tld
                             // 为了支持反向后缀匹配，将域名部分反转存储
-                            let suffix = pattern.strip_prefix("*.").ok_or_else(|| {
-                                ConfigError::InvalidRouteRule(format!(
-                                    "Invalid wildcard pattern '{}': expected '*.domain.tld' or '*'",
-                                    pattern
-                                ))
-                            })?;
                             let suffix = Self::normalize_domain_like(suffix.to_string());
                             let normalized_pattern = format!("*.{}", suffix);
                             let reversed_suffix = Self::reverse_domain_labels(&suffix);
 
                             // 根据动作类型存储通配符规则
                             match rule.action {
+                                RouteAction::Allow => {
+                                    wildcard_allow_rules.insert(
+                                        reversed_suffix,
+                                        WildcardRule {
+                                            target: target.clone(),
+                                            pattern: normalized_pattern,
+                                        },
+                                    );
+                                }
                                 RouteAction::Block => {
                                     wildcard_block_rules.insert(
                                         reversed_suffix,
@@ -256,6 +739,27 @@ impl Router {
                                     );
                                 }
                             }
+                        } else {
+                            // 处理任意标签位置的通配符规则：ads.*.example.com
+                            // 转换为锚定的正则表达式，走正则匹配路径
+                            let expr = label_wildcard_regex(&pattern).ok_or_else(|| {
+                                ConfigError::InvalidRouteRule(format!(
+                                    "Invalid wildcard pattern '{}': expected '*', '*.domain.tld' or '*' as whole labels",
+                                    pattern
+                                ))
+                            })?;
+                            let compiled = CompiledRegexRule {
+                                pattern: Self::normalize_domain_like(pattern),
+                                regex: Regex::new(&expr)?,
+                                action: rule.action,
+                                target: target.clone(),
+                            };
+
+                            match rule.action {
+                                RouteAction::Allow => label_wildcard_allow_rules.push(compiled),
+                                RouteAction::Block => label_wildcard_block_rules.push(compiled),
+                                RouteAction::Forward => label_wildcard_forward_rules.push(compiled),
+                            }
                         }
                     }
                 }
@@ -267,6 +771,14 @@ impl Router {
 
                         // 根据动作类型添加正则表达式规则
                         match action {
+                            RouteAction::Allow => {
+                                regex_allow_rules.push(CompiledRegexRule {
+                                    pattern,
+                                    regex,
+                                    action: RouteAction::Allow,
+                                    target: target.clone(),
+                                });
+                            }
                             RouteAction::Block => {
                                 regex_block_rules.push(CompiledRegexRule {
                                     pattern,
@@ -290,82 +802,315 @@ impl Router {
         }
 
         // 创建正则表达式预筛选映射
-        let regex_block_prefilter = Self::build_regex_prefilter(&regex_block_rules);
-        let regex_forward_prefilter = Self::build_regex_prefilter(&regex_forward_rules);
-
-        // 更新路由规则数量指标
-        METRICS
-            .route_rules_count()
-            .with_label_values(&[rule_type_labels::EXACT, rule_source_labels::STATIC])
-            .set((exact_block_rules.len() + exact_forward_rules.len()) as i64);
-
-        let wildcard_count = wildcard_block_rules.len()
-            + wildcard_forward_rules.len()
-            + global_wildcard_block_rule.is_some() as usize
-            + global_wildcard_forward_rule.is_some() as usize;
-
-        METRICS
-            .route_rules_count()
-            .with_label_values(&[rule_type_labels::WILDCARD, rule_source_labels::STATIC])
-            .set(wildcard_count as i64);
-
-        METRICS
-            .route_rules_count()
-            .with_label_values(&[rule_type_labels::REGEX, rule_source_labels::STATIC])
-            .set((regex_block_rules.len() + regex_forward_rules.len()) as i64);
+        let regex_allow_prefilter = RegexPrefilter::build(Self::patterns(&regex_allow_rules));
+        let regex_block_prefilter = RegexPrefilter::build(Self::patterns(&regex_block_rules));
+        let regex_forward_prefilter = RegexPrefilter::build(Self::patterns(&regex_forward_rules));
+
+        let label_wildcard_allow_prefilter =
+            Self::build_label_wildcard_prefilter(&label_wildcard_allow_rules);
+        let label_wildcard_block_prefilter =
+            Self::build_label_wildcard_prefilter(&label_wildcard_block_rules);
+        let label_wildcard_forward_prefilter =
+            Self::build_label_wildcard_prefilter(&label_wildcard_forward_rules);
+
+        // 没有 allow 规则时跳过每次查询的 allow 查找
+        let has_allow_rules = !exact_allow_rules.is_empty()
+            || !wildcard_allow_rules.is_empty()
+            || global_wildcard_allow_rule.is_some()
+            || !label_wildcard_allow_rules.is_empty()
+            || !regex_allow_rules.is_empty();
 
         let router = Self {
+            has_allow_rules,
+            exact_allow_rules,
             exact_block_rules,
             exact_forward_rules,
+            wildcard_allow_rules,
             wildcard_block_rules,
             wildcard_forward_rules,
+            global_wildcard_allow_rule,
             global_wildcard_block_rule,
             global_wildcard_forward_rule,
+            label_wildcard_allow_rules,
+            label_wildcard_block_rules,
+            label_wildcard_forward_rules,
+            label_wildcard_allow_prefilter,
+            label_wildcard_block_prefilter,
+            label_wildcard_forward_prefilter,
+            regex_allow_rules,
             regex_block_rules,
             regex_forward_rules,
+            regex_allow_prefilter,
             regex_block_prefilter,
             regex_forward_prefilter,
+            default_upstream_group: None,
+            strict_allowlist: false,
+            max_labels: None,
+            rule_sources: Vec::new(),
+            client_rules: Vec::new(),
         };
 
         Ok(router)
     }
 
+    // 设置默认上游组
+    pub fn with_default_upstream_group(mut self, group: Option<String>) -> Self {
+        self.default_upstream_group = group;
+        self
+    }
+
+    // 设置严格白名单模式
+    pub fn with_strict_allowlist(mut self, strict_allowlist: bool) -> Self {
+        if strict_allowlist && self.global_wildcard_forward_rule.is_some() {
+            warn!("Ignoring global wildcard forward rule '*' in strict allowlist mode");
+        }
+        self.strict_allowlist = strict_allowlist;
+        self.client_rules = std::mem::take(&mut self.client_rules)
+            .into_iter()
+            .map(|mut rules| {
+                rules.router = rules.router.with_strict_allowlist(strict_allowlist);
+                rules
+            })
+            .collect();
+        self
+    }
+
+    // 设置查询名称最大标签数
+    pub fn with_max_labels(mut self, max_labels: Option<usize>) -> Self {
+        self.max_labels = max_labels;
+        for rules in &mut self.client_rules {
+            rules.router.max_labels = max_labels;
+        }
+        self
+    }
+
+    // 应用路由配置：默认上游组、严格白名单模式与最大标签数
+    pub fn with_routing_config(self, routing_config: &RoutingConfig) -> Self {
+        self.with_default_upstream_group(routing_config.default_upstream_group.clone())
+            .with_strict_allowlist(routing_config.strict_allowlist)
+            .with_max_labels(routing_config.max_labels)
+    }
+
+    // 设置规则来源汇总
+    pub fn with_rule_sources(mut self, rule_sources: Vec<RuleSourceSummary>) -> Self {
+        self.rule_sources = rule_sources;
+        self
+    }
+
+    // 获取规则来源汇总
+    pub fn rule_sources(&self) -> &[RuleSourceSummary] {
+        &self.rule_sources
+    }
+
+    // 获取默认上游组
+    pub fn default_upstream_group(&self) -> Option<&str> {
+        self.default_upstream_group.as_deref()
+    }
+
+    // 按匹配类型与动作统计生效规则数量（标签通配符与全局通配符计入 wildcard，包含客户端网段规则）
+    pub fn rule_counts(&self) -> Vec<RuleCount> {
+        let mut counts = self.own_rule_counts();
+        for rules in &self.client_rules {
+            for (total, count) in counts.iter_mut().zip(rules.router.own_rule_counts()) {
+                total.rules += count.rules;
+            }
+        }
+        counts
+    }
+
+    // 统计本路由引擎（不含客户端网段规则）的生效规则数量
+    fn own_rule_counts(&self) -> Vec<RuleCount> {
+        let count = |match_type, action: RouteAction, rules| RuleCount {
+            match_type,
+            action: action.into(),
+            rules,
+        };
+        vec![
+            count(
+                rule_type_labels::EXACT,
+                RouteAction::Allow,
+                self.exact_allow_rules.len(),
+            ),
+            count(
+                rule_type_labels::WILDCARD,
+                RouteAction::Allow,
+                self.wildcard_allow_rules.len()
+                    + self.label_wildcard_allow_rules.len()
+                    + self.global_wildcard_allow_rule.is_some() as usize,
+            ),
+            count(
+                rule_type_labels::REGEX,
+                RouteAction::Allow,
+                self.regex_allow_rules.len(),
+            ),
+            count(
+                rule_type_labels::EXACT,
+                RouteAction::Block,
+                self.exact_block_rules.len(),
+            ),
+            count(
+                rule_type_labels::WILDCARD,
+                RouteAction::Block,
+                self.wildcard_block_rules.len()
+                    + self.label_wildcard_block_rules.len()
+                    + self.global_wildcard_block_rule.is_some() as usize,
+            ),
+            count(
+                rule_type_labels::REGEX,
+                RouteAction::Block,
+                self.regex_block_rules.len(),
+            ),
+            count(
+                rule_type_labels::EXACT,
+                RouteAction::Forward,
+                self.exact_forward_rules.len(),
+            ),
+            count(
+                rule_type_labels::WILDCARD,
+                RouteAction::Forward,
+                self.wildcard_forward_rules.len()
+                    + self.label_wildcard_forward_rules.len()
+                    + self.global_wildcard_forward_rule.is_some() as usize,
+            ),
+            count(
+                rule_type_labels::REGEX,
+                RouteAction::Forward,
+                self.regex_forward_rules.len(),
+            ),
+        ]
+    }
+
+    // 按匹配优先级遍历所有生效规则：allow 先于 block 先于 forward，同一动作内为精确>通配符>标签通配符>正则>全局通配符
+    // 客户端网段规则排在最后，按网段分组依次列出
+    // 路由引擎构建后不再修改，同一实例多次遍历的顺序一致，可用于分页
+    pub fn rules(&self) -> impl Iterator<Item = RuleEntry<'_>> {
+        self.own_rules()
+            .chain(self.client_rules.iter().flat_map(|rules| {
+                rules.router.own_rules().map(|mut entry| {
+                    entry.client_cidrs = Some(rules.labels.as_slice());
+                    entry
+                })
+            }))
+    }
+
+    // 遍历本路由引擎（不含客户端网段规则）的生效规则
+    fn own_rules(&self) -> impl Iterator<Item = RuleEntry<'_>> {
+        self.rules_by_action(RouteAction::Allow)
+            .chain(self.rules_by_action(RouteAction::Block))
+            .chain(self.rules_by_action(RouteAction::Forward))
+    }
+
+    // 遍历指定动作的生效规则
+    fn rules_by_action(&self, action: RouteAction) -> impl Iterator<Item = RuleEntry<'_>> {
+        let (exact, wildcard, label_wildcard, regex, global) = match action {
+            RouteAction::Allow => (
+                &self.exact_allow_rules,
+                &self.wildcard_allow_rules,
+                &self.label_wildcard_allow_rules,
+                &self.regex_allow_rules,
+                &self.global_wildcard_allow_rule,
+            ),
+            RouteAction::Block => (
+                &self.exact_block_rules,
+                &self.wildcard_block_rules,
+                &self.label_wildcard_block_rules,
+                &self.regex_block_rules,
+                &self.global_wildcard_block_rule,
+            ),
+            RouteAction::Forward => (
+                &self.exact_forward_rules,
+                &self.wildcard_forward_rules,
+                &self.label_wildcard_forward_rules,
+                &self.regex_forward_rules,
+                &self.global_wildcard_forward_rule,
+            ),
+        };
+
+        exact
+            .iter()
+            .map(move |(pattern, target)| {
+                RuleEntry::new(rule_type_labels::EXACT, action, pattern, target)
+            })
+            .chain(wildcard.values().map(move |rule| {
+                RuleEntry::new(
+                    rule_type_labels::WILDCARD,
+                    action,
+                    &rule.pattern,
+                    &rule.target,
+                )
+            }))
+            .chain(label_wildcard.iter().map(move |rule| {
+                RuleEntry::new(
+                    rule_type_labels::WILDCARD,
+                    action,
+                    &rule.pattern,
+                    &rule.target,
+                )
+            }))
+            .chain(regex.iter().map(move |rule| {
+                RuleEntry::new(rule_type_labels::REGEX, action, &rule.pattern, &rule.target)
+            }))
+            .chain(global.iter().map(move |rule| {
+                RuleEntry::new(
+                    rule_type_labels::WILDCARD,
+                    action,
+                    &rule.pattern,
+                    &rule.target,
+                )
+            }))
+    }
+
+    // 没有任何规则匹配时，生成转发到默认上游组的兜底匹配
+    fn default_group_match(&self, domain: &str) -> Option<RouteMatch> {
+        if self.strict_allowlist {
+            return None;
+        }
+        let group = self.default_upstream_group.as_ref()?;
+
+        debug!(
+            "Rule match: no rule matched '{}', forwarding to default upstream group: {}",
+            domain, group
+        );
+
+        Some(RouteMatch {
+            domain: domain.to_string(),
+            action: RouteAction::Forward,
+            target: Some(group.clone()),
+            weighted_targets: None,
+            fallback: None,
+            rule_type: rule_type_labels::DEFAULT,
+            pattern: String::new(),
+            client_scope: None,
+            rule_source: rule_source_labels::STATIC,
+        })
+    }
+
     // 尝试精确匹配规则
     fn try_exact_match(&self, domain: &str, action: RouteAction) -> Option<RouteMatch> {
         let rules = match action {
+            RouteAction::Allow => &self.exact_allow_rules,
             RouteAction::Block => &self.exact_block_rules,
             RouteAction::Forward => &self.exact_forward_rules,
         };
 
         if let Some(target) = rules.get(domain) {
-            let target_default = rule_type_labels::NO_TARGET;
-            let target_str = target
-                .as_ref()
-                .map(|s| s.as_str())
-                .unwrap_or(target_default);
+            let target_str = target.label();
 
             debug!(
                 "Rule match: Exact {:?} match '{}' -> Target: {}",
                 action, domain, target_str
             );
 
-            // 记录路由匹配指标
-            METRICS
-                .route_matches_total()
-                .with_label_values(&[
-                    rule_type_labels::EXACT,
-                    target_str,
-                    rule_source_labels::STATIC,
-                    <&'static str>::from(action),
-                ])
-                .inc();
-
             return Some(RouteMatch {
                 domain: domain.to_string(),
                 action,
-                target: target.as_ref().map(|arc_str| arc_str.to_string()),
+                target: target.group_name(),
+                weighted_targets: target.weighted.clone(),
+                fallback: target.fallback.clone(),
                 rule_type: rule_type_labels::EXACT,
                 pattern: domain.to_string(),
+                client_scope: None,
+                rule_source: target.source_label(),
             });
         }
 
@@ -373,14 +1118,23 @@ impl Router {
     }
 
     // 尝试通配符匹配规则
-    fn try_wildcard_match(&self, domain: &str, action: RouteAction) -> Option<RouteMatch> {
+    // `reversed` 为整个域名按标签反转后的结果，由调用方计算一次并在 block/forward 两轮匹配间复用；
+    // 各级后缀通过切片获得，每次查询只产生一次分配
+    fn try_wildcard_match(
+        &self,
+        domain: &str,
+        reversed: &str,
+        action: RouteAction,
+    ) -> Option<RouteMatch> {
         let rules = match action {
+            RouteAction::Allow => &self.wildcard_allow_rules,
             RouteAction::Block => &self.wildcard_block_rules,
             RouteAction::Forward => &self.wildcard_forward_rules,
         };
+        if rules.is_empty() {
+            return None;
+        }
 
-        let target_default = rule_type_labels::NO_TARGET;
-        let reversed = Self::reverse_domain_labels(domain);
         let mut end = reversed.len();
 
         // 先检查最长（最具体）的反转后缀，再逐步缩短：a.b.c -> c.b.a -> c.b -> c
@@ -388,11 +1142,7 @@ impl Router {
             let key = &reversed[..end];
 
             if let Some(rule) = rules.get(key) {
-                let target_str = rule
-                    .target
-                    .as_ref()
-                    .map(|s| s.as_str())
-                    .unwrap_or(target_default);
+                let target_str = rule.target.label();
 
                 debug!(
                     "Rule match: Wildcard {:?} match '{}' -> Pattern: '{}', Target: {}",
@@ -402,23 +1152,16 @@ impl Router {
                     target_str
                 );
 
-                // 记录路由匹配指标
-                METRICS
-                    .route_matches_total()
-                    .with_label_values(&[
-                        rule_type_labels::WILDCARD,
-                        target_str,
-                        rule_source_labels::STATIC,
-                        <&'static str>::from(action),
-                    ])
-                    .inc();
-
                 return Some(RouteMatch {
                     domain: domain.to_string(),
                     action,
-                    target: rule.target.as_ref().map(|arc_str| arc_str.to_string()),
+                    target: rule.target.group_name(),
+                    weighted_targets: rule.target.weighted.clone(),
+                    fallback: rule.target.fallback.clone(),
                     rule_type: rule_type_labels::WILDCARD,
                     pattern: rule.pattern.clone(),
+                    client_scope: None,
+                    rule_source: rule.target.source_label(),
                 });
             }
 
@@ -431,48 +1174,87 @@ impl Router {
         None
     }
 
-    // 尝试正则表达式匹配规则
-    fn try_regex_match(&self, domain: &str, action: RouteAction) -> Option<RouteMatch> {
+    // 尝试标签通配符匹配规则
+    fn try_label_wildcard_match(&self, domain: &str, action: RouteAction) -> Option<RouteMatch> {
         let (rules, prefilter) = match action {
-            RouteAction::Block => (&self.regex_block_rules, &self.regex_block_prefilter),
-            RouteAction::Forward => (&self.regex_forward_rules, &self.regex_forward_prefilter),
+            RouteAction::Allow => (
+                &self.label_wildcard_allow_rules,
+                &self.label_wildcard_allow_prefilter,
+            ),
+            RouteAction::Block => (
+                &self.label_wildcard_block_rules,
+                &self.label_wildcard_block_prefilter,
+            ),
+            RouteAction::Forward => (
+                &self.label_wildcard_forward_rules,
+                &self.label_wildcard_forward_prefilter,
+            ),
         };
 
         if rules.is_empty() {
             return None;
         }
 
-        let target_default = rule_type_labels::NO_TARGET;
+        // 字面标签必须与域名的某个标签完全相同，据此筛选候选规则；后定义的规则优先
+        let mut candidates: Vec<usize> = domain
+            .split(wildcards::DOT)
+            .filter_map(|label| prefilter.get(label))
+            .flatten()
+            .copied()
+            .collect();
 
-        // 使用预筛选优化正则表达式匹配：
-        // - domain 已在 find_match 中做了小写归一化，因此这里不再为每个 segment 额外分配 lowercased String。
-        // - 为了行为确定性以及“后定义优先”，对候选规则 idx 排序后逆序匹配。
-        let mut candidates: Vec<usize> = Vec::new();
-        for segment in domain.split('.') {
-            if segment.len() < 2 {
-                continue; // 跳过太短的片段
-            }
+        candidates.sort_unstable();
+        candidates.dedup();
 
-            if let Some(rule_indices) = prefilter.get(segment) {
-                candidates.extend(rule_indices.iter().copied());
+        for &rule_idx in candidates.iter().rev() {
+            let rule = &rules[rule_idx];
+            if rule.regex.is_match(domain) {
+                let target_str = rule.target.label();
+
+                debug!(
+                    "Rule match: Label wildcard {:?} match '{}' -> Pattern: '{}', Target: {}",
+                    action,
+                    domain,
+                    rule.pattern.as_str(),
+                    target_str
+                );
+
+                return Some(RouteMatch {
+                    domain: domain.to_string(),
+                    action,
+                    target: rule.target.group_name(),
+                    weighted_targets: rule.target.weighted.clone(),
+                    fallback: rule.target.fallback.clone(),
+                    rule_type: rule_type_labels::WILDCARD,
+                    pattern: rule.pattern.clone(),
+                    client_scope: None,
+                    rule_source: rule.target.source_label(),
+                });
             }
         }
 
-        if candidates.is_empty() {
+        None
+    }
+
+    // 尝试正则表达式匹配规则
+    fn try_regex_match(&self, domain: &str, action: RouteAction) -> Option<RouteMatch> {
+        let (rules, prefilter) = match action {
+            RouteAction::Allow => (&self.regex_allow_rules, &self.regex_allow_prefilter),
+            RouteAction::Block => (&self.regex_block_rules, &self.regex_block_prefilter),
+            RouteAction::Forward => (&self.regex_forward_rules, &self.regex_forward_prefilter),
+        };
+
+        if rules.is_empty() {
             return None;
         }
 
-        candidates.sort_unstable();
-        candidates.dedup();
+        // 使用预筛选优化正则表达式匹配，为了行为确定性以及“后定义优先”，对候选规则逆序匹配
+        let candidates = prefilter.candidates(domain);
 
         for &rule_idx in candidates.iter().rev() {
             let rule = &rules[rule_idx];
             if rule.regex.is_match(domain) {
-                let target_str = rule
-                    .target
-                    .as_ref()
-                    .map(|s| s.as_str())
-                    .unwrap_or(target_default);
+                let target_str = rule.target.label();
 
                 debug!(
                     "Rule match: Regex {:?} match '{}' -> Pattern: '{}', Target: {}",
@@ -482,23 +1264,16 @@ impl Router {
                     target_str
                 );
 
-                // 记录路由匹配指标
-                METRICS
-                    .route_matches_total()
-                    .with_label_values(&[
-                        rule_type_labels::REGEX,
-                        target_str,
-                        rule_source_labels::STATIC,
-                        <&'static str>::from(action),
-                    ])
-                    .inc();
-
                 return Some(RouteMatch {
                     domain: domain.to_string(),
                     action,
-                    target: rule.target.as_ref().map(|arc_str| arc_str.to_string()),
+                    target: rule.target.group_name(),
+                    weighted_targets: rule.target.weighted.clone(),
+                    fallback: rule.target.fallback.clone(),
                     rule_type: rule_type_labels::REGEX,
                     pattern: rule.pattern.clone(),
+                    client_scope: None,
+                    rule_source: rule.target.source_label(),
                 });
             }
         }
@@ -509,17 +1284,15 @@ impl Router {
     // 尝试全局通配符匹配规则
     fn try_global_wildcard_match(&self, domain: &str, action: RouteAction) -> Option<RouteMatch> {
         let global_rule = match action {
+            RouteAction::Allow => &self.global_wildcard_allow_rule,
             RouteAction::Block => &self.global_wildcard_block_rule,
+            // 严格白名单模式下不存在隐式兜底转发
+            RouteAction::Forward if self.strict_allowlist => return None,
             RouteAction::Forward => &self.global_wildcard_forward_rule,
         };
 
         if let Some(rule) = global_rule {
-            let target_default = rule_type_labels::NO_TARGET;
-            let target_str = rule
-                .target
-                .as_ref()
-                .map(|s| s.as_str())
-                .unwrap_or(target_default);
+            let target_str = rule.target.label();
 
             debug!(
                 "Rule match: Global wildcard {:?} match '{}' -> Pattern: '{}', Target: {}",
@@ -529,52 +1302,86 @@ impl Router {
                 target_str
             );
 
-            // 记录路由匹配指标
-            METRICS
-                .route_matches_total()
-                .with_label_values(&[
-                    rule_type_labels::WILDCARD,
-                    target_str,
-                    rule_source_labels::STATIC,
-                    <&'static str>::from(action),
-                ])
-                .inc();
-
             return Some(RouteMatch {
                 domain: domain.to_string(),
                 action,
-                target: rule.target.as_ref().map(|arc_str| arc_str.to_string()),
+                target: rule.target.group_name(),
+                weighted_targets: rule.target.weighted.clone(),
+                fallback: rule.target.fallback.clone(),
                 rule_type: rule_type_labels::WILDCARD,
                 pattern: rule.pattern.clone(),
+                client_scope: None,
+                rule_source: rule.target.source_label(),
             });
         }
 
         None
     }
 
-    // 查找匹配规则
-    //
-    // 查找顺序（优先级从高到低）：
-    // 1. 精确匹配 block 规则
-    // 2. 通配符 block 规则（按特定性从高到低）
-    // 3. 正则表达式 block 规则
-    // 4. 全局通配符 block 规则
-    // 5. 精确匹配 forward 规则
-    // 6. 通配符 forward 规则（按特定性从高到低）
-    // 7. 正则表达式 forward 规则
-    // 8. 全局通配符 forward 规则
-    //
-    // 这种优先级顺序确保：
-    // - 所有 block 规则优先于所有 forward 规则
-    // - 在同类规则中，遵循精确匹配 > 通配符匹配 > 正则匹配 > 全局通配符的优先级
+    // 检查名称是否命中 allow 规则
+    fn is_allowed(&self, domain: &str, reversed: &str) -> bool {
+        if !self.has_allow_rules {
+            return false;
+        }
+        self.try_exact_match(domain, RouteAction::Allow).is_some()
+            || self
+                .try_wildcard_match(domain, reversed, RouteAction::Allow)
+                .is_some()
+            || self
+                .try_label_wildcard_match(domain, RouteAction::Allow)
+                .is_some()
+            || self.try_regex_match(domain, RouteAction::Allow).is_some()
+            || self
+                .try_global_wildcard_match(domain, RouteAction::Allow)
+                .is_some()
+    }
+
+    // 按优先级检查所有 block 规则
+    fn try_block_match(&self, domain: &str, reversed: &str) -> Option<RouteMatch> {
+        self.try_exact_match(domain, RouteAction::Block)
+            .or_else(|| self.try_wildcard_match(domain, reversed, RouteAction::Block))
+            .or_else(|| self.try_label_wildcard_match(domain, RouteAction::Block))
+            .or_else(|| self.try_regex_match(domain, RouteAction::Block))
+            .or_else(|| self.try_global_wildcard_match(domain, RouteAction::Block))
+    }
+
+    // 在客户端网段规则中查找匹配：依次检查客户端所在网段的规则，未命中时返回 None，
+    // 由调用方继续使用 find_match 匹配不限客户端的规则。
+    // 不限客户端的 block 规则（未被不限客户端的 allow 规则豁免）优先于所有客户端网段规则，
+    // 此时同样返回 None，使全局拦截对所有客户端生效
+    pub fn find_client_match(&self, query_name: &Name, client: IpAddr) -> Option<RouteMatch> {
+        // 双栈监听时 IPv4 客户端以 IPv4 映射地址呈现，统一还原为 IPv4 再匹配
+        let client = client.to_canonical();
+        let mut candidates = self
+            .client_rules
+            .iter()
+            .filter(|rules| rules.contains(&client))
+            .peekable();
+        candidates.peek()?;
+        if self.is_globally_blocked(query_name) {
+            return None;
+        }
+        candidates.find_map(|rules| {
+            let mut route_match = rules.router.find_match(query_name).ok()?;
+            route_match.client_scope = Some(rules.scope.clone());
+            Some(route_match)
+        })
+    }
+
+    // 名称是否命中本路由引擎的 block 规则且未被 allow 规则豁免
+    fn is_globally_blocked(&self, query_name: &Name) -> bool {
+        let Some(domain) = Self::match_domain(query_name) else {
+            return false;
+        };
+        let reversed = Self::reverse_domain_labels(&domain);
+        !self.is_allowed(&domain, &reversed) && self.try_block_match(&domain, &reversed).is_some()
+    }
+
+    // 将查询名称转换为用于规则匹配的小写域名（不含末尾的点），空名称返回 None
     //
-    // 整体查找匹配规则
-    // 精确匹配 block > 通配符 block > 正则 block > 全局通配符 block > 精确匹配 forward > 通配符 forward > 正则 forward > 全局通配符 forward
-    pub fn find_match(&self, query_name: &Name) -> Result<RouteMatch, AppError> {
-        // 将查询名称转换为字符串（可能包含非 ASCII label），再做大小写归一化以便匹配。
-        //
-        // 性能：绝大多数域名是 ASCII（punycode 亦为 ASCII），这里用 make_ascii_lowercase 原地转换，
-        // 避免 `to_lowercase()` 产生的新 String 分配；非 ASCII 则回退 Unicode lower 以保持语义。
+    // 性能：绝大多数域名是 ASCII（punycode 亦为 ASCII），这里用 make_ascii_lowercase 原地转换，
+    // 避免 `to_lowercase()` 产生的新 String 分配；非 ASCII 则回退 Unicode lower 以保持语义。
+    fn match_domain(query_name: &Name) -> Option<String> {
         let mut domain = query_name.to_string();
 
         // 移除末尾可能存在的点，确保正则表达式等能正确匹配
@@ -583,7 +1390,7 @@ impl Router {
         }
 
         if domain.is_empty() {
-            return Err(AppError::NoRouteMatch(domain));
+            return None;
         }
 
         if domain.is_ascii() {
@@ -591,41 +1398,125 @@ impl Router {
         } else {
             domain = domain.to_lowercase();
         }
+        Some(domain)
+    }
 
-        // 1. 先检查所有 Block 规则
-        // 精确匹配 block > 通配符 block > 正则 block > 全局通配符 block
-        if let Some(match_result) = self.try_exact_match(&domain, RouteAction::Block) {
-            return Ok(match_result);
-        }
+    // 查找匹配规则
+    //
+    // 查找顺序（优先级从高到低）：
+    // 0. allow 规则（精确 > 通配符 > 标签通配符 > 正则 > 全局通配符）：命中时跳过所有 block 规则
+    // 1. 精确匹配 block 规则
+    // 2. 通配符 block 规则（按特定性从高到低）
+    // 3. 标签通配符 block 规则（如 "ads.*.example.com"）
+    // 4. 正则表达式 block 规则
+    // 5. 全局通配符 block 规则
+    // 6. 精确匹配 forward 规则
+    // 7. 通配符 forward 规则（按特定性从高到低）
+    // 8. 标签通配符 forward 规则
+    // 9. 正则表达式 forward 规则
+    // 10. 全局通配符 forward 规则（严格白名单模式下忽略）
+    // 11. 默认上游组（若已配置，严格白名单模式下忽略）
+    //
+    // 这种优先级顺序确保：
+    // - allow 规则可以为个别名称豁免范围更大的 block 规则
+    // - 所有 block 规则优先于所有 forward 规则
+    // - 在同类规则中，遵循精确匹配 > 通配符匹配 > 标签通配符匹配 > 正则匹配 > 全局通配符的优先级
+    //
+    // 整体查找匹配规则
+    // 精确匹配 block > 通配符 block > 标签通配符 block > 正则 block > 全局通配符 block > 精确匹配 forward > 通配符 forward > 标签通配符 forward > 正则 forward > 全局通配符 forward
+    pub fn find_match(&self, query_name: &Name) -> Result<RouteMatch, AppError> {
+        let (route_match, allowed) = self.match_rules(query_name)?;
+        Self::record_match(&route_match, allowed);
+        Ok(route_match)
+    }
 
-        if let Some(match_result) = self.try_wildcard_match(&domain, RouteAction::Block) {
-            return Ok(match_result);
-        }
+    // 记录路由匹配指标：每次查询只记录最终生效的规则，
+    // 名称因 allow 规则豁免拦截而转发时动作标签记为 allow
+    fn record_match(route_match: &RouteMatch, allowed: bool) {
+        let target = match (&route_match.target, &route_match.weighted_targets) {
+            (Some(group), _) => group.as_str(),
+            (None, Some(_)) => rule_type_labels::WEIGHTED_TARGET,
+            (None, None) => rule_type_labels::NO_TARGET,
+        };
+        let action = if allowed && route_match.action == RouteAction::Forward {
+            rule_action_labels::ALLOW
+        } else {
+            route_match.action.into()
+        };
+        metrics::backend().increment(
+            CounterMetric::RouteMatches,
+            &[
+                route_match.rule_type,
+                target,
+                route_match.rule_source,
+                action,
+            ],
+        );
+    }
 
-        if let Some(match_result) = self.try_regex_match(&domain, RouteAction::Block) {
-            return Ok(match_result);
+    // 按优先级匹配规则，同时返回名称是否命中 allow 规则
+    fn match_rules(&self, query_name: &Name) -> Result<(RouteMatch, bool), AppError> {
+        // 标签数过多的名称（例如随机子域名洪泛攻击）跳过规则匹配，避免逐级剥离标签的开销
+        if let Some(max_labels) = self.max_labels {
+            if query_name.num_labels() as usize > max_labels {
+                let domain = query_name.to_ascii();
+                debug!(
+                    "Query name exceeds max_labels {} ({} labels), skipping rule matching",
+                    max_labels,
+                    query_name.num_labels()
+                );
+                return self
+                    .default_group_match(&domain)
+                    .map(|route_match| (route_match, false))
+                    .ok_or(AppError::TooManyLabels(domain));
+            }
         }
 
-        if let Some(match_result) = self.try_global_wildcard_match(&domain, RouteAction::Block) {
-            return Ok(match_result);
+        // 将查询名称转换为字符串（可能包含非 ASCII label），再做大小写归一化以便匹配。
+        let Some(domain) = Self::match_domain(query_name) else {
+            return Err(AppError::NoRouteMatch(String::new()));
+        };
+
+        // 反转后的域名供两轮通配符匹配复用
+        let reversed = Self::reverse_domain_labels(&domain);
+
+        // 0. 命中 allow 规则的名称豁免所有 Block 规则
+        let allowed = self.is_allowed(&domain, &reversed);
+        if !allowed {
+            // 1. 先检查所有 Block 规则
+            // 精确匹配 block > 通配符 block > 标签通配符 block > 正则 block > 全局通配符 block
+            if let Some(match_result) = self.try_block_match(&domain, &reversed) {
+                return Ok((match_result, false));
+            }
         }
 
         // 2. 再检查所有 Forward 规则
-        // 精确匹配 forward > 通配符 forward > 正则 forward > 全局通配符 forward
+        // 精确匹配 forward > 通配符 forward > 标签通配符 forward > 正则 forward > 全局通配符 forward
         if let Some(match_result) = self.try_exact_match(&domain, RouteAction::Forward) {
-            return Ok(match_result);
+            return Ok((match_result, allowed));
         }
 
-        if let Some(match_result) = self.try_wildcard_match(&domain, RouteAction::Forward) {
-            return Ok(match_result);
+        if let Some(match_result) =
+            self.try_wildcard_match(&domain, &reversed, RouteAction::Forward)
+        {
+            return Ok((match_result, allowed));
+        }
+
+        if let Some(match_result) = self.try_label_wildcard_match(&domain, RouteAction::Forward) {
+            return Ok((match_result, allowed));
         }
 
         if let Some(match_result) = self.try_regex_match(&domain, RouteAction::Forward) {
-            return Ok(match_result);
+            return Ok((match_result, allowed));
         }
 
         if let Some(match_result) = self.try_global_wildcard_match(&domain, RouteAction::Forward) {
-            return Ok(match_result);
+            return Ok((match_result, allowed));
+        }
+
+        // 3. 最后兜底到默认上游组
+        if let Some(match_result) = self.default_group_match(&domain) {
+            return Ok((match_result, allowed));
         }
 
         // 没有匹配的规则
@@ -637,6 +1528,7 @@ impl Router {
 impl From<RouteAction> for &'static str {
     fn from(action: RouteAction) -> Self {
         match action {
+            RouteAction::Allow => rule_action_labels::ALLOW,
             RouteAction::Block => rule_action_labels::BLOCK,
             RouteAction::Forward => rule_action_labels::FORWARD,
         }
@@ -1,15 +1,23 @@
+use crate::connection_limit::ConnectionLimiter;
 use crate::error::AppError;
-use crate::handler::RequestHandler as DnsRequestHandler;
-use crate::metrics::METRICS;
-use crate::r#const::{error_labels, protocol_labels};
+use crate::extended_error::extended_error_option;
+use crate::handler::{RequestContext, RequestHandler as DnsRequestHandler};
+use crate::metrics::{self, CounterMetric, HistogramMetric};
+use crate::r#const::{error_labels, protocol_labels, server_defaults, upstream_labels};
 use hickory_proto::op::{Header, Message, MessageType, OpCode, ResponseCode};
-use hickory_proto::serialize::binary::{BinEncodable, BinEncoder};
-use hickory_server::authority::MessageResponseBuilder;
-use hickory_server::server::{Request, RequestHandler, ResponseHandler, ResponseInfo};
+use hickory_proto::rr::Record;
+use hickory_proto::serialize::binary::{BinDecodable, BinEncodable, BinEncoder};
+use hickory_server::authority::{MessageRequest, MessageResponse, MessageResponseBuilder};
+use hickory_server::server::{
+    Protocol as ServerProtocol, Request, RequestHandler, ResponseHandler, ResponseInfo,
+};
+use socket2::{Domain, Protocol as SocketProtocol, Socket, Type};
 use std::net::SocketAddr;
-use std::sync::Arc;
-use std::time::Instant;
-use tokio::net::{TcpListener, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinSet;
 use tokio_graceful_shutdown::{IntoSubsystem, SubsystemHandle};
 use tracing::{debug, error, info, warn};
 
@@ -18,22 +26,39 @@ use tracing::{debug, error, info, warn};
 pub struct HandlerAdapter {
     // 内部请求处理器
     handler: Arc<DnsRequestHandler>,
+    // UDP 响应大小上限（字节）
+    udp_max_response_size: Option<usize>,
 }
 
 #[doc(hidden)]
 pub fn parse_request_message(request: &Request) -> Result<Message, AppError> {
+    let buffer = encode_request(request)?;
+    Ok(Message::from_vec(&buffer)?)
+}
+
+// 将请求重新编码为二进制报文
+fn encode_request(request: &Request) -> Result<Vec<u8>, AppError> {
     let mut buffer = Vec::with_capacity(512);
     {
         let mut encoder = BinEncoder::new(&mut buffer);
         request.emit(&mut encoder)?;
     }
-    Ok(Message::from_vec(&buffer)?)
+    Ok(buffer)
 }
 
 impl HandlerAdapter {
     // 创建新的处理器适配器
     pub fn new(handler: Arc<DnsRequestHandler>) -> Self {
-        Self { handler }
+        Self {
+            handler,
+            udp_max_response_size: None,
+        }
+    }
+
+    // 设置 UDP 响应大小上限，与客户端通告的缓冲区取较小值
+    pub fn with_udp_max_response_size(mut self, udp_max_response_size: Option<usize>) -> Self {
+        self.udp_max_response_size = udp_max_response_size;
+        self
     }
 }
 
@@ -55,20 +80,20 @@ impl RequestHandler for HandlerAdapter {
         };
 
         // 增加请求计数
-        METRICS
-            .dns_requests_total()
-            .with_label_values(&[protocol])
-            .inc();
+        metrics::backend().increment(CounterMetric::DnsRequests, &[protocol]);
+
+        // 记录客户端查询统计
+        self.handler.record_client(request.src().ip());
 
         // 检查是否为查询请求或支持的操作码
         if request.op_code() != OpCode::Query {
             debug!("Unsupported operation code: {:?}", request.op_code());
 
             // 记录错误
-            METRICS
-                .dns_request_errors_total()
-                .with_label_values(&[error_labels::UNSUPPORTED_OPCODE])
-                .inc();
+            metrics::backend().increment(
+                CounterMetric::DnsRequestErrors,
+                &[error_labels::UNSUPPORTED_OPCODE],
+            );
 
             let mut header = Header::new();
             header.set_id(request.id());
@@ -91,10 +116,10 @@ impl RequestHandler for HandlerAdapter {
             debug!("Unsupported message type: {:?}", request.message_type());
 
             // 记录错误
-            METRICS
-                .dns_request_errors_total()
-                .with_label_values(&[error_labels::UNSUPPORTED_MESSAGE_TYPE])
-                .inc();
+            metrics::backend().increment(
+                CounterMetric::DnsRequestErrors,
+                &[error_labels::UNSUPPORTED_MESSAGE_TYPE],
+            );
 
             let mut header = Header::new();
             header.set_id(request.id());
@@ -125,15 +150,25 @@ impl RequestHandler for HandlerAdapter {
             request.src()
         );
 
-        let message = match parse_request_message(request) {
-            Ok(message) => message,
+        let message = match encode_request(request)
+            .and_then(|buffer| Ok((Message::from_vec(&buffer)?, buffer.len())))
+        {
+            Ok((message, request_size)) => {
+                // 记录请求大小
+                metrics::backend().observe(
+                    HistogramMetric::DnsRequestBytes,
+                    &[protocol],
+                    request_size as f64,
+                );
+                message
+            }
             Err(e) => {
                 error!("Failed to parse request message: {}", e);
 
-                METRICS
-                    .dns_request_errors_total()
-                    .with_label_values(&[error_labels::REQUEST_ERROR])
-                    .inc();
+                metrics::backend().increment(
+                    CounterMetric::DnsRequestErrors,
+                    &[error_labels::REQUEST_ERROR],
+                );
 
                 let mut header = Header::new();
                 header.set_id(request.id());
@@ -145,10 +180,11 @@ impl RequestHandler for HandlerAdapter {
 
                 // 记录处理时间
                 let duration = start_time.elapsed();
-                METRICS
-                    .dns_request_duration_seconds()
-                    .with_label_values(&[protocol, query_type.to_string().as_str()])
-                    .observe(duration.as_secs_f64());
+                metrics::backend().observe(
+                    HistogramMetric::DnsRequestDuration,
+                    &[protocol, query_type.to_string().as_str()],
+                    duration.as_secs_f64(),
+                );
 
                 return response_handler
                     .send_response(response)
@@ -161,32 +197,87 @@ impl RequestHandler for HandlerAdapter {
         };
 
         // 异步处理请求
-        match self.handler.handle_request(&message).await {
+        let context = RequestContext::from_client(request.src()).with_protocol(protocol);
+        match self
+            .handler
+            .handle_request_with_context(&message, &context)
+            .await
+        {
             Ok(result) => {
                 // 构建响应
-                let header = *result.header();
+                let mut header = *result.header();
 
                 // 记录响应码指标
-                METRICS
-                    .dns_response_codes_total()
-                    .with_label_values(&[header.response_code().to_string().as_str()])
-                    .inc();
+                metrics::backend().increment(
+                    CounterMetric::DnsResponseCodes,
+                    &[header.response_code().to_string().as_str()],
+                );
 
-                let builder = MessageResponseBuilder::from_message_request(request);
+                // 记录响应大小
+                let response_size = result.to_vec().map(|bytes| bytes.len()).ok();
+                if let Some(response_size) = response_size {
+                    metrics::backend().observe(
+                        HistogramMetric::DnsResponseBytes,
+                        &[protocol],
+                        response_size as f64,
+                    );
+                }
+
+                // UDP 响应超过客户端通告的缓冲区大小时截断：设置 TC 位并清空各记录段，客户端将改用 TCP 重试
+                // 客户端未携带 EDNS 或通告值小于 512 时按 512 字节计（RFC 1035），配置了上限时取较小值
+                let udp_limit = match self.udp_max_response_size {
+                    Some(max) => max.min(message.max_payload() as usize),
+                    None => message.max_payload() as usize,
+                };
+                let truncated = protocol == protocol_labels::UDP
+                    && response_size.is_some_and(|size| size > udp_limit);
+                if truncated {
+                    let group = context.upstream_group().unwrap_or(upstream_labels::NONE);
+                    debug!(
+                        "Truncating {} byte response for {} to UDP limit of {} bytes (group: {})",
+                        response_size.unwrap_or_default(),
+                        query_name,
+                        udp_limit,
+                        group
+                    );
+                    metrics::backend().increment(CounterMetric::TruncatedResponses, &[group]);
+                    header.set_truncated(true);
+                    header.set_answer_count(0);
+                    header.set_name_server_count(0);
+                    header.set_additional_count(0);
+                }
+                let sections: [&[Record]; 3] = if truncated {
+                    [&[], &[], &[]]
+                } else {
+                    [
+                        result.answers(),
+                        result.name_servers(),
+                        result.additionals(),
+                    ]
+                };
+
+                let mut builder = MessageResponseBuilder::from_message_request(request);
+                // 启用 EDNS 透传或携带扩展错误时保留响应的 OPT 记录及其选项
+                if self.handler.edns_passthrough() || extended_error_option(&result).is_some() {
+                    if let Some(edns) = result.extensions() {
+                        builder.edns(edns.clone());
+                    }
+                }
                 let response = builder.build(
                     header,
-                    result.answers().iter(),
-                    result.name_servers().iter(),
-                    result.additionals().iter(),
+                    sections[0].iter(),
+                    sections[1].iter(),
+                    sections[2].iter(),
                     None, // 不传递扩展信息
                 );
 
                 // 记录处理时间
                 let duration = start_time.elapsed();
-                METRICS
-                    .dns_request_duration_seconds()
-                    .with_label_values(&[protocol, query_type.to_string().as_str()])
-                    .observe(duration.as_secs_f64());
+                metrics::backend().observe(
+                    HistogramMetric::DnsRequestDuration,
+                    &[protocol, query_type.to_string().as_str()],
+                    duration.as_secs_f64(),
+                );
 
                 response_handler
                     .send_response(response)
@@ -202,10 +293,10 @@ impl RequestHandler for HandlerAdapter {
                 error!("Error processing DNS request: {}", e);
 
                 // 记录错误
-                METRICS
-                    .dns_request_errors_total()
-                    .with_label_values(&[error_labels::HANDLER_ERROR])
-                    .inc();
+                metrics::backend().increment(
+                    CounterMetric::DnsRequestErrors,
+                    &[error_labels::HANDLER_ERROR],
+                );
 
                 let mut header = Header::new();
                 header.set_id(request.id());
@@ -217,10 +308,11 @@ impl RequestHandler for HandlerAdapter {
 
                 // 记录处理时间
                 let duration = start_time.elapsed();
-                METRICS
-                    .dns_request_duration_seconds()
-                    .with_label_values(&[protocol, query_type.to_string().as_str()])
-                    .observe(duration.as_secs_f64());
+                metrics::backend().observe(
+                    HistogramMetric::DnsRequestDuration,
+                    &[protocol, query_type.to_string().as_str()],
+                    duration.as_secs_f64(),
+                );
 
                 response_handler
                     .send_response(response)
@@ -246,6 +338,455 @@ pub struct DnsServerConfig {
     pub tcp_timeout: u64,
     // HTTP空闲超时时间（秒）
     pub http_timeout: u64,
+    // TCP监听套接字选项
+    pub tcp_listener: TcpListenerOptions,
+    // DNS over TCP 最大并发连接数（None 表示不限制）
+    pub max_tcp_connections: Option<usize>,
+    // 是否要求 UDP 与 TCP 都绑定成功（否则只要有一种协议可用即继续运行）
+    pub require_both_protocols: bool,
+    // 是否记录无法解析的请求报文（客户端地址与报文长度，debug 级别）
+    pub log_malformed_requests: bool,
+    // 额外的 IPv6 UDP 绑定地址（以 IPV6_V6ONLY 绑定）
+    pub udp_v6_bind_addr: Option<SocketAddr>,
+    // 额外的 IPv6 TCP 绑定地址（以 IPV6_V6ONLY 绑定）
+    pub tcp_v6_bind_addr: Option<SocketAddr>,
+    // IPv6 主绑定地址是否同时接收 IPv4 映射地址
+    pub dual_stack: bool,
+    // UDP 响应大小上限（字节），与客户端通告的缓冲区取较小值（None 表示不限制）
+    pub udp_max_response_size: Option<usize>,
+}
+
+// TCP监听套接字选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpListenerOptions {
+    // 是否启用 SO_REUSEADDR
+    pub reuse_addr: bool,
+    // 是否启用 SO_REUSEPORT（仅 Unix）
+    pub reuse_port: bool,
+    // 监听队列长度
+    pub backlog: u32,
+    // IPv6 套接字的 IPV6_V6ONLY 选项（None 表示沿用系统默认值）
+    pub only_v6: Option<bool>,
+}
+
+impl Default for TcpListenerOptions {
+    fn default() -> Self {
+        Self {
+            reuse_addr: true,
+            reuse_port: false,
+            backlog: server_defaults::DEFAULT_TCP_BACKLOG,
+            only_v6: None,
+        }
+    }
+}
+
+impl DnsServerConfig {
+    // UDP 绑定目标：主地址按 dual_stack 设置 IPV6_V6ONLY，额外的 IPv6 地址始终仅接收 IPv6
+    fn udp_bind_targets(&self) -> Vec<(SocketAddr, Option<bool>)> {
+        let primary = (
+            self.udp_bind_addr,
+            self.udp_bind_addr.is_ipv6().then_some(!self.dual_stack),
+        );
+        std::iter::once(primary)
+            .chain(self.udp_v6_bind_addr.map(|addr| (addr, Some(true))))
+            .collect()
+    }
+
+    // TCP 绑定目标：规则与 UDP 相同
+    fn tcp_bind_targets(&self) -> Vec<(SocketAddr, TcpListenerOptions)> {
+        let primary = TcpListenerOptions {
+            only_v6: self.tcp_bind_addr.is_ipv6().then_some(!self.dual_stack),
+            ..self.tcp_listener
+        };
+        let v6_only = TcpListenerOptions {
+            only_v6: Some(true),
+            ..self.tcp_listener
+        };
+        std::iter::once((self.tcp_bind_addr, primary))
+            .chain(self.tcp_v6_bind_addr.map(|addr| (addr, v6_only)))
+            .collect()
+    }
+
+    // 启动前探测 UDP/TCP 监听地址是否可绑定，尽早暴露端口冲突与权限问题
+    pub fn probe_bind(&self) -> Result<(), AppError> {
+        let udp = self
+            .udp_bind_targets()
+            .into_iter()
+            .try_for_each(|(addr, only_v6)| probe_udp_bind(addr, only_v6));
+        let tcp = self
+            .tcp_bind_targets()
+            .into_iter()
+            .try_for_each(|(addr, options)| probe_tcp_bind(addr, &options));
+        match (udp, tcp) {
+            (Ok(()), Ok(())) => Ok(()),
+            (Err(e), Ok(())) | (Ok(()), Err(e)) if !self.require_both_protocols => {
+                warn!("{}, continuing with the other protocol", e);
+                Ok(())
+            }
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        }
+    }
+
+    // 单一协议绑定失败时的处理：要求两种协议时返回错误，否则记录警告并跳过该协议
+    fn check_partial_bind<T>(
+        &self,
+        protocol: &'static str,
+        addr: SocketAddr,
+        source: std::io::Error,
+    ) -> Result<Option<T>, AppError> {
+        if self.require_both_protocols {
+            return Err(AppError::Bind {
+                protocol,
+                addr,
+                source,
+            });
+        }
+        warn!(
+            "DNS server continuing without {} listener (require_both_protocols is disabled)",
+            protocol
+        );
+        Ok(None)
+    }
+}
+
+// 探测 UDP 地址是否可绑定（绑定后立即释放）
+pub fn probe_udp_bind(addr: SocketAddr, only_v6: Option<bool>) -> Result<(), AppError> {
+    bind_udp_socket(addr, only_v6)
+        .map(drop)
+        .map_err(|source| AppError::Bind {
+            protocol: protocol_labels::UDP,
+            addr,
+            source,
+        })
+}
+
+// 探测 TCP 地址是否可监听（监听后立即释放）
+pub fn probe_tcp_bind(addr: SocketAddr, options: &TcpListenerOptions) -> Result<(), AppError> {
+    bind_tcp_socket(addr, options)
+        .map(drop)
+        .map_err(|source| AppError::Bind {
+            protocol: protocol_labels::TCP,
+            addr,
+            source,
+        })
+}
+
+// 使用 socket2 创建TCP监听器，以支持 SO_REUSEPORT 与自定义监听队列长度
+pub fn bind_tcp_listener(
+    addr: SocketAddr,
+    options: &TcpListenerOptions,
+) -> std::io::Result<TcpListener> {
+    TcpListener::from_std(bind_tcp_socket(addr, options)?.into())
+}
+
+// 使用 socket2 绑定 UDP 套接字，以便显式设置 IPv6 套接字的 IPV6_V6ONLY
+pub fn bind_udp_socket(
+    addr: SocketAddr,
+    only_v6: Option<bool>,
+) -> std::io::Result<std::net::UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(addr),
+        Type::DGRAM,
+        Some(SocketProtocol::UDP),
+    )?;
+    if let (true, Some(only_v6)) = (addr.is_ipv6(), only_v6) {
+        socket.set_only_v6(only_v6)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    Ok(socket.into())
+}
+
+// 创建并监听 TCP 套接字
+fn bind_tcp_socket(addr: SocketAddr, options: &TcpListenerOptions) -> std::io::Result<Socket> {
+    let socket = Socket::new(
+        Domain::for_address(addr),
+        Type::STREAM,
+        Some(SocketProtocol::TCP),
+    )?;
+    if let (true, Some(only_v6)) = (addr.is_ipv6(), options.only_v6) {
+        socket.set_only_v6(only_v6)?;
+    }
+    socket.set_reuse_address(options.reuse_addr)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(options.reuse_port)?;
+    #[cfg(not(unix))]
+    if options.reuse_port {
+        warn!("SO_REUSEPORT is not supported on this platform, ignoring reuse_port");
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(options.backlog.min(i32::MAX as u32) as i32)?;
+    Ok(socket)
+}
+
+// 自有监听循环的响应处理器：保存编码后的响应，由监听循环写回客户端
+#[derive(Clone)]
+struct BufferedResponseHandle {
+    response: Arc<Mutex<Option<Vec<u8>>>>,
+    // 传输协议（UDP 响应按客户端通告的缓冲区大小编码）
+    protocol: ServerProtocol,
+}
+
+impl BufferedResponseHandle {
+    // 创建指定传输协议的响应处理器
+    fn new(protocol: ServerProtocol) -> Self {
+        Self {
+            response: Arc::default(),
+            protocol,
+        }
+    }
+
+    // 取出已编码的响应
+    fn take(&self) -> Option<Vec<u8>> {
+        self.response
+            .lock()
+            .ok()
+            .and_then(|mut response| response.take())
+    }
+}
+
+#[async_trait::async_trait]
+impl ResponseHandler for BufferedResponseHandle {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> std::io::Result<ResponseInfo> {
+        // 与 hickory 一致：UDP 使用响应 EDNS 的负载大小，未携带 EDNS 时使用 RFC 6891 推荐值
+        let max_size = match self.protocol {
+            ServerProtocol::Udp => response
+                .get_edns()
+                .as_ref()
+                .map(|edns| edns.max_payload())
+                .unwrap_or(hickory_proto::udp::MAX_RECEIVE_BUFFER_SIZE as u16),
+            _ => u16::MAX,
+        };
+        let mut buffer = Vec::with_capacity(512);
+        let info = {
+            let mut encoder = BinEncoder::new(&mut buffer);
+            encoder.set_max_size(max_size);
+            response
+                .destructive_emit(&mut encoder)
+                .map_err(|e| std::io::Error::other(format!("error encoding message: {}", e)))?
+        };
+        if let Ok(mut slot) = self.response.lock() {
+            *slot = Some(buffer);
+        }
+        Ok(info)
+    }
+}
+
+// 受限 TCP 监听器的 accept 循环：达到连接上限后暂停 accept，新连接在监听队列中等待
+async fn serve_limited_tcp(
+    listener: TcpListener,
+    adapter: Arc<HandlerAdapter>,
+    idle_timeout: Duration,
+    limiter: ConnectionLimiter,
+    log_malformed: bool,
+) {
+    loop {
+        let permit = limiter.acquire().await;
+        let (stream, src) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Failed to accept TCP connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(
+                    server_defaults::ACCEPT_ERROR_BACKOFF_MS,
+                ))
+                .await;
+                continue;
+            }
+        };
+        let guard = permit.connected();
+        let adapter = adapter.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            if let Err(e) =
+                serve_tcp_connection(stream, src, adapter, idle_timeout, log_malformed).await
+            {
+                debug!("TCP connection from {} closed: {}", src, e);
+            }
+        });
+    }
+}
+
+// 处理单个 DNS over TCP 连接（RFC 1035 两字节长度前缀），空闲超时后关闭
+//
+// 流水线查询（RFC 7766 §6.2.1.1）并发处理，应答按完成顺序写回；
+// 单个连接同时处理的查询数受 MAX_TCP_PIPELINED_QUERIES 限制，达到上限后暂停读取
+async fn serve_tcp_connection(
+    stream: TcpStream,
+    src: SocketAddr,
+    adapter: Arc<HandlerAdapter>,
+    idle_timeout: Duration,
+    log_malformed: bool,
+) -> std::io::Result<()> {
+    let (mut reader, writer) = stream.into_split();
+    let writer = Arc::new(tokio::sync::Mutex::new(writer));
+    let in_flight = Arc::new(tokio::sync::Semaphore::new(
+        server_defaults::MAX_TCP_PIPELINED_QUERIES,
+    ));
+    let mut queries = JoinSet::new();
+
+    let result = loop {
+        // 信号量不会被关闭，获取许可不会失败
+        let Ok(permit) = in_flight.clone().acquire_owned().await else {
+            break Ok(());
+        };
+        let length = match tokio::time::timeout(idle_timeout, reader.read_u16()).await {
+            Ok(Ok(length)) => length,
+            // 客户端关闭连接或空闲超时
+            Ok(Err(_)) | Err(_) => break Ok(()),
+        };
+        let mut buffer = vec![0u8; length as usize];
+        match tokio::time::timeout(idle_timeout, reader.read_exact(&mut buffer)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => break Err(e),
+            Err(_) => break Err(std::io::Error::from(std::io::ErrorKind::TimedOut)),
+        }
+
+        let message = match MessageRequest::from_bytes(&buffer) {
+            Ok(message) => message,
+            Err(e) => {
+                record_malformed_request(
+                    protocol_labels::TCP,
+                    src,
+                    buffer.len(),
+                    &e,
+                    log_malformed,
+                );
+                break Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+            }
+        };
+        let adapter = adapter.clone();
+        let writer = writer.clone();
+        queries.spawn(async move {
+            let _permit = permit;
+            let request = Request::new(message, src, ServerProtocol::Tcp);
+            let response_handle = BufferedResponseHandle::new(ServerProtocol::Tcp);
+            adapter
+                .handle_request(&request, response_handle.clone())
+                .await;
+
+            if let Some(response) = response_handle.take() {
+                // 长度前缀与报文一次写出，避免并发应答交错
+                let mut frame = Vec::with_capacity(response.len() + 2);
+                frame.extend_from_slice(&(response.len() as u16).to_be_bytes());
+                frame.extend_from_slice(&response);
+                if let Err(e) = writer.lock().await.write_all(&frame).await {
+                    debug!("Failed to write TCP response to {}: {}", src, e);
+                }
+            }
+        });
+        // 回收已完成的查询任务
+        while queries.try_join_next().is_some() {}
+    };
+
+    // 停止读取后等待进行中的查询写回应答
+    while queries.join_next().await.is_some() {}
+    result
+}
+
+// 记录无法解析的请求报文
+fn record_malformed_request(
+    protocol: &str,
+    src: SocketAddr,
+    length: usize,
+    error: &dyn std::fmt::Display,
+    log_malformed: bool,
+) {
+    metrics::backend().increment(CounterMetric::DnsMalformedRequests, &[protocol]);
+    if log_malformed {
+        debug!(
+            "Malformed {} DNS request from {} ({} bytes): {}",
+            protocol,
+            src.ip(),
+            length,
+            error
+        );
+    }
+}
+
+// UDP 监听循环：解析失败的报文在交给处理器前计数，头部可读的查询返回 FORMERR
+async fn serve_udp(socket: UdpSocket, adapter: Arc<HandlerAdapter>, log_malformed: bool) {
+    let socket = Arc::new(socket);
+    let mut buffer = vec![0u8; hickory_proto::udp::MAX_RECEIVE_BUFFER_SIZE];
+    loop {
+        let (length, src) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("Failed to receive UDP packet: {}", e);
+                continue;
+            }
+        };
+        // 源端口为 0 的报文无法应答
+        if src.port() == 0 {
+            continue;
+        }
+        let packet = &buffer[..length];
+
+        let message = match MessageRequest::from_bytes(packet) {
+            Ok(message) => message,
+            Err(e) => {
+                record_malformed_request(protocol_labels::UDP, src, length, &e, log_malformed);
+                // 头部可读的查询返回 FORMERR；不应答响应报文，避免反射攻击
+                if let Ok(header) = Header::from_bytes(packet) {
+                    if header.message_type() == MessageType::Query {
+                        let response = Message::error_msg(
+                            header.id(),
+                            header.op_code(),
+                            ResponseCode::FormErr,
+                        );
+                        if let Ok(bytes) = response.to_vec() {
+                            let _ = socket.send_to(&bytes, src).await;
+                        }
+                    }
+                }
+                continue;
+            }
+        };
+        // 不处理响应报文，避免反射攻击
+        if message.message_type() == MessageType::Response {
+            continue;
+        }
+
+        let socket = socket.clone();
+        let adapter = adapter.clone();
+        tokio::spawn(async move {
+            let request = Request::new(message, src, ServerProtocol::Udp);
+            let response_handle = BufferedResponseHandle::new(ServerProtocol::Udp);
+            adapter
+                .handle_request(&request, response_handle.clone())
+                .await;
+            if let Some(response) = response_handle.take() {
+                if let Err(e) = socket.send_to(&response, src).await {
+                    debug!("Failed to send UDP response to {}: {}", src, e);
+                }
+            }
+        });
+    }
+}
+
+// 等待自有监听任务结束，未启动时永不完成
+async fn listener_done(task: Option<tokio::task::JoinHandle<()>>) {
+    match task {
+        Some(task) => {
+            let _ = task.await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+// 等待任一监听循环结束；返回时丢弃 JoinSet 以中止其余监听循环
+async fn first_done(mut loops: JoinSet<()>) {
+    loops.join_next().await;
 }
 
 // DNS 服务器
@@ -267,43 +808,104 @@ impl DnsServer {
 impl IntoSubsystem<AppError> for DnsServer {
     async fn run(self, subsys: SubsystemHandle) -> Result<(), AppError> {
         // 创建处理器适配器
-        let adapter = HandlerAdapter::new(self.handler.clone());
+        let adapter = HandlerAdapter::new(self.handler.clone())
+            .with_udp_max_response_size(self.config.udp_max_response_size);
 
         // 创建服务器实例
         let mut server = hickory_server::ServerFuture::new(adapter);
 
-        // 绑定 UDP 端口
-        let udp_socket = match UdpSocket::bind(self.config.udp_bind_addr).await {
-            Ok(socket) => {
-                info!("DNS server UDP listening on {}", self.config.udp_bind_addr);
-                socket
+        // 分别绑定 UDP 与 TCP 端口，是否允许只启用其中一种协议由配置决定
+        let mut udp_sockets = Vec::new();
+        for (addr, only_v6) in self.config.udp_bind_targets() {
+            match bind_udp_socket(addr, only_v6).and_then(UdpSocket::from_std) {
+                Ok(socket) => {
+                    info!("DNS server UDP listening on {}", addr);
+                    udp_sockets.push(socket);
+                }
+                Err(e) => {
+                    error!("Failed to bind UDP socket: {}", e);
+                    self.config
+                        .check_partial_bind::<()>(protocol_labels::UDP, addr, e)?;
+                }
             }
-            Err(e) => {
-                error!("Failed to bind UDP socket: {}", e);
-                return Err(AppError::Io(e));
+        }
+        let mut tcp_listeners = Vec::new();
+        for (addr, options) in self.config.tcp_bind_targets() {
+            match bind_tcp_listener(addr, &options) {
+                Ok(listener) => {
+                    info!("DNS server TCP listening on {}", addr);
+                    tcp_listeners.push(listener);
+                }
+                Err(e) => {
+                    error!("Failed to bind TCP listener: {}", e);
+                    self.config
+                        .check_partial_bind::<()>(protocol_labels::TCP, addr, e)?;
+                }
             }
-        };
-        server.register_socket(udp_socket);
+        }
+        if udp_sockets.is_empty() && tcp_listeners.is_empty() {
+            return Err(AppError::Internal(
+                "DNS server failed to bind both UDP and TCP".to_string(),
+            ));
+        }
 
-        // 绑定 TCP 端口
-        let tcp_listener = match TcpListener::bind(self.config.tcp_bind_addr).await {
-            Ok(listener) => {
-                info!("DNS server TCP listening on {}", self.config.tcp_bind_addr);
-                listener
+        // hickory 服务器是否注册了监听器（未注册时 block_until_done 会立即返回）
+        let mut server_registered = false;
+        let log_malformed = self.config.log_malformed_requests;
+
+        // UDP 使用自有的接收循环，以便统计无法解析的报文
+        let udp_task = (!udp_sockets.is_empty()).then(|| {
+            let adapter = Arc::new(
+                HandlerAdapter::new(self.handler.clone())
+                    .with_udp_max_response_size(self.config.udp_max_response_size),
+            );
+            let mut loops = JoinSet::new();
+            for udp_socket in udp_sockets {
+                loops.spawn(serve_udp(udp_socket, adapter.clone(), log_malformed));
             }
-            Err(e) => {
-                error!("Failed to bind TCP listener: {}", e);
-                return Err(AppError::Io(e));
-            }
-        };
+            tokio::spawn(first_done(loops))
+        });
 
         // 设置TCP超时
         let tcp_timeout = std::time::Duration::from_secs(self.config.tcp_timeout);
-        server.register_listener(tcp_listener, tcp_timeout);
+
+        // 配置了连接数上限时使用自有的 accept 循环，否则交给 hickory 处理
+        let tcp_task = match self.config.max_tcp_connections {
+            _ if tcp_listeners.is_empty() => None,
+            Some(max_connections) => {
+                info!("DNS server TCP connections limited to {}", max_connections);
+                // 所有 TCP 监听地址共享同一连接上限
+                let limiter = ConnectionLimiter::new(Some(max_connections), protocol_labels::TCP);
+                let adapter = Arc::new(
+                    HandlerAdapter::new(self.handler.clone())
+                        .with_udp_max_response_size(self.config.udp_max_response_size),
+                );
+                let mut loops = JoinSet::new();
+                for tcp_listener in tcp_listeners {
+                    loops.spawn(serve_limited_tcp(
+                        tcp_listener,
+                        adapter.clone(),
+                        tcp_timeout,
+                        limiter.clone(),
+                        log_malformed,
+                    ));
+                }
+                Some(tokio::spawn(first_done(loops)))
+            }
+            None => {
+                for tcp_listener in tcp_listeners {
+                    server.register_listener(tcp_listener, tcp_timeout);
+                }
+                server_registered = true;
+                None
+            }
+        };
+        let udp_abort = udp_task.as_ref().map(|task| task.abort_handle());
+        let tcp_abort = tcp_task.as_ref().map(|task| task.abort_handle());
 
         // 使用tokio::select!监听服务器和关闭信号
         tokio::select! {
-            result = server.block_until_done() => {
+            result = server.block_until_done(), if server_registered => {
                 if let Err(e) = result {
                     error!("DNS server error: {}", e);
                 } else {
@@ -311,9 +913,22 @@ impl IntoSubsystem<AppError> for DnsServer {
                 }
                 Ok(())
             }
+            _ = listener_done(udp_task) => {
+                info!("DNS server UDP listener stopped");
+                Ok(())
+            }
+            _ = listener_done(tcp_task) => {
+                info!("DNS server TCP listener stopped");
+                Ok(())
+            }
             _ = subsys.on_shutdown_requested() => {
                 info!("Shutdown requested, stopping DNS server");
 
+                // 停止自有的 UDP 接收循环与受限 TCP 监听器的 accept 循环
+                for abort in udp_abort.into_iter().chain(tcp_abort) {
+                    abort.abort();
+                }
+
                 // 使用timeout包装graceful shutdown
                 match tokio::time::timeout(
                     std::time::Duration::from_secs(15),
@@ -0,0 +1,478 @@
+// src/stats.rs
+
+use crate::r#const::{client_stats_limits, latency_histogram};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// 客户端查询计数条目
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ClientCount {
+    // 客户端 IP
+    pub client: IpAddr,
+    // 查询次数（衰减后）
+    pub queries: u64,
+}
+
+// 客户端查询统计（Top Talkers）
+// 使用分片并发计数表：查询路径只做无锁计数与插入，淘汰延后到周期维护中批量执行。
+// 维护时淘汰计数最小的客户端，其最大计数作为新客户端的初始计数下限（Space-Saving 算法），
+// 并周期性地将所有计数减半，使统计结果反映近期流量
+pub struct ClientStats {
+    // 客户端计数表
+    counts: DashMap<IpAddr, AtomicU64>,
+    // 新客户端继承的计数下限（上次淘汰的最大计数）
+    floor: AtomicU64,
+    // 最大跟踪客户端数量
+    capacity: usize,
+    // 衰减间隔
+    decay_interval: Duration,
+    // 创建时间（时间戳基准）
+    created: Instant,
+    // 上次维护时间（相对创建时间的毫秒数）
+    last_maintenance: AtomicU64,
+    // 上次衰减时间（相对创建时间的毫秒数）
+    last_decay: AtomicU64,
+}
+
+impl Default for ClientStats {
+    fn default() -> Self {
+        Self::new(
+            client_stats_limits::MAX_TRACKED_CLIENTS,
+            Duration::from_secs(client_stats_limits::DECAY_INTERVAL),
+        )
+    }
+}
+
+impl ClientStats {
+    // 创建客户端统计
+    pub fn new(capacity: usize, decay_interval: Duration) -> Self {
+        Self {
+            counts: DashMap::with_capacity(capacity.min(1024)),
+            floor: AtomicU64::new(0),
+            capacity: capacity.max(1),
+            decay_interval,
+            created: Instant::now(),
+            last_maintenance: AtomicU64::new(0),
+            last_decay: AtomicU64::new(0),
+        }
+    }
+
+    // 记录一次客户端查询
+    pub fn record(&self, client: IpAddr) {
+        self.maybe_maintain();
+
+        if let Some(count) = self.counts.get(&client) {
+            count.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        // 超出维护前允许的上限时暂不跟踪新客户端，等待下次维护腾出空间
+        if self.counts.len() >= self.max_len() {
+            return;
+        }
+        let floor = self.floor.load(Ordering::Relaxed);
+        self.counts
+            .entry(client)
+            .or_insert_with(|| AtomicU64::new(floor))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 获取查询次数最多的 N 个客户端
+    pub fn top(&self, n: usize) -> Vec<ClientCount> {
+        self.maintain();
+
+        let mut result: Vec<ClientCount> = self
+            .counts
+            .iter()
+            .map(|entry| ClientCount {
+                client: *entry.key(),
+                queries: entry.value().load(Ordering::Relaxed),
+            })
+            .collect();
+
+        // 按计数降序排列，计数相同时按 IP 排序以保证结果稳定
+        result.sort_by(|a, b| b.queries.cmp(&a.queries).then(a.client.cmp(&b.client)));
+        result.truncate(n);
+        result
+    }
+
+    // 当前跟踪的客户端数量
+    pub fn len(&self) -> usize {
+        self.maintain();
+        self.counts.len()
+    }
+
+    // 是否没有跟踪任何客户端
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // 立即执行一次衰减：所有计数减半，移除归零的客户端
+    pub fn decay(&self) {
+        self.apply_decay();
+        self.last_decay
+            .store(self.elapsed_millis(), Ordering::Relaxed);
+    }
+
+    // 清空统计
+    pub fn clear(&self) {
+        self.counts.clear();
+        self.floor.store(0, Ordering::Relaxed);
+        self.last_decay
+            .store(self.elapsed_millis(), Ordering::Relaxed);
+    }
+
+    // 维护前允许跟踪的客户端数量上限
+    fn max_len(&self) -> usize {
+        self.capacity
+            .saturating_mul(client_stats_limits::OVERFLOW_FACTOR)
+    }
+
+    // 相对创建时间的毫秒数
+    fn elapsed_millis(&self) -> u64 {
+        self.created.elapsed().as_millis() as u64
+    }
+
+    // 到达维护间隔时由一个调用方执行维护，其余调用方不等待
+    fn maybe_maintain(&self) {
+        let now = self.elapsed_millis();
+        let last = self.last_maintenance.load(Ordering::Relaxed);
+        if now.saturating_sub(last) < client_stats_limits::MAINTENANCE_INTERVAL_MS {
+            return;
+        }
+        if self
+            .last_maintenance
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.maintain();
+        }
+    }
+
+    // 执行维护：到期时衰减，并淘汰超出容量的客户端
+    fn maintain(&self) {
+        self.maybe_decay();
+        self.evict_overflow();
+    }
+
+    // 到达衰减间隔时执行衰减
+    fn maybe_decay(&self) {
+        if self.decay_interval.is_zero() {
+            return;
+        }
+        let interval = self.decay_interval.as_millis() as u64;
+        let now = self.elapsed_millis();
+        let last = self.last_decay.load(Ordering::Relaxed);
+        let elapsed = now.saturating_sub(last);
+        if elapsed < interval {
+            return;
+        }
+        if self
+            .last_decay
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+
+        // 按经过的衰减周期数减半，长时间空闲后一次性衰减到位
+        let periods = elapsed / interval;
+        for _ in 0..periods.min(64) {
+            self.apply_decay();
+            if self.counts.is_empty() {
+                break;
+            }
+        }
+    }
+
+    // 所有计数减半
+    fn apply_decay(&self) {
+        self.counts.retain(|_, count| {
+            let halved = count.load(Ordering::Relaxed) / 2;
+            count.store(halved, Ordering::Relaxed);
+            halved > 0
+        });
+        let floor = self.floor.load(Ordering::Relaxed);
+        self.floor.store(floor / 2, Ordering::Relaxed);
+    }
+
+    // 淘汰计数最小的客户端直到回到容量以内
+    fn evict_overflow(&self) {
+        let overflow = self.counts.len().saturating_sub(self.capacity);
+        if overflow == 0 {
+            return;
+        }
+
+        let mut entries: Vec<(u64, IpAddr)> = self
+            .counts
+            .iter()
+            .map(|entry| (entry.value().load(Ordering::Relaxed), *entry.key()))
+            .collect();
+        let overflow = entries.len().saturating_sub(self.capacity);
+        if overflow == 0 {
+            return;
+        }
+        entries.select_nth_unstable(overflow - 1);
+
+        let mut max_evicted = 0;
+        for (count, client) in &entries[..overflow] {
+            self.counts.remove(client);
+            max_evicted = max_evicted.max(*count);
+        }
+        self.floor.fetch_max(max_evicted, Ordering::Relaxed);
+    }
+}
+
+// 延迟直方图（指数分桶）
+// 用于在进程内近似计算延迟分位数，精度取决于桶的增长倍数
+pub struct LatencyHistogram {
+    // 各桶上界（毫秒）
+    bounds: Vec<f64>,
+    // 各桶计数（最后一个为溢出桶）
+    counts: Vec<AtomicU64>,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyHistogram {
+    // 创建延迟直方图
+    pub fn new() -> Self {
+        let bounds: Vec<f64> = (0..latency_histogram::BUCKETS)
+            .map(|i| {
+                latency_histogram::FIRST_BOUND_MS * latency_histogram::GROWTH_FACTOR.powi(i as i32)
+            })
+            .collect();
+        let counts = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Self { bounds, counts }
+    }
+
+    // 记录一次耗时
+    pub fn record(&self, duration: Duration) {
+        let millis = duration.as_secs_f64() * 1000.0;
+        let index = self.bounds.partition_point(|bound| *bound < millis);
+        self.counts[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 计算分位数（毫秒）：在所在桶的上下界之间按排名线性插值；
+    // 溢出桶没有上界，返回最后一个有界桶的上界
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let counts: Vec<u64> = self
+            .counts
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+
+        let rank = q.clamp(0.0, 1.0) * total as f64;
+        let mut cumulative = 0;
+        for (index, &count) in counts.iter().enumerate() {
+            if count == 0 || ((cumulative + count) as f64) < rank {
+                cumulative += count;
+                continue;
+            }
+            let Some(&upper) = self.bounds.get(index) else {
+                break;
+            };
+            let lower = if index == 0 {
+                0.0
+            } else {
+                self.bounds[index - 1]
+            };
+            let fraction = (rank - cumulative as f64) / count as f64;
+            return Some(lower + (upper - lower) * fraction.clamp(0.0, 1.0));
+        }
+        self.bounds.last().copied()
+    }
+}
+
+// 单个上游组的统计
+#[derive(Default)]
+pub struct GroupStats {
+    // 请求总数
+    requests: AtomicU64,
+    // 失败次数
+    errors: AtomicU64,
+    // 延迟直方图
+    latency: LatencyHistogram,
+}
+
+// 上游组统计摘要
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct GroupStatsSnapshot {
+    // 上游组名称
+    pub name: String,
+    // 请求总数
+    pub requests: u64,
+    // 失败次数
+    pub errors: u64,
+    // 成功率（0.0 - 1.0），无请求时为空
+    pub success_rate: Option<f64>,
+    // 延迟 P50（毫秒）
+    pub p50_ms: Option<f64>,
+    // 延迟 P95（毫秒）
+    pub p95_ms: Option<f64>,
+    // 延迟 P99（毫秒）
+    pub p99_ms: Option<f64>,
+}
+
+impl GroupStats {
+    // 记录一次上游请求结果
+    pub fn record(&self, duration: Duration, success: bool) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.latency.record(duration);
+    }
+
+    // 生成统计摘要
+    pub fn snapshot(&self, name: &str) -> GroupStatsSnapshot {
+        let requests = self.requests.load(Ordering::Relaxed);
+        let errors = self.errors.load(Ordering::Relaxed);
+        GroupStatsSnapshot {
+            name: name.to_string(),
+            requests,
+            errors,
+            success_rate: (requests > 0)
+                .then(|| requests.saturating_sub(errors) as f64 / requests as f64),
+            p50_ms: self.latency.quantile(0.50),
+            p95_ms: self.latency.quantile(0.95),
+            p99_ms: self.latency.quantile(0.99),
+        }
+    }
+}
+
+// 上游统计（按组）
+#[derive(Default)]
+pub struct UpstreamStats {
+    // 各组统计，组集合在初始化时确定
+    groups: HashMap<String, GroupStats>,
+}
+
+impl UpstreamStats {
+    // 根据组名称创建上游统计
+    pub fn new<I, S>(group_names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            groups: group_names
+                .into_iter()
+                .map(|name| (name.into(), GroupStats::default()))
+                .collect(),
+        }
+    }
+
+    // 记录一次上游请求结果
+    pub fn record(&self, group_name: &str, duration: Duration, success: bool) {
+        if let Some(stats) = self.groups.get(group_name) {
+            stats.record(duration, success);
+        }
+    }
+
+    // 生成所有组的统计摘要（按组名排序）
+    pub fn snapshot(&self) -> Vec<GroupStatsSnapshot> {
+        let mut result: Vec<GroupStatsSnapshot> = self
+            .groups
+            .iter()
+            .map(|(name, stats)| stats.snapshot(name))
+            .collect();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        result
+    }
+}
+
+// 远程规则源状态摘要
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct RuleSourceSnapshot {
+    // 规则源 URL
+    pub source: String,
+    // 上次成功加载时间（Unix 秒），从未成功时为空
+    pub last_success: Option<u64>,
+    // 距上次成功加载的时长（秒），从未成功时为空
+    pub age_secs: Option<u64>,
+    // 是否超过最大陈旧时长
+    pub stale: bool,
+}
+
+// 远程规则源新鲜度统计：记录每个规则源上次成功加载的时间
+pub struct RuleSourceStats {
+    // 规则源 URL -> 上次成功加载时间
+    sources: Mutex<HashMap<String, Option<SystemTime>>>,
+    // 最大陈旧时长，未设置时规则源始终视为健康
+    max_staleness: Option<Duration>,
+}
+
+impl RuleSourceStats {
+    // 创建规则源统计
+    pub fn new(max_staleness: Option<Duration>) -> Self {
+        Self {
+            sources: Mutex::new(HashMap::new()),
+            max_staleness,
+        }
+    }
+
+    // 最大陈旧时长
+    pub fn max_staleness(&self) -> Option<Duration> {
+        self.max_staleness
+    }
+
+    // 登记规则源（尚未成功加载）
+    pub fn register(&self, source: &str) {
+        self.sources
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(source.to_string())
+            .or_insert(None);
+    }
+
+    // 记录规则源加载成功
+    pub fn record_success(&self, source: &str) {
+        self.record_success_at(source, SystemTime::now());
+    }
+
+    // 记录规则源在指定时间加载成功
+    pub fn record_success_at(&self, source: &str, at: SystemTime) {
+        self.sources
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(source.to_string(), Some(at));
+    }
+
+    // 生成所有规则源的状态摘要（按 URL 排序）
+    pub fn snapshot(&self) -> Vec<RuleSourceSnapshot> {
+        let now = SystemTime::now();
+        let sources = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+        let mut result: Vec<RuleSourceSnapshot> = sources
+            .iter()
+            .map(|(source, last_success)| {
+                let age = last_success.map(|at| now.duration_since(at).unwrap_or_default());
+                RuleSourceSnapshot {
+                    source: source.clone(),
+                    last_success: last_success
+                        .map(|at| at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()),
+                    age_secs: age.map(|age| age.as_secs()),
+                    // 从未成功加载的规则源在配置了最大陈旧时长时同样视为陈旧
+                    stale: self
+                        .max_staleness
+                        .is_some_and(|max| age.is_none_or(|age| age > max)),
+                }
+            })
+            .collect();
+        result.sort_by(|a, b| a.source.cmp(&b.source));
+        result
+    }
+}
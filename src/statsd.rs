@@ -0,0 +1,79 @@
+use crate::metrics::{CounterMetric, HistogramMetric, MetricsBackend};
+use std::fmt::Write;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use tracing::debug;
+
+// StatsD 指标后端：通过 UDP 发送 DogStatsD 格式（带标签）的指标
+pub struct StatsdBackend {
+    // 已连接到 StatsD 服务器的 UDP 套接字
+    socket: UdpSocket,
+    // 指标名称前缀
+    prefix: String,
+}
+
+impl StatsdBackend {
+    // 创建 StatsD 指标后端
+    pub fn new(address: SocketAddr, prefix: &str) -> io::Result<Self> {
+        let bind_addr: SocketAddr = if address.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(bind_addr)?;
+        socket.connect(address)?;
+        // 指标发送位于请求热路径上，不能阻塞
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    // 格式化并发送一条指标：`<prefix>.<name>:<value>|<type>|#<tag>:<value>,...`
+    fn send(&self, name: &str, value: &str, kind: &str, label_names: &[&str], labels: &[&str]) {
+        let mut packet = String::with_capacity(128);
+        if self.prefix.is_empty() {
+            let _ = write!(packet, "{}:{}|{}", name, value, kind);
+        } else {
+            let _ = write!(packet, "{}.{}:{}|{}", self.prefix, name, value, kind);
+        }
+        for (i, (label_name, label)) in label_names.iter().zip(labels).enumerate() {
+            packet.push_str(if i == 0 { "|#" } else { "," });
+            packet.push_str(label_name);
+            packet.push(':');
+            // 标签值中的分隔符会破坏报文格式，替换为下划线
+            packet.extend(label.chars().map(|c| match c {
+                '|' | ',' | '#' | ':' => '_',
+                c => c,
+            }));
+        }
+
+        // UDP 发送失败（例如缓冲区已满）时直接丢弃，不影响请求处理
+        if let Err(e) = self.socket.send(packet.as_bytes()) {
+            debug!("Failed to send StatsD metric {}: {}", name, e);
+        }
+    }
+}
+
+impl MetricsBackend for StatsdBackend {
+    fn increment(&self, metric: CounterMetric, labels: &[&str]) {
+        self.send(metric.name(), "1", "c", metric.label_names(), labels);
+    }
+
+    fn observe(&self, metric: HistogramMetric, labels: &[&str], value: f64) {
+        // 时长以毫秒计时器上报，其余以直方图上报
+        let (value, kind) = if metric.is_duration() {
+            (value * 1000.0, "ms")
+        } else {
+            (value, "h")
+        };
+        self.send(
+            metric.name(),
+            &value.to_string(),
+            kind,
+            metric.label_names(),
+            labels,
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::config::DnsClientConfig;
 use crate::error::AppError;
+use crate::upstream::wire::validate_wire_message;
 use dashmap::DashMap;
 use futures_util::StreamExt;
 use hickory_proto::op::Message;
@@ -213,6 +214,10 @@ impl DnsClient {
             .ok_or_else(|| AppError::Upstream("empty response stream".to_string()))?
             .map_err(|e| AppError::Upstream(e.to_string()))?;
 
-        Ok(response.into_message())
+        // 校验上游原始报文结构，拒绝畸形报文
+        let (message, buffer) = response.into_parts();
+        validate_wire_message(&buffer)?;
+
+        Ok(message)
     }
 }
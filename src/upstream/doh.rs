@@ -1,42 +1,228 @@
 use crate::{
     config::{DoHContentType, DoHMethod, DoHUpstreamServerConfig},
     error::AppError,
-    r#const::http_headers,
-    upstream::{http_client::HttpClient, json::JsonConverter},
+    r#const::{http_headers, upstream_defaults},
+    upstream::{http_client::HttpClient, json::JsonConverter, wire::validate_wire_message},
 };
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use hickory_proto::{
     op::Message,
+    rr::{domain::Label, Name},
     serialize::binary::{BinEncodable, BinEncoder},
 };
-use reqwest_middleware::ClientWithMiddleware;
+use rand::Rng;
+use reqwest::{header::HeaderMap, StatusCode};
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use tracing::warn;
 
 pub struct DoHClient<'a> {
     client: &'a ClientWithMiddleware,
     json_converter: JsonConverter,
+    // 是否严格校验上游响应 ID
+    strict_id_check: bool,
+    // 是否对查询名称做 0x20 随机大小写并校验响应回显
+    case_randomization: bool,
+    // JSON 请求被拒绝（406/415）时是否改用二进制格式重试
+    json_fallback: bool,
+    // 传递给上游的 W3C traceparent 头
+    traceparent: Option<&'a str>,
+    // 上游组配置的自定义请求头
+    headers: Option<&'a HeaderMap>,
 }
 
 impl<'a> DoHClient<'a> {
     pub fn new(client: &'a ClientWithMiddleware) -> Self {
         Self {
             client,
-            json_converter: JsonConverter,
+            json_converter: JsonConverter::default(),
+            strict_id_check: false,
+            case_randomization: false,
+            json_fallback: false,
+            traceparent: None,
+            headers: None,
         }
     }
 
+    // 设置是否严格校验上游响应 ID
+    pub fn with_strict_id_check(mut self, strict_id_check: bool) -> Self {
+        self.strict_id_check = strict_id_check;
+        self
+    }
+
+    // 设置是否对查询名称做 0x20 随机大小写
+    pub fn with_case_randomization(mut self, case_randomization: bool) -> Self {
+        self.case_randomization = case_randomization;
+        self
+    }
+
+    // 设置 JSON 请求被拒绝时是否改用二进制格式重试
+    pub fn with_json_fallback(mut self, json_fallback: bool) -> Self {
+        self.json_fallback = json_fallback;
+        self
+    }
+
+    // 设置 JSON 应答中是否保留未支持类型的记录
+    pub fn with_preserve_unknown_records(mut self, preserve_unknown_records: bool) -> Self {
+        self.json_converter = self
+            .json_converter
+            .with_preserve_unknown_records(preserve_unknown_records);
+        self
+    }
+
+    // 设置传递给上游的 traceparent 头
+    pub fn with_traceparent(mut self, traceparent: Option<&'a str>) -> Self {
+        self.traceparent = traceparent;
+        self
+    }
+
+    // 设置上游组的自定义请求头
+    pub fn with_headers(mut self, headers: Option<&'a HeaderMap>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    // 添加追踪上下文头与自定义请求头（同名时覆盖已有请求头）
+    fn add_extra_headers(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = match self.traceparent {
+            Some(traceparent) => request.header(http_headers::TRACEPARENT, traceparent),
+            None => request,
+        };
+        match self.headers {
+            Some(headers) => request.headers(headers.clone()),
+            None => request,
+        }
+    }
+
+    // 校验上游响应 ID：严格模式下必须与实际发送的事务 ID 一致
+    fn check_response_id(&self, response: &Message) -> Result<(), AppError> {
+        let expected = upstream_defaults::DOH_QUERY_ID;
+        if self.strict_id_check && response.id() != expected {
+            warn!(
+                "Upstream response ID mismatch: expected {}, got {}",
+                expected,
+                response.id()
+            );
+            return Err(AppError::UpstreamIdMismatch {
+                expected,
+                actual: response.id(),
+            });
+        }
+        Ok(())
+    }
+
+    // 编码出站查询，并按 RFC 8484 §4.1 将事务 ID 置为 0 以提高 HTTP 缓存命中率
+    fn encode_query(query: &Message, capacity: usize) -> Result<Vec<u8>, AppError> {
+        let mut buffer = Vec::with_capacity(capacity);
+        // 使用二进制编码器将查询消息写入缓冲区
+        let mut encoder = BinEncoder::new(&mut buffer);
+        query.emit(&mut encoder)?;
+
+        // DNS 报文头前两个字节即为事务 ID
+        buffer[..2].copy_from_slice(&upstream_defaults::DOH_QUERY_ID.to_be_bytes());
+        Ok(buffer)
+    }
+
     // 发送DoH请求的入口方法
     pub async fn send_request(
         &self,
         query: &Message,
         server: &DoHUpstreamServerConfig,
     ) -> Result<Message, AppError> {
-        // 根据配置的方法选择GET或POST
+        // JSON 格式的应答不携带原始问题部分，0x20 编码仅用于二进制报文
+        if self.case_randomization && server.content_type == DoHContentType::Message {
+            return self.send_case_randomized(query, server).await;
+        }
+
+        let result = self.send_by_method(query, server).await;
+        match result {
+            Err(AppError::UpstreamStatus(status))
+                if self.json_fallback
+                    && server.content_type == DoHContentType::Json
+                    && matches!(
+                        status,
+                        StatusCode::NOT_ACCEPTABLE | StatusCode::UNSUPPORTED_MEDIA_TYPE
+                    ) =>
+            {
+                warn!(
+                    "Upstream {} rejected JSON request with {}, retrying with binary format",
+                    server.url.host_str().unwrap_or_default(),
+                    status
+                );
+                self.send_json_fallback(query, server).await
+            }
+            result => result,
+        }
+    }
+
+    // 以二进制格式（application/dns-message）重新向同一服务器发送请求
+    async fn send_json_fallback(
+        &self,
+        query: &Message,
+        server: &DoHUpstreamServerConfig,
+    ) -> Result<Message, AppError> {
+        let mut binary = server.clone();
+        binary.content_type = DoHContentType::Message;
+        if self.case_randomization {
+            return self.send_case_randomized(query, &binary).await;
+        }
+        self.send_by_method(query, &binary).await
+    }
+
+    // 根据配置的方法选择GET或POST
+    async fn send_by_method(
+        &self,
+        query: &Message,
+        server: &DoHUpstreamServerConfig,
+    ) -> Result<Message, AppError> {
         match &server.method {
             DoHMethod::Get => self.send_doh_request_get(query, server).await,
             DoHMethod::Post => self.send_doh_request_post(query, server).await,
         }
     }
 
+    // 以随机大小写的查询名称发送请求，校验响应的问题部分逐字节回显后恢复原始查询名称
+    async fn send_case_randomized(
+        &self,
+        query: &Message,
+        server: &DoHUpstreamServerConfig,
+    ) -> Result<Message, AppError> {
+        let mut randomized = query.clone();
+        for q in randomized.queries_mut() {
+            q.set_name(randomize_case(q.name()));
+        }
+
+        let mut response = self.send_by_method(&randomized, server).await?;
+        // 问题数量不一致（例如问题部分为空）时无法逐一比对大小写，直接拒绝
+        if response.queries().len() != randomized.queries().len() {
+            warn!(
+                "Upstream response question count mismatch: expected {}, got {}",
+                randomized.queries().len(),
+                response.queries().len()
+            );
+            return Err(AppError::MalformedUpstreamResponse(format!(
+                "question count mismatch: expected {}, got {}",
+                randomized.queries().len(),
+                response.queries().len()
+            )));
+        }
+        for (sent, received) in randomized.queries().iter().zip(response.queries()) {
+            if !sent.name().eq_case(received.name()) {
+                warn!(
+                    "Upstream response question case mismatch: expected {}, got {}",
+                    sent.name(),
+                    received.name()
+                );
+                return Err(AppError::UpstreamCaseMismatch {
+                    expected: sent.name().to_string(),
+                    actual: received.name().to_string(),
+                });
+            }
+        }
+
+        *response.queries_mut() = query.queries().to_vec();
+        Ok(response)
+    }
+
     // 发送DoH POST请求
     async fn send_doh_request_post(
         &self,
@@ -49,11 +235,8 @@ impl<'a> DoHClient<'a> {
         // 根据内容类型处理
         match server.content_type {
             DoHContentType::Message => {
-                // 创建一个可复用的缓冲区
-                let mut buffer = Vec::with_capacity(512); // 512字节对于DNS查询是一个合理的初始容量
-                                                          // 使用二进制编码器将查询消息写入缓冲区
-                let mut encoder = BinEncoder::new(&mut buffer);
-                query.emit(&mut encoder)?;
+                // 编码查询消息，512字节对于DNS查询是一个合理的初始容量
+                let buffer = Self::encode_query(query, 512)?;
 
                 // 创建POST请求
                 let mut request = self
@@ -63,22 +246,30 @@ impl<'a> DoHClient<'a> {
                         http_headers::ACCEPT,
                         http_headers::content_types::DNS_MESSAGE,
                     )
+                    // 二进制报文体积小、压缩收益低，不请求压缩
+                    .header(
+                        http_headers::ACCEPT_ENCODING,
+                        http_headers::encodings::IDENTITY,
+                    )
                     .header(
                         http_headers::CONTENT_TYPE,
                         http_headers::content_types::DNS_MESSAGE,
                     )
                     .body(buffer);
 
-                // 添加认证信息
+                // 添加认证信息、追踪上下文与自定义请求头
                 request = HttpClient::add_auth_to_request(request, &server.auth)?;
+                request = self.add_extra_headers(request);
 
                 // 发送请求并返回响应体
                 let response_data = HttpClient::send_request(request).await?;
 
-                // 解析二进制响应为DNS消息
+                // 校验报文结构后解析二进制响应为DNS消息
+                validate_wire_message(&response_data)?;
                 let mut message = Message::from_vec(&response_data)?;
 
-                // 复制请求ID
+                // 校验并复制请求ID
+                self.check_response_id(&message)?;
                 message.set_id(query.id());
 
                 Ok(message)
@@ -105,11 +296,8 @@ impl<'a> DoHClient<'a> {
         // 根据内容类型处理
         match server.content_type {
             DoHContentType::Message => {
-                // 创建一个可复用的缓冲区
-                let mut buffer = Vec::with_capacity(2048);
-                // 使用二进制编码器将查询消息写入缓冲区
-                let mut encoder = BinEncoder::new(&mut buffer);
-                query.emit(&mut encoder)?;
+                // 编码查询消息
+                let buffer = Self::encode_query(query, 2048)?;
 
                 // Base64Url编码
                 let b64_data = URL_SAFE_NO_PAD.encode(&buffer);
@@ -118,21 +306,31 @@ impl<'a> DoHClient<'a> {
                 url.query_pairs_mut().append_pair("dns", &b64_data);
 
                 // 创建GET请求
-                let mut request = self.client.get(url).header(
-                    http_headers::ACCEPT,
-                    http_headers::content_types::DNS_MESSAGE,
-                );
+                let mut request = self
+                    .client
+                    .get(url)
+                    .header(
+                        http_headers::ACCEPT,
+                        http_headers::content_types::DNS_MESSAGE,
+                    )
+                    .header(
+                        http_headers::ACCEPT_ENCODING,
+                        http_headers::encodings::IDENTITY,
+                    );
 
-                // 添加认证信息
+                // 添加认证信息、追踪上下文与自定义请求头
                 request = HttpClient::add_auth_to_request(request, &server.auth)?;
+                request = self.add_extra_headers(request);
 
                 // 发送请求并返回响应体
                 let response_data = HttpClient::send_request(request).await?;
 
-                // 解析二进制响应为DNS消息
+                // 校验报文结构后解析二进制响应为DNS消息
+                validate_wire_message(&response_data)?;
                 let mut message = Message::from_vec(&response_data)?;
 
-                // 复制请求ID
+                // 校验并复制请求ID
+                self.check_response_id(&message)?;
                 message.set_id(query.id());
 
                 Ok(message)
@@ -153,14 +351,15 @@ impl<'a> DoHClient<'a> {
                     url.query_pairs_mut().append_pair("dnssec_data", "true");
                 }
 
-                // 创建GET请求
+                // 创建GET请求，Accept-Encoding 由客户端自动添加（gzip、br），响应体自动解压
                 let mut request = self
                     .client
                     .get(url)
                     .header(http_headers::ACCEPT, http_headers::content_types::DNS_JSON);
 
-                // 添加认证信息
+                // 添加认证信息、追踪上下文与自定义请求头
                 request = HttpClient::add_auth_to_request(request, &server.auth)?;
+                request = self.add_extra_headers(request);
 
                 // 发送请求并返回响应体
                 let response_data = HttpClient::send_request(request).await?;
@@ -171,3 +370,32 @@ impl<'a> DoHClient<'a> {
         }
     }
 }
+
+// 随机翻转名称中 ASCII 字母的大小写（DNS 0x20 编码）
+fn randomize_case(name: &Name) -> Name {
+    let mut rng = rand::thread_rng();
+    let labels: Result<Vec<Label>, _> = name
+        .iter()
+        .map(|label| {
+            let bytes: Vec<u8> = label
+                .iter()
+                .map(|b| {
+                    if b.is_ascii_alphabetic() && rng.gen::<bool>() {
+                        b ^ 0x20
+                    } else {
+                        *b
+                    }
+                })
+                .collect();
+            Label::from_raw_bytes(&bytes)
+        })
+        .collect();
+
+    match labels.and_then(Name::from_labels) {
+        Ok(mut randomized) => {
+            randomized.set_fqdn(name.is_fqdn());
+            randomized
+        }
+        Err(_) => name.clone(),
+    }
+}
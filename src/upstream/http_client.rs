@@ -3,24 +3,32 @@ use crate::{
     error::{AppError, HttpClientError, InvalidProxyConfig},
     r#const::{http_headers, retry_limits},
 };
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{StatusCode, Url};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, RequestBuilder};
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
-use retry_policies::Jitter;
+use std::collections::HashMap;
+use std::sync::Once;
 use std::time::Duration;
-use tracing::debug;
+use tracing::{debug, warn, Level};
+
+// TCP Fast Open 不生效的警告只记录一次（每个上游组都会创建客户端）
+static TCP_FASTOPEN_WARNING: Once = Once::new();
 
 pub struct HttpClient;
 
 impl HttpClient {
     // 创建HTTP客户端
+    // 重试由 UpstreamManager 逐次执行，以便记录每次重试
     pub fn create(
         config: &HttpClientConfig,
         proxy: Option<&str>,
-        retry_config: Option<&RetryConfig>,
+        max_connections: Option<usize>,
     ) -> Result<ClientWithMiddleware, AppError> {
         debug!(
-            "Creating HTTP client for upstream, config: {:?}, proxy: {:?}, retry_config: {:?}",
-            config, proxy, retry_config
+            "Creating HTTP client for upstream, config: {:?}, proxy: {:?}",
+            config, proxy
         );
 
         // 创建客户端构建器
@@ -39,6 +47,41 @@ impl HttpClient {
             client_builder = client_builder.pool_idle_timeout(Duration::from_secs(idle_timeout));
         }
 
+        // 按组的连接上限限制每个主机保留的空闲连接数
+        if let Some(max_connections) = max_connections {
+            client_builder = client_builder.pool_max_idle_per_host(max_connections);
+        }
+
+        // 配置本地源地址
+        if let Some(addr) = config.bind_address {
+            client_builder = client_builder.local_address(addr);
+        }
+
+        // TCP Fast Open 需要在 connect 之前设置套接字选项（Linux 上为 TCP_FASTOPEN_CONNECT），
+        // 而 reqwest 连接器既不支持该选项，也不允许替换为自定义连接，启用时仅记录警告
+        if config.tcp_fastopen {
+            TCP_FASTOPEN_WARNING.call_once(|| {
+                warn!(
+                    "http_client.tcp_fastopen is enabled but the upstream HTTP connector cannot set TCP Fast Open on outgoing sockets, connecting without it"
+                );
+            });
+        }
+
+        // 不经协商直接使用 HTTP/2（h2c）
+        if config.http2_prior_knowledge {
+            client_builder = client_builder.http2_prior_knowledge();
+        }
+
+        // 配置 HTTP/2 keepalive PING，空闲连接同样发送
+        if let Some(interval) = config.http2_keepalive_interval {
+            client_builder = client_builder
+                .http2_keep_alive_interval(Duration::from_secs(interval))
+                .http2_keep_alive_while_idle(true);
+        }
+        if let Some(timeout) = config.http2_keepalive_timeout {
+            client_builder = client_builder.http2_keep_alive_timeout(Duration::from_secs(timeout));
+        }
+
         // 配置用户代理
         if let Some(ref agent) = config.agent {
             client_builder = client_builder.user_agent(agent);
@@ -62,31 +105,33 @@ impl HttpClient {
             )))
         })?;
 
-        // 配置重试策略（根据组的重试配置）
-        let middleware_client = if let Some(retry) = retry_config {
-            // 使用指数退避策略，基于组的重试配置
-            let retry_policy = ExponentialBackoff::builder()
-                // 设置重试时间间隔的上下限
-                .retry_bounds(
-                    Duration::from_secs(retry_limits::MIN_DELAY as u64),
-                    Duration::from_secs(retry_limits::MAX_DELAY as u64),
-                )
-                // 设置指数退避的基数
-                .base(retry.delay)
-                // 使用有界抖动来避免多个客户端同时重试
-                .jitter(Jitter::Bounded)
-                // 配置最大重试次数
-                .build_with_max_retries(retry.attempts);
-
-            ClientBuilder::new(client)
-                .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-                .build()
-        } else {
-            // 不进行重试
-            ClientBuilder::new(client).build()
-        };
+        Ok(ClientBuilder::new(client).build())
+    }
 
-        Ok(middleware_client)
+    // 判断错误是否为可重试的瞬时错误（连接/超时错误，或 5xx、408、429 状态码）
+    pub fn is_retryable(error: &AppError) -> bool {
+        match error {
+            AppError::Http(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            AppError::UpstreamStatus(status) => {
+                status.is_server_error()
+                    || *status == StatusCode::REQUEST_TIMEOUT
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+
+    // 计算第 n 次重试前的等待时间：以 delay 为基数指数退避，并在下限与退避值之间加入有界抖动
+    pub fn retry_delay(retry: &RetryConfig, past_retries: u32) -> Duration {
+        let min_delay = retry_limits::MIN_DELAY as u64;
+        let max_delay = retry_limits::MAX_DELAY as u64;
+        let backoff = (retry.delay as u64)
+            .checked_pow(past_retries)
+            .map_or(max_delay, |factor| min_delay.saturating_mul(factor))
+            .clamp(min_delay, max_delay);
+
+        let millis = rand::thread_rng().gen_range(min_delay * 1000..=backoff * 1000);
+        Duration::from_millis(millis)
     }
 
     // 处理认证头添加
@@ -123,17 +168,54 @@ impl HttpClient {
         Ok(req)
     }
 
+    // 构建自定义请求头，值标记为敏感以便在调试日志中脱敏
+    pub fn build_headers(headers: &HashMap<String, String>) -> Result<HeaderMap, AppError> {
+        let mut header_map = HeaderMap::with_capacity(headers.len());
+        for (name, value) in headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                AppError::Upstream(format!("Invalid header name '{}': {}", name, e))
+            })?;
+            let mut value = HeaderValue::from_str(value).map_err(|e| {
+                AppError::Upstream(format!("Invalid value for header '{}': {}", name, e))
+            })?;
+            value.set_sensitive(true);
+            header_map.insert(name, value);
+        }
+        Ok(header_map)
+    }
+
     // 发送middleware请求并读取响应体
     pub async fn send_request(request: RequestBuilder) -> Result<bytes::Bytes, AppError> {
+        // 调试模式下输出脱敏后的请求内容
+        let debug_enabled = tracing::enabled!(Level::DEBUG);
+        let request = if debug_enabled {
+            let (client, built) = request.build_split();
+            let built = built?;
+            log_request(&built);
+            RequestBuilder::from_parts(client, built)
+        } else {
+            request
+        };
+
         // 发送请求
         let response = request.send().await?;
 
         // 检查状态码
-        if !response.status().is_success() {
-            return Err(AppError::Upstream(format!(
-                "Upstream server returned error: {}",
-                response.status()
-            )));
+        let status = response.status();
+        if !status.is_success() {
+            if debug_enabled {
+                let body = response.bytes().await.unwrap_or_default();
+                let snippet = &body[..body.len().min(http_headers::DEBUG_BODY_SNIPPET_LEN)];
+                debug!(
+                    "Upstream responded with status {}, body: {}",
+                    status,
+                    String::from_utf8_lossy(snippet)
+                );
+            }
+            return Err(AppError::UpstreamStatus(status));
+        }
+        if debug_enabled {
+            debug!("Upstream responded with status {}", status);
         }
 
         // 读取响应体
@@ -142,3 +224,61 @@ impl HttpClient {
         Ok(response_data)
     }
 }
+
+// 输出上游请求的方法、脱敏后的 URL、请求头与请求体
+fn log_request(request: &reqwest::Request) {
+    let headers: Vec<String> = request
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = if value.is_sensitive()
+                || http_headers::SENSITIVE_HEADERS.contains(&name.as_str())
+            {
+                http_headers::REDACTED
+            } else {
+                value.to_str().unwrap_or("<binary>")
+            };
+            format!("{}: {}", name, value)
+        })
+        .collect();
+
+    // 二进制请求体以 base64url 输出，JSON 请求体按文本输出
+    let body = match request.body().and_then(|body| body.as_bytes()) {
+        None => "<none>".to_string(),
+        Some(bytes) => match std::str::from_utf8(bytes) {
+            Ok(text) if !text.is_empty() && !text.contains(char::is_control) => text.to_string(),
+            _ => URL_SAFE_NO_PAD.encode(bytes),
+        },
+    };
+
+    debug!(
+        "Sending upstream request: {} {}, headers: [{}], body: {}",
+        request.method(),
+        redact_url(request.url()),
+        headers.join(", "),
+        body
+    );
+}
+
+// 对 URL 中的密码与非 DoH 标准查询参数做脱敏
+pub fn redact_url(url: &Url) -> Url {
+    let mut redacted = url.clone();
+    if redacted.password().is_some() {
+        let _ = redacted.set_password(Some(http_headers::REDACTED));
+    }
+    if redacted.query().is_some() {
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(key, value)| {
+                let value = if http_headers::LOGGABLE_QUERY_PARAMS.contains(&key.as_ref()) {
+                    value.into_owned()
+                } else {
+                    http_headers::REDACTED.to_string()
+                };
+                (key.into_owned(), value)
+            })
+            .collect();
+        redacted.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    redacted
+}
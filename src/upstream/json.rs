@@ -1,10 +1,15 @@
-use crate::{error::AppError, r#const::http_headers};
+use crate::{
+    error::AppError,
+    metrics::{self, CounterMetric},
+    r#const::http_headers,
+};
 use hickory_proto::{
-    op::{Message, MessageType, Query, ResponseCode},
+    op::{Message, MessageType, ResponseCode},
     rr::{
-        rdata::{self as HickoryRData, MX, SRV, TXT},
+        rdata::{self as HickoryRData, MX, NULL, SRV, TXT},
         Name, RData, Record, RecordType,
     },
+    serialize::txt::RDataParser,
 };
 use serde_json::{json, Value as JsonValue};
 use std::net::{Ipv4Addr, Ipv6Addr};
@@ -58,22 +63,136 @@ pub mod dns_section {
     pub const ADDITIONAL: &str = "Additional";
 }
 
-pub struct JsonConverter;
+#[derive(Debug, Default)]
+pub struct JsonConverter {
+    // 是否保留无法转换为已支持类型的记录（按 RFC 3597 原始数据或通用文本格式解析）
+    preserve_unknown_records: bool,
+}
 
-fn normalize_txt_data(raw: &str) -> String {
-    // Google DoH JSON 的 TXT data 通常是一个字符串，有时会包含外层引号，或者使用 `""` 表示拼接。
-    // 这里尽量做保守归一化：去掉首尾引号，并把相邻的双引号当作拼接符移除。
-    let mut s = raw.trim().to_string();
-    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
-        s = s[1..s.len() - 1].to_string();
+// TXT 记录中单个 character-string 的最大字节数（RFC 1035）
+const TXT_MAX_STRING_LEN: usize = 255;
+
+fn parse_txt_segments(raw: &str) -> Vec<String> {
+    // Google DoH JSON 的 TXT data 可能是未加引号的单个字符串，也可能是多个带引号的片段
+    // （例如 `"v=DKIM1; p=..." "..."` 或 `"a""b"`）。每个带引号的片段对应一个 character-string，
+    // 以保留 RFC 1035 的字符串边界。
+    let raw = raw.trim();
+    let segments = if raw.starts_with('"') {
+        let mut segments = Vec::new();
+        let mut current = String::new();
+        let mut in_quotes = false;
+        let mut chars = raw.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if in_quotes => {
+                    segments.push(std::mem::take(&mut current));
+                    in_quotes = false;
+                }
+                '"' => in_quotes = true,
+                '\\' if in_quotes => {
+                    if let Some(escaped) = chars.next() {
+                        current.push(escaped);
+                    }
+                }
+                c if in_quotes => current.push(c),
+                c if c.is_whitespace() => {}
+                c => current.push(c),
+            }
+        }
+        // 未闭合的引号或引号外的残余内容作为最后一个片段
+        if !current.is_empty() {
+            segments.push(current);
+        }
+        segments
+    } else {
+        vec![raw.to_string()]
+    };
+
+    // 超过 255 字节的片段按字符边界拆分
+    let mut strings = Vec::with_capacity(segments.len());
+    for segment in segments {
+        if segment.len() <= TXT_MAX_STRING_LEN {
+            strings.push(segment);
+            continue;
+        }
+        let mut chunk = String::new();
+        for c in segment.chars() {
+            if chunk.len() + c.len_utf8() > TXT_MAX_STRING_LEN {
+                strings.push(std::mem::take(&mut chunk));
+            }
+            chunk.push(c);
+        }
+        if !chunk.is_empty() {
+            strings.push(chunk);
+        }
     }
-    while s.contains("\"\"") {
-        s = s.replace("\"\"", "");
+    if strings.is_empty() {
+        strings.push(String::new());
     }
-    s
+    strings
+}
+
+// 解析 RFC 3597 通用格式的记录数据：`\# <长度> <十六进制数据>`
+fn parse_generic_rdata(data: &str) -> Option<Vec<u8>> {
+    let mut tokens = data.split_whitespace();
+    if tokens.next()? != "\\#" {
+        return None;
+    }
+    let len = tokens.next()?.parse::<usize>().ok()?;
+    let hex: String = tokens.collect();
+    if hex.len() != len * 2 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+// 去除记录段中名称、类型与数据完全相同的重复记录，保留首次出现的记录
+fn dedup_records(records: Vec<Record>, section: &str) -> Vec<Record> {
+    let mut unique: Vec<Record> = Vec::with_capacity(records.len());
+    for record in records {
+        let duplicate = unique.iter().any(|existing| {
+            existing.name() == record.name()
+                && existing.record_type() == record.record_type()
+                && existing.data() == record.data()
+        });
+        if duplicate {
+            debug!(
+                "Dropping duplicate {} record in {} section: {}",
+                record.record_type(),
+                section,
+                record.name()
+            );
+        } else {
+            unique.push(record);
+        }
+    }
+    unique
 }
 
 impl JsonConverter {
+    // 设置是否保留未支持类型的记录
+    pub fn with_preserve_unknown_records(mut self, preserve_unknown_records: bool) -> Self {
+        self.preserve_unknown_records = preserve_unknown_records;
+        self
+    }
+
+    // 解析未内置支持的记录类型：优先按 RFC 3597 通用格式保留原始数据，其次按标准文本格式解析
+    fn parse_unsupported_rdata(&self, record_type: RecordType, data: &str) -> Option<RData> {
+        if !self.preserve_unknown_records {
+            return None;
+        }
+        if let Some(bytes) = parse_generic_rdata(data) {
+            return Some(RData::Unknown {
+                code: record_type,
+                rdata: NULL::with(bytes),
+            });
+        }
+        RData::try_from_str(record_type, data).ok()
+    }
+
     // 将DNS消息转换为DNS JSON格式
     // https://developers.google.com/speed/public-dns/docs/doh/json
     #[allow(dead_code)]
@@ -171,25 +290,22 @@ impl JsonConverter {
             response.set_response_code(rcode);
         }
 
-        // 填充 Question/Query：优先使用 JSON 响应里的 Question（如果存在且可解析），否则回退到原始 query。
-        let mut added_query = false;
+        // 填充 Question/Query：始终使用原始 query，且只设置一次。
+        // JSON 中的 Question 可能缺失、重复或与查询大小写不同，仅用于调试比对。
+        response.add_queries(query.queries().iter().cloned());
         if let Some(questions) = json.get(json_fields::QUESTION).and_then(|q| q.as_array()) {
-            for question in questions {
-                if let (Some(name), Some(q_type)) = (
-                    question.get(json_fields::NAME).and_then(|n| n.as_str()),
-                    question.get(json_fields::TYPE).and_then(|t| t.as_u64()),
-                ) {
-                    if let Ok(domain) = Name::parse(name, None) {
-                        let record_type = RecordType::from(q_type as u16);
-                        response.add_query(Query::query(domain, record_type));
-                        added_query = true;
-                    }
-                }
-            }
-        }
-        if !added_query {
-            for q in query.queries() {
-                response.add_query(q.clone());
+            let matches_query = questions.len() == query.queries().len()
+                && questions.iter().zip(query.queries()).all(|(question, q)| {
+                    question
+                        .get(json_fields::NAME)
+                        .and_then(|n| n.as_str())
+                        .and_then(|name| Name::parse(name, None).ok())
+                        .is_some_and(|name| name == *q.name())
+                        && question.get(json_fields::TYPE).and_then(|t| t.as_u64())
+                            == Some(u64::from(u16::from(q.query_type())))
+                });
+            if !matches_query {
+                debug!("DNS JSON response Question section differs from query, using query");
             }
         }
 
@@ -224,7 +340,7 @@ impl JsonConverter {
             let record_type = RecordType::from(r_type as u16);
 
             // 根据记录类型创建适当的RData
-            match record_type {
+            let record = match record_type {
                 RecordType::A => match data.parse::<Ipv4Addr>() {
                     Ok(addr) => {
                         let octets = addr.octets();
@@ -288,9 +404,8 @@ impl JsonConverter {
                     }
                 }
                 RecordType::TXT => {
-                    // Google's JSON format for TXT provides a single string. We'll treat it as a single entry.
-                    // For multiple strings, the format would be more complex.
-                    let txt_data = TXT::new(vec![normalize_txt_data(data)]);
+                    // 每个片段对应一个 character-string，保留多字符串 TXT 的边界
+                    let txt_data = TXT::new(parse_txt_segments(data));
                     Some(Record::from_rdata(name, ttl as u32, RData::TXT(txt_data)))
                 }
                 RecordType::SRV => {
@@ -336,40 +451,59 @@ impl JsonConverter {
                         None
                     }
                 },
-                _ => {
-                    // 对于其他记录类型，尝试作为未知记录处理
-                    warn!("Unsupported record type: {:?}, data: {}", record_type, data);
-                    None
-                }
+                _ => match self.parse_unsupported_rdata(record_type, data) {
+                    Some(rdata) => Some(Record::from_rdata(name, ttl as u32, rdata)),
+                    None => {
+                        warn!("Unsupported record type: {:?}, data: {}", record_type, data);
+                        None
+                    }
+                },
+            };
+
+            // 统计因无法解析而丢弃的记录
+            if record.is_none() {
+                // 未知类型统一使用 RFC 3597 的 "TYPE<n>" 形式，避免都归入 "Unknown"
+                let type_label = match record_type {
+                    RecordType::Unknown(code) => format!("TYPE{}", code),
+                    known => known.to_string(),
+                };
+                metrics::backend().increment(
+                    CounterMetric::UpstreamDroppedRecords,
+                    &[type_label.as_str()],
+                );
             }
+            record
+        };
+
+        // 解析指定记录段，并去除完全重复的记录
+        let parse_section = |field: &str, section: &str| -> Vec<Record> {
+            let records = json
+                .get(field)
+                .and_then(|a| a.as_array())
+                .map(|items| {
+                    items
+                        .iter()
+                        .filter_map(|item| parse_record(item, section))
+                        .collect()
+                })
+                .unwrap_or_default();
+            dedup_records(records, section)
         };
 
         // 处理Answer部分
-        if let Some(answers) = json.get(json_fields::ANSWER).and_then(|a| a.as_array()) {
-            for answer in answers {
-                if let Some(record) = parse_record(answer, dns_section::ANSWER) {
-                    response.add_answer(record);
-                }
-            }
-        }
+        response.add_answers(parse_section(json_fields::ANSWER, dns_section::ANSWER));
 
         // 处理Authority部分
-        if let Some(authority) = json.get(json_fields::AUTHORITY).and_then(|a| a.as_array()) {
-            for auth in authority {
-                if let Some(record) = parse_record(auth, dns_section::AUTHORITY) {
-                    response.add_name_server(record);
-                }
-            }
-        }
+        response.add_name_servers(parse_section(
+            json_fields::AUTHORITY,
+            dns_section::AUTHORITY,
+        ));
 
         // 处理Additional部分
-        if let Some(additional) = json.get(json_fields::ADDITIONAL).and_then(|a| a.as_array()) {
-            for add in additional {
-                if let Some(record) = parse_record(add, dns_section::ADDITIONAL) {
-                    response.add_additional(record);
-                }
-            }
-        }
+        response.add_additionals(parse_section(
+            json_fields::ADDITIONAL,
+            dns_section::ADDITIONAL,
+        ));
 
         // 处理edns_client_subnet字段
         if let Some(ecs) = json
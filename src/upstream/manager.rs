@@ -1,34 +1,78 @@
 use crate::{
-    balancer::{LoadBalancer, RandomBalancer, RoundRobinBalancer, WeightedBalancer},
+    balancer::{
+        LoadBalancer, RandomBalancer, RoundRobinBalancer, StickyBalancer, WeightedBalancer,
+    },
     config::{
-        DnsClientConfig, HttpClientConfig, LoadBalancingStrategy, UpstreamGroupConfig,
-        UpstreamScheme,
+        DnsClientConfig, HttpClientConfig, LoadBalancingStrategy, RetryConfig, UpstreamGroupConfig,
+        UpstreamScheme, UpstreamServerConfig,
     },
     error::AppError,
-    metrics::METRICS,
+    metrics::{self, CounterMetric, HistogramMetric},
     r#const::{
-        error_labels, protocol_labels, upstream_labels, upstream_protocol_labels,
-        upstream_transport_labels,
+        error_labels, protocol_labels, sticky_limits, strategy_labels, upstream_connection_limits,
+        upstream_labels, upstream_protocol_labels, upstream_transport_labels,
+    },
+    stats::UpstreamStats,
+    upstream::{
+        doh::DoHClient,
+        http_client::{redact_url, HttpClient},
     },
-    upstream::{doh::DoHClient, http_client::HttpClient},
 };
 use hickory_proto::op::Message;
+use reqwest::header::HeaderMap;
 use reqwest_middleware::ClientWithMiddleware;
-use std::{collections::HashMap, sync::Arc, time::Instant};
-use tracing::{debug, error, info};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Semaphore;
+use tracing::{debug, error, info, warn};
 
 use super::dns_client::{DnsClient, DnsTransport};
 
+// 转发上下文：与单次转发相关的客户端信息
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ForwardContext<'a> {
+    // 客户端 IP（用于 sticky 负载均衡）
+    pub client: Option<IpAddr>,
+    // 传递给 DoH 上游的 W3C traceparent 头
+    pub traceparent: Option<&'a str>,
+}
+
 // 上游管理器
 pub struct UpstreamManager {
     // 上游组负载均衡器
     groups: HashMap<String, Arc<dyn LoadBalancer>>,
     // 上游组 scheme
     group_schemes: HashMap<String, UpstreamScheme>,
+    // 上游组负载均衡策略标签
+    group_strategies: HashMap<String, &'static str>,
     // 上游组客户端
     group_clients: HashMap<String, ClientWithMiddleware>,
+    // 每组附加到 DoH 请求的自定义请求头
+    group_headers: HashMap<String, HeaderMap>,
+    // 上游组重试配置
+    group_retries: HashMap<String, RetryConfig>,
+    // 上游组并发请求许可（仅配置了 max_connections 的组）
+    group_permits: HashMap<String, Arc<Semaphore>>,
+    // 初始化失败的上游组及失败原因，转发到这些组的查询直接返回错误
+    unavailable_groups: HashMap<String, String>,
     // DNS 客户端（用于 scheme=dns 的组）
     dns_client: DnsClient,
+    // 各组请求统计
+    stats: Arc<UpstreamStats>,
+    // 是否严格校验 DoH 响应 ID
+    strict_id_check: bool,
+    // 是否对 DoH 查询名称做 0x20 随机大小写
+    case_randomization: bool,
+    // DNS JSON 应答中是否保留未支持类型的记录
+    preserve_unknown_records: bool,
+    // JSON 请求被拒绝时是否改用二进制格式重试
+    json_fallback: bool,
+    // DoH 转发总截止时间（包含重试）
+    forward_deadline: Option<Duration>,
 }
 
 impl UpstreamManager {
@@ -40,8 +84,18 @@ impl UpstreamManager {
     ) -> Result<Self, AppError> {
         let mut group_map = HashMap::with_capacity(groups.len());
         let mut group_schemes = HashMap::with_capacity(groups.len());
+        let mut group_strategies = HashMap::with_capacity(groups.len());
         let mut group_clients = HashMap::new();
+        let mut group_retries = HashMap::new();
+        let mut group_headers = HashMap::new();
+        let mut group_permits = HashMap::new();
+        let mut unavailable_groups = HashMap::new();
         let dns_client = DnsClient::new(dns_config);
+        let strict_id_check = http_config.strict_id_check;
+        let case_randomization = http_config.case_randomization;
+        let preserve_unknown_records = http_config.preserve_unknown_records;
+        let json_fallback = http_config.json_fallback;
+        let forward_deadline = http_config.forward_deadline.map(Duration::from_secs);
 
         // 为每个组创建负载均衡器和HTTP客户端
         for UpstreamGroupConfig {
@@ -51,31 +105,108 @@ impl UpstreamManager {
             servers,
             retry,
             proxy,
+            affinity_ttl,
+            headers,
+            max_connections,
         } in groups
         {
+            let strategy_label = match strategy {
+                LoadBalancingStrategy::RoundRobin => strategy_labels::ROUND_ROBIN,
+                LoadBalancingStrategy::Weighted => strategy_labels::WEIGHTED,
+                LoadBalancingStrategy::Random => strategy_labels::RANDOM,
+                LoadBalancingStrategy::Sticky => strategy_labels::STICKY,
+            };
             let lb: Arc<dyn LoadBalancer> = match strategy {
                 LoadBalancingStrategy::RoundRobin => Arc::new(RoundRobinBalancer::new(servers)),
                 LoadBalancingStrategy::Weighted => Arc::new(WeightedBalancer::new(servers)),
                 LoadBalancingStrategy::Random => Arc::new(RandomBalancer::new(servers)),
+                LoadBalancingStrategy::Sticky => Arc::new(StickyBalancer::new(
+                    servers,
+                    Duration::from_secs(
+                        affinity_ttl.unwrap_or(sticky_limits::DEFAULT_AFFINITY_TTL),
+                    ),
+                )),
             };
 
             if matches!(scheme, UpstreamScheme::Doh) {
-                // 创建该组的HTTP客户端
-                let client = HttpClient::create(&http_config, proxy.as_deref(), retry.as_ref())?;
+                // 创建该组的HTTP客户端，失败时仅将该组标记为不可用，其他组正常工作
+                let client = HttpClient::create(&http_config, proxy.as_deref(), max_connections)
+                    .and_then(|client| {
+                        let headers = if headers.is_empty() {
+                            None
+                        } else {
+                            Some(HttpClient::build_headers(&headers)?)
+                        };
+                        Ok((client, headers))
+                    });
+                let (client, headers) = match client {
+                    Ok(client) => client,
+                    Err(e) => {
+                        error!("Failed to initialize upstream group {}: {}", name, e);
+                        unavailable_groups.insert(name, e.to_string());
+                        continue;
+                    }
+                };
                 group_clients.insert(name.clone(), client);
+                if let Some(headers) = headers {
+                    group_headers.insert(name.clone(), headers);
+                }
+                if let Some(retry) = retry {
+                    group_retries.insert(name.clone(), retry);
+                }
+            }
+
+            if let Some(max_connections) = max_connections {
+                group_permits.insert(name.clone(), Arc::new(Semaphore::new(max_connections)));
             }
 
             group_schemes.insert(name.clone(), scheme);
+            group_strategies.insert(name.clone(), strategy_label);
             group_map.insert(name, lb);
         }
 
+        // 所有上游组均初始化失败时无法转发任何查询，直接返回错误
+        if group_map.is_empty() && !unavailable_groups.is_empty() {
+            let mut reasons: Vec<String> = unavailable_groups
+                .iter()
+                .map(|(name, reason)| format!("{}: {}", name, reason))
+                .collect();
+            reasons.sort();
+            return Err(AppError::Upstream(format!(
+                "All {} upstream group(s) failed to initialize ({})",
+                unavailable_groups.len(),
+                reasons.join("; ")
+            )));
+        }
+
         info!("Initialized {} upstream groups", group_map.len());
+        if !unavailable_groups.is_empty() {
+            warn!(
+                "{} upstream group(s) unavailable due to initialization failures",
+                unavailable_groups.len()
+            );
+        }
+
+        let stats = Arc::new(UpstreamStats::new(
+            group_map.keys().chain(unavailable_groups.keys()).cloned(),
+        ));
 
         Ok(Self {
             groups: group_map,
             group_schemes,
+            group_strategies,
             group_clients,
+            group_headers,
+            group_retries,
+            group_permits,
+            unavailable_groups,
             dns_client,
+            stats,
+            strict_id_check,
+            case_randomization,
+            preserve_unknown_records,
+            json_fallback,
+            forward_deadline,
         })
     }
 
@@ -84,15 +215,136 @@ impl UpstreamManager {
         Ok(Self {
             groups: HashMap::new(),
             group_schemes: HashMap::new(),
+            group_strategies: HashMap::new(),
             group_clients: HashMap::new(),
+            group_headers: HashMap::new(),
+            group_retries: HashMap::new(),
+            group_permits: HashMap::new(),
+            unavailable_groups: HashMap::new(),
             dns_client: DnsClient::new(DnsClientConfig::default()),
+            stats: Arc::new(UpstreamStats::default()),
+            strict_id_check: false,
+            case_randomization: false,
+            preserve_unknown_records: false,
+            json_fallback: false,
+            forward_deadline: None,
         })
     }
 
+    // 记录负载均衡器的一次服务器选择
+    fn record_selection(&self, group_name: &str, server: &UpstreamServerConfig) {
+        let strategy = self
+            .group_strategies
+            .get(group_name)
+            .copied()
+            .unwrap_or(upstream_labels::UNKNOWN);
+        let server_host = match server {
+            UpstreamServerConfig::Doh(s) => s
+                .url
+                .host_str()
+                .unwrap_or(protocol_labels::UNKNOWN)
+                .to_string(),
+            UpstreamServerConfig::Dns(s) => s.addr.ip().to_string(),
+        };
+        metrics::backend().increment(
+            CounterMetric::UpstreamSelections,
+            &[group_name, server_host.as_str(), strategy],
+        );
+    }
+
+    // 获取初始化失败的上游组的失败原因，组可用时返回 None
+    pub fn unavailable_reason(&self, group_name: &str) -> Option<&str> {
+        self.unavailable_groups.get(group_name).map(String::as_str)
+    }
+
+    // 获取各组请求统计
+    pub fn stats(&self) -> Arc<UpstreamStats> {
+        self.stats.clone()
+    }
+
     // 转发查询到指定上游组
     pub async fn forward(&self, query: &Message, group_name: &str) -> Result<Message, AppError> {
+        self.forward_with_context(query, group_name, ForwardContext::default())
+            .await
+    }
+
+    // 携带转发上下文转发查询到指定上游组
+    pub async fn forward_with_context(
+        &self,
+        query: &Message,
+        group_name: &str,
+        context: ForwardContext<'_>,
+    ) -> Result<Message, AppError> {
+        self.forward_recorded(query, group_name, context, None)
+            .await
+    }
+
+    // 将两个查询（例如同名的 A 与 AAAA）并发转发到上游组中的同一台服务器
+    // 上游协商为 HTTP/2 时两个请求复用同一连接上的并发流
+    pub async fn forward_pair(
+        &self,
+        first: &Message,
+        second: &Message,
+        group_name: &str,
+        context: ForwardContext<'_>,
+    ) -> (Result<Message, AppError>, Result<Message, AppError>) {
+        let pinned = match self.groups.get(group_name) {
+            Some(lb) => lb.select_server_for_client(context.client).await.ok(),
+            None => None,
+        };
+        if let Some(server) = pinned {
+            self.record_selection(group_name, server);
+        }
+        // 无法选定服务器时各自按常规流程转发（并记录相应错误）
+        tokio::join!(
+            self.forward_recorded(first, group_name, context, pinned),
+            self.forward_recorded(second, group_name, context, pinned)
+        )
+    }
+
+    // 转发查询并记录组统计
+    async fn forward_recorded<'a>(
+        &'a self,
+        query: &Message,
+        group_name: &str,
+        context: ForwardContext<'_>,
+        pinned: Option<&'a UpstreamServerConfig>,
+    ) -> Result<Message, AppError> {
+        let start_time = Instant::now();
+        let result = self
+            .forward_to_group(query, group_name, context, pinned)
+            .await;
+        self.stats
+            .record(group_name, start_time.elapsed(), result.is_ok());
+        result
+    }
+
+    // 选择上游服务器（或使用指定的服务器）并发送查询
+    async fn forward_to_group<'a>(
+        &'a self,
+        query: &Message,
+        group_name: &str,
+        context: ForwardContext<'_>,
+        pinned: Option<&'a UpstreamServerConfig>,
+    ) -> Result<Message, AppError> {
         debug!("Forwarding request to upstream group: {}", group_name);
 
+        // 初始化失败的组直接返回错误
+        if let Some(reason) = self.unavailable_groups.get(group_name) {
+            debug!("Upstream group {} is unavailable: {}", group_name, reason);
+            metrics::backend().increment(
+                CounterMetric::UpstreamErrors,
+                &[
+                    upstream_protocol_labels::UNKNOWN,
+                    upstream_transport_labels::UNKNOWN,
+                    error_labels::GROUP_UNAVAILABLE,
+                    group_name,
+                    upstream_labels::UNKNOWN,
+                ],
+            );
+            return Err(AppError::UpstreamGroupUnavailable(group_name.to_string()));
+        }
+
         // 获取上游组的负载均衡器
         let load_balancer = match self.groups.get(group_name) {
             Some(lb) => lb,
@@ -110,31 +362,74 @@ impl UpstreamManager {
             }
         };
 
+        let upstream_protocol = match scheme {
+            UpstreamScheme::Doh => upstream_protocol_labels::DOH,
+            UpstreamScheme::Dns => upstream_protocol_labels::DNS,
+        };
+        let upstream_transport = match scheme {
+            UpstreamScheme::Doh => upstream_transport_labels::HTTP,
+            UpstreamScheme::Dns => upstream_transport_labels::UNKNOWN,
+        };
+
+        // 配置了并发上限的组，超出上限的请求排队等待许可；等待时间计入转发截止时间，
+        // 未配置截止时间时最多等待 PERMIT_WAIT_TIMEOUT，超时后直接失败
+        let started = Instant::now();
+        let _permit = match self.group_permits.get(group_name) {
+            Some(permits) => {
+                let wait = self.forward_deadline.unwrap_or(Duration::from_secs(
+                    upstream_connection_limits::PERMIT_WAIT_TIMEOUT,
+                ));
+                match tokio::time::timeout(wait, permits.acquire()).await {
+                    Ok(permit) => permit.ok(),
+                    Err(_) => {
+                        warn!(
+                            "Timed out after {:?} waiting for a connection permit of group {}",
+                            wait, group_name
+                        );
+                        metrics::backend().increment(
+                            CounterMetric::UpstreamErrors,
+                            &[
+                                upstream_protocol,
+                                upstream_transport,
+                                error_labels::CONNECTION_LIMIT,
+                                group_name,
+                                upstream_labels::UNKNOWN,
+                            ],
+                        );
+                        return Err(AppError::Timeout);
+                    }
+                }
+            }
+            None => None,
+        };
+
         // 选择一个上游服务器
-        let selected_server = match load_balancer.select_server().await {
+        let selected = match pinned {
+            Some(server) => Ok(server),
+            None => {
+                let selected = load_balancer.select_server_for_client(context.client).await;
+                if let Ok(server) = selected {
+                    self.record_selection(group_name, server);
+                }
+                selected
+            }
+        };
+        let selected_server = match selected {
             Ok(s) => s,
             Err(e) => {
                 error!("Failed to select upstream server: {}", e);
 
                 // 记录上游错误指标
-                let upstream_protocol = match scheme {
-                    UpstreamScheme::Doh => upstream_protocol_labels::DOH,
-                    UpstreamScheme::Dns => upstream_protocol_labels::DNS,
-                };
-                let upstream_transport = match scheme {
-                    UpstreamScheme::Doh => upstream_transport_labels::HTTP,
-                    UpstreamScheme::Dns => upstream_transport_labels::UNKNOWN,
-                };
-                METRICS
-                    .upstream_errors_total()
-                    .with_label_values(&[
+                metrics::backend().increment(
+                    CounterMetric::UpstreamErrors,
+                    &[
                         upstream_protocol,
                         upstream_transport,
                         error_labels::SELECT_ERROR,
                         group_name,
                         upstream_labels::UNKNOWN,
-                    ])
-                    .inc();
+                    ],
+                );
 
                 return Err(e);
             }
@@ -142,30 +437,6 @@ impl UpstreamManager {
 
         match scheme {
             UpstreamScheme::Doh => {
-                let Some(server) = selected_server.as_doh() else {
-                    error!("Invalid upstream server type for group: {}", group_name);
-                    return Err(AppError::Upstream(
-                        "Invalid upstream server type for this group".to_string(),
-                    ));
-                };
-
-                let server_host = server.url.host_str().unwrap_or(protocol_labels::UNKNOWN);
-                debug!("Selected upstream server: {}", server.url.as_str());
-
-                // 记录上游请求指标
-                METRICS
-                    .upstream_requests_total()
-                    .with_label_values(&[
-                        upstream_protocol_labels::DOH,
-                        upstream_transport_labels::HTTP,
-                        group_name,
-                        server_host,
-                    ])
-                    .inc();
-
-                // 记录开始时间
-                let start_time = Instant::now();
-
                 // 获取组的HTTP客户端
                 let client = match self.group_clients.get(group_name) {
                     Some(c) => c,
@@ -175,44 +446,48 @@ impl UpstreamManager {
                     }
                 };
 
-                // 发送请求（通过reqwest-retry中间件处理重试）
-                let doh_client = DoHClient::new(client);
-                match doh_client.send_request(query, server).await {
-                    Ok(response) => {
-                        // 记录上游请求耗时
-                        let duration = start_time.elapsed();
-                        METRICS
-                            .upstream_duration_seconds()
-                            .with_label_values(&[
-                                upstream_protocol_labels::DOH,
-                                upstream_transport_labels::HTTP,
-                                group_name,
-                                server_host,
-                            ])
-                            .observe(duration.as_secs_f64());
-
-                        Ok(response)
-                    }
-                    Err(e) => {
-                        error!("Upstream request failed: {} - {}", server.url.as_str(), e);
-
-                        // 报告上游失败
-                        load_balancer.report_failure(selected_server).await;
-
-                        // 记录上游错误指标
-                        METRICS
-                            .upstream_errors_total()
-                            .with_label_values(&[
-                                upstream_protocol_labels::DOH,
-                                upstream_transport_labels::HTTP,
-                                error_labels::REQUEST_ERROR,
-                                group_name,
-                                server_host,
-                            ])
-                            .inc();
-
-                        Err(e)
+                // 发送请求（按组的重试配置重试，每次重试重新选择上游服务器）
+                let doh_client = DoHClient::new(client)
+                    .with_strict_id_check(self.strict_id_check)
+                    .with_case_randomization(self.case_randomization)
+                    .with_preserve_unknown_records(self.preserve_unknown_records)
+                    .with_json_fallback(self.json_fallback)
+                    .with_headers(self.group_headers.get(group_name))
+                    .with_traceparent(context.traceparent);
+                let send = self.send_doh_with_retry(
+                    load_balancer.as_ref(),
+                    &doh_client,
+                    query,
+                    selected_server,
+                    group_name,
+                );
+                match self.forward_deadline {
+                    // 截止时间覆盖许可等待与所有重试，超时后放弃剩余重试
+                    Some(deadline) => {
+                        match tokio::time::timeout(deadline.saturating_sub(started.elapsed()), send)
+                            .await
+                        {
+                            Ok(result) => result,
+                            Err(_) => {
+                                warn!(
+                                "Upstream request to group {} exceeded forward deadline of {:?}",
+                                group_name, deadline
+                            );
+                                metrics::backend().increment(
+                                    CounterMetric::UpstreamErrors,
+                                    &[
+                                        upstream_protocol_labels::DOH,
+                                        upstream_transport_labels::HTTP,
+                                        error_labels::DEADLINE_EXCEEDED,
+                                        group_name,
+                                        upstream_labels::UNKNOWN,
+                                    ],
+                                );
+                                Err(AppError::Timeout)
+                            }
+                        }
                     }
+                    None => send.await,
                 }
             }
             UpstreamScheme::Dns => {
@@ -234,24 +509,25 @@ impl UpstreamManager {
                                 DnsTransport::Tcp => upstream_transport_labels::TCP,
                             };
 
-                            METRICS
-                                .upstream_requests_total()
-                                .with_label_values(&[
+                            metrics::backend().increment(
+                                CounterMetric::UpstreamRequests,
+                                &[
                                     upstream_protocol_labels::DNS,
                                     upstream_transport,
                                     group_name,
                                     server_host.as_str(),
-                                ])
-                                .inc();
-                            METRICS
-                                .upstream_duration_seconds()
-                                .with_label_values(&[
+                                ],
+                            );
+                            metrics::backend().observe(
+                                HistogramMetric::UpstreamDuration,
+                                &[
                                     upstream_protocol_labels::DNS,
                                     upstream_transport,
                                     group_name,
                                     server_host.as_str(),
-                                ])
-                                .observe(attempt.duration.as_secs_f64());
+                                ],
+                                attempt.duration.as_secs_f64(),
+                            );
                         }
 
                         Ok(response.message)
@@ -265,24 +541,25 @@ impl UpstreamManager {
                                 DnsTransport::Tcp => upstream_transport_labels::TCP,
                             };
 
-                            METRICS
-                                .upstream_requests_total()
-                                .with_label_values(&[
+                            metrics::backend().increment(
+                                CounterMetric::UpstreamRequests,
+                                &[
                                     upstream_protocol_labels::DNS,
                                     upstream_transport,
                                     group_name,
                                     server_host.as_str(),
-                                ])
-                                .inc();
-                            METRICS
-                                .upstream_duration_seconds()
-                                .with_label_values(&[
+                                ],
+                            );
+                            metrics::backend().observe(
+                                HistogramMetric::UpstreamDuration,
+                                &[
                                     upstream_protocol_labels::DNS,
                                     upstream_transport,
                                     group_name,
                                     server_host.as_str(),
-                                ])
-                                .observe(attempt.duration.as_secs_f64());
+                                ],
+                                attempt.duration.as_secs_f64(),
+                            );
                         }
 
                         if let Some(last_attempt) = e.attempts.last() {
@@ -291,16 +568,16 @@ impl UpstreamManager {
                                 DnsTransport::Tcp => upstream_transport_labels::TCP,
                             };
 
-                            METRICS
-                                .upstream_errors_total()
-                                .with_label_values(&[
+                            metrics::backend().increment(
+                                CounterMetric::UpstreamErrors,
+                                &[
                                     upstream_protocol_labels::DNS,
                                     upstream_transport,
-                                    error_labels::REQUEST_ERROR,
+                                    upstream_error_label(&e.error),
                                     group_name,
                                     server_host.as_str(),
-                                ])
-                                .inc();
+                                ],
+                            );
                         }
 
                         load_balancer.report_failure(selected_server).await;
@@ -311,4 +588,117 @@ impl UpstreamManager {
             }
         }
     }
+
+    // 发送 DoH 请求，遇到可重试错误时按指数退避重试，并为每次重试重新选择（尽量不同的）上游服务器
+    async fn send_doh_with_retry(
+        &self,
+        load_balancer: &dyn LoadBalancer,
+        doh_client: &DoHClient<'_>,
+        query: &Message,
+        first_server: &UpstreamServerConfig,
+        group_name: &str,
+    ) -> Result<Message, AppError> {
+        let retry = self.group_retries.get(group_name);
+        let max_retries = retry.map_or(0, |r| r.attempts);
+        let mut selected_server = first_server;
+        let mut past_retries = 0;
+
+        loop {
+            let Some(server) = selected_server.as_doh() else {
+                error!("Invalid upstream server type for group: {}", group_name);
+                return Err(AppError::Upstream(
+                    "Invalid upstream server type for this group".to_string(),
+                ));
+            };
+
+            let server_host = server.url.host_str().unwrap_or(protocol_labels::UNKNOWN);
+            debug!("Selected upstream server: {}", redact_url(&server.url));
+
+            // 记录上游请求指标
+            metrics::backend().increment(
+                CounterMetric::UpstreamRequests,
+                &[
+                    upstream_protocol_labels::DOH,
+                    upstream_transport_labels::HTTP,
+                    group_name,
+                    server_host,
+                ],
+            );
+
+            // 记录开始时间
+            let start_time = Instant::now();
+
+            let e = match doh_client.send_request(query, server).await {
+                Ok(response) => {
+                    // 记录上游请求耗时
+                    let duration = start_time.elapsed();
+                    metrics::backend().observe(
+                        HistogramMetric::UpstreamDuration,
+                        &[
+                            upstream_protocol_labels::DOH,
+                            upstream_transport_labels::HTTP,
+                            group_name,
+                            server_host,
+                        ],
+                        duration.as_secs_f64(),
+                    );
+
+                    return Ok(response);
+                }
+                Err(e) => e,
+            };
+
+            error!(
+                "Upstream request failed: {} - {}",
+                redact_url(&server.url),
+                e
+            );
+
+            // 报告上游失败
+            load_balancer.report_failure(selected_server).await;
+
+            // 记录上游错误指标
+            let error_type = upstream_error_label(&e);
+            metrics::backend().increment(
+                CounterMetric::UpstreamErrors,
+                &[
+                    upstream_protocol_labels::DOH,
+                    upstream_transport_labels::HTTP,
+                    error_type,
+                    group_name,
+                    server_host,
+                ],
+            );
+
+            if past_retries >= max_retries || !HttpClient::is_retryable(&e) {
+                return Err(e);
+            }
+
+            let delay = retry.map_or(Duration::ZERO, |r| HttpClient::retry_delay(r, past_retries));
+            past_retries += 1;
+            warn!(
+                "Retrying upstream request for group {} in {:?} ({}/{})",
+                group_name, delay, past_retries, max_retries
+            );
+            metrics::backend()
+                .increment(CounterMetric::UpstreamRetries, &[group_name, server_host]);
+            tokio::time::sleep(delay).await;
+
+            // 重新选择上游服务器，尽量避开刚失败的服务器
+            selected_server = load_balancer
+                .select_server_excluding(selected_server)
+                .await?;
+            self.record_selection(group_name, selected_server);
+        }
+    }
+}
+
+// 上游错误对应的指标标签
+fn upstream_error_label(error: &AppError) -> &'static str {
+    match error {
+        AppError::UpstreamIdMismatch { .. } => error_labels::ID_MISMATCH,
+        AppError::UpstreamCaseMismatch { .. } => error_labels::CASE_MISMATCH,
+        AppError::MalformedUpstreamResponse(_) => error_labels::MALFORMED_RESPONSE,
+        _ => error_labels::REQUEST_ERROR,
+    }
 }
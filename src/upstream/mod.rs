@@ -4,10 +4,12 @@ mod doh;
 mod http_client;
 mod json;
 mod manager;
+mod wire;
 
 // 重导出公共API，保持与原来相同的接口
-pub use manager::UpstreamManager;
+pub use manager::{ForwardContext, UpstreamManager};
 
 // 增强重导出
 pub use doh::DoHClient;
 pub use http_client::HttpClient;
+pub use wire::validate_wire_message;
@@ -0,0 +1,101 @@
+use crate::{error::AppError, r#const::wire_limits};
+
+// 校验上游响应的原始报文结构，拒绝可疑的畸形报文：
+// - 名称压缩指针必须指向报文头之后、且严格早于当前位置（杜绝指针环）
+// - 名称总长度不超过 255 字节，标签类型合法
+// - 各段记录不越界，且报文末尾没有多余数据
+pub fn validate_wire_message(raw: &[u8]) -> Result<(), AppError> {
+    if raw.len() < wire_limits::HEADER_LEN {
+        return Err(malformed("message is shorter than the DNS header"));
+    }
+
+    let count = |index: usize| u16::from_be_bytes([raw[index], raw[index + 1]]) as usize;
+    let questions = count(4);
+    let records = count(6) + count(8) + count(10);
+
+    let mut offset = wire_limits::HEADER_LEN;
+    for _ in 0..questions {
+        offset = skip_name(raw, offset)?;
+        // QTYPE + QCLASS
+        offset = advance(raw, offset, 4)?;
+    }
+    for _ in 0..records {
+        offset = skip_name(raw, offset)?;
+        // TYPE + CLASS + TTL + RDLENGTH
+        let rdata = advance(raw, offset, 10)?;
+        let rdlength = u16::from_be_bytes([raw[offset + 8], raw[offset + 9]]) as usize;
+        offset = advance(raw, rdata, rdlength)?;
+    }
+
+    if offset != raw.len() {
+        return Err(malformed(&format!(
+            "{} trailing bytes after the last record",
+            raw.len() - offset
+        )));
+    }
+
+    Ok(())
+}
+
+// 跳过位于 start 处的名称，返回名称之后的位置
+fn skip_name(raw: &[u8], start: usize) -> Result<usize, AppError> {
+    let mut pos = start;
+    // 遇到第一个指针后，名称在原报文中的结束位置
+    let mut end = None;
+    // 指针只能指向该位置之前，每跟随一次指针都会收紧，保证不会成环
+    let mut limit = start;
+    let mut name_len = 0;
+    let mut hops = 0;
+
+    loop {
+        let len = *raw
+            .get(pos)
+            .ok_or_else(|| malformed("name extends past the end of the message"))?;
+
+        match len & 0xC0 {
+            0x00 => {
+                name_len += len as usize + 1;
+                if name_len > wire_limits::MAX_NAME_LEN {
+                    return Err(malformed("name exceeds 255 bytes"));
+                }
+                if len == 0 {
+                    return Ok(end.unwrap_or(pos + 1));
+                }
+                pos = advance(raw, pos + 1, len as usize)?;
+            }
+            0xC0 => {
+                let low = *raw
+                    .get(pos + 1)
+                    .ok_or_else(|| malformed("truncated compression pointer"))?;
+                let target = (((len & 0x3F) as usize) << 8) | low as usize;
+                if target < wire_limits::HEADER_LEN || target >= limit {
+                    return Err(malformed(&format!(
+                        "compression pointer at offset {} does not point backwards (target {})",
+                        pos, target
+                    )));
+                }
+                hops += 1;
+                if hops > wire_limits::MAX_POINTER_HOPS {
+                    return Err(malformed("too many compression pointers in name"));
+                }
+                end.get_or_insert(pos + 2);
+                limit = target;
+                pos = target;
+            }
+            _ => return Err(malformed("unsupported label type")),
+        }
+    }
+}
+
+// 前进 len 字节，越界时返回错误
+fn advance(raw: &[u8], offset: usize, len: usize) -> Result<usize, AppError> {
+    let next = offset + len;
+    if next > raw.len() {
+        return Err(malformed("record extends past the end of the message"));
+    }
+    Ok(next)
+}
+
+fn malformed(reason: &str) -> AppError {
+    AppError::MalformedUpstreamResponse(reason.to_string())
+}
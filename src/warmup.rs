@@ -0,0 +1,152 @@
+use crate::config::{parse_query_type, WarmupConfig};
+use crate::error::AppError;
+use crate::handler::RequestHandler;
+use hickory_proto::op::{Message, MessageType, OpCode, Query};
+use hickory_proto::rr::{Name, RecordType};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::task::JoinSet;
+use tokio_graceful_shutdown::{IntoSubsystem, SubsystemHandle};
+use tracing::{debug, info, warn};
+
+// 服务就绪状态：/readyz 据此返回 200 或 503
+#[derive(Debug)]
+pub struct Readiness {
+    ready: AtomicBool,
+}
+
+impl Readiness {
+    // 创建已就绪的状态
+    pub fn ready() -> Self {
+        Self {
+            ready: AtomicBool::new(true),
+        }
+    }
+
+    // 创建未就绪的状态（等待预热完成）
+    pub fn pending() -> Self {
+        Self {
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    // 是否已就绪
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    // 标记为就绪
+    pub fn mark_ready(&self) {
+        self.ready.store(true, Ordering::Release);
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::ready()
+    }
+}
+
+// 启动预热：通过请求处理器解析配置的域名并写入缓存
+pub struct Warmup {
+    // 请求处理器
+    handler: Arc<RequestHandler>,
+    // 预热查询（名称与类型）
+    queries: Vec<(Name, RecordType)>,
+    // 预热完成后标记就绪
+    readiness: Arc<Readiness>,
+}
+
+impl Warmup {
+    // 根据配置创建预热任务，无法解析的名称或类型会被跳过
+    pub fn new(
+        handler: Arc<RequestHandler>,
+        config: &WarmupConfig,
+        readiness: Arc<Readiness>,
+    ) -> Self {
+        let queries = config
+            .domains
+            .iter()
+            .flat_map(|domain| {
+                let name = Name::from_str(&domain.name).ok().map(|mut name| {
+                    name.set_fqdn(true);
+                    name
+                });
+                domain
+                    .types
+                    .iter()
+                    .filter_map(move |t| Some((name.clone()?, parse_query_type(t)?)))
+            })
+            .collect();
+        Self {
+            handler,
+            queries,
+            readiness,
+        }
+    }
+
+    // 并发执行所有预热查询，返回成功的查询数；失败只记录日志
+    pub async fn run_once(&self) -> usize {
+        let mut tasks = JoinSet::new();
+        for (name, record_type) in &self.queries {
+            let handler = self.handler.clone();
+            let request = warmup_query(name.clone(), *record_type);
+            let label = format!("{} ({})", name, record_type);
+            tasks.spawn(async move { (label, handler.handle_request(&request).await) });
+        }
+
+        let mut succeeded = 0;
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((label, Ok(response))) => {
+                    debug!(
+                        "Warm-up query {} resolved with {}",
+                        label,
+                        response.response_code()
+                    );
+                    succeeded += 1;
+                }
+                Ok((label, Err(e))) => warn!("Warm-up query {} failed: {}", label, e),
+                Err(e) => warn!("Warm-up task failed: {}", e),
+            }
+        }
+        succeeded
+    }
+
+    // 执行预热并标记就绪
+    pub async fn run_and_mark_ready(&self) -> usize {
+        let succeeded = self.run_once().await;
+        info!(
+            "Warm-up completed: {}/{} queries resolved",
+            succeeded,
+            self.queries.len()
+        );
+        self.readiness.mark_ready();
+        succeeded
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoSubsystem<AppError> for Warmup {
+    async fn run(self, subsys: SubsystemHandle) -> Result<(), AppError> {
+        info!("Warm-up started with {} queries", self.queries.len());
+        tokio::select! {
+            _ = subsys.on_shutdown_requested() => info!("Warm-up cancelled by shutdown"),
+            _ = self.run_and_mark_ready() => {}
+        }
+        Ok(())
+    }
+}
+
+// 构造预热查询报文
+fn warmup_query(name: Name, record_type: RecordType) -> Message {
+    let mut message = Message::new();
+    message
+        .set_id(rand::random())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true);
+    message.add_query(Query::query(name, record_type));
+    message
+}
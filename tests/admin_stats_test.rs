@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{ConnectInfo, Query as AxumQuery, State};
+use axum::http::HeaderMap;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hickory_proto::op::ResponseCode;
+use hickory_proto::op::{Message, MessageType, Query};
+use hickory_proto::rr::{Name, RecordType};
+use loadants::{
+    admin::{top_clients_handler, upstream_stats_handler, TopClientsParams},
+    cache::DnsCache,
+    config::{
+        DnsClientConfig, DoHContentType, DoHMethod, DoHUpstreamServerConfig, HttpClientConfig,
+        LoadBalancingStrategy, UpstreamGroupConfig, UpstreamScheme, UpstreamServerConfig,
+    },
+    doh::{
+        handlers::{handle_doh_get, DohGetParams},
+        state::AppState,
+    },
+    handler::RequestHandler,
+    router::Router,
+    stats::{ClientStats, LatencyHistogram},
+    UpstreamManager,
+};
+use reqwest::Url;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+// 创建测试用的 RequestHandler
+fn create_test_handler() -> Arc<RequestHandler> {
+    let cache = Arc::new(DnsCache::new(0, 0, None));
+    let router = Arc::new(Router::new(Vec::new()).expect("Failed to create Router"));
+    let upstream =
+        Arc::new(UpstreamManager::empty().expect("Failed to create empty upstream manager"));
+    Arc::new(RequestHandler::new(cache, router, upstream))
+}
+
+// 创建 base64url 编码的 DNS 查询
+fn encoded_query() -> String {
+    let mut message = Message::new();
+    message.set_id(42);
+    message.set_message_type(MessageType::Query);
+    message.set_recursion_desired(true);
+    message.add_query(Query::query(
+        Name::from_ascii("example.com.").unwrap(),
+        RecordType::A,
+    ));
+    URL_SAFE_NO_PAD.encode(message.to_vec().unwrap())
+}
+
+// 测试 Top Clients 端点反映各客户端的查询次数
+#[tokio::test]
+async fn test_top_clients_endpoint_reflects_counts() {
+    let handler = create_test_handler();
+    let busy: SocketAddr = "192.0.2.10:5353".parse().unwrap();
+    let quiet: SocketAddr = "192.0.2.20:5353".parse().unwrap();
+
+    // 模拟两个客户端发出的查询
+    for (addr, times) in [(busy, 3), (quiet, 1)] {
+        for _ in 0..times {
+            let _ = handle_doh_get(
+                State(AppState::new(handler.clone())),
+                ConnectInfo(addr),
+                HeaderMap::new(),
+                AxumQuery(DohGetParams {
+                    dns: encoded_query(),
+                }),
+            )
+            .await;
+        }
+    }
+
+    let response = top_clients_handler(
+        State(handler.client_stats()),
+        AxumQuery(TopClientsParams { limit: None }),
+    )
+    .await;
+    let body = response.0;
+
+    assert_eq!(body["tracked_clients"], 2);
+    let clients = body["clients"].as_array().unwrap();
+    assert_eq!(clients.len(), 2);
+    assert_eq!(clients[0]["client"], "192.0.2.10");
+    assert_eq!(clients[0]["queries"], 3);
+    assert_eq!(clients[1]["client"], "192.0.2.20");
+    assert_eq!(clients[1]["queries"], 1);
+
+    // limit 参数限制返回数量
+    let response = top_clients_handler(
+        State(handler.client_stats()),
+        AxumQuery(TopClientsParams { limit: Some(1) }),
+    )
+    .await;
+    assert_eq!(response.0["clients"].as_array().unwrap().len(), 1);
+}
+
+// 测试有界计数表淘汰与周期衰减
+#[test]
+fn test_client_stats_bounded_and_decay() {
+    let stats = ClientStats::new(2, Duration::from_secs(3600));
+    let a: IpAddr = "10.0.0.1".parse().unwrap();
+    let b: IpAddr = "10.0.0.2".parse().unwrap();
+    let c: IpAddr = "10.0.0.3".parse().unwrap();
+    let d: IpAddr = "10.0.0.4".parse().unwrap();
+
+    for _ in 0..4 {
+        stats.record(a);
+    }
+    stats.record(b);
+    stats.record(c);
+    stats.record(c);
+
+    // 维护时淘汰计数最小的 b，回到容量以内
+    assert_eq!(stats.len(), 2);
+    let top = stats.top(10);
+    assert_eq!(top[0].client, a);
+    assert_eq!(top[0].queries, 4);
+    assert_eq!(top[1].client, c);
+    assert_eq!(top[1].queries, 2);
+
+    // 新客户端继承被淘汰客户端的计数，随后淘汰计数最小的 c 与 d 之一
+    stats.record(d);
+    let top = stats.top(10);
+    assert_eq!(stats.len(), 2);
+    assert_eq!(top[0].client, a);
+    assert_eq!(top[1].queries, 2);
+
+    // 衰减后计数减半
+    stats.decay();
+    let top = stats.top(10);
+    assert_eq!(top[0].queries, 2);
+    assert_eq!(top[1].queries, 1);
+
+    stats.decay();
+    stats.decay();
+    assert!(stats.is_empty());
+}
+
+// 测试延迟分位数在所在桶的上下界之间插值
+#[test]
+fn test_latency_quantile_interpolates_within_bucket() {
+    let histogram = LatencyHistogram::new();
+    assert_eq!(histogram.quantile(0.5), None);
+
+    // 所有样本落在同一个桶（8.54ms, 12.81ms] 内
+    for _ in 0..100 {
+        histogram.record(Duration::from_millis(10));
+    }
+    let p50 = histogram.quantile(0.5).unwrap();
+    let p99 = histogram.quantile(0.99).unwrap();
+    let max = histogram.quantile(1.0).unwrap();
+    assert!(p50 > 8.6 && p50 < 12.8, "p50 = {}", p50);
+    assert!(p50 < p99 && p99 < max, "p50 = {}, p99 = {}", p50, p99);
+    assert!((max - 0.5 * 1.5f64.powi(8)).abs() < 1e-9);
+}
+
+// 创建指向 mock 服务器的 DoH 上游组
+fn doh_group(name: &str, mock_server: &MockServer) -> UpstreamGroupConfig {
+    UpstreamGroupConfig {
+        name: name.to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::RoundRobin,
+        servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: Url::parse(&format!("{}/dns-query", mock_server.uri())).unwrap(),
+            weight: 1,
+            method: DoHMethod::Post,
+            content_type: DoHContentType::Message,
+            auth: None,
+        })],
+        retry: None,
+        proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
+    }
+}
+
+// 测试上游统计端点反映各组的请求数、错误数与延迟
+#[tokio::test]
+async fn test_upstream_stats_endpoint_reflects_groups() {
+    let query = {
+        let mut message = Message::new();
+        message.set_id(7);
+        message.set_message_type(MessageType::Query);
+        message.add_query(Query::query(
+            Name::from_ascii("example.com.").unwrap(),
+            RecordType::A,
+        ));
+        message
+    };
+
+    // 健康上游：返回正常应答
+    let healthy = MockServer::start().await;
+    let mut answer = query.clone();
+    answer.set_message_type(MessageType::Response);
+    answer.set_response_code(ResponseCode::NoError);
+    Mock::given(method("POST"))
+        .and(path("/dns-query"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Content-Type", "application/dns-message")
+                .set_body_bytes(answer.to_vec().unwrap()),
+        )
+        .mount(&healthy)
+        .await;
+
+    // 故障上游：始终返回 HTTP 500
+    let broken = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/dns-query"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&broken)
+        .await;
+
+    let upstream = UpstreamManager::new(
+        vec![doh_group("alpha", &healthy), doh_group("beta", &broken)],
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    for _ in 0..3 {
+        upstream.forward(&query, "alpha").await.unwrap();
+    }
+    assert!(upstream.forward(&query, "beta").await.is_err());
+
+    let body = upstream_stats_handler(State(upstream.stats())).await.0;
+    let groups = body["groups"].as_array().unwrap();
+    assert_eq!(groups.len(), 2);
+
+    assert_eq!(groups[0]["name"], "alpha");
+    assert_eq!(groups[0]["requests"], 3);
+    assert_eq!(groups[0]["errors"], 0);
+    assert_eq!(groups[0]["success_rate"], 1.0);
+    assert!(groups[0]["p50_ms"].as_f64().unwrap() > 0.0);
+    assert!(groups[0]["p99_ms"].as_f64().unwrap() >= groups[0]["p50_ms"].as_f64().unwrap());
+
+    assert_eq!(groups[1]["name"], "beta");
+    assert_eq!(groups[1]["requests"], 1);
+    assert_eq!(groups[1]["errors"], 1);
+    assert_eq!(groups[1]["success_rate"], 0.0);
+}
@@ -0,0 +1,60 @@
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hickory_proto::op::{Message, MessageType, Query, ResponseCode};
+use hickory_proto::rr::rdata::A;
+use hickory_proto::rr::{Name, RData, Record, RecordType};
+use loadants::cache::{CacheJanitor, DnsCache};
+use loadants::metrics::METRICS;
+
+// 创建 A 查询
+fn create_query(name: &str) -> Message {
+    let mut query = Message::new();
+    query.set_id(1);
+    query.set_message_type(MessageType::Query);
+    query.add_query(Query::query(Name::from_ascii(name).unwrap(), RecordType::A));
+    query
+}
+
+// 创建带单条 A 记录的响应
+fn create_response(query: &Message, ttl: u32) -> Message {
+    let mut response = query.clone();
+    response.set_message_type(MessageType::Response);
+    response.set_response_code(ResponseCode::NoError);
+    response.add_answer(Record::from_rdata(
+        query.queries()[0].name().clone(),
+        ttl,
+        RData::A(A(Ipv4Addr::new(192, 0, 2, 1))),
+    ));
+    // 编解码一次以同步头部中的记录计数
+    Message::from_vec(&response.to_vec().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn test_janitor_updates_cache_entries_gauge_after_expiry() {
+    let cache = Arc::new(DnsCache::new(100, 1, Some(300)));
+    let janitor = CacheJanitor::new(Arc::clone(&cache), Duration::from_secs(30));
+
+    // 两条短 TTL 条目与一条长 TTL 条目
+    for name in ["short1.example.com.", "short2.example.com."] {
+        let query = create_query(name);
+        cache
+            .insert(&query, create_response(&query, 1))
+            .await
+            .unwrap();
+    }
+    let query = create_query("long.example.com.");
+    cache
+        .insert(&query, create_response(&query, 300))
+        .await
+        .unwrap();
+
+    assert_eq!(janitor.run_once().await, 3);
+    assert_eq!(METRICS.cache_entries().get(), 3);
+
+    // 等待短 TTL 条目过期后，清理任务应将指标校准为剩余条目数
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    assert_eq!(janitor.run_once().await, 1);
+    assert_eq!(METRICS.cache_entries().get(), 1);
+}
@@ -0,0 +1,434 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use hickory_proto::op::{Edns, Message, MessageType, Query, ResponseCode};
+use hickory_proto::rr::rdata::A;
+use hickory_proto::rr::{Name, RData, Record, RecordType};
+use loadants::cache::DnsCache;
+use loadants::r#const::cache_limits;
+
+// 创建 A 查询
+fn create_query(name: &str) -> Message {
+    let mut query = Message::new();
+    query.set_id(1);
+    query.set_message_type(MessageType::Query);
+    query.add_query(Query::query(Name::from_ascii(name).unwrap(), RecordType::A));
+    query
+}
+
+// 创建带单条 A 记录的响应
+fn create_response(query: &Message, ttl: u32) -> Message {
+    let mut response = query.clone();
+    response.set_message_type(MessageType::Response);
+    response.set_response_code(ResponseCode::NoError);
+    response.add_answer(Record::from_rdata(
+        query.queries()[0].name().clone(),
+        ttl,
+        RData::A(A(Ipv4Addr::new(192, 0, 2, 1))),
+    ));
+    response
+}
+
+// 测试接近过期的缓存命中返回的 TTL 不低于 serve_ttl_floor
+#[tokio::test]
+async fn test_near_expiry_hit_respects_serve_ttl_floor() {
+    // min_ttl 使条目在缓存中保留 10 秒，而记录本身 TTL 仅 1 秒
+    let cache = DnsCache::new(100, 10, None).with_serve_ttl_floor(5);
+    let query = create_query("floor.example.com.");
+    cache
+        .insert(&query, create_response(&query, 1))
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let hit = cache
+        .get(&query)
+        .await
+        .expect("entry should still be cached");
+    assert!(hit.answers().iter().all(|record| record.ttl() >= 5));
+}
+
+// 测试未配置 serve_ttl_floor 时保持原有的 1 秒下限
+#[tokio::test]
+async fn test_near_expiry_hit_default_floor() {
+    let cache = DnsCache::new(100, 10, None);
+    let query = create_query("default-floor.example.com.");
+    cache
+        .insert(&query, create_response(&query, 1))
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let hit = cache
+        .get(&query)
+        .await
+        .expect("entry should still be cached");
+    assert_eq!(hit.answers()[0].ttl(), 1);
+}
+
+// 测试关闭随机排序后缓存命中保持上游返回的记录顺序
+#[tokio::test]
+async fn test_shuffle_disabled_preserves_answer_order() {
+    let cache = DnsCache::new(100, 60, None).with_shuffle_answers(false);
+    let query = create_query("ordered.example.com.");
+    let mut response = create_response(&query, 300);
+    for i in 2..=8 {
+        response.add_answer(Record::from_rdata(
+            query.queries()[0].name().clone(),
+            300,
+            RData::A(A(Ipv4Addr::new(192, 0, 2, i))),
+        ));
+    }
+    let expected: Vec<RData> = response
+        .answers()
+        .iter()
+        .filter_map(|record| record.data().cloned())
+        .collect();
+    cache.insert(&query, response).await.unwrap();
+
+    for _ in 0..100 {
+        let hit = cache.get(&query).await.expect("entry should be cached");
+        let actual: Vec<RData> = hit
+            .answers()
+            .iter()
+            .filter_map(|record| record.data().cloned())
+            .collect();
+        assert_eq!(actual, expected);
+    }
+}
+
+// 测试缓存命中的响应携带按当前客户端生成的 OPT 记录，而不是上游缓存的 OPT
+#[tokio::test]
+async fn test_cache_hit_rebuilds_opt_record() {
+    let cache = DnsCache::new(100, 60, None);
+    let query = create_query("edns.example.com.");
+    let mut response = create_response(&query, 300);
+    let mut upstream_edns = Edns::new();
+    upstream_edns.set_max_payload(4096);
+    upstream_edns.set_dnssec_ok(true);
+    response.set_edns(upstream_edns);
+    cache.insert(&query, response).await.unwrap();
+
+    // 客户端未使用 EDNS 时不附带 OPT
+    let hit = cache.get(&query).await.expect("entry should be cached");
+    assert!(hit.extensions().is_none());
+
+    // 客户端使用 EDNS 时通告本地负载大小并回显 DO 位
+    let mut edns_query = query.clone();
+    let mut client_edns = Edns::new();
+    client_edns.set_max_payload(512);
+    client_edns.set_dnssec_ok(false);
+    edns_query.set_edns(client_edns);
+    let hit = cache
+        .get(&edns_query)
+        .await
+        .expect("entry should be cached");
+    let edns = hit.extensions().as_ref().expect("OPT should be present");
+    assert_eq!(edns.max_payload(), 1232);
+    assert!(!edns.dnssec_ok());
+
+    let mut do_query = query.clone();
+    let mut client_edns = Edns::new();
+    client_edns.set_dnssec_ok(true);
+    do_query.set_edns(client_edns);
+    let hit = cache.get(&do_query).await.expect("entry should be cached");
+    assert!(hit.extensions().as_ref().unwrap().dnssec_ok());
+}
+
+// 测试剩余TTL低于 min_remaining_ttl 的条目按未命中处理
+#[tokio::test]
+async fn test_min_remaining_ttl_treats_expiring_entry_as_miss() {
+    // 测试响应未更新头部计数，按负面缓存 TTL（2 秒）缓存
+    let cache = DnsCache::new(100, 1, Some(2)).with_min_remaining_ttl(2);
+    let query = create_query("expiring.example.com.");
+    cache
+        .insert(&query, create_response(&query, 2))
+        .await
+        .unwrap();
+
+    // 剩余 2 秒，满足阈值
+    assert!(cache.get(&query).await.is_some());
+
+    // 1 秒后剩余 1 秒，低于阈值，视为未命中
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    assert!(cache.get(&query).await.is_none());
+
+    // 未设置阈值时同样的条目仍然命中
+    let cache = DnsCache::new(100, 1, Some(2));
+    cache
+        .insert(&query, create_response(&query, 2))
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    assert!(cache.get(&query).await.is_some());
+}
+
+// 测试大小写不同的同名查询共享同一缓存条目，且命中时回显客户端的问题部分
+#[tokio::test]
+async fn test_case_variants_share_cache_entry() {
+    let cache = DnsCache::new(100, 60, None);
+    let query = create_query("Example.COM.");
+    cache
+        .insert(&query, create_response(&query, 300))
+        .await
+        .unwrap();
+
+    let variant = create_query("eXaMpLe.cOm.");
+    assert!(cache.contains(&create_query("example.com.")));
+    let hit = cache.get(&variant).await.expect("case variant should hit");
+    assert_eq!(hit.queries(), variant.queries());
+    assert_eq!(hit.queries()[0].name().to_ascii(), "eXaMpLe.cOm.");
+    // 应答记录保持原样
+    assert_eq!(hit.answers()[0].name().to_ascii(), "Example.COM.");
+
+    // 关闭归一后大小写不同的查询各自缓存
+    let cache = DnsCache::new(100, 60, None).with_normalize_key_case(false);
+    cache
+        .insert(&query, create_response(&query, 300))
+        .await
+        .unwrap();
+    assert!(cache.get(&variant).await.is_none());
+}
+
+// 创建指定响应码且不含应答记录的响应
+fn create_error_response(query: &Message, code: ResponseCode) -> Message {
+    let mut response = query.clone();
+    response.set_message_type(MessageType::Response);
+    response.set_response_code(code);
+    response
+}
+
+// 测试 SERVFAIL 按 servfail_ttl 缓存，NXDOMAIN 仍按 negative_ttl 缓存
+#[tokio::test]
+async fn test_servfail_uses_separate_ttl() {
+    let cache = DnsCache::new(100, 1, Some(60)).with_servfail_ttl(1);
+    let servfail_query = create_query("servfail.example.com.");
+    let nxdomain_query = create_query("nxdomain.example.com.");
+    cache
+        .insert(
+            &servfail_query,
+            create_error_response(&servfail_query, ResponseCode::ServFail),
+        )
+        .await
+        .unwrap();
+    cache
+        .insert(
+            &nxdomain_query,
+            create_error_response(&nxdomain_query, ResponseCode::NXDomain),
+        )
+        .await
+        .unwrap();
+
+    let hit = cache
+        .get(&servfail_query)
+        .await
+        .expect("SERVFAIL should be cached");
+    assert_eq!(hit.response_code(), ResponseCode::ServFail);
+
+    // servfail_ttl 过期后 SERVFAIL 条目失效，NXDOMAIN 条目仍然命中
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert!(cache.get(&servfail_query).await.is_none());
+    let hit = cache
+        .get(&nxdomain_query)
+        .await
+        .expect("NXDOMAIN should still be cached");
+    assert_eq!(hit.response_code(), ResponseCode::NXDomain);
+
+    // servfail_ttl 为 0 时不缓存 SERVFAIL
+    let cache = DnsCache::new(100, 1, Some(60)).with_servfail_ttl(0);
+    cache
+        .insert(
+            &servfail_query,
+            create_error_response(&servfail_query, ResponseCode::ServFail),
+        )
+        .await
+        .unwrap();
+    assert!(cache.get(&servfail_query).await.is_none());
+}
+
+// 测试缓存超出容量时固定域名的条目不被淘汰
+#[tokio::test]
+async fn test_pinned_domains_survive_eviction() {
+    let cache = DnsCache::new(10, 1, None).with_pinned_domains(&["infra.example".to_string()]);
+    let pinned = [
+        create_query("infra.example."),
+        create_query("ns1.infra.example."),
+    ];
+    for query in &pinned {
+        cache
+            .insert(query, create_response(query, 300))
+            .await
+            .unwrap();
+    }
+
+    let unpinned: Vec<Message> = (0..200)
+        .map(|i| create_query(&format!("host{}.example.com.", i)))
+        .collect();
+    for query in &unpinned {
+        cache
+            .insert(query, create_response(query, 300))
+            .await
+            .unwrap();
+    }
+    cache.len().await;
+
+    for query in &pinned {
+        assert!(cache.get(query).await.is_some());
+    }
+    let retained = unpinned.iter().filter(|q| cache.contains(q)).count();
+    assert!(retained <= 10, "retained {} unpinned entries", retained);
+
+    // 固定条目同样在 TTL 到期后失效
+    let cache = DnsCache::new(10, 1, Some(1)).with_pinned_domains(&["infra.example".to_string()]);
+    cache
+        .insert(&pinned[0], create_response(&pinned[0], 1))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert!(cache.get(&pinned[0]).await.is_none());
+}
+
+// 测试固定缓存的条目数受上限约束
+#[tokio::test]
+async fn test_pinned_cache_is_bounded() {
+    let cache = DnsCache::new(10, 1, None).with_pinned_domains(&["infra.example".to_string()]);
+    let total = cache_limits::MAX_PINNED_ENTRIES + 500;
+    for i in 0..total {
+        let query = create_query(&format!("host{}.infra.example.", i));
+        cache
+            .insert(&query, create_response(&query, 300))
+            .await
+            .unwrap();
+    }
+    let entries = cache.len().await as u64;
+    assert!(
+        entries <= cache_limits::MAX_PINNED_ENTRIES,
+        "pinned cache holds {} entries",
+        entries
+    );
+}
+
+// 创建 NXDOMAIN 响应
+fn create_nxdomain_response(query: &Message) -> Message {
+    let mut response = query.clone();
+    response.set_message_type(MessageType::Response);
+    response.set_response_code(ResponseCode::NXDomain);
+    response
+}
+
+// 测试启用 NXDOMAIN 截断后，子域名查询命中祖先名称缓存的 NXDOMAIN
+#[tokio::test]
+async fn test_nxdomain_cut_answers_descendants() {
+    let ancestor = create_query("nx.example.com.");
+    let descendant = create_query("a.b.NX.example.com.");
+
+    let cache = DnsCache::new(100, 1, None).with_nxdomain_cut(true);
+    cache
+        .insert(&ancestor, create_nxdomain_response(&ancestor))
+        .await
+        .unwrap();
+    let hit = cache
+        .get(&descendant)
+        .await
+        .expect("descendant should be covered by ancestor NXDOMAIN");
+    assert_eq!(hit.response_code(), ResponseCode::NXDomain);
+    assert_eq!(hit.queries(), descendant.queries());
+
+    // NXDOMAIN 与记录类型无关
+    let mut aaaa = create_query("nx.example.com.");
+    aaaa.queries_mut()[0].set_query_type(RecordType::AAAA);
+    assert!(cache.get(&aaaa).await.is_some());
+
+    // 父名称与兄弟名称不受影响
+    assert!(cache.get(&create_query("example.com.")).await.is_none());
+    assert!(cache.get(&create_query("ok.example.com.")).await.is_none());
+
+    // 未启用时子域名查询未命中
+    let cache = DnsCache::new(100, 1, None);
+    cache
+        .insert(&ancestor, create_nxdomain_response(&ancestor))
+        .await
+        .unwrap();
+    assert!(cache.get(&descendant).await.is_none());
+}
+
+// 测试兄弟名称的肯定应答不影响截断，而名称下存在子域名的应答撤销截断
+#[tokio::test]
+async fn test_nxdomain_cut_contradicted_only_by_descendants() {
+    let cache = DnsCache::new(100, 1, None).with_nxdomain_cut(true);
+    let ancestor = create_query("nx.example.com.");
+    let descendant = create_query("host.nx.example.com.");
+    cache
+        .insert(&ancestor, create_nxdomain_response(&ancestor))
+        .await
+        .unwrap();
+
+    let sibling = create_query("ok.example.com.");
+    cache
+        .insert(&sibling, create_response(&sibling, 300))
+        .await
+        .unwrap();
+    let hit = cache.get(&descendant).await.expect("cut should survive");
+    assert_eq!(hit.response_code(), ResponseCode::NXDomain);
+
+    // 子域名实际存在：截断被撤销，其他子域名不再由截断应答
+    let existing = create_query("www.nx.example.com.");
+    cache
+        .insert(&existing, create_response(&existing, 300))
+        .await
+        .unwrap();
+    assert!(cache.get(&descendant).await.is_none());
+    assert!(cache.get(&existing).await.is_some());
+}
+
+// 测试陈旧窗口内的过期条目仍被返回并标记为陈旧，刷新后恢复为新鲜条目
+#[tokio::test]
+async fn test_stale_while_revalidate_serves_expired_entry() {
+    // create_response 的应答使用负面缓存 TTL，此处设为 1 秒
+    let cache = DnsCache::new(100, 1, Some(1)).with_stale_while_revalidate(30);
+    let query = create_query("stale.example.com.");
+    cache
+        .insert(&query, create_response(&query, 300))
+        .await
+        .unwrap();
+    let (_, stale) = cache.get_with_staleness(&query).await.unwrap();
+    assert!(!stale);
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let (response, stale) = cache
+        .get_with_staleness(&query)
+        .await
+        .expect("expired entry should be served within the stale window");
+    assert!(stale);
+    assert_eq!(response.answers().len(), 1);
+    assert!(response.answers()[0].ttl() >= 1);
+
+    // 同一条目只允许一个进行中的重新验证
+    assert!(cache.begin_revalidation(&query));
+    assert!(!cache.begin_revalidation(&query));
+    cache
+        .insert(&query, create_response(&query, 300))
+        .await
+        .unwrap();
+    cache.end_revalidation(&query, true);
+    let (_, stale) = cache.get_with_staleness(&query).await.unwrap();
+    assert!(!stale);
+    assert!(cache.begin_revalidation(&query));
+
+    // 重新验证失败后在重试间隔内不再发起新的重新验证
+    cache.end_revalidation(&query, false);
+    assert!(!cache.begin_revalidation(&query));
+    cache.sweep_revalidations();
+    assert!(!cache.begin_revalidation(&query));
+
+    // 未启用时过期条目不再返回
+    let cache = DnsCache::new(100, 1, Some(1));
+    cache
+        .insert(&query, create_response(&query, 300))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert!(cache.get(&query).await.is_none());
+}
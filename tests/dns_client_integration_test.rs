@@ -5,6 +5,7 @@ use loadants::config::{
     UpstreamGroupConfig, UpstreamScheme, UpstreamServerConfig,
 };
 use loadants::upstream::UpstreamManager;
+use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::str::FromStr;
 use std::sync::{
@@ -116,6 +117,9 @@ async fn build_dns_manager(addr: SocketAddr, dns_config: DnsClientConfig) -> Ups
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     UpstreamManager::new(groups, HttpClientConfig::default(), dns_config)
@@ -0,0 +1,219 @@
+#![cfg(unix)]
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hickory_proto::op::{Message, MessageType, Query, ResponseCode};
+use hickory_proto::rr::{Name, RecordType};
+use loadants::{
+    cache::DnsCache,
+    config::{DnstapConfig, MatchType, RouteAction, RouteRuleConfig},
+    dnstap::{
+        control_frame, read_control_frame, DnstapLogger, CONTROL_ACCEPT, CONTROL_FINISH,
+        CONTROL_READY, CONTROL_START, CONTROL_STOP,
+    },
+    handler::{RequestContext, RequestHandler},
+    router::Router,
+    UpstreamManager,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+
+// 解码后的 protobuf 字段值
+#[derive(Debug, Clone)]
+enum Field {
+    Varint(u64),
+    Fixed32(u32),
+    Bytes(Vec<u8>),
+}
+
+// 读取 protobuf varint
+fn read_varint(buf: &[u8], pos: &mut usize) -> u64 {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = buf[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+// 将 protobuf 消息解码为（字段号，值）列表
+fn decode_fields(buf: &[u8]) -> Vec<(u64, Field)> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let key = read_varint(buf, &mut pos);
+        let value = match key & 0x07 {
+            0 => Field::Varint(read_varint(buf, &mut pos)),
+            2 => {
+                let len = read_varint(buf, &mut pos) as usize;
+                pos += len;
+                Field::Bytes(buf[pos - len..pos].to_vec())
+            }
+            5 => {
+                pos += 4;
+                Field::Fixed32(u32::from_le_bytes(buf[pos - 4..pos].try_into().unwrap()))
+            }
+            wire_type => panic!("unexpected wire type {}", wire_type),
+        };
+        fields.push((key >> 3, value));
+    }
+    fields
+}
+
+fn field(fields: &[(u64, Field)], number: u64) -> Option<Field> {
+    fields
+        .iter()
+        .find(|(n, _)| *n == number)
+        .map(|(_, value)| value.clone())
+}
+
+fn varint(fields: &[(u64, Field)], number: u64) -> u64 {
+    match field(fields, number) {
+        Some(Field::Varint(value)) => value,
+        other => panic!("field {} is not a varint: {:?}", number, other),
+    }
+}
+
+fn bytes(fields: &[(u64, Field)], number: u64) -> Vec<u8> {
+    match field(fields, number) {
+        Some(Field::Bytes(value)) => value,
+        other => panic!("field {} is not bytes: {:?}", number, other),
+    }
+}
+
+// 读取一个 Frame Streams 数据帧
+async fn read_data_frame(stream: &mut UnixStream) -> Vec<u8> {
+    let len = stream.read_u32().await.unwrap() as usize;
+    assert!(len > 0, "expected data frame, got control frame");
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await.unwrap();
+    payload
+}
+
+#[tokio::test]
+async fn test_dnstap_emits_client_query_and_response_frames() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket_path = dir.path().join("dnstap.sock");
+    let listener = UnixListener::bind(&socket_path).unwrap();
+
+    let config = DnstapConfig {
+        socket_path: Some(socket_path.to_string_lossy().to_string()),
+        address: None,
+        identity: Some("test-node".to_string()),
+        queue_size: 16,
+    };
+    let (logger, output) = DnstapLogger::new(&config).unwrap();
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let output_task = tokio::spawn(output.serve(async {
+        let _ = stop_rx.await;
+    }));
+
+    // 采集端完成双向握手：READY -> ACCEPT -> START
+    let (mut stream, _) = listener.accept().await.unwrap();
+    assert_eq!(
+        read_control_frame(&mut stream).await.unwrap(),
+        CONTROL_READY
+    );
+    stream
+        .write_all(&control_frame(CONTROL_ACCEPT))
+        .await
+        .unwrap();
+    assert_eq!(
+        read_control_frame(&mut stream).await.unwrap(),
+        CONTROL_START
+    );
+
+    let router = Arc::new(
+        Router::new(vec![RouteRuleConfig {
+            match_type: MatchType::Wildcard,
+            patterns: vec!["*".to_string()],
+            action: RouteAction::Block,
+            target: None,
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        }])
+        .unwrap(),
+    );
+    let handler = RequestHandler::new(
+        Arc::new(DnsCache::new(0, 0, Some(0))),
+        router,
+        Arc::new(UpstreamManager::empty().unwrap()),
+    )
+    .with_dnstap(logger);
+
+    let mut query = Message::new();
+    query
+        .set_id(4242)
+        .set_message_type(MessageType::Query)
+        .set_recursion_desired(true);
+    query.add_query(Query::query(
+        Name::from_ascii("ads.example.com.").unwrap(),
+        RecordType::A,
+    ));
+    let client: SocketAddr = "127.0.0.1:5353".parse().unwrap();
+    let context = RequestContext::from_client(client).with_protocol("udp");
+    let response = handler
+        .handle_request_with_context(&query, &context)
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+
+    let frames = tokio::time::timeout(Duration::from_secs(5), async {
+        (
+            read_data_frame(&mut stream).await,
+            read_data_frame(&mut stream).await,
+        )
+    })
+    .await
+    .expect("dnstap frames should be emitted");
+
+    for (frame, expected_type) in [(frames.0, 5), (frames.1, 6)] {
+        let dnstap = decode_fields(&frame);
+        assert_eq!(bytes(&dnstap, 1), b"test-node");
+        assert!(String::from_utf8(bytes(&dnstap, 2))
+            .unwrap()
+            .starts_with("load-ants"));
+        // Dnstap.type = MESSAGE
+        assert_eq!(varint(&dnstap, 15), 1);
+
+        let message = decode_fields(&bytes(&dnstap, 14));
+        assert_eq!(varint(&message, 1), expected_type);
+        // INET / UDP
+        assert_eq!(varint(&message, 2), 1);
+        assert_eq!(varint(&message, 3), 1);
+        assert_eq!(bytes(&message, 4), vec![127, 0, 0, 1]);
+        assert_eq!(varint(&message, 6), 5353);
+        assert!(varint(&message, 8) > 0);
+        assert!(matches!(field(&message, 9), Some(Field::Fixed32(nanos)) if nanos < 1_000_000_000));
+
+        if expected_type == 5 {
+            let logged = Message::from_vec(&bytes(&message, 10)).unwrap();
+            assert_eq!(logged.id(), 4242);
+            assert_eq!(logged.queries()[0].name().to_ascii(), "ads.example.com.");
+        } else {
+            assert!(varint(&message, 12) >= varint(&message, 8));
+            let logged = Message::from_vec(&bytes(&message, 14)).unwrap();
+            assert_eq!(logged.id(), 4242);
+            assert_eq!(logged.response_code(), ResponseCode::NXDomain);
+        }
+    }
+
+    // 关闭时发送 STOP，采集端回复 FINISH
+    stop_tx.send(()).unwrap();
+    assert_eq!(read_control_frame(&mut stream).await.unwrap(), CONTROL_STOP);
+    stream
+        .write_all(&control_frame(CONTROL_FINISH))
+        .await
+        .unwrap();
+    output_task.await.unwrap();
+}
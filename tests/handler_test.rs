@@ -0,0 +1,2490 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hickory_proto::op::{Edns, Message, MessageType, Query, ResponseCode};
+use hickory_proto::rr::dnssec::rdata::{DNSSECRData, NSEC, NSEC3, RRSIG};
+use hickory_proto::rr::dnssec::{
+    tbs, Algorithm, DigestType, KeyFormat, KeyPair, Nsec3HashAlgorithm, Private,
+};
+use hickory_proto::rr::rdata::opt::{EdnsCode, EdnsOption};
+use hickory_proto::rr::rdata::{A, AAAA, ANAME, CNAME, PTR, SOA};
+use hickory_proto::rr::{DNSClass, Name, RData, Record, RecordType};
+use loadants::{
+    cache::DnsCache,
+    config::{
+        AnswerSort, Dns64Config, DnsClientConfig, DnssecConfig, DoHContentType, DoHMethod,
+        DoHUpstreamServerConfig, HttpClientConfig, LoadBalancingStrategy, MatchType, RebindAction,
+        RebindProtectionConfig, ResponseConfig, RootTldAction, RouteAction, RouteRuleConfig,
+        ShadowConfig, TracingConfig, UpstreamGroupConfig, UpstreamScheme, UpstreamServerConfig,
+        UpstreamTimeoutRcode, WarmupConfig, WeightedTargetConfig,
+    },
+    dns64::Dns64,
+    dnssec::DnssecValidator,
+    doh::{handlers::handle_doh_post, state::AppState},
+    extended_error::ExtendedError,
+    handler::{cname_chain_length, RequestContext, RequestHandler},
+    metrics::METRICS,
+    rebind::RebindGuard,
+    router::Router,
+    warmup::{Readiness, Warmup},
+    UpstreamManager,
+};
+use reqwest::Url;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, Request, Respond, ResponseTemplate,
+};
+
+// 根据查询动态生成 DoH 响应的 mock 响应器
+struct DnsResponder<F>(F);
+
+impl<F> Respond for DnsResponder<F>
+where
+    F: Fn(&Message) -> Message + Send + Sync + 'static,
+{
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let query = Message::from_vec(&request.body).expect("Invalid DNS query body");
+        let response = (self.0)(&query);
+        ResponseTemplate::new(200)
+            .insert_header("Content-Type", "application/dns-message")
+            .set_body_bytes(response.to_vec().unwrap())
+    }
+}
+
+// 启动 DoH mock 上游并创建转发所有域名到该上游的处理器
+async fn create_forwarding_handler<F>(
+    mock_server: &MockServer,
+    responder: F,
+) -> (Arc<Router>, Arc<UpstreamManager>)
+where
+    F: Fn(&Message) -> Message + Send + Sync + 'static,
+{
+    Mock::given(method("POST"))
+        .and(path("/dns-query"))
+        .respond_with(DnsResponder(responder))
+        .mount(mock_server)
+        .await;
+
+    let groups = vec![UpstreamGroupConfig {
+        name: "test_group".to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::RoundRobin,
+        servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: Url::parse(&format!("{}/dns-query", mock_server.uri())).unwrap(),
+            weight: 1,
+            method: DoHMethod::Post,
+            content_type: DoHContentType::Message,
+            auth: None,
+        })],
+        retry: None,
+        proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
+    }];
+    let upstream = Arc::new(
+        UpstreamManager::new(
+            groups,
+            HttpClientConfig::default(),
+            DnsClientConfig::default(),
+        )
+        .await
+        .unwrap(),
+    );
+
+    let router = Arc::new(
+        Router::new(vec![RouteRuleConfig {
+            match_type: MatchType::Wildcard,
+            patterns: vec!["*".to_string()],
+            action: RouteAction::Forward,
+            target: Some("test_group".to_string()),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        }])
+        .unwrap(),
+    );
+
+    (router, upstream)
+}
+
+// 创建测试用的 DNS 查询
+fn create_query(name: &str, record_type: RecordType) -> Message {
+    let mut message = Message::new();
+    message.set_id(4321);
+    message.set_message_type(MessageType::Query);
+    message.set_recursion_desired(true);
+    message.add_query(Query::query(Name::from_ascii(name).unwrap(), record_type));
+    message
+}
+
+// 根据查询创建带有指定应答的响应
+fn create_response(query: &Message, answers: Vec<Record>) -> Message {
+    let mut response = Message::new();
+    response.set_id(query.id());
+    response.set_message_type(MessageType::Response);
+    response.set_recursion_desired(true);
+    response.set_recursion_available(true);
+    response.set_response_code(ResponseCode::NoError);
+    response.add_queries(query.queries().to_vec());
+    response.add_answers(answers);
+    response
+}
+
+// 创建 A 记录
+fn a_record(name: &str, addr: Ipv4Addr) -> Record {
+    Record::from_rdata(Name::from_ascii(name).unwrap(), 300, RData::A(A(addr)))
+}
+
+// 创建带有缓存且没有上游的处理器
+fn create_handler(cache: Arc<DnsCache>, response_config: ResponseConfig) -> RequestHandler {
+    let router = Arc::new(Router::new(Vec::new()).expect("Failed to create Router"));
+    let upstream =
+        Arc::new(UpstreamManager::empty().expect("Failed to create empty upstream manager"));
+    RequestHandler::new(cache, router, upstream).with_response_config(response_config)
+}
+
+// 提取应答中的 IPv4 地址
+fn answer_addrs(response: &Message) -> Vec<Ipv4Addr> {
+    response
+        .answers()
+        .iter()
+        .filter_map(|record| match record.data() {
+            Some(RData::A(a)) => Some(a.0),
+            _ => None,
+        })
+        .collect()
+}
+
+// 测试 subnet 排序模式下客户端同一子网的地址排在前面
+#[tokio::test]
+async fn test_answer_sort_prefers_client_subnet() {
+    let cache = Arc::new(DnsCache::new(100, 1, None));
+    let query = create_query("www.example.com.", RecordType::A);
+    let response = create_response(
+        &query,
+        vec![
+            a_record("www.example.com.", Ipv4Addr::new(10, 0, 0, 5)),
+            a_record("www.example.com.", Ipv4Addr::new(192, 168, 1, 10)),
+            a_record("www.example.com.", Ipv4Addr::new(10, 0, 1, 7)),
+            a_record("www.example.com.", Ipv4Addr::new(192, 168, 1, 20)),
+        ],
+    );
+    cache.insert(&query, response).await.unwrap();
+
+    let handler = create_handler(
+        cache,
+        ResponseConfig {
+            answer_sort: AnswerSort::Subnet,
+            ..Default::default()
+        },
+    );
+    let client: SocketAddr = "192.168.1.99:53000".parse().unwrap();
+
+    // 缓存命中时地址顺序随机，多次请求验证排序稳定生效
+    for _ in 0..10 {
+        let result = handler
+            .handle_request_with_context(&query, &RequestContext::from_client(client))
+            .await
+            .unwrap();
+
+        let addrs = answer_addrs(&result);
+        assert_eq!(addrs.len(), 4);
+        assert!(addrs[..2]
+            .iter()
+            .all(|addr| addr.octets()[..3] == [192, 168, 1]));
+        assert!(addrs[2..].iter().all(|addr| addr.octets()[0] == 10));
+    }
+}
+
+// 测试未启用排序或缺少客户端地址时不影响应答内容
+#[tokio::test]
+async fn test_answer_sort_disabled_keeps_answers() {
+    let cache = Arc::new(DnsCache::new(100, 1, None));
+    let query = create_query("www.example.org.", RecordType::A);
+    let response = create_response(
+        &query,
+        vec![
+            a_record("www.example.org.", Ipv4Addr::new(10, 0, 0, 5)),
+            a_record("www.example.org.", Ipv4Addr::new(192, 168, 1, 10)),
+        ],
+    );
+    cache.insert(&query, response).await.unwrap();
+
+    let handler = create_handler(cache, ResponseConfig::default());
+    let result = handler.handle_request(&query).await.unwrap();
+
+    let mut addrs = answer_addrs(&result);
+    addrs.sort();
+    assert_eq!(
+        addrs,
+        vec![Ipv4Addr::new(10, 0, 0, 5), Ipv4Addr::new(192, 168, 1, 10)]
+    );
+}
+
+// 模拟上游：v4only.example.com 只有 A 记录，dual.example.com 同时有 A 和 AAAA 记录
+fn dns64_upstream(query: &Message) -> Message {
+    let q = query.queries()[0].clone();
+    let name = q.name().to_ascii();
+    let answers = match (name.as_str(), q.query_type()) {
+        (_, RecordType::A) => vec![a_record(&name, Ipv4Addr::new(192, 0, 2, 33))],
+        ("dual.example.com.", RecordType::AAAA) => vec![Record::from_rdata(
+            q.name().clone(),
+            300,
+            RData::AAAA(AAAA("2001:db8::1".parse().unwrap())),
+        )],
+        _ => Vec::new(),
+    };
+    create_response(query, answers)
+}
+
+// 创建启用 DNS64 的处理器
+async fn create_dns64_handler(mock_server: &MockServer, exclude: Vec<String>) -> RequestHandler {
+    let (router, upstream) = create_forwarding_handler(mock_server, dns64_upstream).await;
+    let dns64 = Dns64::from_config(&Dns64Config {
+        enabled: true,
+        exclude,
+        ..Dns64Config::default()
+    })
+    .unwrap();
+    RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream).with_dns64(dns64)
+}
+
+// 提取应答中的 IPv6 地址
+fn answer_v6_addrs(response: &Message) -> Vec<Ipv6Addr> {
+    response
+        .answers()
+        .iter()
+        .filter_map(|record| match record.data() {
+            Some(RData::AAAA(aaaa)) => Some(aaaa.0),
+            _ => None,
+        })
+        .collect()
+}
+
+// 模拟上游：所有 A 查询同时返回一个公网地址与一个私有地址
+fn rebind_upstream(query: &Message) -> Message {
+    let name = query.queries()[0].name().to_ascii();
+    create_response(
+        query,
+        vec![
+            a_record(&name, Ipv4Addr::new(203, 0, 113, 7)),
+            a_record(&name, Ipv4Addr::new(192, 168, 1, 20)),
+        ],
+    )
+}
+
+// 创建启用重绑定防护的处理器
+async fn create_rebind_handler(mock_server: &MockServer, action: RebindAction) -> RequestHandler {
+    let (router, upstream) = create_forwarding_handler(mock_server, rebind_upstream).await;
+    let rebind = RebindGuard::from_config(&RebindProtectionConfig {
+        enabled: true,
+        action,
+        allowlist: vec!["corp.internal".to_string()],
+    })
+    .unwrap();
+    RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream)
+        .with_rebind_protection(rebind)
+}
+
+// 测试公网域名应答中的私有地址被移除，白名单域名不受影响
+#[tokio::test]
+async fn test_rebind_protection_strips_private_answers() {
+    let mock_server = MockServer::start().await;
+    let handler = create_rebind_handler(&mock_server, RebindAction::Strip).await;
+
+    let query = create_query("evil.example.com.", RecordType::A);
+    let response = handler.handle_request(&query).await.unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(203, 0, 113, 7)]);
+
+    let query = create_query("git.corp.internal.", RecordType::A);
+    let response = handler.handle_request(&query).await.unwrap();
+    let mut addrs = answer_addrs(&response);
+    addrs.sort();
+    assert_eq!(
+        addrs,
+        vec![
+            Ipv4Addr::new(192, 168, 1, 20),
+            Ipv4Addr::new(203, 0, 113, 7)
+        ]
+    );
+}
+
+// 测试 refuse 模式下包含私有地址的公网域名应答被拒绝
+#[tokio::test]
+async fn test_rebind_protection_refuses_private_answers() {
+    let mock_server = MockServer::start().await;
+    let handler = create_rebind_handler(&mock_server, RebindAction::Refuse).await;
+
+    let query = create_query("evil.example.com.", RecordType::A);
+    let response = handler.handle_request(&query).await.unwrap();
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+    assert!(response.answers().is_empty());
+
+    let query = create_query("corp.internal.", RecordType::A);
+    let response = handler.handle_request(&query).await.unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(response.answers().len(), 2);
+}
+
+// 测试私有地址范围判断
+#[test]
+fn test_rebind_private_address_ranges() {
+    for addr in [
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.0.1",
+        "127.0.0.1",
+        "169.254.1.1",
+        "0.0.0.0",
+        "::1",
+        "fe80::1",
+        "fd00::1",
+        "::ffff:192.168.1.1",
+    ] {
+        assert!(
+            loadants::rebind::is_private_address(addr.parse().unwrap()),
+            "{} should be private",
+            addr
+        );
+    }
+    for addr in ["8.8.8.8", "172.32.0.1", "2001:db8::1", "::ffff:8.8.8.8"] {
+        assert!(
+            !loadants::rebind::is_private_address(addr.parse().unwrap()),
+            "{} should be public",
+            addr
+        );
+    }
+}
+
+// 测试解析 A 查询后会预取 AAAA 伴随查询，随后的 AAAA 查询直接命中缓存
+#[tokio::test]
+async fn test_prefetch_companion_caches_aaaa() {
+    let mock_server = MockServer::start().await;
+    let (router, upstream) = create_forwarding_handler(&mock_server, dns64_upstream).await;
+    let cache = Arc::new(DnsCache::new(100, 1, None));
+    let handler =
+        RequestHandler::new(cache.clone(), router, upstream).with_prefetch_companion(true);
+
+    let query = create_query("dual.example.com.", RecordType::A);
+    handler.handle_request(&query).await.unwrap();
+
+    // 等待异步预取完成
+    let companion = create_query("dual.example.com.", RecordType::AAAA);
+    for _ in 0..50 {
+        if cache.contains(&companion) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 2);
+
+    let response = handler.handle_request(&companion).await.unwrap();
+    assert_eq!(
+        answer_v6_addrs(&response),
+        vec!["2001:db8::1".parse::<Ipv6Addr>().unwrap()]
+    );
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 2);
+}
+
+// 测试客户端网段规则优先于通用规则，且不会命中其他客户端写入的共享缓存
+#[tokio::test]
+async fn test_client_cidr_rules_skip_shared_cache() {
+    let mock_server = MockServer::start().await;
+    let (_, upstream) = create_forwarding_handler(&mock_server, |query| {
+        create_response(
+            query,
+            vec![a_record(
+                "video.example.com.",
+                Ipv4Addr::new(203, 0, 113, 7),
+            )],
+        )
+    })
+    .await;
+    let router = Arc::new(
+        Router::new(vec![
+            RouteRuleConfig {
+                match_type: MatchType::Exact,
+                patterns: vec!["video.example.com".to_string()],
+                action: RouteAction::Block,
+                target: None,
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: vec!["192.168.50.0/24".to_string()],
+                source: None,
+            },
+            RouteRuleConfig {
+                match_type: MatchType::Wildcard,
+                patterns: vec!["*".to_string()],
+                action: RouteAction::Forward,
+                target: Some("test_group".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
+            },
+        ])
+        .unwrap(),
+    );
+    let cache = Arc::new(DnsCache::new(100, 1, None));
+    let handler = RequestHandler::new(cache.clone(), router, upstream);
+    let query = create_query("video.example.com.", RecordType::A);
+    let resolve = |client: &str| {
+        let context = RequestContext::from_client(SocketAddr::new(client.parse().unwrap(), 5353));
+        let handler = &handler;
+        let query = &query;
+        async move {
+            handler
+                .handle_request_with_context(query, &context)
+                .await
+                .unwrap()
+        }
+    };
+
+    // 可信网段客户端经通用规则转发，应答写入缓存
+    let response = resolve("10.0.0.5").await;
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(203, 0, 113, 7)]);
+    assert!(cache.contains(&query));
+
+    // 访客网段客户端命中网段规则被拦截，而不是读取缓存中的应答
+    let response = resolve("192.168.50.10").await;
+    assert!(answer_addrs(&response).is_empty());
+
+    // 可信网段客户端随后仍从缓存得到原应答
+    let response = resolve("10.0.0.5").await;
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(203, 0, 113, 7)]);
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 1);
+}
+
+// 测试客户端网段规则的应答按网段规则组缓存，且全局拦截规则对网段内客户端同样生效
+#[tokio::test]
+async fn test_client_cidr_rules_use_scoped_cache() {
+    let mock_server = MockServer::start().await;
+    let (_, upstream) = create_forwarding_handler(&mock_server, |query| {
+        let name = query.queries()[0].name().to_ascii();
+        create_response(query, vec![a_record(&name, Ipv4Addr::new(203, 0, 113, 7))])
+    })
+    .await;
+    let forward_all = |client_cidrs: Vec<String>| RouteRuleConfig {
+        match_type: MatchType::Wildcard,
+        patterns: vec!["*".to_string()],
+        action: RouteAction::Forward,
+        target: Some("test_group".to_string()),
+        weighted_targets: Vec::new(),
+        fallback: Vec::new(),
+        fallback_on_nxdomain: false,
+        client_cidrs,
+        source: None,
+    };
+    let router = Arc::new(
+        Router::new(vec![
+            RouteRuleConfig {
+                match_type: MatchType::Exact,
+                patterns: vec!["ads.example.com".to_string()],
+                action: RouteAction::Block,
+                target: None,
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
+            },
+            forward_all(vec!["192.168.50.0/24".to_string()]),
+            forward_all(Vec::new()),
+        ])
+        .unwrap(),
+    );
+    let cache = Arc::new(DnsCache::new(100, 1, None));
+    let handler = RequestHandler::new(cache.clone(), router, upstream);
+    let resolve = |name: &str, client: &str| {
+        let context = RequestContext::from_client(SocketAddr::new(client.parse().unwrap(), 5353));
+        let handler = &handler;
+        let query = create_query(name, RecordType::A);
+        async move {
+            handler
+                .handle_request_with_context(&query, &context)
+                .await
+                .unwrap()
+        }
+    };
+    let upstream_calls = || async { mock_server.received_requests().await.unwrap().len() };
+
+    // 网段内客户端的应答写入网段作用域，同网段的后续查询命中缓存
+    resolve("video.example.com.", "192.168.50.10").await;
+    resolve("video.example.com.", "192.168.50.11").await;
+    assert_eq!(upstream_calls().await, 1);
+    assert!(!cache.contains(&create_query("video.example.com.", RecordType::A)));
+
+    // 网段外客户端不读取网段作用域的条目
+    resolve("video.example.com.", "10.0.0.5").await;
+    assert_eq!(upstream_calls().await, 2);
+    assert!(cache.contains(&create_query("video.example.com.", RecordType::A)));
+
+    // 全局拦截规则优先于网段规则
+    let response = resolve("ads.example.com.", "192.168.50.10").await;
+    assert!(answer_addrs(&response).is_empty());
+    assert_eq!(upstream_calls().await, 2);
+}
+
+// 启动统计已接受连接数的 TCP 转发代理
+async fn start_counting_proxy(target: SocketAddr) -> (SocketAddr, Arc<AtomicUsize>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((mut inbound, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut outbound = tokio::net::TcpStream::connect(target).await.unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+            });
+        }
+    });
+    (addr, connections)
+}
+
+// 测试合并模式下 A 与 AAAA 并发发往同一上游，并复用同一条 HTTP/2 连接
+#[tokio::test]
+async fn test_coalesce_companion_reuses_connection() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/dns-query"))
+        .respond_with(DnsResponder(dns64_upstream))
+        .mount(&mock_server)
+        .await;
+    let (proxy_addr, connections) = start_counting_proxy(*mock_server.address()).await;
+
+    let groups = vec![UpstreamGroupConfig {
+        name: "test_group".to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::RoundRobin,
+        servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: Url::parse(&format!("http://{}/dns-query", proxy_addr)).unwrap(),
+            weight: 1,
+            method: DoHMethod::Post,
+            content_type: DoHContentType::Message,
+            auth: None,
+        })],
+        retry: None,
+        proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
+    }];
+    let http_config = HttpClientConfig {
+        http2_prior_knowledge: true,
+        ..HttpClientConfig::default()
+    };
+    let upstream = Arc::new(
+        UpstreamManager::new(groups, http_config, DnsClientConfig::default())
+            .await
+            .unwrap(),
+    );
+    let router = Arc::new(
+        Router::new(vec![RouteRuleConfig {
+            match_type: MatchType::Wildcard,
+            patterns: vec!["*".to_string()],
+            action: RouteAction::Forward,
+            target: Some("test_group".to_string()),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        }])
+        .unwrap(),
+    );
+    let cache = Arc::new(DnsCache::new(100, 1, None));
+    let handler =
+        RequestHandler::new(cache.clone(), router, upstream).with_coalesce_companion(true);
+
+    let query = create_query("dual.example.com.", RecordType::A);
+    let response = handler.handle_request(&query).await.unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+
+    // 伴随查询与主查询一同转发，其应答在后台写入缓存
+    let companion = create_query("dual.example.com.", RecordType::AAAA);
+    for _ in 0..50 {
+        if cache.contains(&companion) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(cache.contains(&companion));
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 2);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    let response = handler.handle_request(&companion).await.unwrap();
+    assert_eq!(
+        answer_v6_addrs(&response),
+        vec!["2001:db8::1".parse::<Ipv6Addr>().unwrap()]
+    );
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 2);
+}
+
+// 测试只有 A 记录的域名会按配置前缀合成 AAAA
+#[tokio::test]
+async fn test_dns64_synthesizes_aaaa_from_a() {
+    let mock_server = MockServer::start().await;
+    let handler = create_dns64_handler(&mock_server, Vec::new()).await;
+
+    let query = create_query("v4only.example.com.", RecordType::AAAA);
+    let response = handler.handle_request(&query).await.unwrap();
+
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(
+        answer_v6_addrs(&response),
+        vec!["64:ff9b::c000:221".parse::<Ipv6Addr>().unwrap()]
+    );
+}
+
+// 测试存在真实 AAAA 记录时不做合成
+#[tokio::test]
+async fn test_dns64_keeps_real_aaaa() {
+    let mock_server = MockServer::start().await;
+    let handler = create_dns64_handler(&mock_server, Vec::new()).await;
+
+    let query = create_query("dual.example.com.", RecordType::AAAA);
+    let response = handler.handle_request(&query).await.unwrap();
+
+    assert_eq!(
+        answer_v6_addrs(&response),
+        vec!["2001:db8::1".parse::<Ipv6Addr>().unwrap()]
+    );
+}
+
+// 测试被排除的 IPv4 范围不参与合成
+#[tokio::test]
+async fn test_dns64_skips_excluded_ranges() {
+    let mock_server = MockServer::start().await;
+    let handler = create_dns64_handler(&mock_server, vec!["192.0.2.0/24".to_string()]).await;
+
+    let query = create_query("v4only.example.com.", RecordType::AAAA);
+    let response = handler.handle_request(&query).await.unwrap();
+
+    assert!(answer_v6_addrs(&response).is_empty());
+}
+
+// 测试 RFC 6052 各前缀长度的地址嵌入
+#[test]
+fn test_dns64_prefix_embedding() {
+    let v4 = Ipv4Addr::new(192, 0, 2, 33);
+    let cases = [
+        ("2001:db8::/32", "2001:db8:c000:221::"),
+        ("2001:db8:100::/40", "2001:db8:1c0:2:21::"),
+        ("2001:db8:122::/48", "2001:db8:122:c000:2:2100::"),
+        ("2001:db8:122:300::/56", "2001:db8:122:3c0:0:221::"),
+        ("2001:db8:122:344::/64", "2001:db8:122:344:c0:2:2100:0"),
+        ("64:ff9b::/96", "64:ff9b::c000:221"),
+    ];
+
+    for (prefix, expected) in cases {
+        let dns64 = Dns64::from_config(&Dns64Config {
+            enabled: true,
+            prefix: prefix.to_string(),
+            exclude: Vec::new(),
+        })
+        .unwrap();
+        assert_eq!(
+            dns64.synthesize_addr(v4),
+            expected.parse::<Ipv6Addr>().unwrap(),
+            "prefix {}",
+            prefix
+        );
+    }
+}
+
+// 模拟上游：返回 hop{N}.chain.test 开始的 CNAME 链，N 由查询名称第一个标签给出
+fn cname_chain_upstream(query: &Message) -> Message {
+    let q = query.queries()[0].clone();
+    let name = q.name().to_ascii();
+    let hops: usize = name
+        .split('.')
+        .next()
+        .and_then(|label| label.strip_prefix("len"))
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0);
+
+    let mut answers = Vec::new();
+    let mut current = q.name().clone();
+    for i in 0..hops {
+        let next = Name::from_ascii(format!("hop{}.chain.test.", i)).unwrap();
+        answers.push(Record::from_rdata(
+            current,
+            300,
+            RData::CNAME(CNAME(next.clone())),
+        ));
+        current = next;
+    }
+    answers.push(a_record(&current.to_ascii(), Ipv4Addr::new(192, 0, 2, 1)));
+    create_response(query, answers)
+}
+
+// 测试 CNAME 链超过上限时返回 SERVFAIL，未超限时正常返回
+#[tokio::test]
+async fn test_max_cname_chain_guard_trips() {
+    let mock_server = MockServer::start().await;
+    let (router, upstream) = create_forwarding_handler(&mock_server, cname_chain_upstream).await;
+    let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream)
+        .with_response_config(ResponseConfig {
+            max_cname_chain: 8,
+            ..Default::default()
+        });
+
+    let response = handler
+        .handle_request(&create_query("len8.chain.test.", RecordType::A))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 1)]);
+
+    let response = handler
+        .handle_request(&create_query("len30.chain.test.", RecordType::A))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::ServFail);
+    assert!(response.answers().is_empty());
+}
+
+// 模拟上游：apex.example.com 只返回指向 target.example.net 的 ANAME 别名
+fn aname_upstream(query: &Message) -> Message {
+    let q = query.queries()[0].clone();
+    let answers = match (q.name().to_ascii().as_str(), q.query_type()) {
+        ("apex.example.com.", _) => vec![Record::from_rdata(
+            q.name().clone(),
+            60,
+            RData::ANAME(ANAME(Name::from_ascii("target.example.net.").unwrap())),
+        )],
+        ("target.example.net.", RecordType::A) => vec![
+            a_record("target.example.net.", Ipv4Addr::new(192, 0, 2, 50)),
+            a_record("target.example.net.", Ipv4Addr::new(192, 0, 2, 51)),
+        ],
+        _ => Vec::new(),
+    };
+    create_response(query, answers)
+}
+
+// 测试开启 flatten_aname 后 ANAME 被展开为查询名称下的 A 记录
+#[tokio::test]
+async fn test_flatten_aname_returns_target_addresses() {
+    let mock_server = MockServer::start().await;
+    let (router, upstream) = create_forwarding_handler(&mock_server, aname_upstream).await;
+    let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream)
+        .with_response_config(ResponseConfig {
+            flatten_aname: true,
+            ..Default::default()
+        });
+
+    let response = handler
+        .handle_request(&create_query("apex.example.com.", RecordType::A))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(
+        answer_addrs(&response),
+        vec![Ipv4Addr::new(192, 0, 2, 50), Ipv4Addr::new(192, 0, 2, 51)]
+    );
+    for record in response.answers() {
+        assert_eq!(record.record_type(), RecordType::A);
+        assert_eq!(record.name().to_ascii(), "apex.example.com.");
+        assert_eq!(record.ttl(), 60);
+    }
+}
+
+// 测试未开启 flatten_aname 时原样返回 ANAME 记录
+#[tokio::test]
+async fn test_flatten_aname_disabled_keeps_alias() {
+    let mock_server = MockServer::start().await;
+    let (router, upstream) = create_forwarding_handler(&mock_server, aname_upstream).await;
+    let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream);
+
+    let response = handler
+        .handle_request(&create_query("apex.example.com.", RecordType::A))
+        .await
+        .unwrap();
+    assert!(answer_addrs(&response).is_empty());
+    assert_eq!(response.answers()[0].record_type(), RecordType::ANAME);
+}
+
+// 测试开启 bypass_on_cd 后 CD=1 查询不读写缓存，CD=0 查询正常命中缓存
+#[tokio::test]
+async fn test_bypass_on_cd_skips_cache() {
+    let mock_server = MockServer::start().await;
+    let (router, upstream) = create_forwarding_handler(&mock_server, |query: &Message| {
+        let name = query.queries()[0].name().to_ascii();
+        create_response(query, vec![a_record(&name, Ipv4Addr::new(192, 0, 2, 7))])
+    })
+    .await;
+    let cache = Arc::new(DnsCache::new(100, 60, None).with_bypass_on_cd(true));
+    let handler = RequestHandler::new(cache, router, upstream);
+
+    let mut cd_query = create_query("cd.example.com.", RecordType::A);
+    cd_query.set_checking_disabled(true);
+    for _ in 0..2 {
+        let response = handler.handle_request(&cd_query).await.unwrap();
+        assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 7)]);
+    }
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 2);
+
+    let query = create_query("cd.example.com.", RecordType::A);
+    for _ in 0..2 {
+        let response = handler.handle_request(&query).await.unwrap();
+        assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 7)]);
+    }
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 3);
+}
+
+// 测试开启 block_explain 后被拦截域名的 TXT 查询返回拦截说明，其他类型仍返回 NXDOMAIN
+#[tokio::test]
+async fn test_block_explain_returns_txt_for_blocked_name() {
+    let router = Router::new(vec![RouteRuleConfig {
+        match_type: MatchType::Wildcard,
+        patterns: vec!["*.ads.example.com".to_string()],
+        action: RouteAction::Block,
+        target: None,
+        weighted_targets: Vec::new(),
+        fallback: Vec::new(),
+        fallback_on_nxdomain: false,
+        client_cidrs: Vec::new(),
+        source: None,
+    }])
+    .unwrap();
+    let handler = RequestHandler::new(
+        Arc::new(DnsCache::new(0, 0, None)),
+        Arc::new(router),
+        Arc::new(UpstreamManager::empty().unwrap()),
+    )
+    .with_response_config(ResponseConfig {
+        block_explain: true,
+        ..Default::default()
+    });
+
+    let response = handler
+        .handle_request(&create_query("tracker.ads.example.com.", RecordType::TXT))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(response.answers().len(), 1);
+    let answer = &response.answers()[0];
+    assert_eq!(
+        answer.name(),
+        &Name::from_ascii("tracker.ads.example.com.").unwrap()
+    );
+    match answer.data() {
+        Some(RData::TXT(txt)) => {
+            let text = txt.to_string();
+            assert!(text.contains("wildcard"), "unexpected TXT: {}", text);
+            assert!(
+                text.contains("*.ads.example.com"),
+                "unexpected TXT: {}",
+                text
+            );
+        }
+        other => panic!("expected TXT answer, got {:?}", other),
+    }
+
+    let response = handler
+        .handle_request(&create_query("tracker.ads.example.com.", RecordType::A))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+    assert!(response.answers().is_empty());
+}
+
+// 测试拦截响应的 SOA 记录携带配置的 block_ttl
+#[tokio::test]
+async fn test_block_response_uses_block_ttl() {
+    for block_ttl in [0, 30] {
+        let router = Router::new(vec![RouteRuleConfig {
+            match_type: MatchType::Exact,
+            patterns: vec!["blocked.example.com".to_string()],
+            action: RouteAction::Block,
+            target: None,
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        }])
+        .unwrap();
+        let handler = RequestHandler::new(
+            Arc::new(DnsCache::new(0, 0, None)),
+            Arc::new(router),
+            Arc::new(UpstreamManager::empty().unwrap()),
+        )
+        .with_response_config(ResponseConfig {
+            block_ttl,
+            ..Default::default()
+        });
+
+        let response = handler
+            .handle_request(&create_query("blocked.example.com.", RecordType::A))
+            .await
+            .unwrap();
+        assert_eq!(response.response_code(), ResponseCode::NXDomain);
+        assert_eq!(response.name_servers().len(), 1);
+        let soa_record = &response.name_servers()[0];
+        assert_eq!(soa_record.ttl(), block_ttl);
+        match soa_record.data() {
+            Some(RData::SOA(soa)) => assert_eq!(soa.minimum(), block_ttl),
+            other => panic!("expected SOA record, got {:?}", other),
+        }
+    }
+}
+
+// 合成的未知 EDNS 选项代码（位于本地/实验用范围）
+const SYNTHETIC_EDNS_CODE: u16 = 65001;
+
+// 构造携带未知 EDNS 选项的查询
+fn create_query_with_unknown_option(name: &str) -> Message {
+    let mut query = create_query(name, RecordType::A);
+    let mut edns = Edns::new();
+    edns.options_mut().insert(EdnsOption::Unknown(
+        SYNTHETIC_EDNS_CODE,
+        b"load-ants".to_vec(),
+    ));
+    query.set_edns(edns);
+    query
+}
+
+// 启动回显 EDNS 选项的上游，返回上游是否收到合成选项的标记
+async fn create_edns_echo_handler(
+    mock_server: &MockServer,
+    edns_passthrough: bool,
+) -> (RequestHandler, Arc<AtomicBool>) {
+    let seen = Arc::new(AtomicBool::new(false));
+    let seen_by_upstream = seen.clone();
+    let (router, upstream) = create_forwarding_handler(mock_server, move |query: &Message| {
+        if let Some(edns) = query.extensions() {
+            if edns
+                .options()
+                .get(EdnsCode::from(SYNTHETIC_EDNS_CODE))
+                .is_some()
+            {
+                seen_by_upstream.store(true, Ordering::SeqCst);
+            }
+        }
+        let name = query.queries()[0].name().to_ascii();
+        let mut response =
+            create_response(query, vec![a_record(&name, Ipv4Addr::new(192, 0, 2, 9))]);
+        if let Some(edns) = query.extensions() {
+            response.set_edns(edns.clone());
+        }
+        response
+    })
+    .await;
+    let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream)
+        .with_response_config(ResponseConfig {
+            edns_passthrough,
+            ..Default::default()
+        });
+    (handler, seen)
+}
+
+// 测试开启 edns_passthrough 后未知 EDNS 选项到达上游并随应答返回
+#[tokio::test]
+async fn test_edns_passthrough_forwards_unknown_option() {
+    let mock_server = MockServer::start().await;
+    let (handler, seen) = create_edns_echo_handler(&mock_server, true).await;
+
+    let response = handler
+        .handle_request(&create_query_with_unknown_option("edns.example.com."))
+        .await
+        .unwrap();
+    assert!(seen.load(Ordering::SeqCst));
+    let option = response
+        .extensions()
+        .as_ref()
+        .and_then(|edns| edns.options().get(EdnsCode::from(SYNTHETIC_EDNS_CODE)));
+    assert_eq!(
+        option,
+        Some(&EdnsOption::Unknown(
+            SYNTHETIC_EDNS_CODE,
+            b"load-ants".to_vec()
+        ))
+    );
+}
+
+// 测试未开启 edns_passthrough 时未知 EDNS 选项不会发送到上游
+#[tokio::test]
+async fn test_edns_passthrough_disabled_strips_unknown_option() {
+    let mock_server = MockServer::start().await;
+    let (handler, seen) = create_edns_echo_handler(&mock_server, false).await;
+
+    let response = handler
+        .handle_request(&create_query_with_unknown_option("edns.example.com."))
+        .await
+        .unwrap();
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 9)]);
+    assert!(!seen.load(Ordering::SeqCst));
+}
+
+// 测试 CNAME 链长度计算与环路检测
+#[test]
+fn test_cname_chain_length_detects_loop() {
+    let a = Name::from_ascii("a.loop.test.").unwrap();
+    let b = Name::from_ascii("b.loop.test.").unwrap();
+    let looped = vec![
+        Record::from_rdata(a.clone(), 60, RData::CNAME(CNAME(b.clone()))),
+        Record::from_rdata(b.clone(), 60, RData::CNAME(CNAME(a.clone()))),
+    ];
+    assert_eq!(cname_chain_length(&looped, &a), None);
+    assert_eq!(cname_chain_length(&looped[..1], &a), Some(1));
+    assert_eq!(cname_chain_length(&[], &a), Some(0));
+}
+
+// 测试用签名区域：包含区域名称、签名密钥与 DS 格式信任锚
+struct SignedZone {
+    zone: Name,
+    key: KeyPair<Private>,
+    key_tag: u16,
+}
+
+impl SignedZone {
+    fn new(zone: &str) -> Self {
+        let pkcs8 = KeyPair::generate_pkcs8(Algorithm::ED25519).unwrap();
+        let key = KeyFormat::Pkcs8
+            .decode_key(&pkcs8, None, Algorithm::ED25519)
+            .unwrap();
+        // 使用 RFC 4034 附录 B 的密钥标签（基于完整 DNSKEY RDATA）
+        let key_tag = key
+            .to_dnskey(Algorithm::ED25519)
+            .unwrap()
+            .calculate_key_tag()
+            .unwrap();
+        Self {
+            zone: Name::from_ascii(zone).unwrap(),
+            key,
+            key_tag,
+        }
+    }
+
+    fn dnskey_record(&self) -> Record {
+        let dnskey = self.key.to_dnskey(Algorithm::ED25519).unwrap();
+        Record::from_rdata(
+            self.zone.clone(),
+            3600,
+            RData::DNSSEC(DNSSECRData::DNSKEY(dnskey)),
+        )
+    }
+
+    fn trust_anchor(&self) -> String {
+        let digest = self
+            .key
+            .to_dnskey(Algorithm::ED25519)
+            .unwrap()
+            .to_digest(&self.zone, DigestType::SHA256)
+            .unwrap();
+        let digest: String = digest
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        format!("{} {} 15 2 {}", self.zone, self.key_tag, digest)
+    }
+
+    // 为 RRset 生成 RRSIG 记录
+    fn sign(&self, records: &[Record]) -> Record {
+        let first = &records[0];
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+        let (inception, expiration) = (now - 3600, now + 3600);
+        let tbs = tbs::rrset_tbs(
+            first.name(),
+            DNSClass::IN,
+            first.name().num_labels(),
+            first.record_type(),
+            Algorithm::ED25519,
+            first.ttl(),
+            expiration,
+            inception,
+            self.key_tag,
+            &self.zone,
+            records,
+        )
+        .unwrap();
+        let sig = self.key.sign(Algorithm::ED25519, &tbs).unwrap();
+        let rrsig = RRSIG::new(
+            first.record_type(),
+            Algorithm::ED25519,
+            first.name().num_labels(),
+            first.ttl(),
+            expiration,
+            inception,
+            self.key_tag,
+            self.zone.clone(),
+            sig,
+        );
+        Record::from_rdata(
+            first.name().clone(),
+            first.ttl(),
+            RData::DNSSEC(DNSSECRData::RRSIG(rrsig)),
+        )
+    }
+}
+
+// 创建设置了 DO 位的查询
+fn create_dnssec_query(name: &str, record_type: RecordType) -> Message {
+    let mut query = create_query(name, record_type);
+    let mut edns = Edns::new();
+    edns.set_dnssec_ok(true);
+    query.set_edns(edns);
+    query
+}
+
+// 创建区域顶点的 SOA 记录
+fn soa_record(zone: &str) -> Record {
+    let zone = Name::from_ascii(zone).unwrap();
+    Record::from_rdata(
+        zone.clone(),
+        3600,
+        RData::SOA(SOA::new(
+            Name::from_ascii("ns.signed.test.").unwrap(),
+            Name::from_ascii("admin.signed.test.").unwrap(),
+            1,
+            3600,
+            600,
+            86400,
+            300,
+        )),
+    )
+}
+
+// 以 base32hex 编码生成 NSEC3 所有者名称
+fn nsec3_owner(hash: &[u8], zone: &str) -> Name {
+    const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuv";
+    let mut label = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in hash {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            label.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        label.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    Name::from_ascii(format!("{}.{}", label, zone)).unwrap()
+}
+
+// 创建 NSEC3 记录（SHA-1，无盐，0 次迭代）
+fn nsec3_record(owner: Name, opt_out: bool, next: Vec<u8>, types: Vec<RecordType>) -> Record {
+    Record::from_rdata(
+        owner,
+        300,
+        RData::DNSSEC(DNSSECRData::NSEC3(NSEC3::new(
+            Nsec3HashAlgorithm::SHA1,
+            opt_out,
+            0,
+            Vec::new(),
+            next,
+            types,
+        ))),
+    )
+}
+
+// 创建启用 DNSSEC 验证的处理器：www 返回正确签名的应答，tampered 返回签名后被篡改的应答，
+// stripped 返回被剥离签名的应答；unsigned 为有 NSEC 证明的未签名委派，optout 为有 NSEC3 opt-out 证明的未签名委派，
+// fake 为没有 DS 否定证明的委派
+async fn create_dnssec_handler(mock_server: &MockServer) -> RequestHandler {
+    let zone = SignedZone::new("signed.test.");
+
+    let dnskey = vec![zone.dnskey_record()];
+    let dnskey_answers = vec![dnskey[0].clone(), zone.sign(&dnskey)];
+
+    let www = vec![a_record("www.signed.test.", Ipv4Addr::new(192, 0, 2, 1))];
+    let www_answers = vec![www[0].clone(), zone.sign(&www)];
+
+    let tampered = vec![a_record(
+        "tampered.signed.test.",
+        Ipv4Addr::new(192, 0, 2, 2),
+    )];
+    let tampered_answers = vec![
+        a_record("tampered.signed.test.", Ipv4Addr::new(203, 0, 113, 66)),
+        zone.sign(&tampered),
+    ];
+
+    let nsec = vec![Record::from_rdata(
+        Name::from_ascii("unsigned.signed.test.").unwrap(),
+        300,
+        RData::DNSSEC(DNSSECRData::NSEC(NSEC::new(
+            Name::from_ascii("www.signed.test.").unwrap(),
+            vec![RecordType::NS, RecordType::RRSIG, RecordType::NSEC],
+        ))),
+    )];
+    let nsec_authority = vec![nsec[0].clone(), zone.sign(&nsec)];
+
+    // 最近祖先 signed.test. 的 NSEC3 精确匹配，另一条设置 opt-out 的 NSEC3 覆盖 optout.signed.test.
+    let encloser_hash = Nsec3HashAlgorithm::SHA1
+        .hash(&[], &zone.zone, 0)
+        .unwrap()
+        .as_ref()
+        .to_vec();
+    let encloser = vec![nsec3_record(
+        nsec3_owner(&encloser_hash, "signed.test."),
+        false,
+        vec![0xff; 20],
+        vec![RecordType::SOA, RecordType::NS, RecordType::DNSKEY],
+    )];
+    let covering = vec![nsec3_record(
+        nsec3_owner(&[0; 20], "signed.test."),
+        true,
+        vec![0xff; 20],
+        Vec::new(),
+    )];
+    let nsec3_authority = vec![
+        encloser[0].clone(),
+        zone.sign(&encloser),
+        covering[0].clone(),
+        zone.sign(&covering),
+    ];
+
+    let responder = move |query: &Message| {
+        let q = &query.queries()[0];
+        let name = q.name().to_ascii();
+        let (answers, authority) = match (name.as_str(), q.query_type()) {
+            ("signed.test.", RecordType::DNSKEY) => (dnskey_answers.clone(), Vec::new()),
+            ("www.signed.test.", RecordType::A) => (www_answers.clone(), Vec::new()),
+            ("tampered.signed.test.", RecordType::A) => (tampered_answers.clone(), Vec::new()),
+            ("unsigned.signed.test.", RecordType::DS) => (Vec::new(), nsec_authority.clone()),
+            ("optout.signed.test.", RecordType::DS) => (Vec::new(), nsec3_authority.clone()),
+            (_, RecordType::A) => (
+                vec![a_record(&name, Ipv4Addr::new(192, 0, 2, 9))],
+                Vec::new(),
+            ),
+            // 未签名委派下的名称属于各自的子区，其余名称属于 signed.test.
+            (_, RecordType::SOA) => {
+                let apex = [
+                    "unsigned.signed.test.",
+                    "optout.signed.test.",
+                    "fake.signed.test.",
+                ]
+                .into_iter()
+                .find(|apex| name.ends_with(apex))
+                .unwrap_or("signed.test.");
+                (Vec::new(), vec![soa_record(apex)])
+            }
+            _ => (Vec::new(), Vec::new()),
+        };
+        let mut response = create_response(query, answers);
+        response.add_name_servers(authority);
+        response
+    };
+
+    let (router, upstream) = create_forwarding_handler(mock_server, responder).await;
+    let dnssec = DnssecValidator::from_config(&DnssecConfig {
+        enabled: true,
+        trust_anchors: vec![zone.trust_anchor()],
+    })
+    .unwrap();
+    RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream).with_dnssec(dnssec)
+}
+
+// 测试正确签名的应答通过验证并设置 AD 位
+#[tokio::test]
+async fn test_dnssec_valid_signature_sets_ad() {
+    let mock_server = MockServer::start().await;
+    let handler = create_dnssec_handler(&mock_server).await;
+
+    let response = handler
+        .handle_request(&create_dnssec_query("www.signed.test.", RecordType::A))
+        .await
+        .unwrap();
+
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.authentic_data());
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 1)]);
+}
+
+// 测试被篡改的应答验证失败并返回 SERVFAIL
+#[tokio::test]
+async fn test_dnssec_tampered_response_servfail() {
+    let mock_server = MockServer::start().await;
+    let handler = create_dnssec_handler(&mock_server).await;
+
+    let response = handler
+        .handle_request(&create_dnssec_query("tampered.signed.test.", RecordType::A))
+        .await
+        .unwrap();
+
+    assert_eq!(response.response_code(), ResponseCode::ServFail);
+    assert!(response.answers().is_empty());
+}
+
+// 测试已签名区域中被剥离签名的应答验证失败，而不是降级为不安全
+#[tokio::test]
+async fn test_dnssec_stripped_signature_servfail() {
+    let mock_server = MockServer::start().await;
+    let handler = create_dnssec_handler(&mock_server).await;
+
+    let response = handler
+        .handle_request(&create_dnssec_query("stripped.signed.test.", RecordType::A))
+        .await
+        .unwrap();
+
+    assert_eq!(response.response_code(), ResponseCode::ServFail);
+    assert!(response.answers().is_empty());
+}
+
+// 测试有签名的 NSEC 证明的未签名委派应答为不安全，没有否定证明的委派验证失败
+#[tokio::test]
+async fn test_dnssec_unsigned_delegation_requires_denial_proof() {
+    let mock_server = MockServer::start().await;
+    let handler = create_dnssec_handler(&mock_server).await;
+
+    let response = handler
+        .handle_request(&create_dnssec_query(
+            "www.unsigned.signed.test.",
+            RecordType::A,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(!response.authentic_data());
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 9)]);
+
+    let response = handler
+        .handle_request(&create_dnssec_query(
+            "www.optout.signed.test.",
+            RecordType::A,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(!response.authentic_data());
+
+    let response = handler
+        .handle_request(&create_dnssec_query("www.fake.signed.test.", RecordType::A))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::ServFail);
+}
+
+// 测试未设置 DO 位的查询不做验证
+#[tokio::test]
+async fn test_dnssec_skipped_without_do_bit() {
+    let mock_server = MockServer::start().await;
+    let handler = create_dnssec_handler(&mock_server).await;
+
+    let response = handler
+        .handle_request(&create_query("tampered.signed.test.", RecordType::A))
+        .await
+        .unwrap();
+
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(!response.authentic_data());
+}
+
+// 创建指向 mock 服务器的 DoH 上游组，并挂载动态应答
+async fn mount_doh_group<F>(
+    name: &str,
+    mock_server: &MockServer,
+    responder: F,
+) -> UpstreamGroupConfig
+where
+    F: Fn(&Message) -> Message + Send + Sync + 'static,
+{
+    Mock::given(method("POST"))
+        .and(path("/dns-query"))
+        .respond_with(DnsResponder(responder))
+        .mount(mock_server)
+        .await;
+
+    UpstreamGroupConfig {
+        name: name.to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::RoundRobin,
+        servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: Url::parse(&format!("{}/dns-query", mock_server.uri())).unwrap(),
+            weight: 1,
+            method: DoHMethod::Post,
+            content_type: DoHContentType::Message,
+            auth: None,
+        })],
+        retry: None,
+        proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
+    }
+}
+
+// 测试影子上游返回不同地址时计入分歧指标，且不影响返回给客户端的应答
+#[tokio::test]
+async fn test_shadow_divergence_counted() {
+    let primary_server = MockServer::start().await;
+    let shadow_server = MockServer::start().await;
+    let seen = Arc::new(AtomicBool::new(false));
+    let shadow_saw_option = seen.clone();
+    let groups = vec![
+        mount_doh_group("primary", &primary_server, |query: &Message| {
+            let name = query.queries()[0].name().to_ascii();
+            create_response(query, vec![a_record(&name, Ipv4Addr::new(192, 0, 2, 1))])
+        })
+        .await,
+        mount_doh_group(
+            "shadow_candidate",
+            &shadow_server,
+            move |query: &Message| {
+                if let Some(edns) = query.extensions() {
+                    if edns
+                        .options()
+                        .get(EdnsCode::from(SYNTHETIC_EDNS_CODE))
+                        .is_some()
+                    {
+                        shadow_saw_option.store(true, Ordering::SeqCst);
+                    }
+                }
+                let name = query.queries()[0].name().to_ascii();
+                create_response(query, vec![a_record(&name, Ipv4Addr::new(198, 51, 100, 1))])
+            },
+        )
+        .await,
+    ];
+    let upstream = Arc::new(
+        UpstreamManager::new(
+            groups,
+            HttpClientConfig::default(),
+            DnsClientConfig::default(),
+        )
+        .await
+        .unwrap(),
+    );
+    let router = Arc::new(
+        Router::new(vec![RouteRuleConfig {
+            match_type: MatchType::Wildcard,
+            patterns: vec!["*".to_string()],
+            action: RouteAction::Forward,
+            target: Some("primary".to_string()),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        }])
+        .unwrap(),
+    );
+    let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream)
+        .with_shadow(&ShadowConfig {
+            group: "shadow_candidate".to_string(),
+            max_concurrency: 4,
+        });
+
+    let divergence = METRICS
+        .shadow_divergence_total()
+        .with_label_values(&["shadow_candidate"]);
+    let before = divergence.get();
+
+    let response = handler
+        .handle_request(&create_query_with_unknown_option("shadow.example.com."))
+        .await
+        .unwrap();
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 1)]);
+
+    // 影子请求异步执行，等待指标更新
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while divergence.get() == before && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(divergence.get(), before + 1);
+
+    // 影子上游收到的是剥离未知 EDNS 选项后实际发往主上游的报文
+    assert!(!seen.load(Ordering::SeqCst));
+}
+
+// 测试加权目标按配置比例在多个上游组之间分流
+#[tokio::test]
+async fn test_weighted_targets_split_traffic() {
+    let stable_server = MockServer::start().await;
+    let canary_server = MockServer::start().await;
+    let stable_addr = Ipv4Addr::new(192, 0, 2, 1);
+    let canary_addr = Ipv4Addr::new(192, 0, 2, 2);
+    let groups = vec![
+        mount_doh_group("stable", &stable_server, move |query: &Message| {
+            let name = query.queries()[0].name().to_ascii();
+            create_response(query, vec![a_record(&name, stable_addr)])
+        })
+        .await,
+        mount_doh_group("canary", &canary_server, move |query: &Message| {
+            let name = query.queries()[0].name().to_ascii();
+            create_response(query, vec![a_record(&name, canary_addr)])
+        })
+        .await,
+    ];
+    let upstream = Arc::new(
+        UpstreamManager::new(
+            groups,
+            HttpClientConfig::default(),
+            DnsClientConfig::default(),
+        )
+        .await
+        .unwrap(),
+    );
+    let router = Arc::new(
+        Router::new(vec![RouteRuleConfig {
+            match_type: MatchType::Wildcard,
+            patterns: vec!["*".to_string()],
+            action: RouteAction::Forward,
+            target: None,
+            weighted_targets: vec![
+                WeightedTargetConfig {
+                    group: "stable".to_string(),
+                    weight: 90,
+                },
+                WeightedTargetConfig {
+                    group: "canary".to_string(),
+                    weight: 10,
+                },
+            ],
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        }])
+        .unwrap(),
+    );
+    let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream);
+
+    let total = 1000;
+    let mut canary_hits = 0;
+    for i in 0..total {
+        let query = create_query(&format!("host{}.example.com.", i), RecordType::A);
+        let response = handler.handle_request(&query).await.unwrap();
+        match answer_addrs(&response).as_slice() {
+            [addr] if *addr == canary_addr => canary_hits += 1,
+            [addr] => assert_eq!(*addr, stable_addr),
+            other => panic!("unexpected answers: {:?}", other),
+        }
+    }
+
+    // 期望约 10% 流量进入 canary，标准差约 1%，允许 ±5%
+    let ratio = canary_hits as f64 / total as f64;
+    assert!((0.05..=0.15).contains(&ratio), "canary ratio {}", ratio);
+}
+
+// 测试启用 tracing.propagate 时 DoH 请求的 traceparent 头被传递给上游
+#[tokio::test]
+async fn test_traceparent_propagated_to_doh_upstream() {
+    use axum::extract::{ConnectInfo, State};
+    use axum::http::HeaderMap;
+    use axum::response::IntoResponse;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    let query = create_query("traced.example.com.", RecordType::A);
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/dns-message".parse().unwrap());
+    headers.insert("traceparent", TRACEPARENT.parse().unwrap());
+    let addr: SocketAddr = "127.0.0.1:5353".parse().unwrap();
+
+    for propagate in [true, false] {
+        let mock_server = MockServer::start().await;
+        let (router, upstream) = create_forwarding_handler(&mock_server, |query: &Message| {
+            let mut response = query.clone();
+            response.set_message_type(MessageType::Response);
+            response
+        })
+        .await;
+        let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream)
+            .with_tracing(&TracingConfig { propagate });
+
+        let response = handle_doh_post(
+            State(AppState::new(Arc::new(handler))),
+            ConnectInfo(addr),
+            headers.clone(),
+            query.to_vec().unwrap().into(),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), 200);
+
+        let requests = mock_server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        let forwarded = requests[0]
+            .headers
+            .get("traceparent")
+            .map(|value| value.to_str().unwrap().to_string());
+        if propagate {
+            assert_eq!(forwarded.as_deref(), Some(TRACEPARENT));
+        } else {
+            assert_eq!(forwarded, None);
+        }
+    }
+}
+
+// 测试格式不合法的 traceparent 头被忽略
+#[test]
+fn test_invalid_traceparent_is_ignored() {
+    let addr: SocketAddr = "127.0.0.1:5353".parse().unwrap();
+    let valid = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+    let context = RequestContext::from_client(addr).with_traceparent(Some(valid));
+    assert_eq!(context.trace_id(), Some("4bf92f3577b34da6a3ce929d0e0e4736"));
+
+    for invalid in [
+        "",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+        "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+    ] {
+        let context = RequestContext::from_client(addr).with_traceparent(Some(invalid));
+        assert_eq!(context.traceparent, None, "accepted {:?}", invalid);
+    }
+}
+
+// 测试上游超时时按 upstream_timeout_rcode 返回响应码
+#[tokio::test]
+async fn test_upstream_timeout_rcode() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/dns-query"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(3)))
+        .mount(&mock_server)
+        .await;
+
+    let groups = vec![UpstreamGroupConfig {
+        name: "slow_group".to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::RoundRobin,
+        servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: Url::parse(&format!("{}/dns-query", mock_server.uri())).unwrap(),
+            weight: 1,
+            method: DoHMethod::Post,
+            content_type: DoHContentType::Message,
+            auth: None,
+        })],
+        retry: None,
+        proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
+    }];
+    let upstream = Arc::new(
+        UpstreamManager::new(
+            groups,
+            HttpClientConfig {
+                request_timeout: 1,
+                ..Default::default()
+            },
+            DnsClientConfig::default(),
+        )
+        .await
+        .unwrap(),
+    );
+    let router = Arc::new(
+        Router::new(vec![RouteRuleConfig {
+            match_type: MatchType::Wildcard,
+            patterns: vec!["*".to_string()],
+            action: RouteAction::Forward,
+            target: Some("slow_group".to_string()),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        }])
+        .unwrap(),
+    );
+
+    for (rcode, expected) in [
+        (UpstreamTimeoutRcode::ServFail, ResponseCode::ServFail),
+        (UpstreamTimeoutRcode::Refused, ResponseCode::Refused),
+    ] {
+        let handler = RequestHandler::new(
+            Arc::new(DnsCache::new(0, 0, None)),
+            router.clone(),
+            upstream.clone(),
+        )
+        .with_response_config(ResponseConfig {
+            upstream_timeout_rcode: rcode,
+            ..Default::default()
+        });
+
+        let response = handler
+            .handle_request(&create_query("slow.example.com.", RecordType::A))
+            .await
+            .unwrap();
+        assert_eq!(response.response_code(), expected);
+    }
+}
+
+// 测试启用 normalize_ttl 时应答记录的 TTL 统一为最小值（包括缓存命中的应答）
+#[tokio::test]
+async fn test_normalize_ttl_uses_minimum_ttl() {
+    let mixed_ttl_upstream = |query: &Message| {
+        let alias = Name::from_ascii("www.mixed.example.com.").unwrap();
+        let target = Name::from_ascii("edge.mixed.example.com.").unwrap();
+        create_response(
+            query,
+            vec![
+                Record::from_rdata(alias, 300, RData::CNAME(CNAME(target.clone()))),
+                Record::from_rdata(target, 60, RData::A(A(Ipv4Addr::new(192, 0, 2, 1)))),
+            ],
+        )
+    };
+    let query = create_query("www.mixed.example.com.", RecordType::A);
+
+    for normalize_ttl in [false, true] {
+        let mock_server = MockServer::start().await;
+        let (router, upstream) = create_forwarding_handler(&mock_server, mixed_ttl_upstream).await;
+        let handler = RequestHandler::new(Arc::new(DnsCache::new(100, 1, None)), router, upstream)
+            .with_response_config(ResponseConfig {
+                normalize_ttl,
+                ..Default::default()
+            });
+
+        // 第一次来自上游，第二次来自缓存
+        for _ in 0..2 {
+            let response = handler.handle_request(&query).await.unwrap();
+            let ttls: Vec<u32> = response.answers().iter().map(Record::ttl).collect();
+            assert_eq!(ttls.len(), 2);
+            if normalize_ttl {
+                assert!(ttls.iter().all(|ttl| *ttl == ttls[0] && *ttl <= 60));
+            } else {
+                assert_eq!(ttls.iter().max(), Some(&300));
+            }
+        }
+    }
+}
+
+// 测试规则重载宽限期内，新规则中已移除的域名仍通过旧规则解析
+#[tokio::test]
+async fn test_reload_grace_period_falls_back_to_previous_rules() {
+    let mock_server = MockServer::start().await;
+    let (_, upstream) = create_forwarding_handler(&mock_server, |query: &Message| {
+        let name = query.queries()[0].name().to_ascii();
+        create_response(query, vec![a_record(&name, Ipv4Addr::new(192, 0, 2, 7))])
+    })
+    .await;
+    let forward_rule = |pattern: &str| {
+        Arc::new(
+            Router::new(vec![RouteRuleConfig {
+                match_type: MatchType::Exact,
+                patterns: vec![pattern.to_string()],
+                action: RouteAction::Forward,
+                target: Some("test_group".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
+            }])
+            .unwrap(),
+        )
+    };
+
+    let handler = RequestHandler::new(
+        Arc::new(DnsCache::new(0, 0, None)),
+        forward_rule("removed.example.com"),
+        upstream,
+    )
+    .with_reload_grace_period(Duration::from_millis(300));
+    let query = create_query("removed.example.com.", RecordType::A);
+
+    // 重载后新规则不再包含该域名，宽限期内回退到旧规则
+    handler.reload_router(forward_rule("kept.example.com"));
+    let response = handler.handle_request(&query).await.unwrap();
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 7)]);
+
+    // 新规则中存在的域名直接使用新规则
+    let response = handler
+        .handle_request(&create_query("kept.example.com.", RecordType::A))
+        .await
+        .unwrap();
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 7)]);
+
+    // 宽限期结束后不再回退
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert!(handler.handle_request(&query).await.is_err());
+}
+
+// 测试严格白名单模式下未列出的域名被拒绝，且全局通配符 forward 规则被忽略
+#[tokio::test]
+async fn test_strict_allowlist_refuses_unmatched_names() {
+    let mock_server = MockServer::start().await;
+    let (_, upstream) = create_forwarding_handler(&mock_server, |query: &Message| {
+        let name = query.queries()[0].name().to_ascii();
+        create_response(query, vec![a_record(&name, Ipv4Addr::new(192, 0, 2, 8))])
+    })
+    .await;
+    let router = Router::new(vec![
+        RouteRuleConfig {
+            match_type: MatchType::Exact,
+            patterns: vec!["allowed.example.com".to_string()],
+            action: RouteAction::Forward,
+            target: Some("test_group".to_string()),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        },
+        // 例如来自远程规则源的全局通配符，严格白名单模式下不生效
+        RouteRuleConfig {
+            match_type: MatchType::Wildcard,
+            patterns: vec!["*".to_string()],
+            action: RouteAction::Forward,
+            target: Some("test_group".to_string()),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        },
+    ])
+    .unwrap()
+    .with_default_upstream_group(Some("test_group".to_string()))
+    .with_strict_allowlist(true);
+
+    let handler = RequestHandler::new(
+        Arc::new(DnsCache::new(0, 0, None)),
+        Arc::new(router),
+        upstream,
+    )
+    .with_strict_allowlist(true);
+
+    let response = handler
+        .handle_request(&create_query("allowed.example.com.", RecordType::A))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 8)]);
+
+    let response = handler
+        .handle_request(&create_query("unlisted.example.com.", RecordType::A))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+    assert!(response.answers().is_empty());
+
+    // 未列出的域名不会被转发到上游
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 1);
+}
+
+// 测试根域名与仅含顶级域名的查询按配置直接应答，不转发到上游
+#[tokio::test]
+async fn test_root_and_tld_queries_use_configured_action() {
+    let mock_server = MockServer::start().await;
+    let (_, upstream) = create_forwarding_handler(&mock_server, |query: &Message| {
+        create_response(query, Vec::new())
+    })
+    .await;
+    let router = Arc::new(
+        Router::new(vec![RouteRuleConfig {
+            match_type: MatchType::Wildcard,
+            patterns: vec!["*".to_string()],
+            action: RouteAction::Forward,
+            target: Some("test_group".to_string()),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        }])
+        .unwrap(),
+    );
+    let create_handler = |action| {
+        RequestHandler::new(
+            Arc::new(DnsCache::new(0, 0, None)),
+            router.clone(),
+            upstream.clone(),
+        )
+        .with_response_config(ResponseConfig {
+            root_tld_action: action,
+            ..Default::default()
+        })
+    };
+
+    let handler = create_handler(RootTldAction::Refused);
+    for name in [".", "com."] {
+        let response = handler
+            .handle_request(&create_query(name, RecordType::NS))
+            .await
+            .unwrap();
+        assert_eq!(response.response_code(), ResponseCode::Refused, "{}", name);
+    }
+    // 普通域名不受影响
+    let response = handler
+        .handle_request(&create_query("example.com.", RecordType::A))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 1);
+
+    let handler = create_handler(RootTldAction::NxDomain);
+    let response = handler
+        .handle_request(&create_query("com.", RecordType::NS))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 1);
+
+    // 默认按路由规则转发
+    let handler = create_handler(RootTldAction::default());
+    let response = handler
+        .handle_request(&create_query("com.", RecordType::NS))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 2);
+}
+
+// 测试查询类型策略：区域传送返回 NOTIMP，未列入允许列表的类型被拒绝，允许的类型正常转发
+#[tokio::test]
+async fn test_query_type_policy() {
+    let mock_server = MockServer::start().await;
+    let (router, upstream) = create_forwarding_handler(&mock_server, dns64_upstream).await;
+    let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream)
+        .with_response_config(ResponseConfig {
+            allowed_query_types: vec!["A".to_string(), "aaaa".to_string(), "AXFR".to_string()],
+            denied_query_types: vec!["AXFR".to_string()],
+            ..Default::default()
+        });
+
+    let response = handler
+        .handle_request(&create_query("example.com.", RecordType::AXFR))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NotImp);
+
+    let response = handler
+        .handle_request(&create_query("example.com.", RecordType::TXT))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+    assert!(mock_server.received_requests().await.unwrap().is_empty());
+
+    let response = handler
+        .handle_request(&create_query("example.com.", RecordType::A))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 33)]);
+}
+
+// 创建携带 EDNS 的查询
+fn create_edns_query(name: &str, record_type: RecordType) -> Message {
+    let mut query = create_query(name, record_type);
+    query.set_edns(Edns::new());
+    query
+}
+
+// 测试启用 extended_errors 后拦截响应携带 EDE 17（Filtered），缓存命中时保留
+#[tokio::test]
+async fn test_extended_error_on_blocked_response() {
+    let router = Router::new(vec![RouteRuleConfig {
+        match_type: MatchType::Exact,
+        patterns: vec!["blocked.example.com".to_string()],
+        action: RouteAction::Block,
+        target: None,
+        weighted_targets: Vec::new(),
+        fallback: Vec::new(),
+        fallback_on_nxdomain: false,
+        client_cidrs: Vec::new(),
+        source: None,
+    }])
+    .unwrap();
+    let handler = RequestHandler::new(
+        Arc::new(DnsCache::new(100, 1, None)),
+        Arc::new(router),
+        Arc::new(UpstreamManager::empty().unwrap()),
+    )
+    .with_response_config(ResponseConfig {
+        extended_errors: true,
+        ..Default::default()
+    });
+
+    let query = create_edns_query("blocked.example.com.", RecordType::A);
+    for _ in 0..2 {
+        let response = handler.handle_request(&query).await.unwrap();
+        assert_eq!(response.response_code(), ResponseCode::NXDomain);
+        let error = ExtendedError::from_message(&response).expect("missing EDE option");
+        assert_eq!(error.info_code, 17);
+        assert!(error.extra_text.contains("blocked.example.com"));
+    }
+
+    // 未使用 EDNS 的客户端不附加 OPT 记录
+    let response = handler
+        .handle_request(&create_query("blocked.example.com.", RecordType::AAAA))
+        .await
+        .unwrap();
+    assert!(response.extensions().is_none());
+}
+
+// 测试上游失败时的 SERVFAIL 携带 EDE 23（Network Error），未启用时不附加
+#[tokio::test]
+async fn test_extended_error_on_upstream_failure() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&mock_server)
+        .await;
+    let (router, upstream) =
+        create_forwarding_handler(&mock_server, |query: &Message| query.clone()).await;
+
+    let query = create_edns_query("example.com.", RecordType::A);
+    for extended_errors in [true, false] {
+        let handler = RequestHandler::new(
+            Arc::new(DnsCache::new(0, 0, None)),
+            router.clone(),
+            upstream.clone(),
+        )
+        .with_response_config(ResponseConfig {
+            extended_errors,
+            ..Default::default()
+        });
+        let response = handler.handle_request(&query).await.unwrap();
+        assert_eq!(response.response_code(), ResponseCode::ServFail);
+        let error = ExtendedError::from_message(&response);
+        if extended_errors {
+            assert_eq!(error.map(|e| e.info_code), Some(23));
+        } else {
+            assert!(error.is_none());
+        }
+    }
+}
+
+// 延迟固定时间后返回动态应答的 mock 响应器
+struct DelayedDnsResponder<F>(F, Duration);
+
+impl<F> Respond for DelayedDnsResponder<F>
+where
+    F: Fn(&Message) -> Message + Send + Sync + 'static,
+{
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let query = Message::from_vec(&request.body).expect("Invalid DNS query body");
+        let response = (self.0)(&query);
+        ResponseTemplate::new(200)
+            .insert_header("Content-Type", "application/dns-message")
+            .set_body_bytes(response.to_vec().unwrap())
+            .set_delay(self.1)
+    }
+}
+
+// 测试上游响应过慢导致超出延迟预算时，超限指标按 upstream 阶段计数
+#[tokio::test]
+async fn test_slo_breach_attributed_to_upstream() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/dns-query"))
+        .respond_with(DelayedDnsResponder(
+            |query: &Message| {
+                let name = query.queries()[0].name().to_ascii();
+                create_response(query, vec![a_record(&name, Ipv4Addr::new(192, 0, 2, 1))])
+            },
+            Duration::from_millis(200),
+        ))
+        .mount(&mock_server)
+        .await;
+    let (router, upstream) = create_forwarding_handler(&mock_server, |query: &Message| {
+        create_response(query, Vec::new())
+    })
+    .await;
+    let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream)
+        .with_latency_budget(Duration::from_millis(50));
+
+    let breaches = METRICS.slo_breach_total().with_label_values(&["upstream"]);
+    let before = breaches.get();
+
+    let response = handler
+        .handle_request(&create_query("slow-slo.example.com.", RecordType::A))
+        .await
+        .unwrap();
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 1)]);
+    assert_eq!(breaches.get(), before + 1);
+}
+
+// 测试本地 PTR 映射直接应答，未映射的 PTR 查询正常转发到上游
+#[tokio::test]
+async fn test_local_ptr_answers_mapped_names() {
+    let mock_server = MockServer::start().await;
+    let (router, upstream) = create_forwarding_handler(&mock_server, |query: &Message| {
+        let name = query.queries()[0].name().clone();
+        create_response(
+            query,
+            vec![Record::from_rdata(
+                name,
+                300,
+                RData::PTR(PTR(Name::from_ascii("upstream.example.com.").unwrap())),
+            )],
+        )
+    })
+    .await;
+    let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream)
+        .with_response_config(ResponseConfig {
+            local_ptr: HashMap::from([
+                (
+                    "1.1.168.192.in-addr.arpa".to_string(),
+                    "router.local".to_string(),
+                ),
+                ("10.0.0.5".to_string(), "nas.local".to_string()),
+            ]),
+            ..Default::default()
+        });
+    let ptr_target = |response: &Message| match response.answers()[0].data() {
+        Some(RData::PTR(ptr)) => ptr.0.to_ascii(),
+        other => panic!("Expected PTR answer, got {:?}", other),
+    };
+
+    // 反向解析名称与 IP 地址形式的映射均在本地应答
+    let response = handler
+        .handle_request(&create_query("1.1.168.192.in-addr.arpa.", RecordType::PTR))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.authoritative());
+    assert_eq!(ptr_target(&response), "router.local.");
+
+    let response = handler
+        .handle_request(&create_query("5.0.0.10.in-addr.arpa.", RecordType::PTR))
+        .await
+        .unwrap();
+    assert_eq!(ptr_target(&response), "nas.local.");
+    assert!(mock_server.received_requests().await.unwrap().is_empty());
+
+    // 未映射的名称转发到上游
+    let response = handler
+        .handle_request(&create_query("2.1.168.192.in-addr.arpa.", RecordType::PTR))
+        .await
+        .unwrap();
+    assert_eq!(ptr_target(&response), "upstream.example.com.");
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 1);
+}
+
+// 测试配置为仅有 IPv4 地址的域名，其 AAAA 查询直接返回 NODATA 而不访问上游
+#[tokio::test]
+async fn test_synthesize_empty_aaaa_skips_upstream() {
+    let mock_server = MockServer::start().await;
+    let (router, upstream) = create_forwarding_handler(&mock_server, |query: &Message| {
+        let name = query.queries()[0].name().to_ascii();
+        create_response(query, vec![a_record(&name, Ipv4Addr::new(192, 0, 2, 1))])
+    })
+    .await;
+    let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream)
+        .with_empty_aaaa_domains(&["ipv4only.example".to_string()]);
+
+    // 配置的域名及其子域名的 AAAA 查询返回 NODATA
+    for name in ["ipv4only.example.", "www.IPv4Only.example."] {
+        let response = handler
+            .handle_request(&create_query(name, RecordType::AAAA))
+            .await
+            .unwrap();
+        assert_eq!(response.response_code(), ResponseCode::NoError);
+        assert!(response.answers().is_empty());
+        assert_eq!(response.name_servers()[0].record_type(), RecordType::SOA);
+    }
+    assert!(mock_server.received_requests().await.unwrap().is_empty());
+
+    // A 查询与其他域名的 AAAA 查询正常转发
+    let response = handler
+        .handle_request(&create_query("www.ipv4only.example.", RecordType::A))
+        .await
+        .unwrap();
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 1)]);
+    handler
+        .handle_request(&create_query("dual.example.", RecordType::AAAA))
+        .await
+        .unwrap();
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 2);
+}
+
+// 测试 fallback_on_nxdomain：主上游组返回 NXDOMAIN 时使用备用上游组的应答
+#[tokio::test]
+async fn test_fallback_on_nxdomain_uses_next_group() {
+    let public_addr = Ipv4Addr::new(192, 0, 2, 53);
+
+    for fallback_on_nxdomain in [true, false] {
+        let internal_server = MockServer::start().await;
+        let public_server = MockServer::start().await;
+        let groups = vec![
+            mount_doh_group("internal", &internal_server, |query: &Message| {
+                let mut response = create_response(query, Vec::new());
+                response.set_response_code(ResponseCode::NXDomain);
+                response
+            })
+            .await,
+            mount_doh_group("public", &public_server, move |query: &Message| {
+                let name = query.queries()[0].name().to_ascii();
+                create_response(query, vec![a_record(&name, public_addr)])
+            })
+            .await,
+        ];
+        let upstream = Arc::new(
+            UpstreamManager::new(
+                groups,
+                HttpClientConfig::default(),
+                DnsClientConfig::default(),
+            )
+            .await
+            .unwrap(),
+        );
+        let router = Arc::new(
+            Router::new(vec![RouteRuleConfig {
+                match_type: MatchType::Wildcard,
+                patterns: vec!["*".to_string()],
+                action: RouteAction::Forward,
+                target: Some("internal".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: vec!["public".to_string()],
+                fallback_on_nxdomain,
+                client_cidrs: Vec::new(),
+                source: None,
+            }])
+            .unwrap(),
+        );
+        let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream);
+
+        let query = create_query("www.example.com.", RecordType::A);
+        let response = handler.handle_request(&query).await.unwrap();
+        let public_requests = public_server.received_requests().await.unwrap().len();
+
+        if fallback_on_nxdomain {
+            assert_eq!(response.response_code(), ResponseCode::NoError);
+            assert_eq!(answer_addrs(&response), vec![public_addr]);
+            assert_eq!(public_requests, 1);
+        } else {
+            // 未开启时 NXDOMAIN 是确定的应答，不尝试备用上游组
+            assert_eq!(response.response_code(), ResponseCode::NXDomain);
+            assert_eq!(public_requests, 0);
+        }
+    }
+}
+
+// 测试上游失败产生的 SERVFAIL 默认不缓存，后续查询重新尝试上游；显式开启 servfail_ttl 后短暂缓存
+#[tokio::test]
+async fn test_servfail_from_failed_forward_not_cached() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&mock_server)
+        .await;
+    let (router, upstream) =
+        create_forwarding_handler(&mock_server, |query: &Message| query.clone()).await;
+
+    let query = create_query("flaky.example.com.", RecordType::A);
+    let handler = RequestHandler::new(
+        Arc::new(DnsCache::new(100, 60, None)),
+        router.clone(),
+        upstream.clone(),
+    );
+    for attempt in 1..=2 {
+        let response = handler.handle_request(&query).await.unwrap();
+        assert_eq!(response.response_code(), ResponseCode::ServFail);
+        assert_eq!(
+            mock_server.received_requests().await.unwrap().len(),
+            attempt
+        );
+    }
+
+    let handler = RequestHandler::new(
+        Arc::new(DnsCache::new(100, 60, None).with_servfail_ttl(5)),
+        router,
+        upstream,
+    );
+    for _ in 0..2 {
+        let response = handler.handle_request(&query).await.unwrap();
+        assert_eq!(response.response_code(), ResponseCode::ServFail);
+    }
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 3);
+}
+
+// 测试拦截响应默认不写入缓存，规则重载后立即按新策略解析
+#[tokio::test]
+async fn test_blocked_response_not_cached() {
+    let mock_server = MockServer::start().await;
+    let (forward_all, upstream) = create_forwarding_handler(&mock_server, |query: &Message| {
+        let name = query.queries()[0].name().to_ascii();
+        create_response(query, vec![a_record(&name, Ipv4Addr::new(192, 0, 2, 9))])
+    })
+    .await;
+    let block_rules = || {
+        Arc::new(
+            Router::new(vec![
+                RouteRuleConfig {
+                    match_type: MatchType::Exact,
+                    patterns: vec!["ads.example.com".to_string()],
+                    action: RouteAction::Block,
+                    target: None,
+                    weighted_targets: Vec::new(),
+                    fallback: Vec::new(),
+                    fallback_on_nxdomain: false,
+                    client_cidrs: Vec::new(),
+                    source: None,
+                },
+                RouteRuleConfig {
+                    match_type: MatchType::Wildcard,
+                    patterns: vec!["*".to_string()],
+                    action: RouteAction::Forward,
+                    target: Some("test_group".to_string()),
+                    weighted_targets: Vec::new(),
+                    fallback: Vec::new(),
+                    fallback_on_nxdomain: false,
+                    client_cidrs: Vec::new(),
+                    source: None,
+                },
+            ])
+            .unwrap(),
+        )
+    };
+    let query = create_query("ads.example.com.", RecordType::A);
+
+    let cache = Arc::new(DnsCache::new(100, 60, None));
+    let handler = RequestHandler::new(cache.clone(), block_rules(), upstream.clone());
+    let response = handler.handle_request(&query).await.unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+    assert!(!cache.contains(&query));
+
+    // 重载移除拦截规则后立即转发到上游
+    handler.reload_router(forward_all);
+    let response = handler.handle_request(&query).await.unwrap();
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(192, 0, 2, 9)]);
+
+    // 显式开启后拦截响应写入缓存
+    let cache = Arc::new(DnsCache::new(100, 60, None));
+    let handler =
+        RequestHandler::new(cache.clone(), block_rules(), upstream).with_cache_blocked(true);
+    handler.handle_request(&query).await.unwrap();
+    assert!(cache.contains(&query));
+}
+
+#[tokio::test]
+async fn test_warmup_populates_cache_and_marks_ready() {
+    let mock_server = MockServer::start().await;
+    let (router, upstream) = create_forwarding_handler(&mock_server, |query: &Message| {
+        let query_record = &query.queries()[0];
+        let rdata = match query_record.query_type() {
+            RecordType::AAAA => RData::AAAA(AAAA("2001:db8::9".parse().unwrap())),
+            _ => RData::A(A(Ipv4Addr::new(192, 0, 2, 9))),
+        };
+        create_response(
+            query,
+            vec![Record::from_rdata(query_record.name().clone(), 300, rdata)],
+        )
+    })
+    .await;
+    let cache = Arc::new(DnsCache::new(100, 60, None));
+    let handler = Arc::new(RequestHandler::new(cache.clone(), router, upstream));
+
+    let config: WarmupConfig = serde_yaml::from_str(
+        r#"
+domains:
+  - name: www.example.com
+  - name: mail.example.com
+    types: [A]
+"#,
+    )
+    .unwrap();
+    let readiness = Arc::new(Readiness::pending());
+    let warmup = Warmup::new(handler, &config, readiness.clone());
+    assert!(!readiness.is_ready());
+
+    assert_eq!(warmup.run_and_mark_ready().await, 3);
+    assert!(readiness.is_ready());
+    assert!(cache.contains(&create_query("www.example.com.", RecordType::A)));
+    assert!(cache.contains(&create_query("www.example.com.", RecordType::AAAA)));
+    assert!(cache.contains(&create_query("mail.example.com.", RecordType::A)));
+    assert!(!cache.contains(&create_query("mail.example.com.", RecordType::AAAA)));
+}
+
+// 测试刚过期的条目立即以陈旧应答返回，同时后台刷新缓存
+#[tokio::test]
+async fn test_stale_while_revalidate_refreshes_in_background() {
+    let mock_server = MockServer::start().await;
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let (router, upstream) = create_forwarding_handler(&mock_server, move |query| {
+        // 首次应答 TTL 为 1 秒，之后返回新地址
+        let (addr, ttl) = match counter.fetch_add(1, Ordering::SeqCst) {
+            0 => (Ipv4Addr::new(203, 0, 113, 1), 1),
+            _ => (Ipv4Addr::new(203, 0, 113, 2), 300),
+        };
+        let mut record = a_record("fresh.example.com.", addr);
+        record.set_ttl(ttl);
+        create_response(query, vec![record])
+    })
+    .await;
+    let cache = Arc::new(DnsCache::new(100, 1, None).with_stale_while_revalidate(30));
+    let handler = RequestHandler::new(cache, router, upstream);
+    let query = create_query("fresh.example.com.", RecordType::A);
+
+    let response = handler.handle_request(&query).await.unwrap();
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(203, 0, 113, 1)]);
+
+    // 条目过期后立即返回旧应答，不等待上游
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = handler.handle_request(&query).await.unwrap();
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(203, 0, 113, 1)]);
+
+    // 后台重新验证完成后命中刷新的应答
+    let refreshed = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let response = handler.handle_request(&query).await.unwrap();
+            if answer_addrs(&response) == vec![Ipv4Addr::new(203, 0, 113, 2)] {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await;
+    assert!(refreshed.is_ok(), "stale entry should be refreshed");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+// 测试重新验证得到 NXDOMAIN 时写回缓存，而不是继续返回陈旧的地址
+#[tokio::test]
+async fn test_stale_while_revalidate_stores_nxdomain() {
+    let mock_server = MockServer::start().await;
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let (router, upstream) = create_forwarding_handler(&mock_server, move |query| {
+        if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+            let mut record = a_record("gone.example.com.", Ipv4Addr::new(203, 0, 113, 1));
+            record.set_ttl(1);
+            create_response(query, vec![record])
+        } else {
+            let mut response = create_response(query, vec![]);
+            response.set_response_code(ResponseCode::NXDomain);
+            response
+        }
+    })
+    .await;
+    let cache = Arc::new(DnsCache::new(100, 1, Some(300)).with_stale_while_revalidate(30));
+    let handler = RequestHandler::new(cache, router, upstream);
+    let query = create_query("gone.example.com.", RecordType::A);
+    handler.handle_request(&query).await.unwrap();
+
+    // 过期后返回陈旧应答并在后台重新验证
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = handler.handle_request(&query).await.unwrap();
+    assert_eq!(answer_addrs(&response), vec![Ipv4Addr::new(203, 0, 113, 1)]);
+
+    let refreshed = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let response = handler.handle_request(&query).await.unwrap();
+            if response.response_code() == ResponseCode::NXDomain {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await;
+    assert!(
+        refreshed.is_ok(),
+        "NXDOMAIN revalidation should replace the stale entry"
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
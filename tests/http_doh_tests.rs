@@ -3,7 +3,10 @@ use std::{collections::HashMap, sync::Arc};
 use axum::{
     body::to_bytes,
     extract::{Query as AxumQuery, State},
-    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    http::{
+        header::{ACCEPT, CONTENT_TYPE},
+        HeaderMap, HeaderValue, StatusCode,
+    },
     response::IntoResponse,
 };
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
@@ -11,6 +14,7 @@ use hickory_proto::op::{Message, MessageType, ResponseCode};
 use hyper::body::Bytes;
 use loadants::{
     cache::DnsCache,
+    config::{MatchType, RouteAction, RouteRuleConfig},
     doh::{
         handlers::{
             handle_doh_get, handle_doh_post, handle_json_get, DohGetParams, DohJsonGetParams,
@@ -39,6 +43,14 @@ fn create_test_handler(_response: Option<Message>) -> Arc<RequestHandler> {
         tcp_timeout: 10,
         http_bind_addr: "127.0.0.1:0".parse().unwrap(),
         http_timeout: 30,
+        tcp_listener: loadants::server::TcpListenerOptions::default(),
+        max_tcp_connections: None,
+        require_both_protocols: true,
+        log_malformed_requests: false,
+        udp_v6_bind_addr: None,
+        tcp_v6_bind_addr: None,
+        dual_stack: true,
+        udp_max_response_size: None,
     };
 
     // 创建一个传统的处理器 - 但不启动实际的服务
@@ -54,6 +66,29 @@ fn create_test_handler(_response: Option<Message>) -> Arc<RequestHandler> {
     ))
 }
 
+// 创建拦截 example.com 的处理器，无需上游即可得到确定的响应（NXDOMAIN）
+fn create_blocking_handler() -> Arc<RequestHandler> {
+    let router = Router::new(vec![RouteRuleConfig {
+        match_type: MatchType::Exact,
+        patterns: vec!["example.com".to_string()],
+        action: RouteAction::Block,
+        target: None,
+        weighted_targets: Vec::new(),
+        fallback: Vec::new(),
+        fallback_on_nxdomain: false,
+        client_cidrs: Vec::new(),
+        source: None,
+    }])
+    .unwrap();
+    Arc::new(RequestHandler::new(
+        Arc::new(DnsCache::new(0, 0, None)),
+        Arc::new(router),
+        Arc::new(
+            loadants::UpstreamManager::empty().expect("Failed to create empty upstream manager"),
+        ),
+    ))
+}
+
 // 测试工具函数
 fn create_test_dns_query() -> Message {
     let mut query = Message::new();
@@ -138,13 +173,14 @@ async fn test_handle_doh_get_missing_param() {
 
     // 创建测试处理器
     let handler = create_test_handler(Some(create_test_dns_response()));
-    let app_state = AppState { handler };
+    let app_state = AppState::new(handler);
     let addr = "127.0.0.1:8080".parse().unwrap();
 
     // 调用处理器
     let response = handle_doh_get(
         State(app_state),
         axum::extract::ConnectInfo(addr),
+        HeaderMap::new(),
         query_params,
     )
     .await;
@@ -163,13 +199,14 @@ async fn test_handle_doh_get_invalid_base64() {
 
     // 创建测试处理器
     let handler = create_test_handler(Some(create_test_dns_response()));
-    let app_state = AppState { handler };
+    let app_state = AppState::new(handler);
     let addr = "127.0.0.1:8080".parse().unwrap();
 
     // 调用处理器
     let response = handle_doh_get(
         State(app_state),
         axum::extract::ConnectInfo(addr),
+        HeaderMap::new(),
         query_params,
     )
     .await;
@@ -188,13 +225,14 @@ async fn test_handle_doh_get_invalid_dns_message() {
 
     // 创建测试处理器
     let handler = create_test_handler(Some(create_test_dns_response()));
-    let app_state = AppState { handler };
+    let app_state = AppState::new(handler);
     let addr = "127.0.0.1:8080".parse().unwrap();
 
     // 调用处理器
     let response = handle_doh_get(
         State(app_state),
         axum::extract::ConnectInfo(addr),
+        HeaderMap::new(),
         query_params,
     )
     .await;
@@ -213,13 +251,14 @@ async fn test_handle_doh_get_handler_error() {
 
     // 创建会返回错误的测试处理器
     let handler = create_test_handler(None);
-    let app_state = AppState { handler };
+    let app_state = AppState::new(handler);
     let addr = "127.0.0.1:8080".parse().unwrap();
 
     // 调用处理器
     let response = handle_doh_get(
         State(app_state),
         axum::extract::ConnectInfo(addr),
+        HeaderMap::new(),
         query_params,
     )
     .await;
@@ -273,7 +312,7 @@ async fn test_handle_doh_post_missing_content_type() {
 
     // 创建测试处理器
     let handler = create_test_handler(Some(create_test_dns_response()));
-    let app_state = AppState { handler };
+    let app_state = AppState::new(handler);
     let addr = "127.0.0.1:8080".parse().unwrap();
 
     // 调用处理器
@@ -302,7 +341,7 @@ async fn test_handle_doh_post_invalid_content_type() {
 
     // 创建测试处理器
     let handler = create_test_handler(Some(create_test_dns_response()));
-    let app_state = AppState { handler };
+    let app_state = AppState::new(handler);
     let addr = "127.0.0.1:8080".parse().unwrap();
 
     // 调用处理器
@@ -333,7 +372,7 @@ async fn test_handle_doh_post_invalid_dns_message() {
 
     // 创建测试处理器
     let handler = create_test_handler(Some(create_test_dns_response()));
-    let app_state = AppState { handler };
+    let app_state = AppState::new(handler);
     let addr = "127.0.0.1:8080".parse().unwrap();
 
     // 调用处理器
@@ -362,7 +401,7 @@ async fn test_handle_doh_post_handler_error() {
 
     // 创建会返回错误的测试处理器
     let handler = create_test_handler(None);
-    let app_state = AppState { handler };
+    let app_state = AppState::new(handler);
     let addr = "127.0.0.1:8080".parse().unwrap();
 
     // 调用处理器
@@ -431,6 +470,48 @@ async fn test_handle_json_get_success() {
     assert!(json.contains("Status"));
 }
 
+// 测试JSON GET响应的内容类型与请求的 ct 参数一致，未指定时使用配置的默认值
+#[tokio::test]
+async fn test_handle_json_get_content_type() {
+    let cases = [
+        (Some("application/x-javascript"), "application/x-javascript"),
+        (Some("application/dns-json"), "application/dns-json"),
+        (Some("application/json"), "application/json"),
+        (None, "application/json"),
+        (Some("text/plain"), "application/json"),
+    ];
+
+    for (ct, expected) in cases {
+        let query_params = AxumQuery(DohJsonGetParams {
+            name: "example.com".to_string(),
+            r#type: Some("A".to_string()),
+            cd: None,
+            do_flag: None,
+            ct: ct.map(str::to_string),
+        });
+
+        // 默认内容类型配置为 application/json，以区分请求指定与默认值
+        let app_state = AppState::new(create_blocking_handler())
+            .with_json_content_type(HeaderValue::from_static("application/json"));
+        let addr = "127.0.0.1:8080".parse().unwrap();
+
+        let response = handle_json_get(
+            State(app_state),
+            axum::extract::ConnectInfo(addr),
+            HeaderMap::new(),
+            query_params,
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], expected, "ct={:?}", ct);
+        let bytes = get_response_bytes(response.into_body()).await;
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["Status"], 3);
+    }
+}
+
 // 测试JSON GET请求缺少name参数
 #[tokio::test]
 async fn test_handle_json_get_missing_name() {
@@ -445,13 +526,14 @@ async fn test_handle_json_get_missing_name() {
 
     // 创建测试处理器
     let handler = create_test_handler(Some(create_test_dns_response()));
-    let app_state = AppState { handler };
+    let app_state = AppState::new(handler);
     let addr = "127.0.0.1:8080".parse().unwrap();
 
     // 调用处理器
     let response = handle_json_get(
         State(app_state),
         axum::extract::ConnectInfo(addr),
+        HeaderMap::new(),
         query_params,
     )
     .await;
@@ -474,13 +556,14 @@ async fn test_handle_json_get_invalid_type() {
 
     // 创建测试处理器
     let handler = create_test_handler(Some(create_test_dns_response()));
-    let app_state = AppState { handler };
+    let app_state = AppState::new(handler);
     let addr = "127.0.0.1:8080".parse().unwrap();
 
     // 调用处理器
     let response = handle_json_get(
         State(app_state),
         axum::extract::ConnectInfo(addr),
+        HeaderMap::new(),
         query_params,
     )
     .await;
@@ -503,13 +586,14 @@ async fn test_handle_json_get_invalid_domain() {
 
     // 创建测试处理器
     let handler = create_test_handler(Some(create_test_dns_response()));
-    let app_state = AppState { handler };
+    let app_state = AppState::new(handler);
     let addr = "127.0.0.1:8080".parse().unwrap();
 
     // 调用处理器
     let response = handle_json_get(
         State(app_state),
         axum::extract::ConnectInfo(addr),
+        HeaderMap::new(),
         query_params,
     )
     .await;
@@ -532,13 +616,14 @@ async fn test_handle_json_get_handler_error() {
 
     // 创建会返回错误的测试处理器
     let handler = create_test_handler(None);
-    let app_state = AppState { handler };
+    let app_state = AppState::new(handler);
     let addr = "127.0.0.1:8080".parse().unwrap();
 
     // 调用处理器
     let response = handle_json_get(
         State(app_state),
         axum::extract::ConnectInfo(addr),
+        HeaderMap::new(),
         query_params,
     )
     .await;
@@ -549,3 +634,217 @@ async fn test_handle_json_get_handler_error() {
         StatusCode::INTERNAL_SERVER_ERROR
     );
 }
+
+// 测试 RFC 8484 GET 端点按 Accept 头协商返回 JSON 或二进制
+#[tokio::test]
+async fn test_handle_doh_get_accept_negotiation() {
+    let addr = "127.0.0.1:8080".parse().unwrap();
+    let dns = URL_SAFE_NO_PAD.encode(encode_dns_message(&create_test_dns_query()));
+
+    // 请求 application/dns-json 时返回可读的 JSON
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, "application/dns-json".parse().unwrap());
+    let response = handle_doh_get(
+        State(AppState::new(create_blocking_handler())),
+        axum::extract::ConnectInfo(addr),
+        headers,
+        AxumQuery(DohGetParams { dns: dns.clone() }),
+    )
+    .await
+    .into_response();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        "application/dns-json"
+    );
+    let bytes = get_response_bytes(response.into_body()).await;
+    let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(json["Status"], 3);
+    assert_eq!(json["Question"][0]["name"], "example.com.");
+
+    // 未指定 Accept 时保持二进制响应
+    let response = handle_doh_get(
+        State(AppState::new(create_blocking_handler())),
+        axum::extract::ConnectInfo(addr),
+        HeaderMap::new(),
+        AxumQuery(DohGetParams { dns }),
+    )
+    .await
+    .into_response();
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        "application/dns-message"
+    );
+    let bytes = get_response_bytes(response.into_body()).await;
+    let message = Message::from_vec(&bytes).unwrap();
+    assert_eq!(message.response_code(), ResponseCode::NXDomain);
+}
+
+// 测试 RFC 8484 POST 端点按 Accept 头协商返回 JSON 或二进制
+#[tokio::test]
+async fn test_handle_doh_post_accept_negotiation() {
+    let addr = "127.0.0.1:8080".parse().unwrap();
+    let body = Bytes::from(encode_dns_message(&create_test_dns_query()));
+
+    // Accept 头包含多个媒体类型与参数时同样识别 JSON
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, "application/dns-message".parse().unwrap());
+    headers.insert(
+        ACCEPT,
+        "text/plain, application/dns-json; q=0.9".parse().unwrap(),
+    );
+    let response = handle_doh_post(
+        State(AppState::new(create_blocking_handler())),
+        axum::extract::ConnectInfo(addr),
+        headers,
+        body.clone(),
+    )
+    .await
+    .into_response();
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        "application/dns-json"
+    );
+    let bytes = get_response_bytes(response.into_body()).await;
+    let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(json["Status"], 3);
+
+    // Accept 为 application/dns-message 时返回二进制
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, "application/dns-message".parse().unwrap());
+    headers.insert(ACCEPT, "application/dns-message".parse().unwrap());
+    let response = handle_doh_post(
+        State(AppState::new(create_blocking_handler())),
+        axum::extract::ConnectInfo(addr),
+        headers,
+        body,
+    )
+    .await
+    .into_response();
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        "application/dns-message"
+    );
+    let bytes = get_response_bytes(response.into_body()).await;
+    let message = Message::from_vec(&bytes).unwrap();
+    assert_eq!(message.response_code(), ResponseCode::NXDomain);
+}
+
+// 测试 DoH 服务器关闭时在 drain_timeout 内停止，即使客户端保持连接不释放
+#[tokio::test]
+async fn test_doh_server_stops_within_drain_timeout() {
+    use loadants::DoHServer;
+    use std::time::{Duration, Instant};
+    use tokio::io::AsyncWriteExt;
+    use tokio_graceful_shutdown::{SubsystemBuilder, SubsystemHandle, Toplevel};
+
+    // 预先获取一个可用端口
+    let addr = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+
+    let handler = create_test_handler(None);
+    let drain_timeout = Duration::from_secs(1);
+    let server = DoHServer::new(addr, 30, handler).with_drain_timeout(drain_timeout);
+
+    // 客户端建立连接并发送不完整的请求头，使连接无法自然结束；关闭期间持续持有该连接
+    let (connected_tx, connected_rx) = tokio::sync::oneshot::channel();
+    let client = tokio::spawn(async move {
+        let mut stream = loop {
+            match tokio::net::TcpStream::connect(addr).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        };
+        stream
+            .write_all(b"GET /dns-query HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n")
+            .await
+            .unwrap();
+        let _ = connected_tx.send(());
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        drop(stream);
+    });
+
+    let requested_at = Arc::new(std::sync::Mutex::new(None::<Instant>));
+    let requested = requested_at.clone();
+
+    let toplevel = Toplevel::new(move |s: SubsystemHandle| async move {
+        s.start(SubsystemBuilder::new("doh", move |h| async move {
+            server.run(h).await
+        }));
+
+        // 连接建立后触发关闭
+        let _ = connected_rx.await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        *requested.lock().unwrap() = Some(Instant::now());
+        s.request_shutdown();
+    });
+
+    let result = toplevel
+        .handle_shutdown_requests(Duration::from_secs(20))
+        .await;
+    let stopped_at = Instant::now();
+    assert!(result.is_ok());
+
+    // 客户端仍然持有连接
+    assert!(!client.is_finished());
+    client.abort();
+
+    // 关闭在 drain_timeout 附近完成，而不是等待全局关闭超时
+    let shutdown_duration = stopped_at - requested_at.lock().unwrap().unwrap();
+    assert!(
+        shutdown_duration < drain_timeout + Duration::from_secs(2),
+        "DoH server took {:?} to stop",
+        shutdown_duration
+    );
+}
+
+// 测试 DoH 服务器在 /resolve 与 /dns-query 路径上均提供 Google JSON API
+#[tokio::test]
+async fn test_doh_server_serves_json_on_resolve_path() {
+    use loadants::DoHServer;
+    use std::time::Duration;
+    use tokio_graceful_shutdown::{SubsystemBuilder, SubsystemHandle, Toplevel};
+
+    // 预先获取一个可用端口
+    let addr = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+
+    let server = DoHServer::new(addr, 30, create_blocking_handler());
+
+    let toplevel = Toplevel::new(move |s: SubsystemHandle| async move {
+        s.start(SubsystemBuilder::new("doh", move |h| async move {
+            server.run(h).await
+        }));
+
+        let client = reqwest::Client::new();
+        for path in ["/resolve", "/dns-query"] {
+            let url = format!("http://{}{}?name=example.com&type=A", addr, path);
+            let response = loop {
+                match client.get(&url).send().await {
+                    Ok(response) => break response,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+                }
+            };
+
+            assert_eq!(response.status(), reqwest::StatusCode::OK, "{}", path);
+            let content_type = response.headers()[CONTENT_TYPE].to_str().unwrap();
+            assert!(content_type.starts_with("application/"), "{}", content_type);
+            assert!(!content_type.contains("dns-message"), "{}", content_type);
+
+            let body: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(body["Status"], 3, "{}", path);
+            assert_eq!(body["Question"][0]["name"], "example.com", "{}", path);
+        }
+
+        s.request_shutdown();
+    });
+
+    let result = toplevel
+        .handle_shutdown_requests(Duration::from_secs(10))
+        .await;
+    assert!(result.is_ok());
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use loadants::log_throttle::{LogDecision, LogThrottle};
+
+// 统计需要输出的日志行数
+fn logged_lines(decisions: &[LogDecision]) -> usize {
+    decisions
+        .iter()
+        .filter(|decision| **decision != LogDecision::Suppressed)
+        .count()
+}
+
+// 测试同一域名被大量拦截时只输出首条日志
+#[test]
+fn test_repeated_blocks_log_once_within_interval() {
+    let throttle = LogThrottle::new(Duration::from_secs(60));
+
+    let decisions: Vec<LogDecision> = (0..10_000)
+        .map(|_| throttle.record("ads.example.com", "block"))
+        .collect();
+
+    assert_eq!(decisions[0], LogDecision::First);
+    assert_eq!(logged_lines(&decisions), 1);
+}
+
+// 测试汇总间隔到达后输出一条包含计数的汇总日志
+#[test]
+fn test_summary_emitted_after_interval() {
+    let throttle = LogThrottle::new(Duration::from_millis(50));
+
+    let mut decisions: Vec<LogDecision> = (0..500)
+        .map(|_| throttle.record("ads.example.com", "block"))
+        .collect();
+    std::thread::sleep(Duration::from_millis(60));
+    decisions.push(throttle.record("ads.example.com", "block"));
+
+    assert_eq!(logged_lines(&decisions), 2);
+    match decisions.last() {
+        Some(LogDecision::Summary { count, elapsed }) => {
+            assert_eq!(*count, 500);
+            assert!(*elapsed >= Duration::from_millis(50));
+        }
+        other => panic!("expected summary, got {:?}", other),
+    }
+}
+
+// 测试不同域名与动作分别节流，且跟踪键数量有上限
+#[test]
+fn test_keys_throttled_independently() {
+    let throttle = LogThrottle::with_capacity(Duration::from_secs(60), 2);
+
+    assert_eq!(
+        throttle.record("a.example.com", "block"),
+        LogDecision::First
+    );
+    assert_eq!(
+        throttle.record("a.example.com", "forward"),
+        LogDecision::First
+    );
+    assert_eq!(
+        throttle.record("a.example.com", "block"),
+        LogDecision::Suppressed
+    );
+
+    // 表满后新键仍会输出首条日志
+    assert_eq!(
+        throttle.record("b.example.com", "block"),
+        LogDecision::First
+    );
+}
@@ -1,12 +1,24 @@
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::StatusCode;
+use loadants::admin::{rule_health_handler, rules_handler, rules_reload_handler, RulesParams};
+use loadants::cache::DnsCache;
 use loadants::config::{
-    AuthConfig, AuthType, HttpClientConfig, MatchType, RemoteRuleConfig, RemoteRuleType,
-    RetryConfig, RouteAction, RouteRuleConfig, RuleFormat,
+    AuthConfig, AuthType, HttpClientConfig, MatchType, MaxRulesAction, RemoteRuleConfig,
+    RemoteRuleType, RetryConfig, RouteAction, RouteRuleConfig, RoutingConfig, RuleFormat,
 };
 use loadants::error::AppError;
+use loadants::handler::RequestHandler;
 use loadants::r#const::remote_rule_limits;
 use loadants::remote_rule::{
-    load_and_merge_rules, ClashRuleParser, RemoteRuleLoader, RuleParser, V2RayRuleParser,
+    load_and_merge_rules, load_and_merge_rules_with_stats, load_rule_set, ClashRuleParser,
+    RemoteRuleLoader, RuleParser, RuleReloader, V2RayRuleParser,
 };
+use loadants::router::Router;
+use loadants::stats::RuleSourceStats;
+use loadants::UpstreamManager;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use wiremock::{
     matchers::{method, path},
     Mock, MockServer, ResponseTemplate,
@@ -99,6 +111,16 @@ sub.domain.org
         idle_timeout: Some(60),
         keepalive: Some(30),
         agent: Some("Test-Agent".to_string()),
+        strict_id_check: false,
+        case_randomization: false,
+        preserve_unknown_records: false,
+        json_fallback: false,
+        forward_deadline: None,
+        bind_address: None,
+        tcp_fastopen: false,
+        http2_prior_knowledge: false,
+        http2_keepalive_interval: None,
+        http2_keepalive_timeout: None,
     };
 
     // 创建远程规则加载器
@@ -257,13 +279,24 @@ async fn test_load_and_merge_rules() {
         patterns: vec!["static.example.com".to_string()],
         action: RouteAction::Block,
         target: None,
+        weighted_targets: Vec::new(),
+        fallback: Vec::new(),
+        fallback_on_nxdomain: false,
+        client_cidrs: Vec::new(),
+        source: None,
     }];
 
     // 创建HTTP客户端配置
     let http_config = HttpClientConfig::default();
 
     // 加载并合并规则
-    let merged_rules = load_and_merge_rules(&remote_configs, &static_rules, &http_config).await;
+    let merged_rules = load_and_merge_rules(
+        &remote_configs,
+        &static_rules,
+        &http_config,
+        &RoutingConfig::default(),
+    )
+    .await;
 
     assert!(merged_rules.is_ok());
 
@@ -377,3 +410,582 @@ async fn test_clash_rule_parser_not_implemented() {
         }
     }
 }
+
+// 启动返回指定数量域名的远程规则源
+async fn start_large_rule_source(count: usize) -> (MockServer, RemoteRuleConfig) {
+    let mock_server = MockServer::start().await;
+    let body = (0..count)
+        .map(|i| format!("full:domain{}.example.com", i))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Mock::given(method("GET"))
+        .and(path("/rules.txt"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .mount(&mock_server)
+        .await;
+
+    let config = RemoteRuleConfig {
+        r#type: RemoteRuleType::Url,
+        url: format!("{}/rules.txt", mock_server.uri()),
+        format: RuleFormat::V2ray,
+        action: RouteAction::Block,
+        target: None,
+        auth: None,
+        retry: None,
+        proxy: None,
+        max_size: remote_rule_limits::DEFAULT_MAX_SIZE,
+    };
+    (mock_server, config)
+}
+
+#[tokio::test]
+async fn test_max_rules_error_aborts_load() {
+    let (_mock_server, remote_config) = start_large_rule_source(50).await;
+    let routing_config = RoutingConfig {
+        max_rules: Some(10),
+        on_max_rules: MaxRulesAction::Error,
+        ..Default::default()
+    };
+
+    let result = load_and_merge_rules(
+        &[remote_config],
+        &[],
+        &HttpClientConfig::default(),
+        &routing_config,
+    )
+    .await;
+
+    assert!(matches!(result, Err(AppError::RuleLimitExceeded(10))));
+}
+
+#[tokio::test]
+async fn test_max_rules_truncate_keeps_limit() {
+    let (_mock_server, remote_config) = start_large_rule_source(50).await;
+    let static_rules = vec![RouteRuleConfig {
+        match_type: MatchType::Exact,
+        patterns: vec![
+            "static1.example.com".to_string(),
+            "static2.example.com".to_string(),
+        ],
+        action: RouteAction::Block,
+        target: None,
+        weighted_targets: Vec::new(),
+        fallback: Vec::new(),
+        fallback_on_nxdomain: false,
+        client_cidrs: Vec::new(),
+        source: None,
+    }];
+    let routing_config = RoutingConfig {
+        max_rules: Some(10),
+        on_max_rules: MaxRulesAction::Truncate,
+        ..Default::default()
+    };
+
+    let rules = load_and_merge_rules(
+        &[remote_config],
+        &static_rules,
+        &HttpClientConfig::default(),
+        &routing_config,
+    )
+    .await
+    .unwrap();
+
+    // 静态规则完整保留，远程规则被截断至上限
+    let total: usize = rules.iter().map(|r| r.patterns.len()).sum();
+    assert_eq!(total, 10);
+    assert_eq!(rules[0], static_rules[0]);
+}
+
+fn large_static_rules(count: usize) -> Vec<RouteRuleConfig> {
+    vec![RouteRuleConfig {
+        match_type: MatchType::Exact,
+        patterns: (0..count)
+            .map(|i| format!("static{}.example.com", i))
+            .collect(),
+        action: RouteAction::Block,
+        target: None,
+        weighted_targets: Vec::new(),
+        fallback: Vec::new(),
+        fallback_on_nxdomain: false,
+        client_cidrs: Vec::new(),
+        source: None,
+    }]
+}
+
+#[tokio::test]
+async fn test_max_rules_error_when_static_rules_exceed_limit() {
+    let (_mock_server, remote_config) = start_large_rule_source(5).await;
+    let routing_config = RoutingConfig {
+        max_rules: Some(10),
+        on_max_rules: MaxRulesAction::Error,
+        ..Default::default()
+    };
+
+    let result = load_and_merge_rules(
+        &[remote_config],
+        &large_static_rules(20),
+        &HttpClientConfig::default(),
+        &routing_config,
+    )
+    .await;
+
+    assert!(matches!(result, Err(AppError::RuleLimitExceeded(10))));
+}
+
+#[tokio::test]
+async fn test_max_rules_truncate_when_static_rules_exceed_limit() {
+    let (_mock_server, remote_config) = start_large_rule_source(5).await;
+    let routing_config = RoutingConfig {
+        max_rules: Some(10),
+        on_max_rules: MaxRulesAction::Truncate,
+        ..Default::default()
+    };
+
+    let rules = load_and_merge_rules(
+        &[remote_config],
+        &large_static_rules(20),
+        &HttpClientConfig::default(),
+        &routing_config,
+    )
+    .await
+    .unwrap();
+
+    // 静态规则被截断至上限，远程规则源全部跳过
+    let total: usize = rules.iter().map(|r| r.patterns.len()).sum();
+    assert_eq!(total, 10);
+    assert!(rules
+        .iter()
+        .flat_map(|r| r.patterns.iter())
+        .all(|p| p.starts_with("static")));
+}
+
+#[tokio::test]
+async fn test_max_rules_truncate_keeps_skipped_sources_fresh() {
+    let (_first_server, first) = start_large_rule_source(50).await;
+    let (_second_server, second) = start_large_rule_source(5).await;
+    let routing_config = RoutingConfig {
+        max_rules: Some(10),
+        on_max_rules: MaxRulesAction::Truncate,
+        max_staleness: Some(600),
+        ..Default::default()
+    };
+    let stats = Arc::new(RuleSourceStats::new(
+        routing_config.max_staleness.map(Duration::from_secs),
+    ));
+
+    let rules = load_and_merge_rules_with_stats(
+        &[first, second],
+        &[],
+        &HttpClientConfig::default(),
+        &routing_config,
+        Some(&stats),
+    )
+    .await
+    .unwrap();
+    let total: usize = rules.iter().map(|r| r.patterns.len()).sum();
+    assert_eq!(total, 10);
+
+    // 因截断而跳过的规则源同样下载成功，不会被判定为陈旧
+    let (status, body) = rule_health_handler(State(stats.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    let sources = body.0["sources"].as_array().unwrap();
+    assert_eq!(sources.len(), 2);
+    assert!(sources.iter().all(|source| source["stale"] == false));
+}
+
+#[tokio::test]
+async fn test_rule_health_reports_stale_source() {
+    let (_mock_server, remote_config) = start_large_rule_source(5).await;
+    let routing_config = RoutingConfig {
+        max_staleness: Some(600),
+        ..Default::default()
+    };
+    let stats = Arc::new(RuleSourceStats::new(
+        routing_config.max_staleness.map(Duration::from_secs),
+    ));
+
+    load_and_merge_rules_with_stats(
+        std::slice::from_ref(&remote_config),
+        &[],
+        &HttpClientConfig::default(),
+        &routing_config,
+        Some(&stats),
+    )
+    .await
+    .unwrap();
+
+    // 刚成功加载的规则源是健康的
+    let (status, body) = rule_health_handler(State(stats.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.0["status"], "healthy");
+    assert_eq!(body.0["max_staleness"], 600);
+    assert_eq!(body.0["sources"][0]["source"], remote_config.url.as_str());
+    assert_eq!(body.0["sources"][0]["stale"], false);
+
+    // 模拟规则源上次成功加载发生在最大陈旧时长之前
+    stats.record_success_at(
+        &remote_config.url,
+        SystemTime::now() - Duration::from_secs(3600),
+    );
+
+    let (status, body) = rule_health_handler(State(stats.clone())).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body.0["status"], "unhealthy");
+    assert_eq!(body.0["sources"][0]["stale"], true);
+    assert!(body.0["sources"][0]["age_secs"].as_u64().unwrap() >= 3600);
+}
+
+#[tokio::test]
+async fn test_rule_health_reports_never_loaded_source() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/rules.txt"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&mock_server)
+        .await;
+
+    let remote_config = RemoteRuleConfig {
+        r#type: RemoteRuleType::Url,
+        url: format!("{}/rules.txt", mock_server.uri()),
+        format: RuleFormat::V2ray,
+        action: RouteAction::Block,
+        target: None,
+        auth: None,
+        retry: None,
+        proxy: None,
+        max_size: remote_rule_limits::DEFAULT_MAX_SIZE,
+    };
+
+    // 未配置最大陈旧时长时，加载失败的规则源不影响健康状态
+    let stats = Arc::new(RuleSourceStats::new(None));
+    load_and_merge_rules_with_stats(
+        std::slice::from_ref(&remote_config),
+        &[],
+        &HttpClientConfig::default(),
+        &RoutingConfig::default(),
+        Some(&stats),
+    )
+    .await
+    .unwrap();
+
+    let (status, body) = rule_health_handler(State(stats)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.0["sources"][0]["last_success"].is_null());
+
+    // 配置了最大陈旧时长时，从未成功加载的规则源视为陈旧
+    let stats = Arc::new(RuleSourceStats::new(Some(Duration::from_secs(600))));
+    load_and_merge_rules_with_stats(
+        std::slice::from_ref(&remote_config),
+        &[],
+        &HttpClientConfig::default(),
+        &RoutingConfig::default(),
+        Some(&stats),
+    )
+    .await
+    .unwrap();
+
+    let (status, body) = rule_health_handler(State(stats)).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body.0["sources"][0]["stale"], true);
+}
+
+// 启动延迟指定时间后返回单个域名的远程规则源
+async fn start_delayed_rule_source(
+    domain: &str,
+    delay: Duration,
+) -> (MockServer, RemoteRuleConfig) {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/rules.txt"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(format!("full:{}", domain))
+                .set_delay(delay),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let config = RemoteRuleConfig {
+        r#type: RemoteRuleType::Url,
+        url: format!("{}/rules.txt", mock_server.uri()),
+        format: RuleFormat::V2ray,
+        action: RouteAction::Block,
+        target: None,
+        auth: None,
+        retry: None,
+        proxy: None,
+        max_size: remote_rule_limits::DEFAULT_MAX_SIZE,
+    };
+    (mock_server, config)
+}
+
+#[tokio::test]
+async fn test_remote_rules_load_concurrently_in_config_order() {
+    // 越靠前的规则源响应越慢，按完成顺序合并会打乱配置顺序
+    let delays = [600, 400, 200].map(Duration::from_millis);
+    let mut servers = Vec::new();
+    let mut remote_configs = Vec::new();
+    for (i, delay) in delays.iter().enumerate() {
+        let (server, config) =
+            start_delayed_rule_source(&format!("source{}.example.com", i), *delay).await;
+        servers.push(server);
+        remote_configs.push(config);
+    }
+    let static_rules = vec![RouteRuleConfig {
+        match_type: MatchType::Exact,
+        patterns: vec!["static.example.com".to_string()],
+        action: RouteAction::Block,
+        target: None,
+        weighted_targets: Vec::new(),
+        fallback: Vec::new(),
+        fallback_on_nxdomain: false,
+        client_cidrs: Vec::new(),
+        source: None,
+    }];
+    let routing_config = RoutingConfig {
+        max_concurrent_rule_downloads: Some(3),
+        ..Default::default()
+    };
+
+    let start = std::time::Instant::now();
+    let rules = load_and_merge_rules(
+        &remote_configs,
+        &static_rules,
+        &HttpClientConfig::default(),
+        &routing_config,
+    )
+    .await
+    .unwrap();
+    let elapsed = start.elapsed();
+
+    // 并发下载的总耗时小于各规则源延迟之和
+    assert!(
+        elapsed < delays.iter().sum::<Duration>(),
+        "rule sources were not loaded concurrently: {:?}",
+        elapsed
+    );
+
+    // 合并顺序：静态规则在前，远程规则按配置顺序
+    let patterns: Vec<&str> = rules
+        .iter()
+        .flat_map(|rule| rule.patterns.iter().map(String::as_str))
+        .collect();
+    assert_eq!(
+        patterns,
+        vec![
+            "static.example.com",
+            "source0.example.com",
+            "source1.example.com",
+            "source2.example.com",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_rules_endpoint_exports_merged_rules() {
+    let mut servers = Vec::new();
+    let mut remote_configs = Vec::new();
+    for domain in ["remote0.example.com", "remote1.example.com"] {
+        let (server, config) = start_delayed_rule_source(domain, Duration::ZERO).await;
+        servers.push(server);
+        remote_configs.push(config);
+    }
+    let static_rules = vec![
+        RouteRuleConfig {
+            match_type: MatchType::Exact,
+            patterns: vec!["static.example.com".to_string()],
+            action: RouteAction::Forward,
+            target: Some("internal".to_string()),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        },
+        RouteRuleConfig {
+            match_type: MatchType::Wildcard,
+            patterns: vec!["*.ads.example.org".to_string()],
+            action: RouteAction::Block,
+            target: None,
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        },
+    ];
+
+    let merged = load_rule_set(
+        &remote_configs,
+        &static_rules,
+        &HttpClientConfig::default(),
+        &RoutingConfig::default(),
+        None,
+    )
+    .await
+    .unwrap();
+    let router = Router::new(merged.rules)
+        .unwrap()
+        .with_rule_sources(merged.sources);
+    let handler = Arc::new(RequestHandler::new(
+        Arc::new(DnsCache::new(0, 0, None)),
+        Arc::new(router),
+        Arc::new(UpstreamManager::empty().unwrap()),
+    ));
+
+    // 完整导出：统计、来源与规则列表
+    let body = rules_handler(State(handler.clone()), Query(RulesParams::default()))
+        .await
+        .0;
+    assert_eq!(body["total"], 4);
+    let count = |match_type: &str, action: &str| {
+        body["counts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|count| count["type"] == match_type && count["action"] == action)
+            .map(|count| count["rules"].as_u64().unwrap())
+            .unwrap()
+    };
+    assert_eq!(count("exact", "block"), 2);
+    assert_eq!(count("wildcard", "block"), 1);
+    assert_eq!(count("exact", "forward"), 1);
+    assert_eq!(count("regex", "forward"), 0);
+
+    let sources: Vec<(String, u64)> = body["sources"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|source| {
+            (
+                source["source"].as_str().unwrap().to_string(),
+                source["rules"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        sources,
+        vec![
+            ("static".to_string(), 2),
+            (remote_configs[0].url.clone(), 1),
+            (remote_configs[1].url.clone(), 1),
+        ]
+    );
+
+    let rules = body["rules"].as_array().unwrap();
+    assert_eq!(rules.len(), 4);
+    assert!(rules.iter().any(|rule| rule["type"] == "exact"
+        && rule["action"] == "forward"
+        && rule["pattern"] == "static.example.com"
+        && rule["target"] == "internal"
+        && rule["source"] == "static"));
+    assert!(rules
+        .iter()
+        .any(|rule| rule["type"] == "wildcard" && rule["pattern"] == "*.ads.example.org"));
+    assert!(rules.iter().any(|rule| rule["action"] == "block"
+        && rule["pattern"] == "remote1.example.com"
+        && rule["source"] == remote_configs[1].url.as_str()));
+
+    // 分页：第二页从偏移处继续
+    let page = rules_handler(
+        State(handler.clone()),
+        Query(RulesParams {
+            offset: Some(3),
+            limit: Some(2),
+            summary: false,
+        }),
+    )
+    .await
+    .0;
+    assert_eq!(page["rules"].as_array().unwrap().len(), 1);
+    assert_eq!(page["rules"][0], rules[3]);
+
+    // 仅汇总模式不列出规则
+    let summary = rules_handler(
+        State(handler),
+        Query(RulesParams {
+            summary: true,
+            ..Default::default()
+        }),
+    )
+    .await
+    .0;
+    assert_eq!(summary["total"], 4);
+    assert!(summary.get("rules").is_none());
+}
+
+#[tokio::test]
+async fn test_rules_reload_endpoint_applies_updated_source() {
+    let (mock_server, remote_config) =
+        start_delayed_rule_source("old.example.com", Duration::ZERO).await;
+    let static_rules = vec![RouteRuleConfig {
+        match_type: MatchType::Wildcard,
+        patterns: vec!["*".to_string()],
+        action: RouteAction::Forward,
+        target: Some("default".to_string()),
+        weighted_targets: Vec::new(),
+        fallback: Vec::new(),
+        fallback_on_nxdomain: false,
+        client_cidrs: Vec::new(),
+        source: None,
+    }];
+    let reloader = Arc::new(RuleReloader::new(
+        vec![remote_config.clone()],
+        static_rules,
+        HttpClientConfig::default(),
+        RoutingConfig::default(),
+    ));
+    let router = reloader
+        .build_router(reloader.load_rules().await.unwrap())
+        .unwrap();
+    let handler = Arc::new(RequestHandler::new(
+        Arc::new(DnsCache::new(0, 0, None)),
+        Arc::new(router),
+        Arc::new(UpstreamManager::empty().unwrap()),
+    ));
+    let action = |name: &str| {
+        handler
+            .router()
+            .find_match(&name.parse().unwrap())
+            .unwrap()
+            .action
+    };
+    assert_eq!(action("old.example.com."), RouteAction::Block);
+    assert_eq!(action("new.example.com."), RouteAction::Forward);
+
+    // 规则源更新后调用重载接口，路由立即使用新规则
+    mock_server.reset().await;
+    Mock::given(method("GET"))
+        .and(path("/rules.txt"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string("full:new.example.com\nfull:new2.example.com"),
+        )
+        .mount(&mock_server)
+        .await;
+    let body = rules_reload_handler(State((handler.clone(), reloader.clone())))
+        .await
+        .unwrap()
+        .0;
+    assert_eq!(body["status"], "success");
+    assert_eq!(body["total"], 3);
+    assert_eq!(body["sources"][1]["source"], remote_config.url.as_str());
+    assert_eq!(body["sources"][1]["rules"], 2);
+    assert_eq!(action("old.example.com."), RouteAction::Forward);
+    assert_eq!(action("new.example.com."), RouteAction::Block);
+    assert_eq!(action("new2.example.com."), RouteAction::Block);
+
+    // 规则源不可用时放弃重载，保留当前生效的规则
+    mock_server.reset().await;
+    Mock::given(method("GET"))
+        .and(path("/rules.txt"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&mock_server)
+        .await;
+    let (status, body) = rules_reload_handler(State((handler.clone(), reloader)))
+        .await
+        .unwrap_err();
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body.0["status"], "error");
+    assert_eq!(action("new.example.com."), RouteAction::Block);
+}
@@ -2,7 +2,8 @@
 mod tests {
     use hickory_proto::rr::Name;
     use loadants::config::{MatchType, RouteAction, RouteRuleConfig};
-    use loadants::router::Router;
+    use loadants::metrics::METRICS;
+    use loadants::router::{RegexPrefilter, Router};
     use std::str::FromStr;
 
     // 创建测试规则集
@@ -17,6 +18,11 @@ mod tests {
                 ],
                 action: RouteAction::Forward,
                 target: Some("cloudflare_secure".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
             },
             // 通配符 forward 规则
             RouteRuleConfig {
@@ -24,6 +30,11 @@ mod tests {
                 patterns: vec!["*.corp.local".to_string(), "*.corp.com".to_string()],
                 action: RouteAction::Forward,
                 target: Some("internal_doh".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
             },
             // 精确匹配的 block 规则 - 应覆盖上面的 forward 规则
             RouteRuleConfig {
@@ -31,6 +42,11 @@ mod tests {
                 patterns: vec!["special.corp.com".to_string()],
                 action: RouteAction::Block,
                 target: None,
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
             },
             // 正则匹配的 forward 规则
             RouteRuleConfig {
@@ -38,6 +54,11 @@ mod tests {
                 patterns: vec!["^(api|service)\\..+\\.com$".to_string()],
                 action: RouteAction::Forward,
                 target: Some("google_public".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
             },
             // 正则匹配的 block 规则 - 应覆盖上面的 forward 规则
             RouteRuleConfig {
@@ -45,6 +66,11 @@ mod tests {
                 patterns: vec!["^api\\.service\\.com$".to_string()],
                 action: RouteAction::Block,
                 target: None,
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
             },
             // 全局通配符 forward 规则（默认规则）
             RouteRuleConfig {
@@ -52,6 +78,11 @@ mod tests {
                 patterns: vec!["*".to_string()],
                 action: RouteAction::Forward,
                 target: Some("google_public".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
             },
         ]
     }
@@ -149,6 +180,11 @@ mod tests {
                 patterns: vec!["overlap.example.com".to_string()],
                 action: RouteAction::Forward,
                 target: Some("cloudflare_secure".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
             },
             // 通配符规则 - forward
             RouteRuleConfig {
@@ -156,6 +192,11 @@ mod tests {
                 patterns: vec!["*.example.com".to_string()],
                 action: RouteAction::Forward,
                 target: Some("google_public".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
             },
             // 精确匹配规则 - block (应该优先)
             RouteRuleConfig {
@@ -163,6 +204,11 @@ mod tests {
                 patterns: vec!["overlap.example.com".to_string()],
                 action: RouteAction::Block,
                 target: None,
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
             },
         ];
 
@@ -177,4 +223,614 @@ mod tests {
         assert_eq!(result.action, RouteAction::Block);
         assert_eq!(result.rule_type, "exact");
     }
+
+    #[test]
+    fn test_default_upstream_group_fallback() {
+        let rules = vec![
+            RouteRuleConfig {
+                match_type: MatchType::Exact,
+                patterns: vec!["blocked.example.com".to_string()],
+                action: RouteAction::Block,
+                target: None,
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
+            },
+            RouteRuleConfig {
+                match_type: MatchType::Exact,
+                patterns: vec!["matched.example.com".to_string()],
+                action: RouteAction::Forward,
+                target: Some("rule_group".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
+            },
+        ];
+
+        let router = Router::new(rules)
+            .expect("Failed to create router")
+            .with_default_upstream_group(Some("default_group".to_string()));
+
+        // 未命中任何规则的域名转发到默认上游组
+        let query_name = Name::from_str("unmatched.example.org.").expect("Invalid name");
+        let result = router
+            .find_match(&query_name)
+            .expect("Match should succeed");
+        assert_eq!(result.action, RouteAction::Forward);
+        assert_eq!(result.rule_type, "default");
+        assert_eq!(result.target, Some("default_group".to_string()));
+
+        // 命中 block 规则的域名仍然被拦截
+        let query_name = Name::from_str("blocked.example.com.").expect("Invalid name");
+        let result = router
+            .find_match(&query_name)
+            .expect("Match should succeed");
+        assert_eq!(result.action, RouteAction::Block);
+
+        // 命中 forward 规则的域名使用规则指定的上游组
+        let query_name = Name::from_str("matched.example.com.").expect("Invalid name");
+        let result = router
+            .find_match(&query_name)
+            .expect("Match should succeed");
+        assert_eq!(result.action, RouteAction::Forward);
+        assert_eq!(result.rule_type, "exact");
+        assert_eq!(result.target, Some("rule_group".to_string()));
+    }
+
+    #[test]
+    fn test_no_default_upstream_group_returns_error() {
+        let router = Router::new(Vec::new()).expect("Failed to create router");
+
+        let query_name = Name::from_str("unmatched.example.org.").expect("Invalid name");
+        assert!(router.find_match(&query_name).is_err());
+    }
+
+    #[test]
+    fn test_label_wildcard_match() {
+        let rules = vec![
+            RouteRuleConfig {
+                match_type: MatchType::Wildcard,
+                patterns: vec!["ads.*.example.com".to_string()],
+                action: RouteAction::Block,
+                target: None,
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
+            },
+            RouteRuleConfig {
+                match_type: MatchType::Wildcard,
+                patterns: vec!["*.cdn.*".to_string()],
+                action: RouteAction::Forward,
+                target: Some("cdn_group".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
+            },
+        ];
+        let router = Router::new(rules).expect("Failed to create router");
+
+        // 中间位置的 `*` 恰好匹配一个标签
+        let query_name = Name::from_str("ads.foo.example.com.").expect("Invalid name");
+        let result = router
+            .find_match(&query_name)
+            .expect("Match should succeed");
+        assert_eq!(result.action, RouteAction::Block);
+        assert_eq!(result.rule_type, "wildcard");
+        assert_eq!(result.pattern, "ads.*.example.com");
+
+        let query_name = Name::from_str("ads.example.com.").expect("Invalid name");
+        assert!(router.find_match(&query_name).is_err());
+
+        let query_name = Name::from_str("ads.foo.bar.example.com.").expect("Invalid name");
+        assert!(router.find_match(&query_name).is_err());
+
+        // 首个标签的 `*` 匹配一个或多个标签
+        let query_name = Name::from_str("img.static.cdn.net.").expect("Invalid name");
+        let result = router
+            .find_match(&query_name)
+            .expect("Match should succeed");
+        assert_eq!(result.action, RouteAction::Forward);
+        assert_eq!(result.target, Some("cdn_group".to_string()));
+
+        let query_name = Name::from_str("cdn.net.").expect("Invalid name");
+        assert!(router.find_match(&query_name).is_err());
+    }
+
+    #[test]
+    fn test_label_wildcard_precedence() {
+        let rules = vec![
+            RouteRuleConfig {
+                match_type: MatchType::Regex,
+                patterns: vec!["^ads\\..+\\.example\\.com$".to_string()],
+                action: RouteAction::Forward,
+                target: Some("regex_group".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
+            },
+            RouteRuleConfig {
+                match_type: MatchType::Wildcard,
+                patterns: vec!["ads.*.example.com".to_string()],
+                action: RouteAction::Forward,
+                target: Some("label_group".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
+            },
+            RouteRuleConfig {
+                match_type: MatchType::Wildcard,
+                patterns: vec!["*.foo.example.com".to_string()],
+                action: RouteAction::Forward,
+                target: Some("suffix_group".to_string()),
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
+            },
+        ];
+        let router = Router::new(rules).expect("Failed to create router");
+
+        // 后缀通配符优先于标签通配符
+        let query_name = Name::from_str("ads.foo.example.com.").expect("Invalid name");
+        let result = router
+            .find_match(&query_name)
+            .expect("Match should succeed");
+        assert_eq!(result.target, Some("suffix_group".to_string()));
+
+        // 标签通配符优先于正则
+        let query_name = Name::from_str("ads.bar.example.com.").expect("Invalid name");
+        let result = router
+            .find_match(&query_name)
+            .expect("Match should succeed");
+        assert_eq!(result.target, Some("label_group".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_label_wildcard_rejected() {
+        for pattern in ["ads*.example.com", "*.*", "ads..*.com"] {
+            let rules = vec![RouteRuleConfig {
+                match_type: MatchType::Wildcard,
+                patterns: vec![pattern.to_string()],
+                action: RouteAction::Block,
+                target: None,
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
+            }];
+            assert!(Router::new(rules).is_err(), "pattern '{}'", pattern);
+        }
+    }
+
+    #[test]
+    fn test_max_labels_guard() {
+        let router = Router::new(create_test_rules())
+            .unwrap()
+            .with_max_labels(Some(10));
+
+        // 100 个标签的名称超过上限，跳过规则匹配
+        let long_name = format!("{}corp.com.", "a.".repeat(98));
+        let long_name = Name::from_str(&long_name).unwrap();
+        assert_eq!(long_name.num_labels(), 100);
+        assert!(matches!(
+            router.find_match(&long_name),
+            Err(loadants::AppError::TooManyLabels(_))
+        ));
+
+        // 配置了默认上游组时转发到默认组
+        let router_with_default = Router::new(create_test_rules())
+            .unwrap()
+            .with_max_labels(Some(10))
+            .with_default_upstream_group(Some("fallback".to_string()));
+        let result = router_with_default.find_match(&long_name).unwrap();
+        assert_eq!(result.target.as_deref(), Some("fallback"));
+
+        // 正常名称不受影响
+        let result = router
+            .find_match(&Name::from_str("www.corp.com.").unwrap())
+            .unwrap();
+        assert_eq!(result.target.as_deref(), Some("internal_doh"));
+    }
+
+    #[test]
+    fn test_wildcard_match_multi_label_suffixes() {
+        let rule = |pattern: &str, action: RouteAction, target: Option<&str>| RouteRuleConfig {
+            match_type: MatchType::Wildcard,
+            patterns: vec![pattern.to_string()],
+            action,
+            target: target.map(str::to_string),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        };
+        let router = Router::new(vec![
+            rule("*.example.com", RouteAction::Forward, Some("outer")),
+            rule("*.a.example.com", RouteAction::Forward, Some("inner")),
+            rule("*.bad.a.example.com", RouteAction::Block, None),
+        ])
+        .unwrap();
+        let find = |name: &str| router.find_match(&Name::from_str(name).unwrap());
+
+        // 最长后缀优先
+        let result = find("deep.x.a.example.com.").unwrap();
+        assert_eq!(result.target.as_deref(), Some("inner"));
+        assert_eq!(result.pattern, "*.a.example.com");
+        let result = find("other.example.com.").unwrap();
+        assert_eq!(result.target.as_deref(), Some("outer"));
+
+        // block 规则优先于更短后缀的 forward 规则
+        let result = find("z.y.bad.a.example.com.").unwrap();
+        assert_eq!(result.action, RouteAction::Block);
+
+        // 后缀只在标签边界匹配
+        assert!(find("notexample.com.").is_err());
+        assert!(find("x.nota.example.com.").unwrap().target.as_deref() == Some("outer"));
+    }
+
+    #[test]
+    fn test_trailing_dot_patterns_match() {
+        let rule = |match_type, pattern: &str, target: &str| RouteRuleConfig {
+            match_type,
+            patterns: vec![pattern.to_string()],
+            action: RouteAction::Forward,
+            target: Some(target.to_string()),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        };
+        let router = Router::new(vec![
+            rule(MatchType::Exact, "Example.com.", "exact"),
+            rule(MatchType::Wildcard, "*.corp.example.", "wildcard"),
+            rule(MatchType::Wildcard, "ads.*.example.net.", "label"),
+        ])
+        .unwrap();
+        let find = |name: &str| router.find_match(&Name::from_str(name).unwrap());
+
+        let result = find("example.com.").unwrap();
+        assert_eq!(result.target.as_deref(), Some("exact"));
+        assert_eq!(result.pattern, "example.com");
+
+        let result = find("host.corp.example.").unwrap();
+        assert_eq!(result.target.as_deref(), Some("wildcard"));
+        assert_eq!(result.pattern, "*.corp.example");
+
+        let result = find("ads.cdn.example.net.").unwrap();
+        assert_eq!(result.target.as_deref(), Some("label"));
+        assert_eq!(result.pattern, "ads.*.example.net");
+    }
+
+    #[test]
+    fn test_allow_rules_exempt_names_from_block_rules() {
+        let rule = |match_type, pattern: &str, action, target: Option<&str>| RouteRuleConfig {
+            match_type,
+            patterns: vec![pattern.to_string()],
+            action,
+            target: target.map(str::to_string),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        };
+        let router = Router::new(vec![
+            rule(
+                MatchType::Wildcard,
+                "*.example.com",
+                RouteAction::Block,
+                None,
+            ),
+            rule(
+                MatchType::Exact,
+                "safe.example.com",
+                RouteAction::Allow,
+                None,
+            ),
+            rule(MatchType::Regex, "^trusted\\.", RouteAction::Allow, None),
+            rule(
+                MatchType::Wildcard,
+                "*",
+                RouteAction::Forward,
+                Some("google_public"),
+            ),
+        ])
+        .unwrap();
+        let find = |name: &str| router.find_match(&Name::from_str(name).unwrap()).unwrap();
+
+        // 被 allow 的名称跳过 block 规则，按转发规则正常解析
+        let result = find("safe.example.com.");
+        assert_eq!(result.action, RouteAction::Forward);
+        assert_eq!(result.target.as_deref(), Some("google_public"));
+        assert_eq!(find("trusted.example.com.").action, RouteAction::Forward);
+
+        // allow 不影响同一后缀下的其他名称，也不会匹配子域名
+        assert_eq!(find("ads.example.com.").action, RouteAction::Block);
+        assert_eq!(find("x.safe.example.com.").action, RouteAction::Block);
+    }
+
+    #[test]
+    fn test_allowed_match_is_counted_once() {
+        let rule = |match_type, pattern: &str, action, target: Option<&str>| RouteRuleConfig {
+            match_type,
+            patterns: vec![pattern.to_string()],
+            action,
+            target: target.map(str::to_string),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        };
+        let router = Router::new(vec![
+            rule(
+                MatchType::Wildcard,
+                "*.allow-metrics.test",
+                RouteAction::Block,
+                None,
+            ),
+            rule(
+                MatchType::Exact,
+                "ok.allow-metrics.test",
+                RouteAction::Allow,
+                None,
+            ),
+            rule(
+                MatchType::Wildcard,
+                "*",
+                RouteAction::Forward,
+                Some("allow_metrics_group"),
+            ),
+        ])
+        .unwrap();
+        let count = |match_type: &str, target: &str, action: &str| {
+            METRICS
+                .route_matches_total()
+                .with_label_values(&[match_type, target, "static", action])
+                .get()
+        };
+        let allow_exact = || count("exact", "none", "allow");
+        let allow_forward = || count("wildcard", "allow_metrics_group", "allow");
+        let forward = || count("wildcard", "allow_metrics_group", "forward");
+        let (exact_before, allowed_before, forward_before) =
+            (allow_exact(), allow_forward(), forward());
+
+        // 被 allow 豁免的名称只按最终生效的转发规则计数一次，动作标签为 allow
+        let result = router
+            .find_match(&Name::from_str("ok.allow-metrics.test.").unwrap())
+            .unwrap();
+        assert_eq!(result.action, RouteAction::Forward);
+        assert_eq!(allow_forward(), allowed_before + 1);
+        assert_eq!(forward(), forward_before);
+        assert_eq!(allow_exact(), exact_before);
+    }
+
+    #[test]
+    fn test_regex_prefilter_skips_unrelated_short_overlaps() {
+        let patterns = [
+            "^tracker\\.analytics\\.net$",
+            "^(ads|banner)\\.media\\.example\\.com$",
+        ];
+        let prefilter = RegexPrefilter::build(patterns);
+
+        // 只与关键词共享前缀或短子串的域名不会成为候选
+        assert!(prefilter.candidates("an.sample.net").is_empty());
+        assert!(prefilter.candidates("analytic.sample.net").is_empty());
+        assert!(prefilter.candidates("exam.media.org").is_empty());
+
+        // 域名包含规则必需的字面片段时才执行完整的正则匹配
+        assert_eq!(prefilter.candidates("tracker.analytics.net"), vec![0]);
+        assert_eq!(prefilter.candidates("ads.media.example.com"), vec![1]);
+
+        let router = Router::new(
+            patterns
+                .iter()
+                .map(|pattern| RouteRuleConfig {
+                    match_type: MatchType::Regex,
+                    patterns: vec![pattern.to_string()],
+                    action: RouteAction::Block,
+                    target: None,
+                    weighted_targets: Vec::new(),
+                    fallback: Vec::new(),
+                    fallback_on_nxdomain: false,
+                    client_cidrs: Vec::new(),
+                    source: None,
+                })
+                .collect(),
+        )
+        .unwrap();
+        let find = |name: &str| router.find_match(&Name::from_str(name).unwrap());
+        assert!(find("analytic.sample.net.").is_err());
+        assert_eq!(
+            find("Tracker.Analytics.net.").unwrap().action,
+            RouteAction::Block
+        );
+    }
+
+    #[test]
+    fn test_regex_prefilter_keeps_all_true_matches() {
+        let patterns = [
+            r"^(api|service)\..+\.com$",
+            r"doubleclick",
+            r"^ads?\d*\.tracker\.net$",
+            r"^[a-z]+$",
+            r"(?i)^CDN\.Example\.org$",
+            r"^(?:www\.)?shop\.example\.com$",
+            r"^x{2,}\.y\.io$",
+            r"^metrics-(eu|us)\.vendor\.io$",
+            r"^a\.b$",
+        ];
+        let domains = [
+            "api.foo.com",
+            "service.bar.baz.com",
+            "xdoubleclick.net",
+            "ad.tracker.net",
+            "ads42.tracker.net",
+            "localhost",
+            "cdn.example.org",
+            "shop.example.com",
+            "www.shop.example.com",
+            "xxx.y.io",
+            "metrics-eu.vendor.io",
+            "a.b",
+            "unrelated.example.net",
+        ];
+        let prefilter = RegexPrefilter::build(patterns);
+
+        // 每一对真实匹配的 (规则, 域名) 都必须出现在候选中
+        for domain in domains {
+            let candidates = prefilter.candidates(domain);
+            for (i, pattern) in patterns.iter().enumerate() {
+                if regex::Regex::new(pattern).unwrap().is_match(domain) {
+                    assert!(
+                        candidates.contains(&i),
+                        "rule '{}' was dropped for '{}'",
+                        pattern,
+                        domain
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_regex_prefilter_requires_all_keywords() {
+        let prefilter = RegexPrefilter::build([r"^ads\.tracking-pixel\.example\.com$"]);
+
+        // 只出现部分必需片段的域名不再触发完整的正则匹配
+        assert!(prefilter.candidates("tracking-pixel.other.net").is_empty());
+        assert!(prefilter.candidates("ads.example.com").is_empty());
+        assert_eq!(
+            prefilter.candidates("ads.tracking-pixel.example.com"),
+            vec![0]
+        );
+    }
+
+    // 创建仅对指定客户端网段生效的 forward 规则
+    fn client_rule(pattern: &str, target: &str, client_cidrs: &[&str]) -> RouteRuleConfig {
+        RouteRuleConfig {
+            match_type: MatchType::Wildcard,
+            patterns: vec![pattern.to_string()],
+            action: RouteAction::Forward,
+            target: Some(target.to_string()),
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: client_cidrs.iter().map(|cidr| cidr.to_string()).collect(),
+            source: None,
+        }
+    }
+
+    #[test]
+    fn test_client_cidrs_select_group_by_client_subnet() {
+        let router = Router::new(vec![
+            client_rule("*", "filtering", &["192.168.50.0/24"]),
+            client_rule("*", "unfiltered", &["10.0.0.0/8", "fd00::/8"]),
+            client_rule("*", "default", &[]),
+        ])
+        .unwrap();
+        let name = Name::from_str("video.example.com.").unwrap();
+        let target = |client: &str| {
+            router
+                .find_client_match(&name, client.parse().unwrap())
+                .map(|route_match| {
+                    assert!(route_match.client_scope.is_some());
+                    route_match.target.unwrap()
+                })
+        };
+
+        // 同一名称：访客网段使用过滤上游组，可信网段使用不过滤的上游组
+        assert_eq!(target("192.168.50.20").as_deref(), Some("filtering"));
+        assert_eq!(target("10.1.2.3").as_deref(), Some("unfiltered"));
+        assert_eq!(target("fd00::1").as_deref(), Some("unfiltered"));
+        // 双栈监听时的 IPv4 映射地址按 IPv4 匹配
+        assert_eq!(target("::ffff:192.168.50.20").as_deref(), Some("filtering"));
+
+        // 不在任何网段内的客户端只匹配不限客户端的规则
+        assert_eq!(target("172.16.0.1"), None);
+        let route_match = router.find_match(&name).unwrap();
+        assert_eq!(route_match.target.as_deref(), Some("default"));
+        assert!(route_match.client_scope.is_none());
+    }
+
+    #[test]
+    fn test_client_cidrs_fall_through_to_general_rules() {
+        let router = Router::new(vec![
+            client_rule("*.video.com", "filtering", &["192.168.50.0/24"]),
+            client_rule("*", "default", &[]),
+        ])
+        .unwrap();
+
+        // 网段内客户端未命中网段规则时由调用方继续匹配通用规则
+        let other = Name::from_str("www.example.com.").unwrap();
+        assert!(router
+            .find_client_match(&other, "192.168.50.20".parse().unwrap())
+            .is_none());
+
+        // 客户端网段规则计入规则统计并在导出时附带网段
+        let exported: Vec<_> = router.rules().collect();
+        assert_eq!(exported.len(), 2);
+        assert_eq!(
+            exported[1].client_cidrs,
+            Some(&["192.168.50.0/24".to_string()][..])
+        );
+        let total: usize = router.rule_counts().iter().map(|count| count.rules).sum();
+        assert_eq!(total, 2);
+    }
+
+    #[test]
+    fn test_global_block_precedes_client_rules() {
+        let router = Router::new(vec![
+            RouteRuleConfig {
+                match_type: MatchType::Exact,
+                patterns: vec!["ads.example.com".to_string()],
+                action: RouteAction::Block,
+                target: None,
+                weighted_targets: Vec::new(),
+                fallback: Vec::new(),
+                fallback_on_nxdomain: false,
+                client_cidrs: Vec::new(),
+                source: None,
+            },
+            client_rule("*", "unfiltered", &["10.0.0.0/8"]),
+            client_rule("*", "default", &[]),
+        ])
+        .unwrap();
+        let client = "10.1.2.3".parse().unwrap();
+
+        // 全局拦截的名称不由网段规则处理，调用方回到通用规则得到拦截结果
+        let blocked = Name::from_str("ads.example.com.").unwrap();
+        assert!(router.find_client_match(&blocked, client).is_none());
+        assert_eq!(
+            router.find_match(&blocked).unwrap().action,
+            RouteAction::Block
+        );
+
+        // 其余名称仍由网段规则处理，且同一网段规则组的作用域一致
+        let name = Name::from_str("www.example.com.").unwrap();
+        let route_match = router.find_client_match(&name, client).unwrap();
+        assert_eq!(route_match.target.as_deref(), Some("unfiltered"));
+        assert_eq!(route_match.client_scope.as_deref(), Some("10.0.0.0/8"));
+    }
+
+    #[test]
+    fn test_invalid_client_cidr_rejected() {
+        assert!(Router::new(vec![client_rule("*", "default", &["not-a-cidr"])]).is_err());
+    }
 }
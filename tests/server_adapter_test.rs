@@ -4,6 +4,7 @@ use hickory_proto::serialize::binary::{BinDecodable, BinDecoder};
 use hickory_server::authority::MessageRequest;
 use hickory_server::server::{Protocol, Request};
 use loadants::server::parse_request_message;
+use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
 #[test]
@@ -35,3 +36,814 @@ fn test_parse_request_message_preserves_edns() {
     assert!(parsed.extensions().is_some());
     assert_eq!(parsed.extensions().as_ref().unwrap().max_payload(), 1232);
 }
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_bind_tcp_listener_with_reuse_port() {
+    use loadants::server::{bind_tcp_listener, TcpListenerOptions};
+
+    let options = TcpListenerOptions {
+        reuse_addr: true,
+        reuse_port: true,
+        backlog: 128,
+        only_v6: None,
+    };
+
+    // 两个实例绑定同一端口
+    let first = bind_tcp_listener("127.0.0.1:0".parse().unwrap(), &options).unwrap();
+    let addr = first.local_addr().unwrap();
+    let second = bind_tcp_listener(addr, &options).unwrap();
+    assert_eq!(second.local_addr().unwrap(), addr);
+
+    // 未启用 reuse_port 时第二次绑定失败
+    let exclusive = TcpListenerOptions {
+        reuse_port: false,
+        ..options
+    };
+    let third = bind_tcp_listener("127.0.0.1:0".parse().unwrap(), &exclusive).unwrap();
+    assert!(bind_tcp_listener(third.local_addr().unwrap(), &exclusive).is_err());
+
+    // 监听器可以正常接受连接
+    let client = tokio::net::TcpStream::connect(addr).await;
+    assert!(client.is_ok());
+}
+
+#[tokio::test]
+async fn test_probe_bind_reports_port_conflict() {
+    use loadants::server::{bind_tcp_listener, DnsServerConfig, TcpListenerOptions};
+    use loadants::AppError;
+
+    let options = TcpListenerOptions::default();
+
+    // 第一个实例占用 UDP 与 TCP 端口
+    let udp = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let udp_addr = udp.local_addr().unwrap();
+    let tcp = bind_tcp_listener("127.0.0.1:0".parse().unwrap(), &options).unwrap();
+    let tcp_addr = tcp.local_addr().unwrap();
+
+    let free_addr = |addr: SocketAddr| SocketAddr::new(addr.ip(), 0);
+    let config = |udp_bind_addr, tcp_bind_addr| DnsServerConfig {
+        udp_bind_addr,
+        tcp_bind_addr,
+        http_bind_addr: "127.0.0.1:0".parse().unwrap(),
+        tcp_timeout: 10,
+        http_timeout: 30,
+        tcp_listener: options,
+        max_tcp_connections: None,
+        require_both_protocols: true,
+        log_malformed_requests: false,
+        udp_v6_bind_addr: None,
+        tcp_v6_bind_addr: None,
+        dual_stack: true,
+        udp_max_response_size: None,
+    };
+
+    // 空闲端口探测成功
+    assert!(config(free_addr(udp_addr), free_addr(tcp_addr))
+        .probe_bind()
+        .is_ok());
+
+    // 第二个实例使用相同 UDP 端口时返回描述性的绑定错误
+    let err = config(udp_addr, free_addr(tcp_addr))
+        .probe_bind()
+        .unwrap_err();
+    assert!(matches!(err, AppError::Bind { protocol: "udp", addr, .. } if addr == udp_addr));
+    assert!(err.to_string().contains(&udp_addr.to_string()));
+
+    // 第二个实例使用相同 TCP 端口时同样失败
+    let err = config(free_addr(udp_addr), tcp_addr)
+        .probe_bind()
+        .unwrap_err();
+    assert!(matches!(err, AppError::Bind { protocol: "tcp", addr, .. } if addr == tcp_addr));
+}
+
+// 创建拦截所有域名的处理器，无需上游即可得到确定的响应（NXDOMAIN）
+fn create_blocking_handler() -> std::sync::Arc<loadants::RequestHandler> {
+    use loadants::config::{MatchType, RouteAction, RouteRuleConfig};
+    use loadants::{DnsCache, RequestHandler, Router, UpstreamManager};
+    use std::sync::Arc;
+
+    let router = Router::new(vec![RouteRuleConfig {
+        match_type: MatchType::Wildcard,
+        patterns: vec!["*".to_string()],
+        action: RouteAction::Block,
+        target: None,
+        weighted_targets: Vec::new(),
+        fallback: Vec::new(),
+        fallback_on_nxdomain: false,
+        client_cidrs: Vec::new(),
+        source: None,
+    }])
+    .unwrap();
+    Arc::new(RequestHandler::new(
+        Arc::new(DnsCache::new(0, 0, None)),
+        Arc::new(router),
+        Arc::new(UpstreamManager::empty().unwrap()),
+    ))
+}
+
+// 创建 DNS 查询报文
+fn create_query_message(name: &str) -> Message {
+    let mut message = Message::new();
+    message
+        .set_id(42)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true);
+    message.add_query(Query::query(Name::from_ascii(name).unwrap(), RecordType::A));
+    message
+}
+
+// 通过 TCP 发送一条 DNS 查询（两字节长度前缀）
+async fn send_tcp_query(stream: &mut tokio::net::TcpStream, name: &str) {
+    use tokio::io::AsyncWriteExt;
+
+    let bytes = create_query_message(name).to_vec().unwrap();
+    stream.write_u16(bytes.len() as u16).await.unwrap();
+    stream.write_all(&bytes).await.unwrap();
+}
+
+// 读取一条 TCP DNS 响应
+async fn read_tcp_response(stream: &mut tokio::net::TcpStream) -> Message {
+    use tokio::io::AsyncReadExt;
+
+    let length = stream.read_u16().await.unwrap();
+    let mut buffer = vec![0u8; length as usize];
+    stream.read_exact(&mut buffer).await.unwrap();
+    Message::from_vec(&buffer).unwrap()
+}
+
+#[tokio::test]
+async fn test_max_tcp_connections_delays_extra_connections() {
+    use hickory_proto::op::ResponseCode;
+    use loadants::metrics::METRICS;
+    use loadants::server::{DnsServer, DnsServerConfig, TcpListenerOptions};
+    use std::time::Duration;
+    use tokio_graceful_shutdown::{IntoSubsystem, SubsystemBuilder, Toplevel};
+
+    let handler = create_blocking_handler();
+
+    // 预先获取可用端口
+    let udp_addr = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let tcp_addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let server = DnsServer::new(
+        DnsServerConfig {
+            udp_bind_addr: udp_addr,
+            tcp_bind_addr: tcp_addr,
+            http_bind_addr: "127.0.0.1:0".parse().unwrap(),
+            tcp_timeout: 10,
+            http_timeout: 30,
+            tcp_listener: TcpListenerOptions::default(),
+            max_tcp_connections: Some(1),
+            require_both_protocols: true,
+            log_malformed_requests: false,
+            udp_v6_bind_addr: None,
+            tcp_v6_bind_addr: None,
+            dual_stack: true,
+            udp_max_response_size: None,
+        },
+        handler,
+    );
+    let toplevel = tokio::spawn(
+        Toplevel::new(move |s| async move {
+            s.start(SubsystemBuilder::new("dns", server.into_subsystem()));
+        })
+        .handle_shutdown_requests(Duration::from_secs(5)),
+    );
+
+    // 第一个连接占满唯一的连接许可
+    let mut first = loop {
+        match tokio::net::TcpStream::connect(tcp_addr).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    };
+    send_tcp_query(&mut first, "first.example.com.").await;
+    let response = read_tcp_response(&mut first).await;
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+    assert_eq!(
+        METRICS
+            .active_connections()
+            .with_label_values(&["tcp"])
+            .get(),
+        1
+    );
+
+    // 超出上限的连接在监听队列中等待，暂时得不到响应
+    let mut second = tokio::net::TcpStream::connect(tcp_addr).await.unwrap();
+    send_tcp_query(&mut second, "second.example.com.").await;
+    assert!(
+        tokio::time::timeout(Duration::from_millis(300), read_tcp_response(&mut second))
+            .await
+            .is_err()
+    );
+
+    // 第一个连接关闭后，等待中的连接被接受并得到响应
+    drop(first);
+    let response = tokio::time::timeout(Duration::from_secs(5), read_tcp_response(&mut second))
+        .await
+        .unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+
+    toplevel.abort();
+}
+
+// 测试 DNS 请求计数按实际传输协议（UDP/TCP）打标签
+// 同一进程内的其他测试也会并发发送查询，因此只断言计数至少增加
+#[tokio::test]
+async fn test_dns_requests_counted_by_transport_protocol() {
+    use loadants::metrics::METRICS;
+    use loadants::server::{DnsServer, DnsServerConfig, TcpListenerOptions};
+    use std::time::Duration;
+    use tokio_graceful_shutdown::{IntoSubsystem, SubsystemBuilder, Toplevel};
+
+    let udp_addr = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let tcp_addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let server = DnsServer::new(
+        DnsServerConfig {
+            udp_bind_addr: udp_addr,
+            tcp_bind_addr: tcp_addr,
+            http_bind_addr: "127.0.0.1:0".parse().unwrap(),
+            tcp_timeout: 10,
+            http_timeout: 30,
+            tcp_listener: TcpListenerOptions::default(),
+            max_tcp_connections: None,
+            require_both_protocols: true,
+            log_malformed_requests: false,
+            udp_v6_bind_addr: None,
+            tcp_v6_bind_addr: None,
+            dual_stack: true,
+            udp_max_response_size: None,
+        },
+        create_blocking_handler(),
+    );
+    let toplevel = tokio::spawn(
+        Toplevel::new(move |s| async move {
+            s.start(SubsystemBuilder::new("dns", server.into_subsystem()));
+        })
+        .handle_shutdown_requests(Duration::from_secs(5)),
+    );
+
+    let udp_requests = METRICS.dns_requests_total().with_label_values(&["udp"]);
+    let tcp_requests = METRICS.dns_requests_total().with_label_values(&["tcp"]);
+
+    // 通过 TCP 发送一条查询
+    let mut stream = loop {
+        match tokio::net::TcpStream::connect(tcp_addr).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    };
+    let tcp_before = tcp_requests.get();
+    send_tcp_query(&mut stream, "tcp.example.com.").await;
+    read_tcp_response(&mut stream).await;
+    assert!(tcp_requests.get() > tcp_before);
+
+    // 通过 UDP 发送一条查询
+    let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let udp_before = udp_requests.get();
+    query_udp(&client, udp_addr, create_query_message("udp.example.com.")).await;
+    assert!(udp_requests.get() > udp_before);
+
+    toplevel.abort();
+}
+
+#[tokio::test]
+async fn test_dns_server_continues_with_udp_when_tcp_bind_fails() {
+    use hickory_proto::op::ResponseCode;
+    use loadants::server::{DnsServer, DnsServerConfig, TcpListenerOptions};
+    use std::time::Duration;
+    use tokio_graceful_shutdown::{IntoSubsystem, SubsystemBuilder, Toplevel};
+
+    // 其他进程占用 TCP 端口
+    let held_tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let tcp_addr = held_tcp.local_addr().unwrap();
+    let udp_addr = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let config = |require_both_protocols| DnsServerConfig {
+        udp_bind_addr: udp_addr,
+        tcp_bind_addr: tcp_addr,
+        http_bind_addr: "127.0.0.1:0".parse().unwrap(),
+        tcp_timeout: 10,
+        http_timeout: 30,
+        tcp_listener: TcpListenerOptions::default(),
+        max_tcp_connections: None,
+        require_both_protocols,
+        log_malformed_requests: false,
+        udp_v6_bind_addr: None,
+        tcp_v6_bind_addr: None,
+        dual_stack: true,
+        udp_max_response_size: None,
+    };
+
+    // 要求两种协议时启动探测失败，否则只警告
+    assert!(config(true).probe_bind().is_err());
+    assert!(config(false).probe_bind().is_ok());
+
+    let server = DnsServer::new(config(false), create_blocking_handler());
+    let toplevel = tokio::spawn(
+        Toplevel::new(move |s| async move {
+            s.start(SubsystemBuilder::new("dns", server.into_subsystem()));
+        })
+        .handle_shutdown_requests(Duration::from_secs(5)),
+    );
+
+    // UDP 仍然可以正常提供服务
+    let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let query = create_query_message("udp-only.example.com.")
+        .to_vec()
+        .unwrap();
+    let mut buffer = [0u8; 512];
+    let mut response = None;
+    for _ in 0..50 {
+        client.send_to(&query, udp_addr).await.unwrap();
+        if let Ok(Ok(len)) =
+            tokio::time::timeout(Duration::from_millis(100), client.recv(&mut buffer)).await
+        {
+            response = Some(Message::from_vec(&buffer[..len]).unwrap());
+            break;
+        }
+    }
+    let response = response.expect("DNS server did not answer over UDP");
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+    assert!(!toplevel.is_finished());
+
+    toplevel.abort();
+}
+
+// 启动一个 DNS 服务器：所有查询转发到返回约 1 KB 应答（64 条 A 记录）的 mock 上游
+// 返回 UDP 监听地址与服务器任务
+async fn spawn_large_answer_server(
+    group: &str,
+    udp_max_response_size: Option<usize>,
+) -> (
+    SocketAddr,
+    tokio::task::JoinHandle<Result<(), tokio_graceful_shutdown::errors::GracefulShutdownError>>,
+) {
+    use hickory_proto::rr::rdata::A;
+    use hickory_proto::rr::{RData, Record};
+    use loadants::config::{
+        DoHContentType, DoHMethod, DoHUpstreamServerConfig, HttpClientConfig,
+        LoadBalancingStrategy, MatchType, RouteAction, RouteRuleConfig, UpstreamGroupConfig,
+        UpstreamScheme, UpstreamServerConfig,
+    };
+    use loadants::server::{DnsServer, DnsServerConfig, TcpListenerOptions};
+    use loadants::{DnsCache, DnsClientConfig, RequestHandler, Router, UpstreamManager};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio_graceful_shutdown::{IntoSubsystem, SubsystemBuilder, Toplevel};
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    let name = Name::from_ascii("big.example.com.").unwrap();
+    let mut upstream_response = create_query_message("big.example.com.");
+    upstream_response.set_message_type(MessageType::Response);
+    for i in 0..64u8 {
+        upstream_response.add_answer(Record::from_rdata(
+            name.clone(),
+            300,
+            RData::A(A::new(192, 0, 2, i)),
+        ));
+    }
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("Content-Type", "application/dns-message")
+                .set_body_bytes(upstream_response.to_vec().unwrap()),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let groups = vec![UpstreamGroupConfig {
+        name: group.to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::RoundRobin,
+        servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: format!("{}/dns-query", mock_server.uri()).parse().unwrap(),
+            weight: 1,
+            method: DoHMethod::Get,
+            content_type: DoHContentType::Message,
+            auth: None,
+        })],
+        retry: None,
+        proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
+    }];
+    let upstream = UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    let router = Router::new(vec![RouteRuleConfig {
+        match_type: MatchType::Wildcard,
+        patterns: vec!["*".to_string()],
+        action: RouteAction::Forward,
+        target: Some(group.to_string()),
+        weighted_targets: Vec::new(),
+        fallback: Vec::new(),
+        fallback_on_nxdomain: false,
+        client_cidrs: Vec::new(),
+        source: None,
+    }])
+    .unwrap();
+    let handler = Arc::new(RequestHandler::new(
+        Arc::new(DnsCache::new(0, 0, None)),
+        Arc::new(router),
+        Arc::new(upstream),
+    ));
+
+    let udp_addr = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let tcp_addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let server = DnsServer::new(
+        DnsServerConfig {
+            udp_bind_addr: udp_addr,
+            tcp_bind_addr: tcp_addr,
+            http_bind_addr: "127.0.0.1:0".parse().unwrap(),
+            tcp_timeout: 10,
+            http_timeout: 30,
+            tcp_listener: TcpListenerOptions::default(),
+            max_tcp_connections: None,
+            require_both_protocols: true,
+            log_malformed_requests: false,
+            udp_v6_bind_addr: None,
+            tcp_v6_bind_addr: None,
+            dual_stack: true,
+            udp_max_response_size,
+        },
+        handler,
+    );
+    let toplevel = tokio::spawn(
+        Toplevel::new(move |s| async move {
+            s.start(SubsystemBuilder::new("dns", server.into_subsystem()));
+        })
+        .handle_shutdown_requests(Duration::from_secs(5)),
+    );
+    (udp_addr, toplevel)
+}
+
+// 通过 UDP 发送查询并等待响应
+async fn query_udp(client: &tokio::net::UdpSocket, server: SocketAddr, query: Message) -> Message {
+    let bytes = query.to_vec().unwrap();
+    let mut buffer = [0u8; 4096];
+    for _ in 0..50 {
+        client.send_to(&bytes, server).await.unwrap();
+        if let Ok(Ok(len)) = tokio::time::timeout(
+            std::time::Duration::from_millis(200),
+            client.recv(&mut buffer),
+        )
+        .await
+        {
+            return Message::from_vec(&buffer[..len]).unwrap();
+        }
+    }
+    panic!("DNS server did not answer over UDP");
+}
+
+// 创建通告指定 EDNS 缓冲区大小的查询
+fn create_edns_query_message(name: &str, max_payload: u16) -> Message {
+    let mut query = create_query_message(name);
+    let mut edns = Edns::new();
+    edns.set_max_payload(max_payload);
+    query.set_edns(edns);
+    query
+}
+
+#[tokio::test]
+async fn test_udp_response_truncated_to_client_buffer() {
+    use loadants::metrics::METRICS;
+
+    let (udp_addr, toplevel) = spawn_large_answer_server("big_group", None).await;
+    let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let truncated = || {
+        METRICS
+            .truncated_responses_total()
+            .with_label_values(&["big_group"])
+            .get()
+    };
+
+    // 未携带 EDNS 的客户端（512 字节）收到 TC=1 的空应答
+    let before = truncated();
+    let response = query_udp(&client, udp_addr, create_query_message("big.example.com.")).await;
+    assert!(response.truncated());
+    assert!(response.answers().is_empty());
+    assert_eq!(truncated(), before + 1);
+
+    // 通告 4096 字节缓冲区的客户端收到完整应答
+    let query = create_edns_query_message("big.example.com.", 4096);
+    let response = query_udp(&client, udp_addr, query).await;
+    assert!(!response.truncated());
+    assert_eq!(response.answers().len(), 64);
+    assert_eq!(truncated(), before + 1);
+
+    toplevel.abort();
+}
+
+#[tokio::test]
+async fn test_udp_max_response_size_caps_large_edns_buffer() {
+    use loadants::metrics::METRICS;
+
+    let (udp_addr, toplevel) = spawn_large_answer_server("capped_group", Some(768)).await;
+    let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let truncated = || {
+        METRICS
+            .truncated_responses_total()
+            .with_label_values(&["capped_group"])
+            .get()
+    };
+
+    // 客户端通告 4096 字节缓冲区，但约 1 KB 的应答超过 768 字节上限，仍被截断
+    let before = truncated();
+    let query = create_edns_query_message("big.example.com.", 4096);
+    let response = query_udp(&client, udp_addr, query).await;
+    assert!(response.truncated());
+    assert!(response.answers().is_empty());
+    assert_eq!(truncated(), before + 1);
+
+    toplevel.abort();
+}
+
+#[tokio::test]
+async fn test_malformed_udp_packet_counted_and_server_keeps_serving() {
+    use hickory_proto::op::ResponseCode;
+    use loadants::metrics::METRICS;
+    use loadants::server::{DnsServer, DnsServerConfig, TcpListenerOptions};
+    use std::time::Duration;
+    use tokio_graceful_shutdown::{IntoSubsystem, SubsystemBuilder, Toplevel};
+
+    let udp_addr = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let tcp_addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let server = DnsServer::new(
+        DnsServerConfig {
+            udp_bind_addr: udp_addr,
+            tcp_bind_addr: tcp_addr,
+            http_bind_addr: "127.0.0.1:0".parse().unwrap(),
+            tcp_timeout: 10,
+            http_timeout: 30,
+            tcp_listener: TcpListenerOptions::default(),
+            max_tcp_connections: None,
+            require_both_protocols: true,
+            log_malformed_requests: true,
+            udp_v6_bind_addr: None,
+            tcp_v6_bind_addr: None,
+            dual_stack: true,
+            udp_max_response_size: None,
+        },
+        create_blocking_handler(),
+    );
+    let toplevel = tokio::spawn(
+        Toplevel::new(move |s| async move {
+            s.start(SubsystemBuilder::new("dns", server.into_subsystem()));
+        })
+        .handle_shutdown_requests(Duration::from_secs(5)),
+    );
+
+    // 等待服务器开始应答
+    let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let query = create_query_message("valid.example.com.").to_vec().unwrap();
+    let mut buffer = [0u8; 512];
+    let mut answered = false;
+    for _ in 0..50 {
+        client.send_to(&query, udp_addr).await.unwrap();
+        if let Ok(Ok(_)) =
+            tokio::time::timeout(Duration::from_millis(100), client.recv(&mut buffer)).await
+        {
+            answered = true;
+            break;
+        }
+    }
+    assert!(answered, "DNS server did not answer over UDP");
+
+    let malformed = METRICS
+        .dns_malformed_requests_total()
+        .with_label_values(&["udp"]);
+    let before = malformed.get();
+
+    // 不足一个报文头的垃圾数据被计数并丢弃
+    client.send_to(b"\xde\xad\xbe", udp_addr).await.unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), client.recv(&mut buffer))
+            .await
+            .is_err()
+    );
+    assert_eq!(malformed.get(), before + 1);
+
+    // 报文头完整但问题部分被截断的查询返回 FORMERR
+    client.send_to(&query[..20], udp_addr).await.unwrap();
+    let len = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buffer))
+        .await
+        .unwrap()
+        .unwrap();
+    let response = Message::from_vec(&buffer[..len]).unwrap();
+    assert_eq!(response.id(), 42);
+    assert_eq!(response.response_code(), ResponseCode::FormErr);
+    assert_eq!(malformed.get(), before + 2);
+
+    // 服务器继续正常处理查询
+    client.send_to(&query, udp_addr).await.unwrap();
+    let len = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buffer))
+        .await
+        .unwrap()
+        .unwrap();
+    let response = Message::from_vec(&buffer[..len]).unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+
+    toplevel.abort();
+}
+
+// 测试以 IPV6_V6ONLY 绑定的 IPv6 地址可与同端口的 IPv4 通配地址共存，双栈绑定则冲突
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_v6_only_bind_coexists_with_ipv4_bind() {
+    use loadants::server::{bind_tcp_listener, bind_udp_socket, TcpListenerOptions};
+
+    // 环境不支持 IPv6 时跳过
+    if std::net::UdpSocket::bind("[::1]:0").is_err() {
+        return;
+    }
+
+    let udp_v4 = bind_udp_socket("0.0.0.0:0".parse().unwrap(), None).unwrap();
+    let udp_port = udp_v4.local_addr().unwrap().port();
+    let udp_v6_addr = SocketAddr::new("::".parse().unwrap(), udp_port);
+    assert!(bind_udp_socket(udp_v6_addr, Some(false)).is_err());
+    let udp_v6 = bind_udp_socket(udp_v6_addr, Some(true)).unwrap();
+    assert_eq!(udp_v6.local_addr().unwrap().port(), udp_port);
+
+    let options = TcpListenerOptions {
+        reuse_addr: false,
+        ..TcpListenerOptions::default()
+    };
+    let tcp_v4 = bind_tcp_listener("0.0.0.0:0".parse().unwrap(), &options).unwrap();
+    let tcp_port = tcp_v4.local_addr().unwrap().port();
+    let tcp_v6_addr = SocketAddr::new("::".parse().unwrap(), tcp_port);
+    let dual_stack = TcpListenerOptions {
+        only_v6: Some(false),
+        ..options
+    };
+    assert!(bind_tcp_listener(tcp_v6_addr, &dual_stack).is_err());
+    let v6_only = TcpListenerOptions {
+        only_v6: Some(true),
+        ..options
+    };
+    let tcp_v6 = bind_tcp_listener(tcp_v6_addr, &v6_only).unwrap();
+    assert_eq!(tcp_v6.local_addr().unwrap().port(), tcp_port);
+}
+
+// 按查询回显 NOERROR 应答并延迟返回的 DoH mock 响应器
+struct DelayedEchoResponder(std::time::Duration);
+
+impl wiremock::Respond for DelayedEchoResponder {
+    fn respond(&self, request: &wiremock::Request) -> wiremock::ResponseTemplate {
+        let mut response = Message::from_vec(&request.body).unwrap();
+        response.set_message_type(MessageType::Response);
+        wiremock::ResponseTemplate::new(200)
+            .insert_header("Content-Type", "application/dns-message")
+            .set_body_bytes(response.to_vec().unwrap())
+            .set_delay(self.0)
+    }
+}
+
+#[tokio::test]
+async fn test_tcp_pipelined_queries_processed_concurrently() {
+    use loadants::config::{
+        DnsClientConfig, DoHContentType, DoHMethod, DoHUpstreamServerConfig, HttpClientConfig,
+        LoadBalancingStrategy, MatchType, RouteAction, RouteRuleConfig, UpstreamGroupConfig,
+        UpstreamScheme, UpstreamServerConfig,
+    };
+    use loadants::server::{DnsServer, DnsServerConfig, TcpListenerOptions};
+    use loadants::{DnsCache, RequestHandler, Router, UpstreamManager};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio_graceful_shutdown::{IntoSubsystem, SubsystemBuilder, Toplevel};
+
+    // 上游应答延迟 500ms；fast.example.com 由本地规则直接拦截
+    let mock_server = wiremock::MockServer::start().await;
+    wiremock::Mock::given(wiremock::matchers::method("POST"))
+        .respond_with(DelayedEchoResponder(Duration::from_millis(500)))
+        .mount(&mock_server)
+        .await;
+    let upstream = UpstreamManager::new(
+        vec![UpstreamGroupConfig {
+            name: "slow".to_string(),
+            scheme: UpstreamScheme::Doh,
+            strategy: LoadBalancingStrategy::RoundRobin,
+            servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+                url: reqwest::Url::parse(&format!("{}/dns-query", mock_server.uri())).unwrap(),
+                weight: 1,
+                method: DoHMethod::Post,
+                content_type: DoHContentType::Message,
+                auth: None,
+            })],
+            retry: None,
+            proxy: None,
+            affinity_ttl: None,
+            max_connections: None,
+            headers: HashMap::new(),
+        }],
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    let rule = |pattern: &str, action, target: Option<&str>| RouteRuleConfig {
+        match_type: MatchType::Exact,
+        patterns: vec![pattern.to_string()],
+        action,
+        target: target.map(str::to_string),
+        weighted_targets: Vec::new(),
+        fallback: Vec::new(),
+        fallback_on_nxdomain: false,
+        client_cidrs: Vec::new(),
+        source: None,
+    };
+    let router = Router::new(vec![
+        rule("slow.example.com", RouteAction::Forward, Some("slow")),
+        rule("fast.example.com", RouteAction::Block, None),
+    ])
+    .unwrap();
+    let handler = Arc::new(RequestHandler::new(
+        Arc::new(DnsCache::new(0, 0, None)),
+        Arc::new(router),
+        Arc::new(upstream),
+    ));
+
+    let udp_addr = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let tcp_addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let server = DnsServer::new(
+        DnsServerConfig {
+            udp_bind_addr: udp_addr,
+            tcp_bind_addr: tcp_addr,
+            http_bind_addr: "127.0.0.1:0".parse().unwrap(),
+            tcp_timeout: 10,
+            http_timeout: 30,
+            tcp_listener: TcpListenerOptions::default(),
+            max_tcp_connections: Some(4),
+            require_both_protocols: true,
+            log_malformed_requests: false,
+            udp_v6_bind_addr: None,
+            tcp_v6_bind_addr: None,
+            dual_stack: true,
+            udp_max_response_size: None,
+        },
+        handler,
+    );
+    let toplevel = tokio::spawn(
+        Toplevel::new(move |s| async move {
+            s.start(SubsystemBuilder::new("dns", server.into_subsystem()));
+        })
+        .handle_shutdown_requests(Duration::from_secs(5)),
+    );
+    let mut stream = loop {
+        match tokio::net::TcpStream::connect(tcp_addr).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    };
+
+    // 同一连接上先发送慢查询再发送快查询，快查询的应答不必等待慢查询
+    send_tcp_query(&mut stream, "slow.example.com.").await;
+    send_tcp_query(&mut stream, "fast.example.com.").await;
+    let first = read_tcp_response(&mut stream).await;
+    assert_eq!(first.queries()[0].name().to_ascii(), "fast.example.com.");
+    let second = read_tcp_response(&mut stream).await;
+    assert_eq!(second.queries()[0].name().to_ascii(), "slow.example.com.");
+
+    toplevel.abort();
+}
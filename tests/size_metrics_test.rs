@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use axum::{
+    body::to_bytes,
+    extract::{ConnectInfo, Query as AxumQuery, State},
+    http::{header::CONTENT_TYPE, HeaderMap},
+    response::IntoResponse,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hickory_proto::op::{Message, MessageType, Query};
+use hickory_proto::rr::{Name, RecordType};
+use hyper::body::Bytes;
+use loadants::{
+    cache::DnsCache,
+    config::{MatchType, RouteAction, RouteRuleConfig},
+    doh::{
+        handlers::{handle_doh_get, handle_doh_post, DohGetParams},
+        state::AppState,
+    },
+    handler::RequestHandler,
+    metrics::METRICS,
+    router::Router,
+    UpstreamManager,
+};
+
+// 创建拦截所有域名的处理器（无需上游即可生成响应）
+fn create_blocking_state() -> AppState {
+    let router = Arc::new(
+        Router::new(vec![RouteRuleConfig {
+            match_type: MatchType::Wildcard,
+            patterns: vec!["*".to_string()],
+            action: RouteAction::Block,
+            target: None,
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        }])
+        .unwrap(),
+    );
+    let upstream = Arc::new(UpstreamManager::empty().unwrap());
+    let handler = RequestHandler::new(Arc::new(DnsCache::new(0, 0, None)), router, upstream);
+    AppState::new(Arc::new(handler))
+}
+
+// 创建测试用的 DNS 查询报文
+fn create_query_bytes(name: &str) -> Vec<u8> {
+    let mut query = Message::new();
+    query.set_id(1234);
+    query.set_message_type(MessageType::Query);
+    query.set_recursion_desired(true);
+    query.add_query(Query::query(Name::from_ascii(name).unwrap(), RecordType::A));
+    query.to_vec().unwrap()
+}
+
+// 测试 DoH GET/POST 请求与响应大小被记录到直方图
+#[tokio::test]
+async fn test_doh_size_histograms_observe_wire_lengths() {
+    let request_bytes = METRICS.dns_request_bytes().with_label_values(&["doh"]);
+    let response_bytes = METRICS.dns_response_bytes().with_label_values(&["doh"]);
+    let addr = "127.0.0.1:5353".parse().unwrap();
+    let (request_count, request_sum) = (
+        request_bytes.get_sample_count(),
+        request_bytes.get_sample_sum(),
+    );
+    let (response_count, response_sum) = (
+        response_bytes.get_sample_count(),
+        response_bytes.get_sample_sum(),
+    );
+
+    // POST 请求
+    let post_query = create_query_bytes("post.example.com.");
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, "application/dns-message".parse().unwrap());
+    let response = handle_doh_post(
+        State(create_blocking_state()),
+        ConnectInfo(addr),
+        headers,
+        Bytes::from(post_query.clone()),
+    )
+    .await
+    .into_response();
+    let post_response = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    assert_eq!(request_bytes.get_sample_count() - request_count, 1);
+    assert_eq!(
+        request_bytes.get_sample_sum() - request_sum,
+        post_query.len() as f64
+    );
+    assert_eq!(response_bytes.get_sample_count() - response_count, 1);
+    assert_eq!(
+        response_bytes.get_sample_sum() - response_sum,
+        post_response.len() as f64
+    );
+
+    // GET 请求
+    let get_query = create_query_bytes("a-much-longer-name-for-get.example.com.");
+    let response = handle_doh_get(
+        State(create_blocking_state()),
+        ConnectInfo(addr),
+        HeaderMap::new(),
+        AxumQuery(DohGetParams {
+            dns: URL_SAFE_NO_PAD.encode(&get_query),
+        }),
+    )
+    .await
+    .into_response();
+    let get_response = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    assert_eq!(request_bytes.get_sample_count() - request_count, 2);
+    assert_eq!(
+        request_bytes.get_sample_sum() - request_sum,
+        (post_query.len() + get_query.len()) as f64
+    );
+    assert_eq!(response_bytes.get_sample_count() - response_count, 2);
+    assert_eq!(
+        response_bytes.get_sample_sum() - response_sum,
+        (post_response.len() + get_response.len()) as f64
+    );
+}
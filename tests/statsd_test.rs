@@ -0,0 +1,92 @@
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hickory_proto::op::{Message, MessageType, Query, ResponseCode};
+use hickory_proto::rr::{Name, RecordType};
+use loadants::{
+    cache::DnsCache,
+    config::{MatchType, RouteAction, RouteRuleConfig},
+    handler::RequestHandler,
+    metrics,
+    router::Router,
+    statsd::StatsdBackend,
+    UpstreamManager,
+};
+
+// 测试安装 StatsD 后端后处理请求会发送 DogStatsD 格式的指标
+#[tokio::test]
+async fn test_statsd_packets_emitted_for_processed_request() {
+    // 捕获 StatsD 报文的 UDP 套接字
+    let capture = UdpSocket::bind("127.0.0.1:0").unwrap();
+    capture
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    let backend = StatsdBackend::new(capture.local_addr().unwrap(), "loadants").unwrap();
+    assert!(metrics::install_backend(Box::new(backend)));
+
+    let router = Arc::new(
+        Router::new(vec![RouteRuleConfig {
+            match_type: MatchType::Exact,
+            patterns: vec!["ads.example.com".to_string()],
+            action: RouteAction::Block,
+            target: None,
+            weighted_targets: Vec::new(),
+            fallback: Vec::new(),
+            fallback_on_nxdomain: false,
+            client_cidrs: Vec::new(),
+            source: None,
+        }])
+        .unwrap(),
+    );
+    let handler = RequestHandler::new(
+        Arc::new(DnsCache::new(0, 0, None)),
+        router,
+        Arc::new(UpstreamManager::empty().unwrap()),
+    );
+
+    let mut request = Message::new();
+    request.set_id(1);
+    request.set_message_type(MessageType::Query);
+    request.add_query(Query::query(
+        Name::from_ascii("ads.example.com.").unwrap(),
+        RecordType::A,
+    ));
+    let response = handler.handle_request(&request).await.unwrap();
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+
+    // 收集报文直到超时
+    let mut packets = Vec::new();
+    let mut buf = [0u8; 1500];
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while Instant::now() < deadline {
+        match capture.recv(&mut buf) {
+            Ok(len) => packets.push(String::from_utf8_lossy(&buf[..len]).to_string()),
+            Err(_) => break,
+        }
+    }
+
+    assert!(
+        packets
+            .iter()
+            .any(|p| p == "loadants.dns_query_type_total:1|c|#type:A"),
+        "packets: {:?}",
+        packets
+    );
+    assert!(
+        packets.iter().any(
+            |p| p.starts_with("loadants.route_matches_total:1|c|#rule_type:exact,")
+                && p.contains("action:block")
+        ),
+        "packets: {:?}",
+        packets
+    );
+    assert!(
+        packets
+            .iter()
+            .any(|p| p.starts_with("loadants.dns_request_duration_seconds:")
+                && p.contains("|ms|#")),
+        "packets: {:?}",
+        packets
+    );
+}
@@ -1,3 +1,4 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use hickory_proto::op::{Message, OpCode, Query, ResponseCode};
 use hickory_proto::rr::rdata::A;
 use hickory_proto::rr::{Name, RData, Record, RecordType};
@@ -7,14 +8,19 @@ use loadants::config::{
     UpstreamGroupConfig, UpstreamScheme, UpstreamServerConfig,
 };
 use loadants::error::AppError;
-use loadants::upstream::UpstreamManager;
+use loadants::metrics::METRICS;
+use loadants::upstream::{ForwardContext, UpstreamManager};
 use reqwest::Url;
-use std::net::{Ipv4Addr, SocketAddr};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::UdpSocket;
 use wiremock::{
     matchers::{header, method, path},
-    Mock, MockServer, ResponseTemplate,
+    Mock, MockServer, Request, Respond, ResponseTemplate,
 };
 
 // 测试DNS消息辅助函数
@@ -131,11 +137,14 @@ async fn test_dns_scheme_forward_via_udp() {
         scheme: UpstreamScheme::Dns,
         strategy: LoadBalancingStrategy::RoundRobin,
         servers: vec![UpstreamServerConfig::Dns(DnsUpstreamServerConfig {
-            addr: SocketAddr::from(server_addr),
+            addr: server_addr,
             weight: 1,
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     let http_config = HttpClientConfig::default();
@@ -197,6 +206,16 @@ async fn test_upstream_manager_creation() {
         idle_timeout: Some(60),
         keepalive: Some(30),
         agent: Some("Test-Agent".to_string()),
+        strict_id_check: false,
+        case_randomization: false,
+        preserve_unknown_records: false,
+        json_fallback: false,
+        forward_deadline: None,
+        bind_address: None,
+        tcp_fastopen: false,
+        http2_prior_knowledge: false,
+        http2_keepalive_interval: None,
+        http2_keepalive_timeout: None,
     };
 
     // 创建上游组配置
@@ -223,6 +242,9 @@ async fn test_upstream_manager_creation() {
             ],
             retry: None,
             proxy: None,
+            affinity_ttl: None,
+            max_connections: None,
+            headers: HashMap::new(),
         },
         UpstreamGroupConfig {
             name: "weighted_group".to_string(),
@@ -246,6 +268,9 @@ async fn test_upstream_manager_creation() {
             ],
             retry: None,
             proxy: None,
+            affinity_ttl: None,
+            max_connections: None,
+            headers: HashMap::new(),
         },
     ];
 
@@ -276,6 +301,9 @@ async fn test_upstream_doh_get_message() {
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 设置mock响应
@@ -345,6 +373,9 @@ async fn test_upstream_doh_post_message() {
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 设置mock响应
@@ -402,6 +433,9 @@ async fn test_upstream_doh_get_json() {
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 设置mock响应 - 匹配任何GET请求到/dns-query
@@ -462,6 +496,9 @@ async fn test_upstream_doh_post_json_fails() {
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 创建上游管理器 - 不应该验证配置，因为这里我们直接创建了不合规的配置
@@ -517,6 +554,9 @@ async fn test_upstream_with_auth() {
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 设置mock响应，验证Bearer认证头
@@ -576,6 +616,9 @@ async fn test_basic_auth() {
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 设置mock响应，验证Basic认证头
@@ -639,6 +682,9 @@ async fn test_load_balancing_round_robin() {
         ],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 设置第一个服务器的mock响应
@@ -708,6 +754,9 @@ async fn test_error_handling() {
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 设置错误响应
@@ -759,6 +808,9 @@ async fn test_retry_config() {
             delay: 1,
         }),
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 创建上游管理器
@@ -798,6 +850,9 @@ async fn test_json_response_parsing() {
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 设置具有多种记录类型的JSON响应，包括Authority和Additional部分
@@ -938,6 +993,9 @@ async fn test_json_error_response() {
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 设置错误响应
@@ -1005,6 +1063,9 @@ async fn test_json_txt_response() {
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 设置TXT记录响应
@@ -1075,6 +1136,9 @@ async fn test_json_edns_client_subnet() {
         })],
         retry: None,
         proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
     }];
 
     // 设置包含edns_client_subnet的响应
@@ -1122,3 +1186,1656 @@ async fn test_json_edns_client_subnet() {
     assert!(dns_response.recursion_desired());
     assert!(dns_response.recursion_available());
 }
+
+// 创建单服务器 DoH 组（GET + Message）
+fn create_message_group(mock_server: &MockServer) -> Vec<UpstreamGroupConfig> {
+    vec![UpstreamGroupConfig {
+        name: "test_group".to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::RoundRobin,
+        servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: Url::parse(&format!("{}/dns-query", mock_server.uri())).unwrap(),
+            weight: 1,
+            method: DoHMethod::Get,
+            content_type: DoHContentType::Message,
+            auth: None,
+        })],
+        retry: None,
+        proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
+    }]
+}
+
+// 挂载返回指定 ID 的 mock 响应
+async fn mount_message_response(mock_server: &MockServer, id: u16) {
+    Mock::given(method("GET"))
+        .and(path("/dns-query"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("Content-Type", "application/dns-message")
+                .set_body_bytes(create_test_dns_response(id)),
+        )
+        .mount(mock_server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_strict_id_check_rejects_mismatched_id() {
+    let mock_server = MockServer::start().await;
+    mount_message_response(&mock_server, 999).await;
+
+    let http_config = HttpClientConfig {
+        strict_id_check: true,
+        ..Default::default()
+    };
+    let manager = UpstreamManager::new(
+        create_message_group(&mock_server),
+        http_config,
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let response = manager.forward(&query, "test_group").await;
+
+    assert!(matches!(
+        response,
+        Err(AppError::UpstreamIdMismatch {
+            expected: 0,
+            actual: 999
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_strict_id_check_rejects_unsent_query_id() {
+    // 出站查询的事务 ID 为 0，回显客户端原始 ID 的响应不是对该查询的应答
+    let mock_server = MockServer::start().await;
+    mount_message_response(&mock_server, 1234).await;
+
+    let http_config = HttpClientConfig {
+        strict_id_check: true,
+        ..Default::default()
+    };
+    let manager = UpstreamManager::new(
+        create_message_group(&mock_server),
+        http_config,
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let response = manager.forward(&query, "test_group").await;
+
+    assert!(matches!(
+        response,
+        Err(AppError::UpstreamIdMismatch {
+            expected: 0,
+            actual: 1234
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_strict_id_check_accepts_zero_id() {
+    let mock_server = MockServer::start().await;
+    mount_message_response(&mock_server, 0).await;
+
+    let http_config = HttpClientConfig {
+        strict_id_check: true,
+        ..Default::default()
+    };
+    let manager = UpstreamManager::new(
+        create_message_group(&mock_server),
+        http_config,
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let response = manager.forward(&query, "test_group").await.unwrap();
+
+    // ID 0 被接受并替换为请求 ID
+    assert_eq!(response.id(), 1234);
+}
+
+#[tokio::test]
+async fn test_mismatched_id_rewritten_without_strict_check() {
+    let mock_server = MockServer::start().await;
+    mount_message_response(&mock_server, 999).await;
+
+    let manager = UpstreamManager::new(
+        create_message_group(&mock_server),
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let response = manager.forward(&query, "test_group").await.unwrap();
+
+    assert_eq!(response.id(), 1234);
+}
+
+#[tokio::test]
+async fn test_outbound_doh_query_uses_zero_id() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(path("/dns-query"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("Content-Type", "application/dns-message")
+                .set_body_bytes(create_test_dns_response(0)),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let url = Url::parse(&format!("{}/dns-query", mock_server.uri())).unwrap();
+    let groups = [DoHMethod::Get, DoHMethod::Post]
+        .into_iter()
+        .enumerate()
+        .map(|(i, doh_method)| UpstreamGroupConfig {
+            name: format!("group_{}", i),
+            scheme: UpstreamScheme::Doh,
+            strategy: LoadBalancingStrategy::RoundRobin,
+            servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+                url: url.clone(),
+                weight: 1,
+                method: doh_method,
+                content_type: DoHContentType::Message,
+                auth: None,
+            })],
+            retry: None,
+            proxy: None,
+            affinity_ttl: None,
+            max_connections: None,
+            headers: HashMap::new(),
+        })
+        .collect();
+
+    let manager = UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    for group in ["group_0", "group_1"] {
+        let response = manager.forward(&query, group).await.unwrap();
+        // 返回给客户端的响应恢复原始 ID
+        assert_eq!(response.id(), 1234);
+    }
+
+    // 出站的 GET 与 POST 报文 ID 均为 0
+    let requests = mock_server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    for request in requests {
+        let wire = match request.url.query_pairs().find(|(k, _)| k == "dns") {
+            Some((_, b64)) => URL_SAFE_NO_PAD.decode(b64.as_bytes()).unwrap(),
+            None => request.body.clone(),
+        };
+        let outbound = Message::from_vec(&wire).unwrap();
+        assert_eq!(outbound.id(), 0);
+        assert_eq!(outbound.queries(), query.queries());
+    }
+}
+
+#[tokio::test]
+async fn test_json_duplicate_records_deduplicated() {
+    let mock_server = MockServer::start().await;
+
+    // 包含重复 A 记录（仅 TTL 不同）的 JSON 响应
+    let json_response = r#"{
+        "Status": 0,
+        "TC": false,
+        "RD": true,
+        "RA": true,
+        "AD": false,
+        "CD": false,
+        "Question": [{"name": "example.com.", "type": 1}],
+        "Answer": [
+            {"name": "example.com.", "type": 1, "TTL": 300, "data": "93.184.216.34"},
+            {"name": "example.com.", "type": 1, "TTL": 300, "data": "93.184.216.34"},
+            {"name": "example.com.", "type": 1, "TTL": 120, "data": "93.184.216.34"},
+            {"name": "example.com.", "type": 1, "TTL": 300, "data": "93.184.216.35"}
+        ],
+        "Authority": [
+            {"name": "example.com.", "type": 2, "TTL": 300, "data": "ns1.example.com."},
+            {"name": "example.com.", "type": 2, "TTL": 300, "data": "ns1.example.com."}
+        ]
+    }"#;
+
+    Mock::given(method("GET"))
+        .and(path("/resolve"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("Content-Type", "application/dns-json")
+                .set_body_string(json_response),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let groups = vec![UpstreamGroupConfig {
+        name: "test_group".to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::RoundRobin,
+        servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: Url::parse(&format!("{}/resolve", mock_server.uri())).unwrap(),
+            weight: 1,
+            method: DoHMethod::Get,
+            content_type: DoHContentType::Json,
+            auth: None,
+        })],
+        retry: None,
+        proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
+    }];
+    let manager = UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let response = manager.forward(&query, "test_group").await.unwrap();
+
+    // 重复记录被移除，保留首次出现的 TTL
+    let answers: Vec<_> = response
+        .answers()
+        .iter()
+        .map(|r| (r.ttl(), r.data().cloned()))
+        .collect();
+    assert_eq!(
+        answers,
+        vec![
+            (300, Some(RData::A(A(Ipv4Addr::new(93, 184, 216, 34))))),
+            (300, Some(RData::A(A(Ipv4Addr::new(93, 184, 216, 35))))),
+        ]
+    );
+    assert_eq!(response.name_servers().len(), 1);
+}
+
+// 创建返回指定 JSON 响应的 JSON 上游管理器
+async fn create_json_manager(mock_server: &MockServer, json_response: &str) -> UpstreamManager {
+    Mock::given(method("GET"))
+        .and(path("/resolve"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("Content-Type", "application/dns-json")
+                .set_body_string(json_response),
+        )
+        .mount(mock_server)
+        .await;
+
+    let groups = vec![UpstreamGroupConfig {
+        name: "json_group".to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::RoundRobin,
+        servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: Url::parse(&format!("{}/resolve", mock_server.uri())).unwrap(),
+            weight: 1,
+            method: DoHMethod::Get,
+            content_type: DoHContentType::Json,
+            auth: None,
+        })],
+        retry: None,
+        proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
+    }];
+    UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_json_error_response_single_question() {
+    let mock_server = MockServer::start().await;
+
+    // 错误响应中的 Question 与查询大小写不同且重复出现
+    let json_response = r#"{
+        "Status": 3,
+        "RD": true,
+        "RA": true,
+        "Question": [
+            {"name": "Missing.Example.com.", "type": 1},
+            {"name": "missing.example.com.", "type": 1}
+        ]
+    }"#;
+    let manager = create_json_manager(&mock_server, json_response).await;
+
+    let query = create_test_dns_query("missing.example.com", RecordType::A);
+    let response = manager.forward(&query, "json_group").await.unwrap();
+
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+    assert_eq!(response.queries(), query.queries());
+}
+
+#[tokio::test]
+async fn test_json_error_response_question_not_duplicated() {
+    let mock_server = MockServer::start().await;
+    let manager = create_json_manager(&mock_server, &create_test_error_json_response()).await;
+
+    let query = create_test_dns_query("dnssec-failed.org", RecordType::A);
+    let response = manager.forward(&query, "json_group").await.unwrap();
+
+    // SERVFAIL 响应中的 Question 只出现一次
+    assert_eq!(response.response_code(), ResponseCode::ServFail);
+    assert_eq!(response.queries().len(), 1);
+    assert_eq!(
+        response.queries()[0].name().to_ascii(),
+        "dnssec-failed.org."
+    );
+    assert_eq!(response.queries()[0].query_type(), RecordType::A);
+}
+
+#[tokio::test]
+async fn test_json_txt_and_srv_parsing() {
+    let mock_server = MockServer::start().await;
+
+    let json_response = r#"{
+        "Status": 0,
+        "Answer": [
+            {"name": "example.com.", "type": 16, "TTL": 300, "data": "\"v=spf1 include:_spf.example.com ~all\""},
+            {"name": "_sip._udp.example.com.", "type": 33, "TTL": 600, "data": "10 60 5060 sip.example.com."}
+        ]
+    }"#;
+    let manager = create_json_manager(&mock_server, json_response).await;
+
+    let query = create_test_dns_query("example.com", RecordType::ANY);
+    let response = manager.forward(&query, "json_group").await.unwrap();
+    let answers = response.answers();
+    assert_eq!(answers.len(), 2);
+
+    // 外层引号被去除，内容保持不变
+    match answers[0].data() {
+        Some(RData::TXT(txt)) => {
+            assert_eq!(txt.txt_data().len(), 1);
+            assert_eq!(
+                txt.txt_data()[0].as_ref(),
+                b"v=spf1 include:_spf.example.com ~all"
+            );
+        }
+        other => panic!("unexpected TXT data: {:?}", other),
+    }
+
+    // SRV 记录按 priority/weight/port/target 解析
+    match answers[1].data() {
+        Some(RData::SRV(srv)) => {
+            assert_eq!(srv.priority(), 10);
+            assert_eq!(srv.weight(), 60);
+            assert_eq!(srv.port(), 5060);
+            assert_eq!(srv.target().to_ascii(), "sip.example.com.");
+            assert_eq!(answers[1].ttl(), 600);
+        }
+        other => panic!("unexpected SRV data: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_json_multi_string_txt_preserves_boundaries() {
+    let mock_server = MockServer::start().await;
+
+    // 拆分为两个字符串的 DKIM 记录，以及一个超过 255 字节的未加引号字符串
+    let long_value = "a".repeat(300);
+    let json_response = format!(
+        r#"{{
+        "Status": 0,
+        "Answer": [
+            {{"name": "sel._domainkey.example.com.", "type": 16, "TTL": 300, "data": "\"v=DKIM1; k=rsa; p=MIIBIjANBgkqh\" \"kiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEA\""}},
+            {{"name": "sel._domainkey.example.com.", "type": 16, "TTL": 300, "data": "{}"}}
+        ]
+    }}"#,
+        long_value
+    );
+    let manager = create_json_manager(&mock_server, &json_response).await;
+
+    let query = create_test_dns_query("sel._domainkey.example.com", RecordType::TXT);
+    let response = manager.forward(&query, "json_group").await.unwrap();
+    let answers = response.answers();
+    assert_eq!(answers.len(), 2);
+
+    match answers[0].data() {
+        Some(RData::TXT(txt)) => {
+            let strings: Vec<&[u8]> = txt.txt_data().iter().map(|s| s.as_ref()).collect();
+            assert_eq!(
+                strings,
+                vec![
+                    b"v=DKIM1; k=rsa; p=MIIBIjANBgkqh".as_ref(),
+                    b"kiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEA".as_ref(),
+                ]
+            );
+        }
+        other => panic!("unexpected TXT data: {:?}", other),
+    }
+
+    match answers[1].data() {
+        Some(RData::TXT(txt)) => {
+            let lengths: Vec<usize> = txt.txt_data().iter().map(|s| s.len()).collect();
+            assert_eq!(lengths, vec![255, 45]);
+        }
+        other => panic!("unexpected TXT data: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_forward_deadline_abandons_retries() {
+    let mock_server = MockServer::start().await;
+
+    // 每次请求都超过单次请求超时，3 次重试总耗时远超截止时间
+    Mock::given(method("GET"))
+        .and(path("/dns-query"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("Content-Type", "application/dns-message")
+                .set_body_bytes(create_test_dns_response(1234))
+                .set_delay(std::time::Duration::from_secs(3)),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut groups = create_message_group(&mock_server);
+    groups[0].retry = Some(RetryConfig {
+        attempts: 3,
+        delay: 1,
+    });
+    let http_config = HttpClientConfig {
+        request_timeout: 1,
+        forward_deadline: Some(1),
+        ..Default::default()
+    };
+    let manager = UpstreamManager::new(groups, http_config, DnsClientConfig::default())
+        .await
+        .unwrap();
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let start = std::time::Instant::now();
+    let result = manager.forward(&query, "test_group").await;
+
+    assert!(matches!(result, Err(AppError::Timeout)));
+    assert!(start.elapsed() < std::time::Duration::from_millis(1900));
+}
+
+#[tokio::test]
+async fn test_group_permit_wait_bounded_by_forward_deadline() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/dns-query"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("Content-Type", "application/dns-message")
+                .set_body_bytes(create_test_dns_response(1234))
+                .set_delay(std::time::Duration::from_secs(3)),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut groups = create_message_group(&mock_server);
+    groups[0].name = "permit_group".to_string();
+    groups[0].max_connections = Some(1);
+    let http_config = HttpClientConfig {
+        request_timeout: 5,
+        forward_deadline: Some(1),
+        ..Default::default()
+    };
+    let manager = UpstreamManager::new(groups, http_config, DnsClientConfig::default())
+        .await
+        .unwrap();
+
+    // 第二个请求等待许可的时间计入截止时间，不会在第一个请求超时后再等待一个完整的截止时间
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let start = std::time::Instant::now();
+    let (first, second) = tokio::join!(
+        manager.forward(&query, "permit_group"),
+        manager.forward(&query, "permit_group")
+    );
+
+    assert!(matches!(first, Err(AppError::Timeout)));
+    assert!(matches!(second, Err(AppError::Timeout)));
+    assert!(start.elapsed() < std::time::Duration::from_millis(1900));
+}
+
+#[tokio::test]
+async fn test_upstream_retries_counted() {
+    let mock_server = MockServer::start().await;
+
+    // 前两次请求返回 503，之后返回正常响应
+    Mock::given(method("GET"))
+        .and(path("/dns-query"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(2)
+        .mount(&mock_server)
+        .await;
+    mount_message_response(&mock_server, 1234).await;
+
+    let mut groups = create_message_group(&mock_server);
+    groups[0].name = "retry_metric_group".to_string();
+    groups[0].retry = Some(RetryConfig {
+        attempts: 3,
+        delay: 1,
+    });
+    let manager = UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let retries = METRICS
+        .upstream_retries_total()
+        .with_label_values(&["retry_metric_group", "127.0.0.1"]);
+    let before = retries.get();
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let response = manager.forward(&query, "retry_metric_group").await;
+
+    assert!(response.is_ok());
+    assert_eq!(retries.get() - before, 2);
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_retry_reselects_different_server() {
+    // 第一个服务器总是失败，第二个服务器正常响应
+    let failing_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/dns-query"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&failing_server)
+        .await;
+    let healthy_server = MockServer::start().await;
+    mount_message_response(&healthy_server, 1234).await;
+
+    let mut groups = create_message_group(&failing_server);
+    let mut healthy = groups[0].servers[0].clone();
+    if let UpstreamServerConfig::Doh(server) = &mut healthy {
+        server.url = Url::parse(&format!("{}/dns-query", healthy_server.uri())).unwrap();
+    }
+    groups[0].servers.push(healthy);
+    groups[0].retry = Some(RetryConfig {
+        attempts: 1,
+        delay: 1,
+    });
+    let manager = UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let response = manager.forward(&query, "test_group").await;
+
+    // 轮询先选中失败的服务器，重试落在第二个服务器上
+    assert!(response.is_ok());
+    assert_eq!(failing_server.received_requests().await.unwrap().len(), 1);
+    assert_eq!(healthy_server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_bind_address_applied_to_upstream_client() {
+    let mock_server = MockServer::start().await;
+    mount_message_response(&mock_server, 1234).await;
+
+    // 从回环地址发起连接可以正常访问监听在回环地址上的上游
+    let http_config = HttpClientConfig {
+        bind_address: Some("127.0.0.1".parse().unwrap()),
+        ..Default::default()
+    };
+    let manager = UpstreamManager::new(
+        create_message_group(&mock_server),
+        http_config,
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    let query = create_test_dns_query("example.com", RecordType::A);
+    assert!(manager.forward(&query, "test_group").await.is_ok());
+
+    // 本机不支持 IPv6 回环地址时跳过地址族不匹配的检查
+    if std::net::UdpSocket::bind("[::1]:0").is_err() {
+        return;
+    }
+
+    // 源地址为 IPv6 时无法连接 IPv4 上游，说明源地址确实作用于客户端
+    let http_config = HttpClientConfig {
+        bind_address: Some("::1".parse().unwrap()),
+        ..Default::default()
+    };
+    let manager = UpstreamManager::new(
+        create_message_group(&mock_server),
+        http_config,
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    assert!(manager.forward(&query, "test_group").await.is_err());
+}
+
+// 测试启用 tcp_fastopen 时配置被接受且上游客户端可以正常创建并转发
+#[tokio::test]
+async fn test_tcp_fastopen_flag_accepted() {
+    let mock_server = MockServer::start().await;
+    mount_message_response(&mock_server, 1234).await;
+
+    let http_config = HttpClientConfig {
+        tcp_fastopen: true,
+        ..Default::default()
+    };
+    let manager = UpstreamManager::new(
+        create_message_group(&mock_server),
+        http_config,
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    let query = create_test_dns_query("example.com", RecordType::A);
+    assert!(manager.forward(&query, "test_group").await.is_ok());
+}
+
+// 挂载 sticky 测试用的上游响应，failing 路径返回 500
+async fn mount_sticky_responses(mock_server: &MockServer, paths: &[String], failing: Option<&str>) {
+    mock_server.reset().await;
+    for server_path in paths {
+        let template = if Some(server_path.as_str()) == failing {
+            ResponseTemplate::new(500)
+        } else {
+            ResponseTemplate::new(200)
+                .append_header("Content-Type", "application/dns-message")
+                .set_body_bytes(create_test_dns_response(1234))
+        };
+        Mock::given(method("GET"))
+            .and(path(server_path.as_str()))
+            .respond_with(template)
+            .mount(mock_server)
+            .await;
+    }
+}
+
+// 返回收到的请求路径
+async fn received_paths(mock_server: &MockServer) -> Vec<String> {
+    mock_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| request.url.path().to_string())
+        .collect()
+}
+
+// 测试 sticky 策略下同一客户端固定使用同一服务器，直到该服务器失败
+#[tokio::test]
+async fn test_sticky_balancer_pins_client_until_unhealthy() {
+    let mock_server = MockServer::start().await;
+    let paths: Vec<String> = (1..=3).map(|i| format!("/sticky{}", i)).collect();
+    let groups = vec![UpstreamGroupConfig {
+        name: "sticky_group".to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::Sticky,
+        servers: paths
+            .iter()
+            .map(|server_path| {
+                UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+                    url: Url::parse(&format!("{}{}", mock_server.uri(), server_path)).unwrap(),
+                    weight: 1,
+                    method: DoHMethod::Get,
+                    content_type: DoHContentType::Message,
+                    auth: None,
+                })
+            })
+            .collect(),
+        retry: None,
+        proxy: None,
+        affinity_ttl: Some(300),
+        max_connections: None,
+        headers: HashMap::new(),
+    }];
+    mount_sticky_responses(&mock_server, &paths, None).await;
+
+    let manager = UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let client = ForwardContext {
+        client: Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 10))),
+        traceparent: None,
+    };
+    let other_client = ForwardContext {
+        client: Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 20))),
+        traceparent: None,
+    };
+
+    // 同一客户端的多次查询（中间穿插其他客户端）命中同一服务器
+    for _ in 0..3 {
+        manager
+            .forward_with_context(&query, "sticky_group", client)
+            .await
+            .unwrap();
+        manager
+            .forward_with_context(&query, "sticky_group", other_client)
+            .await
+            .unwrap();
+    }
+    let received = received_paths(&mock_server).await;
+    let pinned = received[0].clone();
+    assert!(received.iter().step_by(2).all(|p| *p == pinned));
+    assert!(received.iter().skip(1).step_by(2).all(|p| *p != pinned));
+
+    // 绑定的服务器失败后被标记为不健康，客户端切换到另一服务器并保持
+    mount_sticky_responses(&mock_server, &paths, Some(&pinned)).await;
+    assert!(manager
+        .forward_with_context(&query, "sticky_group", client)
+        .await
+        .is_err());
+    for _ in 0..3 {
+        manager
+            .forward_with_context(&query, "sticky_group", client)
+            .await
+            .unwrap();
+    }
+    let received = received_paths(&mock_server).await;
+    assert_eq!(received[0], pinned);
+    let repinned = received[1].clone();
+    assert_ne!(repinned, pinned);
+    assert!(received[1..].iter().all(|p| *p == repinned));
+}
+
+// 挂载返回指定原始报文的 mock 响应
+async fn mount_raw_response(mock_server: &MockServer, body: Vec<u8>) {
+    Mock::given(method("GET"))
+        .and(path("/dns-query"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("Content-Type", "application/dns-message")
+                .set_body_bytes(body),
+        )
+        .mount(mock_server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_malformed_upstream_response_rejected() {
+    // 应答记录名称原本是指向问题名称（偏移 12）的压缩指针，改为指向自身形成指针环
+    let mut looped = create_test_dns_response(1234);
+    assert_eq!(&looped[29..31], &[0xC0, 0x0C]);
+    looped[30] = 29;
+
+    // 报文末尾附加多余数据
+    let mut trailing = create_test_dns_response(1234);
+    trailing.extend_from_slice(&[0xDE, 0xAD]);
+
+    for (group_name, body) in [
+        ("malformed_loop_group", looped),
+        ("malformed_trailing_group", trailing),
+    ] {
+        let mock_server = MockServer::start().await;
+        mount_raw_response(&mock_server, body).await;
+
+        let mut groups = create_message_group(&mock_server);
+        groups[0].name = group_name.to_string();
+        let manager = UpstreamManager::new(
+            groups,
+            HttpClientConfig::default(),
+            DnsClientConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let errors = METRICS.upstream_errors_total().with_label_values(&[
+            "doh",
+            "http",
+            "malformed_response",
+            group_name,
+            "127.0.0.1",
+        ]);
+        let before = errors.get();
+
+        let query = create_test_dns_query("example.com", RecordType::A);
+        let response = manager.forward(&query, group_name).await;
+
+        assert!(
+            matches!(response, Err(AppError::MalformedUpstreamResponse(_))),
+            "{}: {:?}",
+            group_name,
+            response
+        );
+        assert_eq!(errors.get() - before, 1);
+    }
+
+    // 正常报文不受影响
+    let mock_server = MockServer::start().await;
+    mount_raw_response(&mock_server, create_test_dns_response(1234)).await;
+    let manager = UpstreamManager::new(
+        create_message_group(&mock_server),
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    let query = create_test_dns_query("example.com", RecordType::A);
+    assert!(manager.forward(&query, "test_group").await.is_ok());
+}
+
+// 计算 gzip 尾部所需的 CRC32（IEEE）
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+// 构造使用存储块（不压缩）的 gzip 数据流
+fn gzip_stored(data: &[u8]) -> Vec<u8> {
+    let len = data.len() as u16;
+    let mut out = vec![0x1f, 0x8b, 0x08, 0x00, 0, 0, 0, 0, 0x00, 0xff];
+    // 最后一个块，类型为存储块
+    out.push(0x01);
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(&(!len).to_le_bytes());
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+#[tokio::test]
+async fn test_json_gzip_response() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/resolve"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("Content-Type", "application/dns-json")
+                .append_header("Content-Encoding", "gzip")
+                .set_body_bytes(gzip_stored(create_test_json_response().as_bytes())),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let groups = vec![UpstreamGroupConfig {
+        name: "test_group".to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::RoundRobin,
+        servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: Url::parse(&format!("{}/resolve", mock_server.uri())).unwrap(),
+            weight: 1,
+            method: DoHMethod::Get,
+            content_type: DoHContentType::Json,
+            auth: None,
+        })],
+        retry: None,
+        proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
+    }];
+    let manager = UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let response = manager.forward(&query, "test_group").await.unwrap();
+
+    // 响应体被自动解压后解析
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(!response.answers().is_empty());
+
+    // JSON 请求声明支持压缩
+    let requests = mock_server.received_requests().await.unwrap();
+    let accept_encoding = requests[0]
+        .headers
+        .get("accept-encoding")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    assert!(accept_encoding.contains("gzip"));
+    assert!(accept_encoding.contains("br"));
+}
+
+// 回显查询问题部分的 mock 响应器，lowercase 为 true 时将问题名称改为小写
+struct EchoQuestion {
+    lowercase: bool,
+    drop_question: bool,
+}
+
+impl Respond for EchoQuestion {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let (_, b64) = request.url.query_pairs().find(|(k, _)| k == "dns").unwrap();
+        let query = Message::from_vec(&URL_SAFE_NO_PAD.decode(b64.as_bytes()).unwrap()).unwrap();
+        let mut response = query.clone();
+        response.set_message_type(hickory_proto::op::MessageType::Response);
+        if self.lowercase {
+            for q in response.queries_mut() {
+                q.set_name(q.name().to_lowercase());
+            }
+        }
+        if self.drop_question {
+            response.queries_mut().clear();
+        }
+        ResponseTemplate::new(200)
+            .append_header("Content-Type", "application/dns-message")
+            .set_body_bytes(response.to_vec().unwrap())
+    }
+}
+
+#[tokio::test]
+async fn test_case_randomization() {
+    let http_config = HttpClientConfig {
+        case_randomization: true,
+        ..Default::default()
+    };
+    let name = "abcdefghijklmnopqrstuvwxyzabcdefghijklmnop.example.com";
+    let query = create_test_dns_query(name, RecordType::A);
+
+    // 上游收到随机大小写的名称，回显一致时返回原始大小写的问题部分
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/dns-query"))
+        .respond_with(EchoQuestion {
+            lowercase: false,
+            drop_question: false,
+        })
+        .mount(&mock_server)
+        .await;
+    let manager = UpstreamManager::new(
+        create_message_group(&mock_server),
+        http_config.clone(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    let response = manager.forward(&query, "test_group").await.unwrap();
+    assert_eq!(
+        response.queries()[0].name().to_ascii(),
+        format!("{}.", name)
+    );
+
+    let requests = mock_server.received_requests().await.unwrap();
+    let (_, b64) = requests[0]
+        .url
+        .query_pairs()
+        .find(|(k, _)| k == "dns")
+        .unwrap();
+    let sent = Message::from_vec(&URL_SAFE_NO_PAD.decode(b64.as_bytes()).unwrap()).unwrap();
+    let sent_name = sent.queries()[0].name().to_ascii();
+    assert_eq!(sent_name.to_lowercase(), format!("{}.", name));
+    assert_ne!(sent_name, format!("{}.", name));
+
+    // 回显大小写不一致的响应被拒绝
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/dns-query"))
+        .respond_with(EchoQuestion {
+            lowercase: true,
+            drop_question: false,
+        })
+        .mount(&mock_server)
+        .await;
+    let manager = UpstreamManager::new(
+        create_message_group(&mock_server),
+        http_config.clone(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    let response = manager.forward(&query, "test_group").await;
+    assert!(matches!(
+        response,
+        Err(AppError::UpstreamCaseMismatch { .. })
+    ));
+
+    // 问题部分为空的响应无法校验大小写，同样被拒绝
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/dns-query"))
+        .respond_with(EchoQuestion {
+            lowercase: false,
+            drop_question: true,
+        })
+        .mount(&mock_server)
+        .await;
+    let manager = UpstreamManager::new(
+        create_message_group(&mock_server),
+        http_config,
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    let response = manager.forward(&query, "test_group").await;
+    assert!(matches!(
+        response,
+        Err(AppError::MalformedUpstreamResponse(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_json_unsupported_record_types() {
+    // 包含 HTTPS（标准文本格式）与私有类型 65400（RFC 3597 通用格式）的应答
+    let json_response = r#"{
+        "Status": 0,
+        "TC": false,
+        "RD": true,
+        "RA": true,
+        "Question": [{"name": "example.com.", "type": 65}],
+        "Answer": [
+            {"name": "example.com.", "type": 65, "TTL": 300, "data": "1 . alpn=h2"},
+            {"name": "example.com.", "type": 65400, "TTL": 300, "data": "\\# 4 c0000201"}
+        ]
+    }"#;
+    let query = create_test_dns_query("example.com", RecordType::HTTPS);
+
+    let mut managers = Vec::new();
+    for preserve_unknown_records in [false, true] {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/resolve"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .append_header("Content-Type", "application/dns-json")
+                    .set_body_string(json_response),
+            )
+            .mount(&mock_server)
+            .await;
+        let groups = vec![UpstreamGroupConfig {
+            name: "json_group".to_string(),
+            scheme: UpstreamScheme::Doh,
+            strategy: LoadBalancingStrategy::RoundRobin,
+            servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+                url: Url::parse(&format!("{}/resolve", mock_server.uri())).unwrap(),
+                weight: 1,
+                method: DoHMethod::Get,
+                content_type: DoHContentType::Json,
+                auth: None,
+            })],
+            retry: None,
+            proxy: None,
+            affinity_ttl: None,
+            max_connections: None,
+            headers: HashMap::new(),
+        }];
+        let http_config = HttpClientConfig {
+            preserve_unknown_records,
+            ..Default::default()
+        };
+        let manager = UpstreamManager::new(groups, http_config, DnsClientConfig::default())
+            .await
+            .unwrap();
+        managers.push((mock_server, manager));
+    }
+
+    // 默认丢弃并按类型计数
+    let dropped = |record_type: &str| {
+        METRICS
+            .upstream_dropped_records_total()
+            .with_label_values(&[record_type])
+            .get()
+    };
+    let (https_before, private_before) = (dropped("HTTPS"), dropped("TYPE65400"));
+    let response = managers[0].1.forward(&query, "json_group").await.unwrap();
+    assert!(response.answers().is_empty());
+    assert_eq!(dropped("HTTPS"), https_before + 1);
+    assert_eq!(dropped("TYPE65400"), private_before + 1);
+
+    // 开启后保留为可编码的记录
+    let response = managers[1].1.forward(&query, "json_group").await.unwrap();
+    let types: Vec<_> = response.answers().iter().map(|r| r.record_type()).collect();
+    assert_eq!(types, vec![RecordType::HTTPS, RecordType::Unknown(65400)]);
+    let decoded = Message::from_vec(&response.to_vec().unwrap()).unwrap();
+    assert_eq!(decoded.answers().len(), 2);
+    assert!(matches!(
+        decoded.answers()[1].data(),
+        Some(RData::Unknown { rdata, .. }) if rdata.anything() == [192, 0, 2, 1]
+    ));
+}
+
+// 收集日志输出的写入器
+#[derive(Clone, Default)]
+struct CapturedLogs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl CapturedLogs {
+    fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+#[tokio::test]
+async fn test_debug_logging_redacts_request() {
+    let mock_server = MockServer::start().await;
+
+    let groups = vec![UpstreamGroupConfig {
+        name: "test_group".to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::RoundRobin,
+        servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: Url::parse(&format!(
+                "{}/dns-query?api_key=secret-key",
+                mock_server.uri()
+            ))
+            .unwrap(),
+            weight: 1,
+            method: DoHMethod::Post,
+            content_type: DoHContentType::Message,
+            auth: Some(AuthConfig {
+                r#type: AuthType::Bearer,
+                username: None,
+                password: None,
+                token: Some("secret-token".to_string()),
+            }),
+        })],
+        retry: None,
+        proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::new(),
+    }];
+
+    Mock::given(method("POST"))
+        .and(path("/dns-query"))
+        .respond_with(ResponseTemplate::new(503).set_body_string("upstream overloaded"))
+        .mount(&mock_server)
+        .await;
+
+    let manager = UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    let query = create_test_dns_query("example.com", RecordType::A);
+
+    // 在当前线程上捕获 DEBUG 级别日志
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    let guard = tracing::subscriber::set_default(subscriber);
+    let response = manager.forward(&query, "test_group").await;
+    drop(guard);
+
+    assert!(response.is_err());
+    let output = logs.contents();
+    assert!(
+        output.contains("Sending upstream request: POST"),
+        "{}",
+        output
+    );
+    assert!(output.contains("/dns-query?api_key=***"), "{}", output);
+    assert!(output.contains("authorization: ***"), "{}", output);
+    assert!(!output.contains("secret-key"), "{}", output);
+    assert!(!output.contains("secret-token"), "{}", output);
+    assert!(output.contains("status 503"), "{}", output);
+    assert!(output.contains("upstream overloaded"), "{}", output);
+}
+
+#[tokio::test]
+async fn test_json_fallback_to_binary() {
+    let query = create_test_dns_query("example.com", RecordType::A);
+
+    for json_fallback in [false, true] {
+        let mock_server = MockServer::start().await;
+
+        // JSON 请求返回 415，二进制请求正常应答
+        Mock::given(method("GET"))
+            .and(path("/dns-query"))
+            .and(header("Accept", "application/dns-json"))
+            .respond_with(ResponseTemplate::new(415))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/dns-query"))
+            .and(header("Accept", "application/dns-message"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .append_header("Content-Type", "application/dns-message")
+                    .set_body_bytes(create_test_dns_response(1234)),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let groups = vec![UpstreamGroupConfig {
+            name: "json_group".to_string(),
+            scheme: UpstreamScheme::Doh,
+            strategy: LoadBalancingStrategy::RoundRobin,
+            servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+                url: Url::parse(&format!("{}/dns-query", mock_server.uri())).unwrap(),
+                weight: 1,
+                method: DoHMethod::Get,
+                content_type: DoHContentType::Json,
+                auth: None,
+            })],
+            retry: None,
+            proxy: None,
+            affinity_ttl: None,
+            max_connections: None,
+            headers: HashMap::new(),
+        }];
+        let http_config = HttpClientConfig {
+            json_fallback,
+            ..Default::default()
+        };
+        let manager = UpstreamManager::new(groups, http_config, DnsClientConfig::default())
+            .await
+            .unwrap();
+
+        let response = manager.forward(&query, "json_group").await;
+        let binary_requests = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.url.query().is_some_and(|q| q.contains("dns=")))
+            .count();
+
+        if json_fallback {
+            let response = response.unwrap();
+            assert_eq!(response.id(), query.id());
+            assert_eq!(response.answers().len(), 1);
+            assert_eq!(binary_requests, 1);
+        } else {
+            assert!(matches!(
+                response,
+                Err(AppError::UpstreamStatus(status)) if status.as_u16() == 415
+            ));
+            assert_eq!(binary_requests, 0);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_group_custom_headers() {
+    let mock_server = MockServer::start().await;
+
+    // 仅在携带自定义请求头（且 Accept 被覆盖）时应答
+    Mock::given(method("GET"))
+        .and(path("/dns-query"))
+        .and(header("X-Api-Key", "secret-key"))
+        .and(header("Accept", "application/dns-message; profile=custom"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("Content-Type", "application/dns-message")
+                .set_body_bytes(create_test_dns_response(1234)),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let groups = vec![UpstreamGroupConfig {
+        name: "header_group".to_string(),
+        scheme: UpstreamScheme::Doh,
+        strategy: LoadBalancingStrategy::RoundRobin,
+        servers: vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: Url::parse(&format!("{}/dns-query", mock_server.uri())).unwrap(),
+            weight: 1,
+            method: DoHMethod::Get,
+            content_type: DoHContentType::Message,
+            auth: None,
+        })],
+        retry: None,
+        proxy: None,
+        affinity_ttl: None,
+        max_connections: None,
+        headers: HashMap::from([
+            ("X-Api-Key".to_string(), "secret-key".to_string()),
+            (
+                "Accept".to_string(),
+                "application/dns-message; profile=custom".to_string(),
+            ),
+        ]),
+    }];
+
+    let manager = UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let response = manager.forward(&query, "header_group").await;
+    assert!(response.is_ok(), "{:?}", response.err());
+
+    // 覆盖的请求头只出现一次
+    let requests = mock_server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].headers.get_all("accept").iter().count(), 1);
+}
+
+#[tokio::test]
+async fn test_weighted_selections_follow_weights() {
+    let mock_server = MockServer::start().await;
+    mount_message_response(&mock_server, 1234).await;
+
+    // 两个服务器指向同一 mock 服务器，通过不同主机名区分指标标签
+    let port = mock_server.address().port();
+    let server = |host: &str, weight: u32| {
+        UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+            url: Url::parse(&format!("http://{}:{}/dns-query", host, port)).unwrap(),
+            weight,
+            method: DoHMethod::Get,
+            content_type: DoHContentType::Message,
+            auth: None,
+        })
+    };
+    let mut groups = create_message_group(&mock_server);
+    groups[0].name = "selection_metric_group".to_string();
+    groups[0].strategy = LoadBalancingStrategy::Weighted;
+    groups[0].servers = vec![server("127.0.0.1", 3), server("localhost", 1)];
+    let manager = UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let selections = |host: &str| {
+        METRICS
+            .upstream_selections_total()
+            .with_label_values(&["selection_metric_group", host, "weighted"])
+            .get()
+    };
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    for _ in 0..40 {
+        // 选择结果与请求是否成功无关，均计入选择指标
+        let _ = manager.forward(&query, "selection_metric_group").await;
+    }
+
+    assert_eq!(selections("127.0.0.1"), 30);
+    assert_eq!(selections("localhost"), 10);
+}
+
+#[tokio::test]
+async fn test_group_max_connections_queues_excess_requests() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/dns-query"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("Content-Type", "application/dns-message")
+                .set_body_bytes(create_test_dns_response(1234))
+                .set_delay(std::time::Duration::from_millis(300)),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut groups = create_message_group(&mock_server);
+    groups[0].max_connections = Some(2);
+    let manager = std::sync::Arc::new(
+        UpstreamManager::new(
+            groups,
+            HttpClientConfig::default(),
+            DnsClientConfig::default(),
+        )
+        .await
+        .unwrap(),
+    );
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    let tasks: Vec<_> = (0..4)
+        .map(|_| {
+            let manager = manager.clone();
+            let query = query.clone();
+            tokio::spawn(async move { manager.forward(&query, "test_group").await })
+        })
+        .collect();
+
+    // 上限为 2 时只有两个请求同时到达上游，其余请求排队
+    tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 2);
+
+    for task in tasks {
+        assert!(task.await.unwrap().is_ok());
+    }
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn test_group_init_failure_marks_only_that_group_unavailable() {
+    let mock_server = MockServer::start().await;
+    mount_message_response(&mock_server, 1234).await;
+
+    let mut groups = create_message_group(&mock_server);
+    let mut bad_group = groups[0].clone();
+    bad_group.name = "bad_proxy_group".to_string();
+    bad_group.proxy = Some("not a valid proxy url".to_string());
+    groups.push(bad_group);
+
+    let manager = UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await
+    .expect("a failing group must not abort manager creation");
+    let query = create_test_dns_query("example.com", RecordType::A);
+
+    let response = manager.forward(&query, "test_group").await.unwrap();
+    assert_eq!(response.answers().len(), 1);
+    assert!(manager.unavailable_reason("test_group").is_none());
+
+    assert!(manager.unavailable_reason("bad_proxy_group").is_some());
+    let errors_before = METRICS
+        .upstream_errors_total()
+        .with_label_values(&[
+            "unknown",
+            "unknown",
+            "group_unavailable",
+            "bad_proxy_group",
+            "unknown",
+        ])
+        .get();
+    let err = manager
+        .forward(&query, "bad_proxy_group")
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, AppError::UpstreamGroupUnavailable(ref group) if group == "bad_proxy_group")
+    );
+    let errors_after = METRICS
+        .upstream_errors_total()
+        .with_label_values(&[
+            "unknown",
+            "unknown",
+            "group_unavailable",
+            "bad_proxy_group",
+            "unknown",
+        ])
+        .get();
+    assert_eq!(errors_after, errors_before + 1);
+}
+
+#[tokio::test]
+async fn test_all_groups_init_failure_is_an_error() {
+    let mock_server = MockServer::start().await;
+    let mut groups = create_message_group(&mock_server);
+    groups[0].proxy = Some("not a valid proxy url".to_string());
+
+    // 没有任何可用的上游组时创建管理器失败，而不是仅记录警告
+    let result = UpstreamManager::new(
+        groups,
+        HttpClientConfig::default(),
+        DnsClientConfig::default(),
+    )
+    .await;
+    assert!(matches!(result, Err(AppError::Upstream(ref reason)) if reason.contains("test_group")));
+}
+
+// 启动解析客户端 HTTP/2 帧的 TCP 转发代理，统计已接受连接数与客户端发出的 PING 帧数
+async fn start_h2_ping_proxy(
+    target: std::net::SocketAddr,
+) -> (std::net::SocketAddr, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let pings = Arc::new(AtomicUsize::new(0));
+    let (connection_counter, ping_counter) = (connections.clone(), pings.clone());
+    tokio::spawn(async move {
+        while let Ok((inbound, _)) = listener.accept().await {
+            connection_counter.fetch_add(1, Ordering::SeqCst);
+            let ping_counter = ping_counter.clone();
+            tokio::spawn(async move {
+                let outbound = tokio::net::TcpStream::connect(target).await.unwrap();
+                let (mut client_read, mut client_write) = inbound.into_split();
+                let (mut server_read, mut server_write) = outbound.into_split();
+                tokio::spawn(async move {
+                    let _ = tokio::io::copy(&mut server_read, &mut client_write).await;
+                });
+
+                // 跳过 24 字节的连接前言后按 9 字节帧头逐帧解析
+                let mut pending = Vec::new();
+                let mut preface_skipped = false;
+                let mut chunk = [0u8; 4096];
+                loop {
+                    let n = match client_read.read(&mut chunk).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => n,
+                    };
+                    if server_write.write_all(&chunk[..n]).await.is_err() {
+                        break;
+                    }
+                    pending.extend_from_slice(&chunk[..n]);
+                    if !preface_skipped {
+                        if pending.len() < 24 {
+                            continue;
+                        }
+                        pending.drain(..24);
+                        preface_skipped = true;
+                    }
+                    while pending.len() >= 9 {
+                        let len = u32::from_be_bytes([0, pending[0], pending[1], pending[2]]);
+                        let frame_len = 9 + len as usize;
+                        if pending.len() < frame_len {
+                            break;
+                        }
+                        // PING 帧（类型 0x6），不含 ACK 标志
+                        if pending[3] == 0x6 && pending[4] & 0x1 == 0 {
+                            ping_counter.fetch_add(1, Ordering::SeqCst);
+                        }
+                        pending.drain(..frame_len);
+                    }
+                }
+            });
+        }
+    });
+    (addr, connections, pings)
+}
+
+// 创建经由代理访问 mock 服务器的 h2c 上游管理器
+async fn create_h2c_manager(
+    mock_server: &MockServer,
+    http_config: HttpClientConfig,
+) -> (UpstreamManager, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+    mount_message_response(mock_server, 1234).await;
+    let (proxy_addr, connections, pings) = start_h2_ping_proxy(*mock_server.address()).await;
+
+    let mut groups = create_message_group(mock_server);
+    groups[0].servers = vec![UpstreamServerConfig::Doh(DoHUpstreamServerConfig {
+        url: Url::parse(&format!("http://{}/dns-query", proxy_addr)).unwrap(),
+        weight: 1,
+        method: DoHMethod::Get,
+        content_type: DoHContentType::Message,
+        auth: None,
+    })];
+    let http_config = HttpClientConfig {
+        http2_prior_knowledge: true,
+        ..http_config
+    };
+    let manager = UpstreamManager::new(groups, http_config, DnsClientConfig::default())
+        .await
+        .unwrap();
+    (manager, connections, pings)
+}
+
+#[tokio::test]
+async fn test_http2_keepalive_sends_pings_on_idle_connection() {
+    let query = create_test_dns_query("example.com", RecordType::A);
+
+    // 未配置时空闲连接上不发送 PING
+    let mock_server = MockServer::start().await;
+    let (manager, _, pings) = create_h2c_manager(&mock_server, HttpClientConfig::default()).await;
+    assert!(manager.forward(&query, "test_group").await.is_ok());
+    tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+    assert_eq!(pings.load(Ordering::SeqCst), 0);
+
+    // 配置后空闲连接按间隔发送 PING
+    let mock_server = MockServer::start().await;
+    let http_config = HttpClientConfig {
+        http2_keepalive_interval: Some(1),
+        http2_keepalive_timeout: Some(5),
+        ..Default::default()
+    };
+    let (manager, _, pings) = create_h2c_manager(&mock_server, http_config).await;
+    assert!(manager.forward(&query, "test_group").await.is_ok());
+    tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+    assert!(pings.load(Ordering::SeqCst) >= 1);
+}
+
+#[tokio::test]
+async fn test_http2_keepalive_reuses_idle_connection() {
+    let mock_server = MockServer::start().await;
+    let http_config = HttpClientConfig {
+        http2_keepalive_interval: Some(1),
+        http2_keepalive_timeout: Some(5),
+        ..Default::default()
+    };
+    let (manager, connections, _) = create_h2c_manager(&mock_server, http_config).await;
+
+    let query = create_test_dns_query("example.com", RecordType::A);
+    assert!(manager.forward(&query, "test_group").await.is_ok());
+
+    // 长时间空闲后再次查询，复用原连接并快速返回
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    let start = std::time::Instant::now();
+    assert!(manager.forward(&query, "test_group").await.is_ok());
+    assert!(start.elapsed() < std::time::Duration::from_millis(500));
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), 2);
+}